@@ -1,10 +1,21 @@
-use crate::app::utils::timestamp_recipient_sort;
-use crate::config::Config;
-use crate::messages::attachments::save_attachment;
-use crate::messages::receive::{self, MessageDto, contact, format_message};
+use crate::app::recipient_index::RecipientIndex;
+use crate::config::{Config, IdentityPolicy};
+use crate::emoji::{self, EmojiUsage};
+use crate::identity::{self, IdentityStore, VerificationStatus};
+use crate::message_requests::{MessageRequests, RequestResponse};
+use crate::messages::attachments::{
+    self, load_attachment_image, parse_attachment_field, save_attachment,
+};
+use crate::messages::deletion::{self, HiddenConversations, LocalDeletion};
+use crate::messages::expiry::{self, ExpiryIndex, next_preset};
+use crate::messages::mentions::{self, Mention, resolve_mentions};
+use crate::messages::receipts::{ReceiptStore, extract_receipt, receipt_thread, send_read_receipt};
+use crate::messages::receive::{self, MessageDto, contact, extract_typing, format_message};
 use crate::messages::send::{self};
 use crate::paths;
-use crate::profile::get_profile_tui;
+use crate::profile::{get_my_profile_avatar_tui, get_profile_tui};
+use crate::profile_cache::{self, ProfileCache};
+use crate::ui::chat::{THUMBNAIL_HEIGHT, THUMBNAIL_WIDTH};
 use crate::ui::render_ui;
 use anyhow::{Error, Result, anyhow, bail};
 use arboard::Clipboard;
@@ -12,6 +23,7 @@ use crossterm::event::{self, Event, KeyModifiers};
 use crossterm::event::{KeyCode, KeyEventKind};
 use futures::future::join_all;
 use futures::{StreamExt, pin_mut};
+use image::DynamicImage;
 use presage::Manager;
 use presage::libsignal_service::Profile;
 use presage::libsignal_service::groups_v2::Member;
@@ -25,16 +37,19 @@ use presage::proto::{AttachmentPointer, GroupContextV2};
 use presage_store_sqlite::SqliteStore;
 use ratatui::Terminal;
 use ratatui::backend::CrosstermBackend;
+use ratatui::layout::Rect;
+use ratatui_image::Resize;
 use ratatui_image::picker::Picker;
-use ratatui_image::protocol::StatefulProtocol;
+use ratatui_image::protocol::{Protocol, StatefulProtocol};
 use regex::Regex;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::io::Stderr;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::sync::mpsc::{self, Receiver, Sender};
 
-use std::{fs, io, mem};
+use std::{fs, io, mem, slice};
 use tokio::runtime::Builder;
 use tokio::sync::Mutex;
 use tokio_util::task::LocalPoolHandle;
@@ -47,15 +62,18 @@ use crate::account_management::{
 use crate::devices::link_new_device_for_account;
 use crate::notifications::send_notification;
 use crate::retry_manager::{OutgoingMessage, RetryManager};
+use crate::search::{SearchFilter, SearchHit, SearchIndex, index_sent};
+use crate::sidecar::AccountFile;
 use image::ImageFormat;
-use presage::store::ContentsStore;
+use presage::store::{ContentsStore, Thread};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::time::interval;
 
+mod recipient_index;
 mod utils;
 
-#[derive(Clone, PartialEq, Eq, Hash)]
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum RecipientId {
     Contact(Uuid),
     Group(GroupMasterKeyBytes),
@@ -67,6 +85,16 @@ impl Default for RecipientId {
     }
 }
 
+impl RecipientId {
+    /// Conversation in the store
+    pub fn thread(&self) -> Thread {
+        match self {
+            RecipientId::Contact(uuid) => Thread::Contact(*uuid),
+            RecipientId::Group(master_key) => Thread::Group(*master_key),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum DisplayRecipientType {
     Contact(DisplayContact),
@@ -77,6 +105,8 @@ pub enum DisplayRecipientType {
 pub struct DisplayRecipient {
     recipient_type: DisplayRecipientType,
     latest_message_timestamp: Option<u64>,
+    /// Conversation with a sender who isn't a contact, waiting to be accepted
+    request: bool,
 }
 
 impl DisplayRecipient {
@@ -87,6 +117,17 @@ impl DisplayRecipient {
         }
     }
 
+    pub fn is_request(&self) -> bool {
+        self.request
+    }
+
+    pub fn phone_number(&self) -> Option<&str> {
+        match &self.recipient_type {
+            DisplayRecipientType::Contact(c) => c.phone_number(),
+            DisplayRecipientType::Group(_) => None,
+        }
+    }
+
     pub fn id(&self) -> RecipientId {
         match &self.recipient_type {
             DisplayRecipientType::Contact(c) => c.id(),
@@ -99,17 +140,26 @@ impl DisplayRecipient {
 pub struct DisplayContact {
     display_name: String,
     uuid: Uuid,
+    phone_number: Option<String>,
 }
 
 impl DisplayContact {
-    fn new(display_name: String, uuid: Uuid) -> Self {
-        Self { display_name, uuid }
+    fn new(display_name: String, uuid: Uuid, phone_number: Option<String>) -> Self {
+        Self {
+            display_name,
+            uuid,
+            phone_number,
+        }
     }
 
     pub fn display_name(&self) -> &str {
         &self.display_name
     }
 
+    pub fn phone_number(&self) -> Option<&str> {
+        self.phone_number.as_deref()
+    }
+
     pub fn id(&self) -> RecipientId {
         RecipientId::Contact(self.uuid)
     }
@@ -160,7 +210,13 @@ pub enum CurrentScreen {
     AccountSelector,
     CreatingAccount,
     ConfirmDelete,
+    ConfirmLocalDelete,
+    ConfirmRequest,
     Recaptcha,
+    Search,
+    EmojiPicker,
+    ImageViewer,
+    SafetyNumber,
 }
 
 #[derive(PartialEq)]
@@ -182,7 +238,7 @@ pub struct ContactInfo {
     pub name: String,
     pub description: Option<String>,
     pub phone_number: Option<String>,
-    pub verified_state: Option<i32>,
+    pub verification: VerificationStatus,
     pub expire_timer: u32,
     pub has_avatar: bool,
 }
@@ -194,6 +250,7 @@ pub struct GroupInfo {
     pub description: String,
     pub has_avatar: bool,
     pub members: Vec<MemberInfo>,
+    pub expire_timer: u32,
 }
 
 #[derive(Clone)]
@@ -206,6 +263,65 @@ pub struct MemberInfo {
     pub(crate) profile_key: ProfileKey,
 }
 
+impl MemberInfo {
+    /// Name, phone number or the start of the UUID, whichever is known first
+    pub fn display_name(&self) -> String {
+        match (&self.name, &self.phone_number) {
+            (Some(name), _) if !name.is_empty() => name.clone(),
+            (_, Some(phone_number)) => phone_number.clone(),
+            _ => self.uuid.to_string().chars().take(8).collect(),
+        }
+    }
+}
+
+/// Thumbnails kept in memory, the least recently shown ones are dropped above it
+const THUMBNAILS_KEPT: usize = 64;
+
+/// Thumbnails of image attachments by their cache key, bounded by `THUMBNAILS_KEPT`
+#[derive(Default)]
+pub struct Thumbnails {
+    images: HashMap<String, Protocol>,
+    /// Cache keys from the least recently shown thumbnail
+    order: VecDeque<String>,
+}
+
+impl Thumbnails {
+    pub fn get(&self, key: &str) -> Option<&Protocol> {
+        self.images.get(key)
+    }
+
+    /// Adds the thumbnail, returns the key of the thumbnail dropped to make room for it
+    fn insert(&mut self, key: String, image: Protocol) -> Option<String> {
+        self.touch(&key);
+        if self.images.insert(key.clone(), image).is_none() {
+            self.order.push_back(key);
+        }
+        if self.order.len() <= THUMBNAILS_KEPT {
+            return None;
+        }
+        let evicted = self.order.pop_front()?;
+        self.images.remove(&evicted);
+        Some(evicted)
+    }
+
+    /// Marks the thumbnail as shown, so it is dropped last
+    fn touch(&mut self, key: &str) {
+        if let Some(index) = self.order.iter().position(|kept| kept == key) {
+            let key = self.order.remove(index).expect("position is in bounds");
+            self.order.push_back(key);
+        }
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        self.images.contains_key(key)
+    }
+
+    fn clear(&mut self) {
+        self.images.clear();
+        self.order.clear();
+    }
+}
+
 #[derive(PartialEq, Clone)]
 pub enum AccountLinkingField {
     AccountName,
@@ -225,6 +341,10 @@ pub struct App {
 
     pub selected_recipient: usize,
     pub message_selected: usize,
+    /// Deletion on this device waiting for confirmation
+    pub pending_local_deletion: Option<(RecipientId, LocalDeletion)>,
+    /// Deleting or blocking a message request, waiting for confirmation
+    pub pending_request_response: Option<(Uuid, RequestResponse)>,
 
     // New fields for contact info
     pub selected_contact_info: Option<ContactInfo>,
@@ -247,6 +367,47 @@ pub struct App {
     pub attachment_error: Option<String>,
 
     pub quoted_message: Option<MessageDto>,
+    /// Our message whose text is being replaced by the input
+    pub editing_message: Option<MessageDto>,
+
+    pub search_query: String,
+    pub search_results: Vec<SearchHit>,
+    pub search_selected: usize,
+    /// Message that gets selected once the history of its conversation is loaded
+    pub pending_jump: Option<(RecipientId, u64)>,
+
+    /// Contacts typing in every conversation, with the time of their last typing event
+    pub typing: HashMap<RecipientId, HashMap<Uuid, Instant>>,
+    /// Conversation we told that we are typing and when we did it last
+    typing_sent: Option<(RecipientId, Instant)>,
+
+    /// Members of groups, used to autocomplete mentions
+    pub group_members: HashMap<GroupMasterKeyBytes, Vec<MemberInfo>>,
+    /// Text typed after `@` while the mention autocomplete is open
+    pub mention_query: Option<String>,
+    pub mention_selected: usize,
+    /// Members picked from the mention autocomplete, by conversation and their position in
+    /// its input
+    input_mentions: HashMap<RecipientId, Vec<Mention>>,
+
+    /// Shortcode typed into the reaction picker
+    pub emoji_query: String,
+    pub emoji_selected: usize,
+    pub emoji_usage: EmojiUsage,
+
+    /// Timestamps of messages whose spoilers are shown
+    pub revealed_spoilers: HashSet<u64>,
+
+    /// Thumbnails of image attachments of the latest loaded chats
+    pub attachment_thumbnails: Thumbnails,
+    /// Cache keys of thumbnails that were requested, loaded or not
+    pub requested_thumbnails: HashSet<String>,
+    /// Image opened in the full-screen viewer, `None` while it is loading
+    pub image_viewer: Option<StatefulProtocol>,
+    /// Safety number with the selected contact, `None` while it is loading
+    pub safety_number: Option<identity::SafetyNumber>,
+    /// When the safety number with contacts changed, for contacts not verified since
+    pub safety_number_changes: HashMap<Uuid, u64>,
 
     pub retry_manager: Arc<Mutex<RetryManager>>,
     pub message_id_map: HashMap<String, String>,
@@ -318,18 +479,53 @@ pub enum EventApp {
     Resize(u16, u16),
     UiStatus(UiStatusMessage),
     CaptchaError(String),
+    SearchResults(String, Vec<SearchHit>),
+    /// Sender started (`true`) or stopped typing in the conversation
+    Typing(RecipientId, Uuid, bool),
+    /// Disappearing messages timer of the conversation with the contact was changed, in seconds
+    ExpireTimerChanged(Uuid, u32),
+    /// Thumbnail of an image attachment, identified by its cache key
+    AttachmentThumbnailReceived(String, DynamicImage),
+    /// Image attachment to show in the full-screen viewer
+    AttachmentImageReceived(DynamicImage),
+    SafetyNumberReceived(identity::SafetyNumber),
+    /// Safety number with the contact changed at the time, in milliseconds since the epoch
+    SafetyNumberChanged(Uuid, u64),
 }
 pub enum EventSend {
     SendText(RecipientId, String, Option<MessageDto>),
     SendAttachment(RecipientId, String, String, Option<MessageDto>),
     DeleteMessage(RecipientId, u64),
-    ReactToMessage(RecipientId, bool, MessageDto),
+    /// Delete messages or the whole conversation on this device only
+    DeleteLocally(RecipientId, LocalDeletion),
+    /// Accept, delete or block the message request of the sender
+    RespondToRequest(Uuid, RequestResponse),
+    EditMessage(RecipientId, u64, String),
+    /// React to the message with the emoji, or remove our reaction (`true`)
+    ReactToMessage(RecipientId, String, bool, MessageDto),
     GetMessagesForContact(String),
     GetMessagesForGroup(GroupMasterKeyBytes),
     GetContactInfo(String),
     GetGroupInfo(GroupMasterKeyBytes),
     GetMemberAvatar(MemberInfo),
     SaveAttachment(Box<AttachmentPointer>, PathBuf),
+    /// Fetch the image attachment through the cache in the directory, as a thumbnail (`true`)
+    /// or for the full-screen viewer
+    GetAttachmentImage(Box<AttachmentPointer>, PathBuf, bool),
+    Search(String),
+    /// Received messages of the conversation shown in the chat, identified by their author and
+    /// timestamp. Read receipts are sent for them when the flag is set.
+    MarkRead(RecipientId, Vec<(Uuid, u64)>, bool),
+    /// Tell the conversation that we started (`true`) or stopped typing
+    Typing(RecipientId, bool),
+    /// Change disappearing messages timer of the conversation with the contact, in seconds
+    SetExpireTimer(Uuid, u32),
+    /// Disappearing timers of received messages of the conversation which started because the
+    /// messages were read, by timestamp of the message and when it disappears
+    StartExpiryTimers(RecipientId, Vec<(u64, u64)>),
+    GetSafetyNumber(Uuid),
+    /// Mark the safety number with the contact as verified (`true`) or unverified
+    SetVerified(Uuid, bool),
 }
 
 impl App {
@@ -339,7 +535,8 @@ impl App {
         let picker = Picker::from_query_stdio().ok();
 
         let available_accounts = list_accounts().unwrap_or_default();
-        let current_account = Config::load().get_current_account().cloned();
+        let config = Config::load();
+        let current_account = config.get_current_account().cloned();
 
         let clipboard = match Clipboard::new() {
             Ok(clipboard) => Some(clipboard),
@@ -360,6 +557,8 @@ impl App {
             account_selected: 0,
             recipients: vec![],
             selected_recipient: 0,
+            pending_local_deletion: None,
+            pending_request_response: None,
             message_selected: 0,
             character_index: 0,
             current_screen: CurrentScreen::LinkingNewDevice,
@@ -370,11 +569,31 @@ impl App {
             attachment_path: String::new(),
             attachment_error: None,
             quoted_message: None,
+            editing_message: None,
+            search_query: String::new(),
+            search_results: Vec::new(),
+            search_selected: 0,
+            pending_jump: None,
+            typing: HashMap::new(),
+            typing_sent: None,
+            group_members: HashMap::new(),
+            mention_query: None,
+            mention_selected: 0,
+            input_mentions: HashMap::new(),
+            emoji_query: String::new(),
+            emoji_selected: 0,
+            emoji_usage: EmojiUsage::load_current().unwrap_or_default(),
+            revealed_spoilers: HashSet::new(),
+            attachment_thumbnails: Thumbnails::default(),
+            requested_thumbnails: HashSet::new(),
+            image_viewer: None,
+            safety_number: None,
+            safety_number_changes: HashMap::new(),
             input_focus: InputFocus::Message,
 
             ui_status_info: None,
 
-            retry_manager: Arc::new(Mutex::new(RetryManager::new())),
+            retry_manager: Arc::new(Mutex::new(RetryManager::new(config.retry_policy.clone()))),
             message_id_map: HashMap::new(),
 
             profile: None,
@@ -392,7 +611,7 @@ impl App {
             group_avatar_image: None,
             selected_member_info: None,
 
-            config: Config::load(),
+            config,
             config_selected: 0,
 
             manager: None,
@@ -465,7 +684,16 @@ impl App {
         loop {
             terminal.draw(|f| render_ui(f, self))?;
 
-            if let Ok(event) = self.rx_tui.recv()
+            // Typing indicators and disappearing messages have to go away even when no
+            // other event arrives
+            let event = match self.next_wakeup() {
+                None => self.rx_tui.recv().ok(),
+                Some(timeout) => self.rx_tui.recv_timeout(timeout).ok(),
+            };
+            self.expire_typing();
+            self.expire_messages();
+
+            if let Some(event) = event
                 && self.handle_event(event, &self.tx_tui.clone()).await?
             {
                 return Ok(true);
@@ -500,6 +728,14 @@ impl App {
         self.selected_recipient = 0;
         self.contact_messages.clear();
         self.group_messages.clear();
+        self.search_query.clear();
+        self.search_results.clear();
+        self.pending_jump = None;
+        self.typing.clear();
+        self.typing_sent = None;
+        self.group_members.clear();
+        self.mention_query = None;
+        self.emoji_usage = EmojiUsage::load_for_account(&account_name).unwrap_or_default();
         self.current_screen = CurrentScreen::Syncing;
 
         if self.rx_thread.is_none() {
@@ -708,6 +944,8 @@ impl App {
                 Ok(false)
             }
             EventApp::GroupInfoReceived(group_info) => {
+                self.group_members
+                    .insert(group_info.master_key, group_info.members.clone());
                 self.selected_group_info = Some(group_info);
                 self.selected_group_member = 0;
                 Ok(false)
@@ -797,14 +1035,96 @@ impl App {
                 self.avatar_cache = Some(avatar_data);
                 Ok(false)
             }
-            EventApp::GetContactMessageHistory(uuid_str, messages) => {
+            EventApp::GetContactMessageHistory(uuid_str, mut messages) => {
+                self.message_selected = match Uuid::parse_str(&uuid_str) {
+                    Ok(uuid) => {
+                        let recipient = RecipientId::Contact(uuid);
+                        self.mark_read(&recipient, &mut messages);
+                        self.take_pending_jump(&recipient, &messages)
+                    }
+                    Err(_) => 0,
+                };
+                self.request_thumbnails(&messages);
                 self.contact_messages.insert(uuid_str, messages);
-                self.message_selected = 0;
                 Ok(false)
             }
-            EventApp::GetGroupMessageHistory(master_key, messages) => {
+            EventApp::GetGroupMessageHistory(master_key, mut messages) => {
+                let recipient = RecipientId::Group(master_key);
+                self.mark_read(&recipient, &mut messages);
+                self.message_selected = self.take_pending_jump(&recipient, &messages);
+                self.request_thumbnails(&messages);
                 self.group_messages.insert(master_key, messages);
-                self.message_selected = 0;
+                Ok(false)
+            }
+            EventApp::AttachmentThumbnailReceived(key, image) => {
+                if let Some(picker) = &mut self.picker {
+                    let size = Rect::new(0, 0, THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT);
+                    match picker.new_protocol(image, size, Resize::Fit(None)) {
+                        Ok(protocol) => {
+                            // A dropped thumbnail is loaded again from the disk cache when its
+                            // chat is shown the next time
+                            if let Some(evicted) = self.attachment_thumbnails.insert(key, protocol)
+                            {
+                                self.requested_thumbnails.remove(&evicted);
+                            }
+                        }
+                        Err(error) => warn!(%error, "Failed to create thumbnail"),
+                    }
+                }
+                Ok(false)
+            }
+            EventApp::SafetyNumberReceived(safety_number) => {
+                if safety_number.status != VerificationStatus::Changed {
+                    self.safety_number_changes.remove(&safety_number.uuid);
+                }
+                if self.current_screen == CurrentScreen::SafetyNumber {
+                    self.safety_number = Some(safety_number);
+                }
+                Ok(false)
+            }
+            EventApp::SafetyNumberChanged(uuid, timestamp) => {
+                if self.config.identity_policy != IdentityPolicy::Trust {
+                    self.safety_number_changes.insert(uuid, timestamp);
+                }
+                Ok(false)
+            }
+            EventApp::AttachmentImageReceived(image) => {
+                if self.current_screen == CurrentScreen::ImageViewer
+                    && let Some(picker) = &mut self.picker
+                {
+                    self.image_viewer = Some(picker.new_resize_protocol(image));
+                }
+                Ok(false)
+            }
+            EventApp::Typing(recipient, sender, started) => {
+                if self.uuid == Some(sender) {
+                    return Ok(false);
+                }
+                let typing = self.typing.entry(recipient.clone()).or_default();
+                if started {
+                    typing.insert(sender, Instant::now());
+                } else {
+                    typing.remove(&sender);
+                    if typing.is_empty() {
+                        self.typing.remove(&recipient);
+                    }
+                }
+                Ok(false)
+            }
+            EventApp::ExpireTimerChanged(uuid, seconds) => {
+                if let Some(contact_info) = &mut self.selected_contact_info
+                    && contact_info.uuid == uuid.to_string()
+                {
+                    contact_info.expire_timer = seconds;
+                }
+                Ok(false)
+            }
+            EventApp::SearchResults(query, hits) => {
+                // Results of an older query may arrive after the user kept typing
+                if query == self.search_query {
+                    self.search_results = hits;
+                    self.search_selected = 0;
+                }
                 Ok(false)
             }
             EventApp::ReceiveMessage => {
@@ -825,6 +1145,502 @@ impl App {
         }
     }
 
+    /// How long the UI may wait for an event before something shown on the screen expires
+    fn next_wakeup(&self) -> Option<Duration> {
+        let now = chrono::Utc::now().timestamp_millis() as u64;
+        let next_expiry = self
+            .contact_messages
+            .values()
+            .chain(self.group_messages.values())
+            .flatten()
+            .filter_map(|msg| msg.expires_at)
+            .min()
+            .map(|expires_at| Duration::from_millis(expires_at.saturating_sub(now)));
+        let typing = (!self.typing.is_empty()).then_some(TYPING_CHECK_INTERVAL);
+
+        match (next_expiry, typing) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Removes messages whose disappearing timer ran out, the store is purged in the background
+    fn expire_messages(&mut self) {
+        let now = chrono::Utc::now().timestamp_millis() as u64;
+        let is_live = |msg: &MessageDto| msg.expires_at.is_none_or(|expires_at| expires_at > now);
+        for messages in self.contact_messages.values_mut() {
+            messages.retain(is_live);
+        }
+        for messages in self.group_messages.values_mut() {
+            messages.retain(is_live);
+        }
+    }
+
+    /// Forgets contacts whose typing wasn't refreshed in time, e.g. because the stop got lost
+    fn expire_typing(&mut self) {
+        for typing in self.typing.values_mut() {
+            typing.retain(|_, last_event| last_event.elapsed() < TYPING_TIMEOUT);
+        }
+        self.typing.retain(|_, typing| !typing.is_empty());
+    }
+
+    /// Sends typing started for the selected conversation while its input isn't empty, the
+    /// event is repeated only every few seconds
+    fn update_typing(&mut self) {
+        if !self.config.send_typing_indicators || self.selected_is_request() {
+            return;
+        }
+        let (recipient, input_empty) = match self.recipients.get(self.selected_recipient) {
+            Some((recipient, input)) => (recipient.id(), input.is_empty()),
+            None => return,
+        };
+        if input_empty {
+            self.stop_typing();
+            return;
+        }
+
+        let refresh = match &self.typing_sent {
+            Some((typing_recipient, sent_at)) => {
+                *typing_recipient != recipient || sent_at.elapsed() >= TYPING_REFRESH_INTERVAL
+            }
+            None => true,
+        };
+        if refresh {
+            if let Some((typing_recipient, _)) = &self.typing_sent
+                && *typing_recipient != recipient
+            {
+                self.stop_typing();
+            }
+            if let Err(error) = self.tx_tui.send(EventSend::Typing(recipient.clone(), true)) {
+                error!(%error, "Failed to request sending typing indicator");
+            }
+            self.typing_sent = Some((recipient, Instant::now()));
+        }
+    }
+
+    /// Sends typing stopped if we told a conversation that we are typing
+    fn stop_typing(&mut self) {
+        if let Some((recipient, _)) = self.typing_sent.take()
+            && let Err(error) = self.tx_tui.send(EventSend::Typing(recipient, false))
+        {
+            error!(%error, "Failed to request sending typing indicator");
+        }
+    }
+
+    /// Marks received messages of the opened chat as read, read receipts are sent if enabled in
+    /// the config. Disappearing timers of the read messages start.
+    fn mark_read(&self, recipient: &RecipientId, messages: &mut [MessageDto]) {
+        let chat_opened = matches!(
+            self.current_screen,
+            CurrentScreen::Writing | CurrentScreen::InspectMesseges
+        ) && self
+            .recipients
+            .get(self.selected_recipient)
+            .is_some_and(|(selected, _)| selected.id() == *recipient && !selected.is_request());
+        if !chat_opened {
+            return;
+        }
+
+        let now = chrono::Utc::now().timestamp_millis() as u64;
+        let timers = messages
+            .iter_mut()
+            .filter(|msg| Some(msg.uuid) != self.uuid && msg.expires_at.is_none())
+            .filter_map(|msg| {
+                let expires_at = expiry::timer_end(now, msg.expire_timer?);
+                msg.expires_at = Some(expires_at);
+                Some((msg.timestamp, expires_at))
+            })
+            .collect::<Vec<_>>();
+        if !timers.is_empty()
+            && let Err(error) = self
+                .tx_tui
+                .send(EventSend::StartExpiryTimers(recipient.clone(), timers))
+        {
+            error!(%error, "Failed to request starting disappearing timers");
+        }
+
+        let received = messages
+            .iter()
+            .filter(|msg| Some(msg.uuid) != self.uuid)
+            .map(|msg| (msg.uuid, msg.timestamp))
+            .collect::<Vec<_>>();
+        if received.is_empty() {
+            return;
+        }
+
+        let event =
+            EventSend::MarkRead(recipient.clone(), received, self.config.send_read_receipts);
+        if let Err(error) = self.tx_tui.send(event) {
+            error!(%error, "Failed to request marking messages as read");
+        }
+    }
+
+    /// Asks for members of the selected group, unless they are known already
+    fn request_group_members(&self) {
+        if let Some((recipient, _)) = self.recipients.get(self.selected_recipient)
+            && let RecipientId::Group(master_key) = recipient.id()
+            && !self.group_members.contains_key(&master_key)
+            && let Err(error) = self.tx_tui.send(EventSend::GetGroupInfo(master_key))
+        {
+            error!(%error, "Failed to request group members");
+        }
+    }
+
+    /// Members of the selected group with the names they are mentioned by, empty for contacts
+    fn selected_group_mention_names(&self) -> Vec<(Uuid, String)> {
+        let Some((recipient, _)) = self.recipients.get(self.selected_recipient) else {
+            return Vec::new();
+        };
+        let RecipientId::Group(master_key) = recipient.id() else {
+            return Vec::new();
+        };
+        self.group_members
+            .get(&master_key)
+            .map(|members| {
+                members
+                    .iter()
+                    .map(|member| (member.uuid, member.display_name()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Opens the mention autocomplete on `@` typed at the start of a word in a group chat, and
+    /// closes it once the word ends
+    fn update_mention_query(&mut self, new_char: char) {
+        if let Some(query) = &mut self.mention_query {
+            if new_char.is_whitespace() {
+                self.mention_query = None;
+            } else {
+                query.push(new_char);
+                self.mention_selected = 0;
+            }
+            return;
+        }
+
+        let Some((recipient, input)) = self.recipients.get(self.selected_recipient) else {
+            return;
+        };
+        let word_start = input.chars().last().is_none_or(char::is_whitespace);
+        if new_char == '@' && word_start && matches!(recipient.id(), RecipientId::Group(_)) {
+            self.mention_query = Some(String::new());
+            self.mention_selected = 0;
+        }
+    }
+
+    /// Members of the selected group matching the text typed after `@`, without us
+    pub fn mention_candidates(&self) -> Vec<(Uuid, String)> {
+        let Some(query) = &self.mention_query else {
+            return Vec::new();
+        };
+        let query = query.to_lowercase();
+        self.selected_group_mention_names()
+            .into_iter()
+            .filter(|(uuid, name)| Some(*uuid) != self.uuid && name.to_lowercase().contains(&query))
+            .collect()
+    }
+
+    /// Replaces the text typed after `@` with the name of the selected member
+    fn complete_mention(&mut self) {
+        let Some(query) = &self.mention_query else {
+            return;
+        };
+        let Some((uuid, name)) = self
+            .mention_candidates()
+            .into_iter()
+            .nth(self.mention_selected)
+        else {
+            return;
+        };
+
+        let query_len = query.chars().count();
+        if let Some((recipient, input)) = self.recipients.get_mut(self.selected_recipient) {
+            for _ in 0..query_len {
+                input.pop();
+            }
+            // The `@` typed before the query starts the mention
+            let start = input.len().saturating_sub('@'.len_utf8());
+            input.push_str(&name);
+            self.input_mentions
+                .entry(recipient.id())
+                .or_default()
+                .push(Mention {
+                    uuid,
+                    range: start..input.len(),
+                });
+            input.push(' ');
+            self.character_index = input.chars().count();
+        }
+        self.mention_query = None;
+        self.update_typing();
+    }
+
+    /// Directory caching attachments of the current account
+    fn attachment_cache_dir(&self) -> Option<PathBuf> {
+        self.current_account
+            .as_deref()
+            .map(paths::account_attachment_cache_dir)
+    }
+
+    /// Asks for thumbnails of image attachments that were not requested yet
+    fn request_thumbnails(&mut self, messages: &[MessageDto]) {
+        if !self.config.show_images || self.picker.is_none() {
+            return;
+        }
+        let Some(cache_dir) = self.attachment_cache_dir() else {
+            return;
+        };
+
+        for attachment in messages.iter().filter_map(|msg| msg.attachment.as_ref()) {
+            if !attachments::is_image(attachment) {
+                continue;
+            }
+            let Some(key) = attachments::cache_key(attachment) else {
+                continue;
+            };
+            if self.attachment_thumbnails.contains(&key) {
+                self.attachment_thumbnails.touch(&key);
+            } else if self.requested_thumbnails.insert(key) {
+                let event = EventSend::GetAttachmentImage(
+                    Box::new(attachment.clone()),
+                    cache_dir.clone(),
+                    true,
+                );
+                if let Err(error) = self.tx_tui.send(event) {
+                    error!(%error, "Failed to request attachment thumbnail");
+                }
+            }
+        }
+    }
+
+    /// Opens the image attachment of the selected message in the full-screen viewer
+    fn open_image_viewer(&mut self) {
+        if !self.config.show_images || self.picker.is_none() {
+            return;
+        }
+        let Some(attachment) = self
+            .selected_message()
+            .and_then(|msg| msg.attachment.clone())
+            .filter(attachments::is_image)
+        else {
+            return;
+        };
+        let Some(cache_dir) = self.attachment_cache_dir() else {
+            return;
+        };
+
+        self.image_viewer = None;
+        self.current_screen = CurrentScreen::ImageViewer;
+        let event = EventSend::GetAttachmentImage(Box::new(attachment), cache_dir, false);
+        if let Err(error) = self.tx_tui.send(event) {
+            error!(%error, "Failed to request attachment image");
+        }
+    }
+
+    /// File name of the attachment of the selected message
+    pub fn selected_attachment_name(&self) -> Option<String> {
+        self.selected_message()?
+            .attachment
+            .as_ref()?
+            .file_name
+            .clone()
+    }
+
+    /// Whether the selected conversation is a message request, we don't reply to it or send
+    /// receipts and typing indicators until it is accepted
+    pub fn selected_is_request(&self) -> bool {
+        self.recipients
+            .get(self.selected_recipient)
+            .is_some_and(|(recipient, _)| recipient.is_request())
+    }
+
+    /// Answers the selected message request, deleting and blocking are confirmed first
+    fn respond_to_request(&mut self, response: RequestResponse) {
+        let Some((recipient, _)) = self.recipients.get(self.selected_recipient) else {
+            return;
+        };
+        let RecipientId::Contact(sender) = recipient.id() else {
+            return;
+        };
+        if !recipient.is_request() {
+            return;
+        }
+
+        match response {
+            RequestResponse::Accept => self
+                .tx_tui
+                .send(EventSend::RespondToRequest(sender, response))
+                .unwrap(),
+            RequestResponse::Delete | RequestResponse::Block => {
+                self.pending_request_response = Some((sender, response));
+                self.current_screen = CurrentScreen::ConfirmRequest;
+            }
+        }
+    }
+
+    /// Asks to confirm deleting messages of the selected conversation on this device
+    fn confirm_local_deletion(&mut self, deletion: LocalDeletion) {
+        if let Some((recipient, _)) = self.recipients.get(self.selected_recipient) {
+            self.pending_local_deletion = Some((recipient.id(), deletion));
+            self.current_screen = CurrentScreen::ConfirmLocalDelete;
+        }
+    }
+
+    /// Message selected in the opened chat
+    fn selected_message(&self) -> Option<&MessageDto> {
+        let (recipient, _) = self.recipients.get(self.selected_recipient)?;
+        let messages = match recipient.id() {
+            RecipientId::Contact(uuid) => self.contact_messages.get(&uuid.to_string()),
+            RecipientId::Group(master_key) => self.group_messages.get(&master_key),
+        }?;
+        messages.get(self.message_selected)
+    }
+
+    /// Emoji of our current reaction to the selected message
+    pub fn own_reaction(&self) -> Option<String> {
+        let own_uuid = self.uuid?;
+        self.selected_message()?
+            .reactions
+            .get(&own_uuid)
+            .filter(|reaction| !reaction.remove())
+            .map(|reaction| reaction.emoji().to_string())
+    }
+
+    /// Emojis offered by the reaction picker for the typed shortcode
+    pub fn emoji_options(&self) -> Vec<String> {
+        if self.emoji_query.is_empty() {
+            self.emoji_usage.suggestions()
+        } else {
+            emoji::search(&self.emoji_query)
+                .into_iter()
+                .map(String::from)
+                .collect()
+        }
+    }
+
+    /// Reacts to the selected message with the emoji selected in the picker, choosing the
+    /// emoji we already reacted with removes the reaction
+    fn react_with_selected_emoji(&mut self) {
+        let Some(emoji) = self.emoji_options().into_iter().nth(self.emoji_selected) else {
+            return;
+        };
+        let Some(message) = self.selected_message().cloned() else {
+            return;
+        };
+        let recipient = self.recipients[self.selected_recipient].0.id();
+
+        let remove = self.own_reaction().as_deref() == Some(emoji.as_str());
+        if !remove {
+            self.emoji_usage.record(&emoji);
+        }
+
+        if let Err(error) = self
+            .tx_tui
+            .send(EventSend::ReactToMessage(recipient, emoji, remove, message))
+        {
+            error!(%error, "Failed to request sending reaction");
+        }
+    }
+
+    /// Puts text of our selected message into the input, so sending it replaces the message
+    fn start_editing(&mut self) {
+        let Some(own_uuid) = self.uuid else {
+            return;
+        };
+        let selected_recipient_id = self.recipients[self.selected_recipient].0.id();
+        let message = match selected_recipient_id {
+            RecipientId::Contact(uuid) => self.contact_messages.get(&uuid.to_string()),
+            RecipientId::Group(group_key) => self.group_messages.get(&group_key),
+        }
+        .and_then(|messages| messages.get(self.message_selected))
+        .filter(|message| message.uuid == own_uuid && message.attachment.is_none())
+        .cloned();
+
+        if let Some(message) = message
+            && let Some((recipient, input)) = self.recipients.get_mut(self.selected_recipient)
+        {
+            *input = message.text.clone();
+            self.input_mentions
+                .insert(recipient.id(), message.mentions.clone());
+            self.character_index = input.chars().count();
+            self.quoted_message = None;
+            self.input_focus = InputFocus::Message;
+            self.editing_message = Some(message);
+            self.current_screen = CurrentScreen::Writing;
+        }
+    }
+
+    /// Leaves editing mode and drops the edited text from the input
+    fn stop_editing(&mut self) {
+        if self.editing_message.take().is_some()
+            && let Some((recipient, input)) = self.recipients.get_mut(self.selected_recipient)
+        {
+            input.clear();
+            self.input_mentions.remove(&recipient.id());
+            self.character_index = 0;
+        }
+    }
+
+    /// Position of the message the user jumped to from search, 0 selects the latest message
+    fn take_pending_jump(&mut self, recipient: &RecipientId, messages: &[MessageDto]) -> usize {
+        match &self.pending_jump {
+            Some((jump_recipient, timestamp)) if jump_recipient == recipient => {
+                let position = messages
+                    .iter()
+                    .position(|message| message.timestamp == *timestamp)
+                    .unwrap_or(0);
+                self.pending_jump = None;
+                position
+            }
+            _ => 0,
+        }
+    }
+
+    /// Opens the conversation of the selected search hit with the hit selected
+    fn jump_to_search_hit(&mut self) {
+        let Some(hit) = self.search_results.get(self.search_selected) else {
+            return;
+        };
+
+        match self
+            .recipients
+            .iter()
+            .position(|(recipient, _)| recipient.id() == hit.recipient)
+        {
+            Some(position) => {
+                self.selected_recipient = position;
+                self.pending_jump = Some((hit.recipient.clone(), hit.timestamp));
+                self.quoted_message = None;
+                self.synchronize_messages_for_selected_recipient();
+                self.current_screen = CurrentScreen::InspectMesseges;
+            }
+            None => {
+                self.ui_status_info = Some(UiStatusInfo {
+                    status_message: UiStatusMessage::Error(
+                        "Conversation of this message is not in the contact list".to_string(),
+                    ),
+                    last_screen: CurrentScreen::Search,
+                });
+                self.current_screen = CurrentScreen::Popup;
+            }
+        }
+    }
+
+    fn update_search(&mut self) {
+        if self.search_query.trim().is_empty() {
+            self.search_results.clear();
+            self.search_selected = 0;
+            return;
+        }
+
+        if let Err(error) = self
+            .tx_tui
+            .send(EventSend::Search(self.search_query.clone()))
+        {
+            error!(%error, "Failed to send search request");
+        }
+    }
+
     fn enter_char(&mut self, new_char: char) {
         if let Some((_, input)) = self.recipients.get_mut(self.selected_recipient) {
             input.push(new_char);
@@ -833,11 +1649,15 @@ impl App {
     }
 
     fn delete_char(&mut self) {
-        if let Some((_, input)) = self.recipients.get_mut(self.selected_recipient)
+        if let Some((recipient, input)) = self.recipients.get_mut(self.selected_recipient)
             && self.character_index > 0
         {
             input.pop();
             self.character_index -= 1;
+            // A mention is dropped once a character of it is deleted
+            if let Some(mentions) = self.input_mentions.get_mut(&recipient.id()) {
+                mentions.retain(|mention| mention.range.end <= input.len());
+            }
         }
     }
 
@@ -868,19 +1688,48 @@ impl App {
         }
     }
 
+    /// Checks every attachment in the field, the error names the first one that is wrong
     fn validate_attachment_path(&mut self) {
-        if self.attachment_path.trim().is_empty() {
-            self.attachment_error = None;
-        } else if !Path::new(&self.attachment_path).exists() {
-            self.attachment_error = Some("File does not exist".to_string());
-        } else if !Path::new(&self.attachment_path).is_file() {
-            self.attachment_error = Some("Path is not a file".to_string());
-        } else {
-            self.attachment_error = None;
-        }
-    }
+        let attachments = parse_attachment_field(&self.attachment_path);
+        let several = attachments.len() > 1;
+        self.attachment_error = attachments.iter().find_map(|attachment| {
+            let path = Path::new(&attachment.path);
+            let error = if !path.exists() {
+                "File does not exist"
+            } else if !path.is_file() {
+                "Path is not a file"
+            } else {
+                return None;
+            };
+            match several {
+                true => Some(format!("{error}: {}", attachment.path)),
+                false => Some(error.to_string()),
+            }
+        });
+    }
+
+    /// Attachments before the last one in the field, which is the one being typed
+    fn completed_attachments(&self) -> &str {
+        match self
+            .attachment_path
+            .rfind(attachments::ATTACHMENT_SEPARATOR)
+        {
+            Some(index) => &self.attachment_path[..=index],
+            None => "",
+        }
+    }
 
     fn submit_message(&mut self, tx: &Sender<EventSend>) {
+        if self.selected_is_request() {
+            self.ui_status_info = Some(UiStatusInfo {
+                status_message: UiStatusMessage::Info(
+                    "Accept the message request with (y) in the chat list to reply".to_string(),
+                ),
+                last_screen: CurrentScreen::Writing,
+            });
+            self.current_screen = CurrentScreen::Popup;
+            return;
+        }
         let has_attachment = !self.attachment_path.trim().is_empty();
         if has_attachment {
             self.validate_attachment_path();
@@ -888,28 +1737,35 @@ impl App {
                 return;
             }
         }
+        self.mention_query = None;
         if let Some((recipient, input)) = self.recipients.get_mut(self.selected_recipient) {
-            let message_text = input.trim().to_string();
+            let picked = self
+                .input_mentions
+                .get(&recipient.id())
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            let message_text = mentions::encode_mentions(input, picked).trim().to_string();
+
+            if let Some(edited) = &self.editing_message {
+                if !message_text.is_empty() {
+                    tx.send(EventSend::EditMessage(
+                        recipient.id(),
+                        edited.timestamp,
+                        message_text,
+                    ))
+                    .unwrap();
+                    input.clear();
+                    self.input_mentions.remove(&recipient.id());
+                    self.character_index = 0;
+                    self.editing_message = None;
+                }
+                return;
+            }
+
             let has_attachment = !self.attachment_path.trim().is_empty();
             let has_text = !message_text.is_empty();
 
             if has_text || has_attachment {
-                let outgoing = OutgoingMessage::new(
-                    recipient.id(),
-                    message_text.clone(),
-                    if has_attachment {
-                        Some(self.attachment_path.clone())
-                    } else {
-                        None
-                    },
-                    self.quoted_message.clone(),
-                    None,
-                );
-
-                if let Ok(mut manager) = self.retry_manager.try_lock() {
-                    let _message_id = manager.add_message(outgoing.clone());
-                }
-
                 if has_attachment {
                     tx.send(EventSend::SendAttachment(
                         recipient.id(),
@@ -929,6 +1785,7 @@ impl App {
                 }
 
                 input.clear();
+                self.input_mentions.remove(&recipient.id());
                 self.character_index = 0;
                 self.quoted_message = None;
             }
@@ -963,6 +1820,7 @@ impl App {
             Main => match key.code {
                 KeyCode::Right | KeyCode::Char('d') | KeyCode::Enter => {
                     self.synchronize_messages_for_selected_recipient();
+                    self.request_group_members();
                     self.current_screen = Writing;
                 }
                 KeyCode::Char('q') | KeyCode::Esc => self.current_screen = Exiting,
@@ -981,6 +1839,10 @@ impl App {
                     self.refresh_accounts();
                     self.current_screen = AccountSelector;
                 }
+                KeyCode::Char('/') => self.current_screen = Search,
+                KeyCode::Char('y') => self.respond_to_request(RequestResponse::Accept),
+                KeyCode::Char('x') => self.respond_to_request(RequestResponse::Delete),
+                KeyCode::Char('b') => self.respond_to_request(RequestResponse::Block),
                 KeyCode::Char('i') => {
                     let selected_recipient_id = self.recipients[self.selected_recipient].0.id();
                     match selected_recipient_id {
@@ -994,6 +1856,10 @@ impl App {
                             self.tx_tui
                                 .send(EventSend::GetGroupInfo(master_key))
                                 .unwrap();
+                            // Receipts of our latest message are shown next to the members
+                            self.tx_tui
+                                .send(EventSend::GetMessagesForGroup(master_key))
+                                .unwrap();
                             self.current_screen = GroupInfo;
                         }
                     };
@@ -1022,6 +1888,7 @@ impl App {
                 KeyCode::Char('e') if key.modifiers.contains(KeyModifiers::CONTROL) => {
                     self.current_screen = Writing
                 }
+                KeyCode::Char('e') => self.start_editing(),
 
                 KeyCode::Up => {
                     let recipient_id = self.recipients[self.selected_recipient].0.id();
@@ -1050,6 +1917,7 @@ impl App {
                 }
 
                 KeyCode::Char('r') => {
+                    self.stop_editing();
                     let selected_recipient_id = self.recipients[self.selected_recipient].0.id();
                     self.quoted_message = match selected_recipient_id {
                         RecipientId::Contact(uuid) => {
@@ -1156,12 +2024,74 @@ impl App {
                         self.tx_tui
                             .send(EventSend::ReactToMessage(
                                 selected_recipient_id,
+                                "👍".to_string(),
                                 remove,
                                 target_send.clone(),
                             ))
                             .unwrap();
                     }
                 }
+                KeyCode::Char('p') => {
+                    if self.selected_message().is_some() {
+                        self.emoji_query.clear();
+                        self.emoji_selected = 0;
+                        self.current_screen = EmojiPicker;
+                    }
+                }
+                KeyCode::Char('o') => self.open_image_viewer(),
+                KeyCode::Char('v') => {
+                    if let Some(timestamp) = self.selected_message().map(|msg| msg.timestamp)
+                        && !self.revealed_spoilers.remove(&timestamp)
+                    {
+                        self.revealed_spoilers.insert(timestamp);
+                    }
+                }
+                KeyCode::Char('x') => {
+                    if let Some(timestamp) = self.selected_message().map(|msg| msg.timestamp) {
+                        self.confirm_local_deletion(LocalDeletion::Message(timestamp));
+                    }
+                }
+                KeyCode::Char('h') => self.confirm_local_deletion(LocalDeletion::History),
+                KeyCode::Char('X') => self.confirm_local_deletion(LocalDeletion::Conversation),
+                _ => {}
+            },
+            ConfirmRequest => match key.code {
+                KeyCode::Char('y') => {
+                    if let Some((sender, response)) = self.pending_request_response.take() {
+                        self.tx_tui
+                            .send(EventSend::RespondToRequest(sender, response))
+                            .unwrap();
+                    }
+                    self.current_screen = Main;
+                }
+                KeyCode::Char('n') | KeyCode::Esc => {
+                    self.pending_request_response = None;
+                    self.current_screen = Main;
+                }
+                KeyCode::Char('c') if key.modifiers.bits() == KeyModifiers::CONTROL.bits() => {
+                    return Ok(true);
+                }
+                _ => {}
+            },
+            ConfirmLocalDelete => match key.code {
+                KeyCode::Char('y') => {
+                    if let Some((recipient, deletion)) = self.pending_local_deletion.take() {
+                        self.current_screen = match deletion {
+                            LocalDeletion::Conversation => Main,
+                            _ => InspectMesseges,
+                        };
+                        self.tx_tui
+                            .send(EventSend::DeleteLocally(recipient, deletion))
+                            .unwrap();
+                    }
+                }
+                KeyCode::Char('n') | KeyCode::Esc => {
+                    self.pending_local_deletion = None;
+                    self.current_screen = InspectMesseges;
+                }
+                KeyCode::Char('c') if key.modifiers.bits() == KeyModifiers::CONTROL.bits() => {
+                    return Ok(true);
+                }
                 _ => {}
             },
 
@@ -1174,16 +2104,20 @@ impl App {
                 }
                 KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
                     self.quoted_message = None;
+                    self.stop_editing();
                 }
+                KeyCode::Esc if self.mention_query.is_some() => self.mention_query = None,
                 KeyCode::Esc | KeyCode::Left => {
                     self.quoted_message = None;
+                    self.mention_query = None;
+                    self.stop_editing();
+                    self.stop_typing();
                     self.current_screen = Main
                 }
-                KeyCode::Tab => {
-                    if matches!(self.input_focus, InputFocus::Attachment) {
-                        self.autocomplete_path();
-                    }
-                }
+                KeyCode::Tab => match self.input_focus {
+                    InputFocus::Message => self.complete_mention(),
+                    InputFocus::Attachment => self.autocomplete_path(),
+                },
                 KeyCode::Char('t') if key.modifiers.contains(KeyModifiers::CONTROL) => {
                     self.input_focus = match self.input_focus {
                         InputFocus::Message => InputFocus::Attachment,
@@ -1191,15 +2125,21 @@ impl App {
                     };
                 }
 
+                KeyCode::Enter if !self.mention_candidates().is_empty() => self.complete_mention(),
                 KeyCode::Enter => {
                     self.submit_message(tx);
+                    self.update_typing();
                     self.synchronize_messages_for_selected_recipient();
                 }
                 KeyCode::Char('c') if key.modifiers.bits() == KeyModifiers::CONTROL.bits() => {
                     return Ok(true);
                 }
                 KeyCode::Char(to_insert) => match self.input_focus {
-                    InputFocus::Message => self.enter_char(to_insert),
+                    InputFocus::Message => {
+                        self.update_mention_query(to_insert);
+                        self.enter_char(to_insert);
+                        self.update_typing();
+                    }
                     InputFocus::Attachment => {
                         if to_insert == '\t' {
                             self.autocomplete_path();
@@ -1210,13 +2150,29 @@ impl App {
                     }
                 },
                 KeyCode::Backspace => match self.input_focus {
-                    InputFocus::Message => self.delete_char(),
+                    InputFocus::Message => {
+                        if let Some(query) = &mut self.mention_query
+                            && query.pop().is_none()
+                        {
+                            self.mention_query = None;
+                        }
+                        self.delete_char();
+                        self.update_typing();
+                    }
                     InputFocus::Attachment => {
                         self.attachment_path.pop();
                         self.validate_attachment_path();
                     }
                 },
 
+                KeyCode::Up if self.mention_query.is_some() => {
+                    self.mention_selected = self.mention_selected.saturating_sub(1);
+                }
+                KeyCode::Down if self.mention_query.is_some() => {
+                    if self.mention_selected + 1 < self.mention_candidates().len() {
+                        self.mention_selected += 1;
+                    }
+                }
                 KeyCode::Up => {
                     let selected_recipient_id = self.recipients[self.selected_recipient].0.id();
                     let last_message = match selected_recipient_id {
@@ -1413,7 +2369,7 @@ impl App {
                     }
                 }
                 KeyCode::Down | KeyCode::Char('s') => {
-                    if self.config_selected < 2 {
+                    if self.config_selected < 4 {
                         self.config_selected += 1;
                     }
                 }
@@ -1431,6 +2387,8 @@ impl App {
                         }
                         if !self.config.show_images {
                             self.avatar_image = None;
+                            self.attachment_thumbnails.clear();
+                            self.requested_thumbnails.clear();
                         } else if self.avatar_cache.is_some() {
                             self.load_avatar();
                         }
@@ -1441,6 +2399,18 @@ impl App {
                             warn!("Failed to save config: {e:?}");
                         }
                     }
+                    3 => {
+                        self.config.toggle_send_read_receipts();
+                        if let Err(e) = self.config.save() {
+                            warn!("Failed to save config: {e:?}");
+                        }
+                    }
+                    4 => {
+                        self.config.toggle_send_typing_indicators();
+                        if let Err(e) = self.config.save() {
+                            warn!("Failed to save config: {e:?}");
+                        }
+                    }
                     _ => {}
                 },
                 KeyCode::Char('c') if key.modifiers.bits() == KeyModifiers::CONTROL.bits() => {
@@ -1455,6 +2425,25 @@ impl App {
                     self.contact_avatar_cache = None;
                     self.contact_avatar_image = None;
                 }
+                KeyCode::Char('t') => {
+                    if let Some(contact_info) = &self.selected_contact_info
+                        && let Ok(uuid) = Uuid::parse_str(&contact_info.uuid)
+                    {
+                        let seconds = next_preset(contact_info.expire_timer);
+                        self.tx_tui
+                            .send(EventSend::SetExpireTimer(uuid, seconds))
+                            .unwrap();
+                    }
+                }
+                KeyCode::Char('v') => {
+                    if let Some(contact_info) = &self.selected_contact_info
+                        && let Ok(uuid) = Uuid::parse_str(&contact_info.uuid)
+                    {
+                        self.safety_number = None;
+                        self.current_screen = SafetyNumber;
+                        self.tx_tui.send(EventSend::GetSafetyNumber(uuid)).unwrap();
+                    }
+                }
                 KeyCode::Char('c') if key.modifiers.bits() == KeyModifiers::CONTROL.bits() => {
                     return Ok(true);
                 }
@@ -1599,6 +2588,95 @@ impl App {
                     }
                 }
             },
+            EmojiPicker => match key.code {
+                KeyCode::Esc => self.current_screen = InspectMesseges,
+                KeyCode::Enter => {
+                    self.react_with_selected_emoji();
+                    self.current_screen = InspectMesseges;
+                }
+                KeyCode::Down => {
+                    if self.emoji_selected + 1 < self.emoji_options().len() {
+                        self.emoji_selected += 1;
+                    }
+                }
+                KeyCode::Up => {
+                    if self.emoji_selected > 0 {
+                        self.emoji_selected -= 1;
+                    }
+                }
+                KeyCode::Char('c') if key.modifiers.bits() == KeyModifiers::CONTROL.bits() => {
+                    return Ok(true);
+                }
+                KeyCode::Char(to_insert) => {
+                    self.emoji_query.push(to_insert);
+                    self.emoji_selected = 0;
+                }
+                KeyCode::Backspace => {
+                    self.emoji_query.pop();
+                    self.emoji_selected = 0;
+                }
+                _ => {}
+            },
+            SafetyNumber => match key.code {
+                KeyCode::Char('c') if key.modifiers.bits() == KeyModifiers::CONTROL.bits() => {
+                    return Ok(true);
+                }
+                KeyCode::Esc | KeyCode::Left | KeyCode::Char('q') => {
+                    self.safety_number = None;
+                    self.current_screen = ContactInfo;
+                    // The verified state shown in the contact info may have changed
+                    if let Some(contact_info) = &self.selected_contact_info {
+                        self.tx_tui
+                            .send(EventSend::GetContactInfo(contact_info.uuid.clone()))
+                            .unwrap();
+                    }
+                }
+                KeyCode::Char('v') => {
+                    if let Some(safety_number) = &self.safety_number {
+                        let verified = safety_number.status != VerificationStatus::Verified;
+                        self.tx_tui
+                            .send(EventSend::SetVerified(safety_number.uuid, verified))
+                            .unwrap();
+                    }
+                }
+                _ => {}
+            },
+            ImageViewer => match key.code {
+                KeyCode::Char('c') if key.modifiers.bits() == KeyModifiers::CONTROL.bits() => {
+                    return Ok(true);
+                }
+                KeyCode::Esc | KeyCode::Left | KeyCode::Char('q') | KeyCode::Char('o') => {
+                    self.image_viewer = None;
+                    self.current_screen = InspectMesseges;
+                }
+                _ => {}
+            },
+            Search => match key.code {
+                KeyCode::Esc => self.current_screen = Main,
+                KeyCode::Enter => self.jump_to_search_hit(),
+                KeyCode::Down => {
+                    if self.search_selected + 1 < self.search_results.len() {
+                        self.search_selected += 1;
+                    }
+                }
+                KeyCode::Up => {
+                    if self.search_selected > 0 {
+                        self.search_selected -= 1;
+                    }
+                }
+                KeyCode::Char('c') if key.modifiers.bits() == KeyModifiers::CONTROL.bits() => {
+                    return Ok(true);
+                }
+                KeyCode::Char(to_insert) => {
+                    self.search_query.push(to_insert);
+                    self.update_search();
+                }
+                KeyCode::Backspace => {
+                    self.search_query.pop();
+                    self.update_search();
+                }
+                _ => {}
+            },
             Syncing => match key.code {
                 KeyCode::Char('c') if key.modifiers.bits() == KeyModifiers::CONTROL.bits() => {
                     return Ok(true);
@@ -1665,9 +2743,18 @@ impl App {
     fn autocomplete_path(&mut self) {
         use std::fs;
 
-        let current_path = self.attachment_path.trim();
+        // Only the attachment being typed is completed, the earlier ones are kept as they are
+        let completed = self.completed_attachments();
+        let current_path = self.attachment_path[completed.len()..].trim().to_string();
+        let completed = match completed.is_empty() {
+            true => String::new(),
+            false => format!("{completed} "),
+        };
+        if current_path.contains(attachments::CAPTION_SEPARATOR) {
+            return;
+        }
         if current_path.is_empty() {
-            self.attachment_path = "./".to_string();
+            self.attachment_path = format!("{completed}./");
             return;
         }
 
@@ -1723,7 +2810,7 @@ impl App {
                     if entry.path().is_dir() {
                         path_str.push('/');
                     }
-                    self.attachment_path = path_str;
+                    self.attachment_path = format!("{completed}{path_str}");
                     self.validate_attachment_path();
                 }
             } else if matches.len() > 1 {
@@ -1744,7 +2831,7 @@ impl App {
                                 .to_string_lossy()
                                 .to_string()
                         };
-                    self.attachment_path = new_path;
+                    self.attachment_path = format!("{completed}{new_path}");
                     self.validate_attachment_path();
                 }
             }
@@ -1799,13 +2886,86 @@ pub async fn init_background_threads(
     retry_manager: Arc<Mutex<RetryManager>>,
     account_name: String,
 ) -> Result<()> {
-    let recipients: Arc<Mutex<Vec<DisplayRecipient>>> = Arc::new(Mutex::new(vec![]));
+    restore_outbox(&manager, &retry_manager, &account_name).await;
+
+    let search_index = match SearchIndex::open_for_account(&account_name).await {
+        Ok(search_index) => Some(search_index),
+        Err(error) => {
+            error!(%error, "Failed to open search index, messages won't be searchable");
+            None
+        }
+    };
+
+    let receipts = match ReceiptStore::load_for_account(&account_name) {
+        Ok(receipts) => receipts,
+        Err(error) => {
+            error!(%error, "Failed to load receipts, starting without them");
+            ReceiptStore::default()
+        }
+    };
+    let receipts = Arc::new(Mutex::new(receipts));
+
+    let expiry = match ExpiryIndex::load_for_account(&account_name) {
+        Ok(expiry) => expiry,
+        Err(error) => {
+            error!(%error, "Failed to load disappearing messages, starting without them");
+            ExpiryIndex::default()
+        }
+    };
+    let expiry = Arc::new(Mutex::new(expiry));
+
+    let identities = match IdentityStore::load_for_account(&account_name) {
+        Ok(identities) => identities,
+        Err(error) => {
+            error!(%error, "Failed to load identities, starting without them");
+            IdentityStore::default()
+        }
+    };
+    // The chat of contacts whose new safety number wasn't verified yet shows the change
+    for (uuid, timestamp) in identities.unverified_changes() {
+        if let Err(error) = tx_thread.send(EventApp::SafetyNumberChanged(uuid, timestamp)) {
+            error!(%error, "Failed to send safety number change");
+        }
+    }
+    let identities = Arc::new(Mutex::new(identities));
+
+    let profiles = match ProfileCache::load_for_account(&account_name) {
+        Ok(profiles) => profiles,
+        Err(error) => {
+            error!(%error, "Failed to load cached profiles, starting without them");
+            ProfileCache::default()
+        }
+    };
+    let profiles = Arc::new(Mutex::new(profiles));
+
+    let hidden = match HiddenConversations::load_for_account(&account_name) {
+        Ok(hidden) => hidden,
+        Err(error) => {
+            error!(%error, "Failed to load hidden conversations, all of them are listed");
+            HiddenConversations::default()
+        }
+    };
+    let requests = match MessageRequests::load_for_account(&account_name) {
+        Ok(requests) => requests,
+        Err(error) => {
+            error!(%error, "Failed to load message requests, starting without them");
+            MessageRequests::default()
+        }
+    };
+    let recipients = Arc::new(Mutex::new(RecipientIndex::new(
+        profiles.clone(),
+        hidden,
+        requests,
+    )));
 
     //spawn thread to sync contacts and new messages
     let tx_synchronization_events = tx_thread.clone();
     let new_manager = manager.clone();
     let sync_account_name = account_name.clone();
     let sync_recipients = recipients.clone();
+    let sync_search_index = search_index.clone();
+    let sync_receipts = receipts.clone();
+    let sync_identities = identities.clone();
     thread::Builder::new()
         .name(String::from("synchronization_thread"))
         .stack_size(1024 * 1024 * 8)
@@ -1821,6 +2981,9 @@ pub async fn init_background_threads(
                     new_manager,
                     sync_account_name,
                     sync_recipients,
+                    sync_search_index,
+                    sync_receipts,
+                    sync_identities,
                 )
                 .await;
             })
@@ -1832,6 +2995,7 @@ pub async fn init_background_threads(
     let rx_sending_thread = rx_thread;
     let tx_status_clone = tx_thread.clone();
     let retry_manager_clone = retry_manager.clone();
+    let background_profiles = profiles.clone();
     thread::Builder::new()
         .name(String::from("background_events_thread"))
         .stack_size(1024 * 1024 * 8)
@@ -1848,6 +3012,11 @@ pub async fn init_background_threads(
                     tx_status_clone,
                     retry_manager_clone,
                     recipients,
+                    search_index,
+                    receipts,
+                    expiry,
+                    background_profiles,
+                    identities,
                 )
                 .await;
             })
@@ -1855,7 +3024,7 @@ pub async fn init_background_threads(
         .unwrap();
 
     // Add profile fetching
-    let mut profile_manager = manager.clone();
+    let profile_manager = manager.clone();
     let tx_profile = tx_thread.clone();
     thread::Builder::new()
         .name(String::from("profile_thread"))
@@ -1867,11 +3036,11 @@ pub async fn init_background_threads(
                 .build()
                 .unwrap();
             runtime.block_on(async move {
-                if let Ok(profile) = get_profile_tui(&mut profile_manager).await {
+                if let Ok(profile) = get_profile_tui(&profile_manager, &profiles).await {
                     let _ = tx_profile.send(EventApp::ProfileReceived(profile));
                 }
                 if let Ok(Some(avatar_data)) =
-                    crate::profile::get_my_profile_avatar_tui(&mut profile_manager).await
+                    get_my_profile_avatar_tui(&profile_manager, &profiles).await
                 {
                     let _ = tx_profile.send(EventApp::AvatarReceived(avatar_data));
                 }
@@ -1883,6 +3052,35 @@ pub async fn init_background_threads(
     Ok(())
 }
 
+/// Load messages which were not delivered before the last exit so they are sent again
+async fn restore_outbox(
+    manager: &Manager<SqliteStore, Registered>,
+    retry_manager: &Arc<Mutex<RetryManager>>,
+    account_name: &str,
+) {
+    let mut retry_mgr = retry_manager.lock().await;
+    if let Err(error) = retry_mgr.load_outbox(paths::account_outbox_path(account_name)) {
+        error!(%error, "Failed to load outbox");
+        return;
+    }
+
+    for (message_id, recipient, timestamp) in retry_mgr.unresolved_quotes() {
+        let thread = match recipient {
+            RecipientId::Contact(uuid) => Thread::Contact(uuid),
+            RecipientId::Group(master_key) => Thread::Group(master_key),
+        };
+        match manager.store().message(&thread, timestamp).await {
+            Ok(Some(content)) => {
+                if let Some(quoted_message) = format_message(&content) {
+                    retry_mgr.restore_quote(&message_id, quoted_message);
+                }
+            }
+            Ok(None) => warn!(timestamp, "Quoted message of outbox entry no longer exists"),
+            Err(error) => error!(%error, "Failed to load quoted message of outbox entry"),
+        }
+    }
+}
+
 pub async fn handle_linking_device_for_account(
     tx: mpsc::Sender<EventApp>,
     account_name: String,
@@ -1962,10 +3160,24 @@ pub async fn handle_synchronization(
     tx: mpsc::Sender<EventApp>,
     mut manager: Manager<SqliteStore, Registered>,
     account_name: String,
-    recipients: Arc<Mutex<Vec<DisplayRecipient>>>,
+    recipients: Arc<Mutex<RecipientIndex>>,
+    search_index: Option<SearchIndex>,
+    receipts: Arc<Mutex<ReceiptStore>>,
+    identities: Arc<Mutex<IdentityStore>>,
 ) {
     let _receiving_span = span!(Level::TRACE, "Receiving loop").entered();
     let mut initialized = false;
+    let purge_deleted = Config::load().purge_deleted_messages;
+
+    // Messages stored while the app wasn't running, e.g. received by the CLI, are indexed first
+    let senders = recipients.lock().await.request_senders();
+    if let Some(search_index) = &search_index
+        && let Err(error) = search_index.catch_up(&manager, &senders).await
+    {
+        error!(%error, "Failed to index stored messages");
+    }
+
+    recipients.lock().await.rebuild(&mut manager).await;
 
     info!("Start initial synchronization");
     loop {
@@ -1999,15 +3211,92 @@ pub async fn handle_synchronization(
                         }
                         Received::Contacts => {
                             debug!("Received contact");
+                            recipients.lock().await.rebuild(&mut manager).await;
                         }
                         Received::Content(content) => {
                             debug!("Received content");
                             trace!("Received message: {content:#?}");
 
+                            // Nothing of blocked senders is shown, their messages are deleted
+                            if !recipients.lock().await.screen(&manager, &content).await {
+                                continue;
+                            }
+
+                            recipients.lock().await.record(&manager, &content).await;
+
+                            if let Some(search_index) = &search_index
+                                && let Err(error) = search_index.index_content(&content).await
+                            {
+                                error!(%error, "Failed to index received message");
+                            }
+
+                            if let Some(receipt) = extract_receipt(&content)
+                                && let Some(thread) = receipt_thread(&manager, &receipt).await
+                            {
+                                receipts.lock().await.record(&thread, &receipt);
+                            }
+
+                            // Messages may come with a new identity key, the chat then shows
+                            // that the safety number changed
+                            let sender = content.metadata.sender.raw_uuid();
+                            if sender != manager.registration_data().service_ids.aci {
+                                let mut identities = identities.lock().await;
+                                match identity::check_identity(&manager, &mut identities, sender)
+                                    .await
+                                {
+                                    Ok(true) => {
+                                        if let Some(timestamp) = identities.last_change(sender)
+                                            && let Err(error) = tx.send(
+                                                EventApp::SafetyNumberChanged(sender, timestamp),
+                                            )
+                                        {
+                                            error!(%error, "Failed to send safety number change");
+                                        }
+                                    }
+                                    Ok(false) => {}
+                                    Err(error) => {
+                                        error!(%error, "Failed to check identity of the sender");
+                                    }
+                                }
+                            }
+
+                            if let Err(error) = expiry::save_timer_update(&manager, &content).await
+                            {
+                                error!(%error, "Failed to save disappearing messages timer");
+                            }
+
+                            if purge_deleted
+                                && let Err(error) =
+                                    deletion::purge_deleted_message(&manager, &content).await
+                            {
+                                error!(%error, "Failed to purge message deleted by its author");
+                            }
+
+                            if initialized && let Some(typing) = extract_typing(&content) {
+                                let recipient = match &typing.group_id {
+                                    Some(group_id) => {
+                                        find_group_by_identifier(&recipients, group_id).await
+                                    }
+                                    None => Some(RecipientId::Contact(typing.sender)),
+                                };
+                                if let Some(recipient) = recipient
+                                    && let Err(e) = tx.send(EventApp::Typing(
+                                        recipient,
+                                        typing.sender,
+                                        typing.started,
+                                    ))
+                                {
+                                    error!(channel_error = %e);
+                                }
+                                continue;
+                            }
+
                             if initialized {
-                                if let Some(formatted_msg) = format_message(&content)
+                                if let Some(mut formatted_msg) = format_message(&content)
                                     && !formatted_msg.sender
                                 {
+                                    resolve_mentions(&manager, slice::from_mut(&mut formatted_msg))
+                                        .await;
                                     handle_notification(&formatted_msg, &manager, &account_name)
                                         .await;
                                 }
@@ -2019,17 +3308,13 @@ pub async fn handle_synchronization(
                         }
                     }
 
-                    let new_recipients = timestamp_recipient_sort(&mut manager).await;
-
-                    let mut recipients_guard = recipients.lock().await;
-                    if recipients_guard.ne(&new_recipients) {
-                        *recipients_guard = new_recipients.clone();
-                        drop(recipients_guard);
-                        if let Err(error) = tx.send(EventApp::ContactsList(new_recipients)) {
-                            error!(?error, "Failed to send recipient list through channel");
-                        }
-                    } else {
-                        drop(recipients_guard);
+                    // While the backlog is received the list is sent at most every few
+                    // hundred milliseconds, the last change is sent when the queue is empty
+                    let update = recipients.lock().await.take_update(initialized);
+                    if let Some(new_recipients) = update
+                        && let Err(error) = tx.send(EventApp::ContactsList(new_recipients))
+                    {
+                        error!(?error, "Failed to send recipient list through channel");
                     }
                 }
 
@@ -2044,42 +3329,48 @@ pub async fn handle_synchronization(
     }
 }
 
-async fn contact_to_display_contact(
+/// Finds the group conversation whose identifier is `group_id`
+async fn find_group_by_identifier(
+    recipients: &Arc<Mutex<RecipientIndex>>,
+    group_id: &[u8],
+) -> Option<RecipientId> {
+    recipients.lock().await.find_group(group_id)
+}
+
+pub(crate) async fn contact_to_display_contact(
     contact: Contact,
-    mut manager: Manager<SqliteStore, Registered>,
+    manager: Manager<SqliteStore, Registered>,
+    profiles: &Mutex<ProfileCache>,
 ) -> Option<DisplayContact> {
-    let uuid_str = contact.uuid.to_string();
-    let profile_key = match manager.store().profile_key(&contact.uuid).await {
-        Ok(profile_key_option) => profile_key_option,
-        Err(error) => {
-            error!(%error, "Failed to retreive profile key from the store.");
-            None
-        }
+    let profile_name = if contact.name.is_empty() {
+        profile_cache::profile_name(profiles, &manager, contact.uuid).await
+    } else {
+        None
     };
+    let display_name = contact_display_name(&contact, profile_name);
+    let phone_number = contact
+        .phone_number
+        .as_ref()
+        .map(|phone_number| phone_number.to_string());
+
+    Some(DisplayContact::new(
+        display_name,
+        contact.uuid,
+        phone_number,
+    ))
+}
 
-    let display_name = if !contact.name.is_empty() {
-        contact.name
-    } else if let Some(profile_key) = profile_key
-        && let Ok(profile) = manager
-            .retrieve_profile_by_uuid(contact.uuid, profile_key)
-            .await
-        && let Some(profile_name) = &profile.name
-    {
-        match &profile_name.family_name {
-            Some(family_mame) => {
-                format!("{} {family_mame}", profile_name.given_name)
-            }
-            None => profile_name.given_name.clone(),
-        }
-    } else if let Some(phone) = contact.phone_number {
+/// Name of the contact, then the name from their profile, their phone number or their UUID
+fn contact_display_name(contact: &Contact, profile_name: Option<String>) -> String {
+    if !contact.name.is_empty() {
+        contact.name.clone()
+    } else if let Some(profile_name) = profile_name {
+        profile_name
+    } else if let Some(phone) = &contact.phone_number {
         phone.to_string()
     } else {
-        uuid_str.clone()
-    };
-
-    let display_contact = DisplayContact::new(display_name, contact.uuid);
-
-    Some(display_contact)
+        contact.uuid.to_string()
+    }
 }
 
 fn group_to_display_group(group: Group, master_key: GroupMasterKeyBytes) -> Option<DisplayGroup> {
@@ -2153,39 +3444,86 @@ async fn handle_notification(
         (sender, group)
     };
 
-    let title = if let Some(group) = group_name {
+    let own_aci = manager.registration_data().service_ids.aci;
+    let title = if formatted_msg.mentions_member(own_aci) {
+        let group = group_name.unwrap_or_default();
+        format!("[{account_name}] {sender_name} mentioned you in {group}")
+    } else if let Some(group) = group_name {
         format!("[{account_name}] {sender_name} → {group}")
     } else {
         format!("[{account_name}] {sender_name}")
     };
 
     info!("Attempting notification for message from: {}", title);
-    if let Err(e) = send_notification(&title, &formatted_msg.text) {
+    // Notifications can't reveal spoilers, so they stay masked
+    if let Err(e) = send_notification(&title, &formatted_msg.masked_text()) {
         error!("Failed to send notification: {}", e);
     } else {
         info!("Notification sent successfully");
     }
 }
 
+const RETRY_IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// How often the store is checked for messages whose disappearing timer ran out
+const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Typing indicator of a contact disappears when it isn't refreshed for this long
+const TYPING_TIMEOUT: Duration = Duration::from_secs(15);
+/// How often typing indicators are checked for expiry while some are shown
+const TYPING_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// Typing started is sent again after this long, so it doesn't time out on the other side
+const TYPING_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+#[allow(clippy::too_many_arguments)]
 pub async fn handle_background_events(
     rx: Receiver<EventSend>,
     mut manager: Manager<SqliteStore, Registered>,
     tx_status: mpsc::Sender<EventApp>,
     retry_manager: Arc<Mutex<RetryManager>>,
-    recipients: Arc<Mutex<Vec<DisplayRecipient>>>,
+    recipients: Arc<Mutex<RecipientIndex>>,
+    search_index: Option<SearchIndex>,
+    receipts: Arc<Mutex<ReceiptStore>>,
+    expiry: Arc<Mutex<ExpiryIndex>>,
+    profiles: Arc<Mutex<ProfileCache>>,
+    identities: Arc<Mutex<IdentityStore>>,
 ) {
     let local_pool = LocalPoolHandle::new(4);
 
-    let mut retry_interval = interval(Duration::from_secs(30));
     let mut cleanup_interval = interval(Duration::from_secs(3600));
+    let mut expiry_interval = interval(EXPIRY_CHECK_INTERVAL);
+
+    // Blocking on the std receiver inside `select!` would stall the timers, so events are
+    // forwarded to an async channel from a separate thread.
+    let (tx_events, mut rx_events) = tokio::sync::mpsc::unbounded_channel();
+    thread::Builder::new()
+        .name(String::from("background_events_forwarder"))
+        .spawn(move || {
+            while let Ok(event) = rx.recv() {
+                if tx_events.send(event).is_err() {
+                    break;
+                }
+            }
+        })
+        .unwrap();
 
     loop {
+        // Failures reported by spawned tasks don't wake up the loop, so it checks the
+        // outbox at least every few seconds even when no retry is scheduled.
+        let retry_delay = retry_manager
+            .lock()
+            .await
+            .next_retry_in()
+            .unwrap_or(RETRY_IDLE_CHECK_INTERVAL)
+            .min(RETRY_IDLE_CHECK_INTERVAL);
+
         tokio::select! {
-            _ = retry_interval.tick() => {
+            _ = tokio::time::sleep(retry_delay) => {
                 handle_retry_tick(
                     &manager,
                     &tx_status,
-                    &retry_manager
+                    &retry_manager,
+                    &search_index,
+                    &identities,
                 ).await;
             }
 
@@ -2193,10 +3531,12 @@ pub async fn handle_background_events(
                 handle_cleanup_tick(&retry_manager).await;
             }
 
-            event = async {
-                rx.recv().ok()
-            } => {
-                if let Some(event) = event {
+            _ = expiry_interval.tick() => {
+                handle_expiry_tick(&manager, &search_index, &expiry).await;
+            }
+
+            event = rx_events.recv() => {
+                if let Some(event) = event {
                     handle_incoming_event(
                         event,
                         &mut manager,
@@ -2204,6 +3544,11 @@ pub async fn handle_background_events(
                         &retry_manager,
                         &local_pool,
                         &recipients,
+                        &search_index,
+                        &receipts,
+                        &expiry,
+                        &profiles,
+                        &identities,
                     ).await;
                 } else {
                     break;
@@ -2217,6 +3562,8 @@ async fn handle_retry_tick(
     manager: &Manager<SqliteStore, Registered>,
     tx_status: &mpsc::Sender<EventApp>,
     retry_manager: &Arc<Mutex<RetryManager>>,
+    search_index: &Option<SearchIndex>,
+    identities: &Arc<Mutex<IdentityStore>>,
 ) {
     let mut retry_mgr = retry_manager.lock().await;
     let messages_to_retry = retry_mgr.messages_to_retry();
@@ -2224,16 +3571,49 @@ async fn handle_retry_tick(
 
     let token_re = Regex::new(r"^.* token ([a-f0-9-]+)$").expect("Failed to compile RegEx");
     for msg in messages_to_retry {
-        let result = if let Some(attachment_path) = &msg.attachment_path {
+        retry_manager.lock().await.mark_sending(&msg.id);
+
+        let result = if let Some(target_send_timestamp) = msg.edit_target_send_timestamp {
+            send_edit(
+                manager,
+                identities,
+                &msg.recipient,
+                target_send_timestamp,
+                msg.text.clone(),
+            )
+            .await
+        } else if let Some(target_send_timestamp) = msg.delete_target_send_timestamp {
+            match &msg.recipient {
+                RecipientId::Contact(uuid) => {
+                    send::contact::send_delete_message_tui(
+                        manager.clone(),
+                        identities,
+                        uuid.to_string(),
+                        target_send_timestamp,
+                    )
+                    .await
+                }
+                RecipientId::Group(master_key) => {
+                    send::group::send_delete_message_tui(
+                        *master_key,
+                        manager.clone(),
+                        identities,
+                        target_send_timestamp,
+                    )
+                    .await
+                }
+            }
+        } else if let Some(attachment_path) = &msg.attachment_path {
             // Fix: Handle RecipientId properly
             match &msg.recipient {
                 RecipientId::Contact(uuid) => {
                     send::contact::send_attachment_tui(
                         uuid.to_string(),
                         msg.text.clone(),
-                        attachment_path.clone(),
+                        parse_attachment_field(attachment_path),
                         msg.quoted_message.clone(),
                         manager.clone(),
+                        identities,
                     )
                     .await
                 }
@@ -2241,9 +3621,10 @@ async fn handle_retry_tick(
                     send::group::send_attachment_tui(
                         master_key,
                         msg.text.clone(),
-                        attachment_path.clone(),
+                        parse_attachment_field(attachment_path),
                         msg.quoted_message.clone(),
                         manager.clone(),
+                        identities,
                     )
                     .await
                 }
@@ -2256,6 +3637,7 @@ async fn handle_retry_tick(
                         msg.text.clone(),
                         msg.quoted_message.clone(),
                         manager.clone(),
+                        identities,
                     )
                     .await
                 }
@@ -2264,12 +3646,14 @@ async fn handle_retry_tick(
                         *master_key,
                         msg.text.clone(),
                         manager.clone(),
+                        identities,
                         msg.quoted_message.clone(),
                     )
                     .await
                 }
             }
         };
+        let sent = result.is_ok();
 
         let mut retry_mgr = retry_manager.lock().await;
         match result {
@@ -2294,16 +3678,17 @@ async fn handle_retry_tick(
                     },
                     None => error!("Failed to extract token from error message."),
                 }
-                let mut retry_mgr = retry_manager.lock().await;
                 // Even though not send this message is marked as sent so there is no retry for it.
                 retry_mgr.mark_sent(&msg.id);
-                drop(retry_mgr);
 
                 _ = tx_status.send(EventApp::NetworkStatusChanged(NetworkStatus::Connected));
             }
+            Err(e) if is_delivery_confirmation_timeout(&e) => {
+                retry_mgr.mark_sent(&msg.id);
+                warn!("Message likely delivered despite confirmation timeout");
+            }
             Err(e) => {
-                let error_msg = e.to_string();
-                retry_mgr.mark_failed(&msg.id, error_msg.clone());
+                retry_mgr.mark_failed(&msg.id, &e);
 
                 if is_connection_error(&e) {
                     let _ = tx_status.send(EventApp::NetworkStatusChanged(
@@ -2313,6 +3698,33 @@ async fn handle_retry_tick(
             }
         }
         drop(retry_mgr);
+
+        if sent {
+            index_sent(search_index.as_ref(), manager, &msg.recipient.thread()).await;
+        }
+    }
+}
+
+/// Deletes disappeared messages from the store and the search index
+async fn handle_expiry_tick(
+    manager: &Manager<SqliteStore, Registered>,
+    search_index: &Option<SearchIndex>,
+    expiry: &Arc<Mutex<ExpiryIndex>>,
+) {
+    let purged = match expiry.lock().await.purge_due(manager).await {
+        Ok(purged) => purged,
+        Err(error) => {
+            error!(%error, "Failed to purge expired messages");
+            return;
+        }
+    };
+
+    if let Some(search_index) = search_index {
+        for (thread, timestamp) in purged {
+            if let Err(error) = search_index.remove_message(&thread, timestamp).await {
+                error!(%error, "Failed to remove expired message from the search index");
+            }
+        }
     }
 }
 
@@ -2322,69 +3734,97 @@ async fn handle_cleanup_tick(retry_manager: &Arc<Mutex<RetryManager>>) {
     drop(retry_mgr);
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_incoming_event(
     event: EventSend,
     manager: &mut Manager<SqliteStore, Registered>,
     tx_status: &mpsc::Sender<EventApp>,
     retry_manager: &Arc<Mutex<RetryManager>>,
     local_pool: &LocalPoolHandle,
-    recipients: &Arc<Mutex<Vec<DisplayRecipient>>>,
+    recipients: &Arc<Mutex<RecipientIndex>>,
+    search_index: &Option<SearchIndex>,
+    receipts: &Arc<Mutex<ReceiptStore>>,
+    expiry: &Arc<Mutex<ExpiryIndex>>,
+    profiles: &Arc<Mutex<ProfileCache>>,
+    identities: &Arc<Mutex<IdentityStore>>,
 ) {
     match event {
         EventSend::SendText(recipient, text, quoted_message) => {
-            let mut manager_inner = manager.clone();
+            let manager_inner = manager.clone();
+            let identities_inner = identities.clone();
             let tx_status_inner = tx_status.clone();
             let retry_manager_inner = retry_manager.clone();
             let recipients_inner = recipients.clone();
+            let search_index_inner = search_index.clone();
+            let sent_to = recipient.clone();
             local_pool.spawn_pinned(move || async move {
-                handle_send_text_event(
+                let sent = handle_send_text_event(
                     recipient,
                     text,
                     quoted_message,
                     manager_inner.clone(),
+                    &identities_inner,
                     tx_status_inner.clone(),
                     retry_manager_inner,
                 )
                 .await;
-                let new_recipients = timestamp_recipient_sort(&mut manager_inner).await;
+                if sent {
+                    index_sent(
+                        search_index_inner.as_ref(),
+                        &manager_inner,
+                        &sent_to.thread(),
+                    )
+                    .await;
+                }
                 let mut recipients_guard = recipients_inner.lock().await;
-                if recipients_guard.ne(&new_recipients) {
-                    *recipients_guard = new_recipients.clone();
-                    drop(recipients_guard);
-                    if let Err(channel_error) =
+                recipients_guard.refresh(&manager_inner, &sent_to).await;
+                let update = recipients_guard.take_update(true);
+                drop(recipients_guard);
+                if let Some(new_recipients) = update
+                    && let Err(channel_error) =
                         tx_status_inner.send(EventApp::ContactsList(new_recipients))
-                    {
-                        error!(%channel_error);
-                    }
+                {
+                    error!(%channel_error);
                 }
             });
         }
         EventSend::SendAttachment(recipient, text, attachment_path, quoted_message) => {
-            let mut manager_inner = manager.clone();
+            let manager_inner = manager.clone();
+            let identities_inner = identities.clone();
             let tx_status_inner = tx_status.clone();
             let retry_manager_inner = retry_manager.clone();
             let recipients_inner = recipients.clone();
+            let search_index_inner = search_index.clone();
+            let sent_to = recipient.clone();
             local_pool.spawn_pinned(move || async move {
-                handle_send_attachment_event(
+                let sent = handle_send_attachment_event(
                     recipient,
                     text,
                     attachment_path,
                     quoted_message,
                     manager_inner.clone(),
+                    &identities_inner,
                     tx_status_inner.clone(),
                     retry_manager_inner.clone(),
                 )
                 .await;
-                let new_recipients = timestamp_recipient_sort(&mut manager_inner).await;
+                if sent {
+                    index_sent(
+                        search_index_inner.as_ref(),
+                        &manager_inner,
+                        &sent_to.thread(),
+                    )
+                    .await;
+                }
                 let mut recipients_guard = recipients_inner.lock().await;
-                if recipients_guard.ne(&new_recipients) {
-                    *recipients_guard = new_recipients.clone();
-                    drop(recipients_guard);
-                    if let Err(channel_error) =
+                recipients_guard.refresh(&manager_inner, &sent_to).await;
+                let update = recipients_guard.take_update(true);
+                drop(recipients_guard);
+                if let Some(new_recipients) = update
+                    && let Err(channel_error) =
                         tx_status_inner.send(EventApp::ContactsList(new_recipients))
-                    {
-                        error!(%channel_error);
-                    }
+                {
+                    error!(%channel_error);
                 }
             });
         }
@@ -2396,23 +3836,90 @@ async fn handle_incoming_event(
                 tx_status,
                 retry_manager,
                 local_pool,
+                search_index,
+                identities,
+            )
+            .await;
+        }
+        EventSend::DeleteLocally(recipient, deletion) => {
+            handle_delete_locally_event(
+                recipient,
+                deletion,
+                manager,
+                tx_status,
+                local_pool,
+                recipients,
+                search_index,
+            );
+        }
+        EventSend::RespondToRequest(sender, response) => {
+            handle_respond_to_request_event(
+                sender,
+                response,
+                manager,
+                tx_status,
+                local_pool,
+                recipients,
+                search_index,
+            );
+        }
+        EventSend::EditMessage(recipient, target_send_timestamp, text) => {
+            handle_edit_message_event(
+                recipient,
+                target_send_timestamp,
+                text,
+                manager,
+                tx_status,
+                retry_manager,
+                local_pool,
+                search_index,
+                identities,
             )
             .await;
         }
         EventSend::GetMessagesForContact(uuid_str) => {
-            handle_get_contact_messages_event(uuid_str, manager, tx_status, local_pool).await;
+            handle_get_contact_messages_event(
+                uuid_str, manager, tx_status, local_pool, receipts, expiry,
+            )
+            .await;
         }
         EventSend::GetMessagesForGroup(master_key) => {
-            handle_get_group_messages_event(master_key, manager, tx_status, local_pool).await;
+            handle_get_group_messages_event(
+                master_key, manager, tx_status, local_pool, receipts, expiry,
+            )
+            .await;
         }
         EventSend::GetContactInfo(uuid_str) => {
-            handle_get_contact_info_event(manager, uuid_str, tx_status, local_pool).await;
+            handle_get_contact_info_event(
+                manager, uuid_str, tx_status, local_pool, profiles, identities,
+            )
+            .await;
         }
         EventSend::GetGroupInfo(master_key) => {
-            handle_get_group_info_event(master_key, manager, tx_status, local_pool).await;
+            handle_get_group_info_event(master_key, manager, tx_status, local_pool, profiles).await;
         }
         EventSend::GetMemberAvatar(member_info) => {
-            handle_get_member_avatar_event(member_info, manager, tx_status, local_pool).await;
+            handle_get_member_avatar_event(member_info, manager, tx_status, local_pool, profiles)
+                .await;
+        }
+        EventSend::GetSafetyNumber(uuid) => {
+            let result = identity::safety_number(manager, &*identities.lock().await, uuid).await;
+            send_safety_number(result, tx_status);
+        }
+        EventSend::SetVerified(uuid, verified) => {
+            let mut identities = identities.lock().await;
+            let result = identity::set_verified(manager, &mut identities, uuid, verified).await;
+            send_safety_number(result, tx_status);
+        }
+        EventSend::GetAttachmentImage(attachment_pointer, cache_dir, thumbnail) => {
+            handle_get_attachment_image_event(
+                *attachment_pointer,
+                cache_dir,
+                thumbnail,
+                manager,
+                tx_status,
+                local_pool,
+            );
         }
         EventSend::SaveAttachment(attachment_pointer, attachment_save_dir) => {
             handle_save_attachment_event(
@@ -2423,14 +3930,68 @@ async fn handle_incoming_event(
             )
             .await;
         }
-        EventSend::ReactToMessage(recipient, remove, message_dto) => handle_react_to_message(
-            recipient,
-            message_dto,
-            remove,
-            manager,
-            tx_status,
-            local_pool,
-        ),
+        EventSend::ReactToMessage(recipient, emoji, remove, message_dto) => {
+            handle_react_to_message(
+                recipient,
+                message_dto,
+                emoji,
+                remove,
+                manager,
+                tx_status,
+                local_pool,
+                identities,
+            )
+        }
+        EventSend::Search(query) => handle_search_event(query, search_index, tx_status).await,
+        EventSend::MarkRead(recipient, messages, send_receipts) => {
+            handle_mark_read_event(
+                recipient,
+                messages,
+                send_receipts,
+                manager,
+                receipts,
+                local_pool,
+            )
+            .await;
+        }
+        EventSend::Typing(recipient, started) => {
+            handle_typing_event(recipient, started, manager, local_pool)
+        }
+        EventSend::SetExpireTimer(uuid, seconds) => {
+            handle_set_expire_timer_event(uuid, seconds, manager, tx_status, local_pool)
+        }
+        EventSend::StartExpiryTimers(recipient, timers) => {
+            expiry.lock().await.start(&recipient.thread(), &timers);
+        }
+    }
+}
+
+/// Maximum number of search hits shown in the TUI
+const TUI_SEARCH_LIMIT: u32 = 100;
+
+async fn handle_search_event(
+    query: String,
+    search_index: &Option<SearchIndex>,
+    tx_status: &mpsc::Sender<EventApp>,
+) {
+    let Some(search_index) = search_index else {
+        return;
+    };
+
+    let filter = SearchFilter {
+        limit: TUI_SEARCH_LIMIT,
+        ..SearchFilter::default()
+    };
+    let hits = match search_index.search(&query, &filter).await {
+        Ok(hits) => hits,
+        Err(error) => {
+            error!(%error, "Failed to search messages");
+            Vec::new()
+        }
+    };
+
+    if let Err(error) = tx_status.send(EventApp::SearchResults(query, hits)) {
+        error!(%error, "Failed to send search results");
     }
 }
 
@@ -2455,14 +4016,54 @@ async fn handle_save_attachment_event(
         }
     }
 }
+/// Loads the image in the background, decoding large images would block other events
+fn handle_get_attachment_image_event(
+    attachment_pointer: AttachmentPointer,
+    cache_dir: PathBuf,
+    thumbnail: bool,
+    manager: &Manager<SqliteStore, Registered>,
+    tx_status: &mpsc::Sender<EventApp>,
+    local_pool: &LocalPoolHandle,
+) {
+    let manager = manager.clone();
+    let tx_status = tx_status.clone();
+    local_pool.spawn_pinned(move || async move {
+        let image =
+            match load_attachment_image(&attachment_pointer, &manager, &cache_dir, thumbnail).await
+            {
+                Ok(image) => image,
+                Err(error) => {
+                    error!(%error, "Failed to load image attachment");
+                    if !thumbnail {
+                        let _ = tx_status.send(EventApp::UiStatus(UiStatusMessage::Error(
+                            format!("Failed to load image: {error}"),
+                        )));
+                    }
+                    return;
+                }
+            };
+
+        let event = match (thumbnail, attachments::cache_key(&attachment_pointer)) {
+            (true, Some(key)) => EventApp::AttachmentThumbnailReceived(key, image),
+            (true, None) => return,
+            (false, _) => EventApp::AttachmentImageReceived(image),
+        };
+        if let Err(error) = tx_status.send(event) {
+            error!(%error, "Failed to send loaded image attachment");
+        }
+    });
+}
+
+/// Sends the message and tracks it in the retry manager, returns whether it was sent
 async fn handle_send_text_event(
     recipient: RecipientId,
     text: String,
     quoted_message: Option<MessageDto>,
     manager: Manager<SqliteStore, Registered>,
+    identities: &Mutex<IdentityStore>,
     tx_status: mpsc::Sender<EventApp>,
     retry_manager: Arc<Mutex<RetryManager>>,
-) {
+) -> bool {
     let outgoing_msg = OutgoingMessage::new(
         recipient.clone(),
         text.clone(),
@@ -2472,7 +4073,9 @@ async fn handle_send_text_event(
     );
     let message_id = {
         let mut retry_mgr = retry_manager.lock().await;
-        retry_mgr.add_message(outgoing_msg)
+        let message_id = retry_mgr.add_message(outgoing_msg);
+        retry_mgr.mark_sending(&message_id);
+        message_id
     };
 
     let recipient_clone = recipient.clone();
@@ -2489,6 +4092,7 @@ async fn handle_send_text_event(
                 text_clone,
                 quoted_message_clone,
                 manager_clone,
+                identities,
             )
             .await
         }
@@ -2497,11 +4101,13 @@ async fn handle_send_text_event(
                 master_key,
                 text_clone,
                 manager_clone,
+                identities,
                 quoted_message_clone,
             )
             .await
         }
     };
+    let sent = send_result.is_ok();
 
     match send_result {
         Ok(_) => {
@@ -2544,7 +4150,7 @@ async fn handle_send_text_event(
                 retry_mgr.mark_sent(&message_id);
                 warn!("Message likely delivered despite confirmation timeout");
             } else {
-                retry_mgr.mark_failed(&message_id, e.to_string());
+                retry_mgr.mark_failed(&message_id, &e);
 
                 if is_connection_error(&e) {
                     let _ = tx_status_clone.send(EventApp::NetworkStatusChanged(
@@ -2557,8 +4163,10 @@ async fn handle_send_text_event(
             drop(retry_mgr);
         }
     }
+    sent
 }
 
+/// Sends the attachments and tracks them in the retry manager, returns whether they were sent
 #[allow(clippy::too_many_arguments)]
 async fn handle_send_attachment_event(
     recipient: RecipientId,
@@ -2566,9 +4174,10 @@ async fn handle_send_attachment_event(
     attachment_path: String,
     quoted_message: Option<MessageDto>,
     manager: Manager<SqliteStore, Registered>,
+    identities: &Mutex<IdentityStore>,
     tx_status: mpsc::Sender<EventApp>,
     retry_manager: Arc<Mutex<RetryManager>>,
-) {
+) -> bool {
     let outgoing_msg = OutgoingMessage::new(
         recipient.clone(),
         text.clone(),
@@ -2578,12 +4187,15 @@ async fn handle_send_attachment_event(
     );
     let message_id = {
         let mut retry_mgr = retry_manager.lock().await;
-        retry_mgr.add_message(outgoing_msg)
+        let message_id = retry_mgr.add_message(outgoing_msg);
+        retry_mgr.mark_sending(&message_id);
+        message_id
     };
 
     let recipient_clone = recipient.clone();
     let text_clone = text.clone();
-    let attachment_path_clone = attachment_path.clone();
+    // The field lists all attachments of the message, see `parse_attachment_field`
+    let attachments = parse_attachment_field(&attachment_path);
     let quoted_message_clone = quoted_message.clone();
     let tx_status_clone = tx_status.clone();
     let retry_manager_clone = Arc::clone(&retry_manager);
@@ -2595,9 +4207,10 @@ async fn handle_send_attachment_event(
             send::contact::send_attachment_tui(
                 uuid.to_string(),
                 text_clone,
-                attachment_path_clone,
+                attachments,
                 quoted_message_clone,
                 manager_clone,
+                identities,
             )
             .await
         }
@@ -2605,13 +4218,15 @@ async fn handle_send_attachment_event(
             send::group::send_attachment_tui(
                 &master_key,
                 text_clone,
-                attachment_path_clone,
+                attachments,
                 quoted_message_clone,
                 manager_clone,
+                identities,
             )
             .await
         }
     };
+    let sent = send_result.is_ok();
 
     match send_result {
         Ok(_) => {
@@ -2656,7 +4271,7 @@ async fn handle_send_attachment_event(
                 retry_mgr.mark_sent(&message_id);
                 warn!("Message likely delivered despite confirmation timeout");
             } else {
-                retry_mgr.mark_failed(&message_id, e.to_string());
+                retry_mgr.mark_failed(&message_id, &e);
                 if is_connection_error(&e) {
                     _ = tx_status_clone.send(EventApp::NetworkStatusChanged(
                         NetworkStatus::Disconnected("Cannot send: WiFi disconnected".to_string()),
@@ -2668,8 +4283,101 @@ async fn handle_send_attachment_event(
             drop(retry_mgr);
         }
     }
+    sent
+}
+
+/// Deletes messages on this device only and updates the conversation in the list
+fn handle_delete_locally_event(
+    recipient: RecipientId,
+    deletion: LocalDeletion,
+    manager: &Manager<SqliteStore, Registered>,
+    tx_status: &mpsc::Sender<EventApp>,
+    local_pool: &LocalPoolHandle,
+    recipients: &Arc<Mutex<RecipientIndex>>,
+    search_index: &Option<SearchIndex>,
+) {
+    let manager = manager.clone();
+    let tx_status = tx_status.clone();
+    let recipients = recipients.clone();
+    let search_index = search_index.clone();
+    local_pool.spawn_pinned(move || async move {
+        let thread = recipient.thread();
+        if let Err(error) =
+            deletion::delete_locally(&manager, &thread, deletion, search_index.as_ref()).await
+        {
+            error!(%error, "Failed to delete messages locally");
+            let _ = tx_status.send(EventApp::UiStatus(UiStatusMessage::Error(format!(
+                "Failed to delete: {error}"
+            ))));
+            return;
+        }
+
+        let mut recipients_guard = recipients.lock().await;
+        match deletion {
+            LocalDeletion::Conversation => recipients_guard.hide(&recipient),
+            _ => recipients_guard.refresh(&manager, &recipient).await,
+        }
+        let update = recipients_guard.take_update(true);
+        drop(recipients_guard);
+        if let Some(new_recipients) = update
+            && let Err(channel_error) = tx_status.send(EventApp::ContactsList(new_recipients))
+        {
+            error!(%channel_error);
+        }
+        if let Err(channel_error) = tx_status.send(EventApp::ReceiveMessage) {
+            error!(%channel_error);
+        }
+    });
 }
 
+/// Answers a message request and updates the conversation in the list
+fn handle_respond_to_request_event(
+    sender: Uuid,
+    response: RequestResponse,
+    manager: &Manager<SqliteStore, Registered>,
+    tx_status: &mpsc::Sender<EventApp>,
+    local_pool: &LocalPoolHandle,
+    recipients: &Arc<Mutex<RecipientIndex>>,
+    search_index: &Option<SearchIndex>,
+) {
+    let mut manager = manager.clone();
+    let tx_status = tx_status.clone();
+    let recipients = recipients.clone();
+    let search_index = search_index.clone();
+    local_pool.spawn_pinned(move || async move {
+        // The profile key is shared first, so the list isn't locked while sending
+        if response == RequestResponse::Accept
+            && let Err(error) = send::contact::send_profile_key(&mut manager, sender).await
+        {
+            error!(%error, "Failed to share profile key");
+            let _ = tx_status.send(EventApp::UiStatus(UiStatusMessage::Error(format!(
+                "Failed to accept message request: {error}"
+            ))));
+            return;
+        }
+
+        let mut recipients_guard = recipients.lock().await;
+        let result = recipients_guard
+            .record_response(&manager, sender, response, search_index.as_ref())
+            .await;
+        let update = recipients_guard.take_update(true);
+        drop(recipients_guard);
+
+        if let Err(error) = result {
+            error!(%error, "Failed to answer message request");
+            let _ = tx_status.send(EventApp::UiStatus(UiStatusMessage::Error(format!(
+                "Failed to answer message request: {error}"
+            ))));
+        }
+        if let Some(new_recipients) = update
+            && let Err(channel_error) = tx_status.send(EventApp::ContactsList(new_recipients))
+        {
+            error!(%channel_error);
+        }
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn handle_delete_message_event(
     recipient: RecipientId,
     target_send_timestamp: u64,
@@ -2677,6 +4385,8 @@ async fn handle_delete_message_event(
     tx_status: &mpsc::Sender<EventApp>,
     retry_manager: &Arc<Mutex<RetryManager>>,
     local_pool: &LocalPoolHandle,
+    search_index: &Option<SearchIndex>,
+    identities: &Arc<Mutex<IdentityStore>>,
 ) {
     let outgoing_msg = OutgoingMessage::new(
         recipient.clone(),
@@ -2687,19 +4397,24 @@ async fn handle_delete_message_event(
     );
     let message_id = {
         let mut retry_mgr = retry_manager.lock().await;
-        retry_mgr.add_message(outgoing_msg)
+        let message_id = retry_mgr.add_message(outgoing_msg);
+        retry_mgr.mark_sending(&message_id);
+        message_id
     };
 
     let recipient_clone = recipient.clone();
     let tx_status_clone = tx_status.clone();
     let retry_manager_clone = Arc::clone(retry_manager);
     let manager_clone = manager.clone();
+    let search_index = search_index.clone();
+    let identities = identities.clone();
 
     local_pool.spawn_pinned(move || async move {
         let send_result = match recipient_clone {
             RecipientId::Contact(uuid) => {
                 send::contact::send_delete_message_tui(
-                    manager_clone,
+                    manager_clone.clone(),
+                    &identities,
                     uuid.to_string(),
                     target_send_timestamp,
                 )
@@ -2708,12 +4423,16 @@ async fn handle_delete_message_event(
             RecipientId::Group(master_key) => {
                 send::group::send_delete_message_tui(
                     master_key,
-                    manager_clone,
+                    manager_clone.clone(),
+                    &identities,
                     target_send_timestamp,
                 )
                 .await
             }
         };
+        if send_result.is_ok() {
+            index_sent(search_index.as_ref(), &manager_clone, &recipient.thread()).await;
+        }
 
         match send_result {
             Ok(_) => {
@@ -2734,7 +4453,7 @@ async fn handle_delete_message_event(
                     retry_mgr.mark_sent(&message_id);
                     warn!("Message likely delivered despite confirmation timeout");
                 } else {
-                    retry_mgr.mark_failed(&message_id, e.to_string());
+                    retry_mgr.mark_failed(&message_id, &e);
 
                     if is_connection_error(&e) {
                         let _ = tx_status_clone.send(EventApp::NetworkStatusChanged(
@@ -2752,23 +4471,133 @@ async fn handle_delete_message_event(
     });
 }
 
+#[allow(clippy::too_many_arguments)]
+async fn handle_edit_message_event(
+    recipient: RecipientId,
+    target_send_timestamp: u64,
+    text: String,
+    manager: &Manager<SqliteStore, Registered>,
+    tx_status: &mpsc::Sender<EventApp>,
+    retry_manager: &Arc<Mutex<RetryManager>>,
+    local_pool: &LocalPoolHandle,
+    search_index: &Option<SearchIndex>,
+    identities: &Arc<Mutex<IdentityStore>>,
+) {
+    let outgoing_msg =
+        OutgoingMessage::edit(recipient.clone(), text.clone(), target_send_timestamp);
+    let message_id = {
+        let mut retry_mgr = retry_manager.lock().await;
+        let message_id = retry_mgr.add_message(outgoing_msg);
+        retry_mgr.mark_sending(&message_id);
+        message_id
+    };
+
+    let tx_status_clone = tx_status.clone();
+    let retry_manager_clone = Arc::clone(retry_manager);
+    let manager_clone = manager.clone();
+    let search_index = search_index.clone();
+    let identities = identities.clone();
+
+    local_pool.spawn_pinned(move || async move {
+        let send_result = send_edit(
+            &manager_clone,
+            &identities,
+            &recipient,
+            target_send_timestamp,
+            text,
+        )
+        .await;
+        if send_result.is_ok() {
+            index_sent(search_index.as_ref(), &manager_clone, &recipient.thread()).await;
+        }
+
+        let mut retry_mgr = retry_manager_clone.lock().await;
+        match send_result {
+            Ok(_) => {
+                retry_mgr.mark_sent(&message_id);
+                let _ =
+                    tx_status_clone.send(EventApp::NetworkStatusChanged(NetworkStatus::Connected));
+                let _ = tx_status_clone.send(EventApp::ReceiveMessage);
+            }
+            Err(e) if is_delivery_confirmation_timeout(&e) => {
+                retry_mgr.mark_sent(&message_id);
+                warn!("Message likely delivered despite confirmation timeout");
+            }
+            Err(e) => {
+                retry_mgr.mark_failed(&message_id, &e);
+
+                if is_connection_error(&e) {
+                    let _ = tx_status_clone.send(EventApp::NetworkStatusChanged(
+                        NetworkStatus::Disconnected("Cannot send: WiFi disconnected".to_string()),
+                    ));
+                } else {
+                    error!("Error editing message: {e:?}");
+                    let _ = tx_status_clone.send(EventApp::UiStatus(UiStatusMessage::Error(
+                        format!("Failed to edit message: {e}"),
+                    )));
+                }
+            }
+        }
+    });
+}
+
+async fn send_edit(
+    manager: &Manager<SqliteStore, Registered>,
+    identities: &Mutex<IdentityStore>,
+    recipient: &RecipientId,
+    target_send_timestamp: u64,
+    text: String,
+) -> Result<()> {
+    match recipient {
+        RecipientId::Contact(uuid) => {
+            send::contact::send_edit_message_tui(
+                manager.clone(),
+                identities,
+                *uuid,
+                target_send_timestamp,
+                text,
+            )
+            .await
+        }
+        RecipientId::Group(master_key) => {
+            send::group::send_edit_message_tui(
+                *master_key,
+                manager.clone(),
+                identities,
+                target_send_timestamp,
+                text,
+            )
+            .await
+        }
+    }
+}
+
 async fn handle_get_contact_messages_event(
     uuid_str: String,
     manager: &Manager<SqliteStore, Registered>,
     tx_status: &mpsc::Sender<EventApp>,
     local_pool: &LocalPoolHandle,
+    receipts: &Arc<Mutex<ReceiptStore>>,
+    expiry: &Arc<Mutex<ExpiryIndex>>,
 ) {
     let manager_clone = manager.clone();
     let tx_status_clone = tx_status.clone();
+    let receipts = receipts.clone();
+    let expiry = expiry.clone();
 
     local_pool.spawn_pinned(move || async move {
         let result =
             contact::list_messages_tui(uuid_str.clone(), "0".to_string(), manager_clone).await;
 
         let messages = match result {
-            Ok(list) => {
+            Ok(mut list) => {
                 let _ =
                     tx_status_clone.send(EventApp::NetworkStatusChanged(NetworkStatus::Connected));
+                if let Ok(uuid) = Uuid::parse_str(&uuid_str) {
+                    let thread = Thread::Contact(uuid);
+                    receipts.lock().await.apply(&thread, &mut list);
+                    track_expiry(&expiry, &thread, &mut list).await;
+                }
                 list
             }
             Err(e) => {
@@ -2798,17 +4627,24 @@ async fn handle_get_group_messages_event(
     manager: &Manager<SqliteStore, Registered>,
     tx_status: &mpsc::Sender<EventApp>,
     local_pool: &LocalPoolHandle,
+    receipts: &Arc<Mutex<ReceiptStore>>,
+    expiry: &Arc<Mutex<ExpiryIndex>>,
 ) {
     let manager_clone = manager.clone();
     let tx_status_clone = tx_status.clone();
+    let receipts = receipts.clone();
+    let expiry = expiry.clone();
 
     local_pool.spawn_pinned(move || async move {
         let result = receive::group::list_messages_tui(manager_clone, master_key, None).await;
 
         let messages = match result {
-            Ok(list) => {
+            Ok(mut list) => {
                 let _ =
                     tx_status_clone.send(EventApp::NetworkStatusChanged(NetworkStatus::Connected));
+                let thread = Thread::Group(master_key);
+                receipts.lock().await.apply(&thread, &mut list);
+                track_expiry(&expiry, &thread, &mut list).await;
                 list
             }
             Err(e) => {
@@ -2833,11 +4669,150 @@ async fn handle_get_group_messages_event(
     });
 }
 
+/// Tracks timers of our listed messages and fills in timers of received ones which already
+/// started, messages whose timer ran out are dropped. Timers of received messages start once the
+/// chat shows them, see `App::mark_read`.
+async fn track_expiry(
+    expiry: &Arc<Mutex<ExpiryIndex>>,
+    thread: &Thread,
+    messages: &mut Vec<MessageDto>,
+) {
+    let now = chrono::Utc::now().timestamp_millis() as u64;
+    expiry.lock().await.track(thread, messages, false, now);
+    expiry::retain_live(messages);
+}
+
+fn handle_typing_event(
+    recipient: RecipientId,
+    started: bool,
+    manager: &Manager<SqliteStore, Registered>,
+    local_pool: &LocalPoolHandle,
+) {
+    let manager_clone = manager.clone();
+    local_pool.spawn_pinned(move || async move {
+        let result = match recipient {
+            RecipientId::Contact(uuid) => {
+                send::contact::send_typing_message_tui(manager_clone, uuid, started).await
+            }
+            RecipientId::Group(master_key) => {
+                send::group::send_typing_message_tui(master_key, manager_clone, started).await
+            }
+        };
+        // Typing indicators are best effort, a lost one just expires on the other side
+        if let Err(error) = result {
+            debug!(%error, "Failed to send typing indicator");
+        }
+    });
+}
+
+fn handle_set_expire_timer_event(
+    uuid: Uuid,
+    seconds: u32,
+    manager: &Manager<SqliteStore, Registered>,
+    tx_status: &mpsc::Sender<EventApp>,
+    local_pool: &LocalPoolHandle,
+) {
+    let mut manager_clone = manager.clone();
+    let tx_status = tx_status.clone();
+    local_pool.spawn_pinned(move || async move {
+        let result = expiry::set_contact_expire_timer(&mut manager_clone, uuid, seconds).await;
+        let event = match result {
+            Ok(()) => EventApp::ExpireTimerChanged(uuid, seconds),
+            Err(error) => {
+                error!(%error, "Failed to change disappearing messages timer");
+                EventApp::UiStatus(UiStatusMessage::Error(format!(
+                    "Failed to change disappearing messages timer: {error}"
+                )))
+            }
+        };
+        if let Err(error) = tx_status.send(event) {
+            error!(%error, "Failed to send disappearing messages timer status");
+        }
+    });
+}
+
+fn send_safety_number(result: Result<identity::SafetyNumber>, tx_status: &mpsc::Sender<EventApp>) {
+    let event = match result {
+        Ok(safety_number) => EventApp::SafetyNumberReceived(safety_number),
+        Err(error) => {
+            error!(%error, "Failed to get safety number");
+            EventApp::UiStatus(UiStatusMessage::Error(format!(
+                "Failed to get safety number: {error}"
+            )))
+        }
+    };
+    if let Err(error) = tx_status.send(event) {
+        error!(%error, "Failed to send safety number");
+    }
+}
+
+/// Marks shown messages as read, read receipts are sent for the ones which weren't read before,
+/// one receipt per author
+async fn handle_mark_read_event(
+    recipient: RecipientId,
+    messages: Vec<(Uuid, u64)>,
+    send_receipts: bool,
+    manager: &Manager<SqliteStore, Registered>,
+    receipts: &Arc<Mutex<ReceiptStore>>,
+    local_pool: &LocalPoolHandle,
+) {
+    let thread = recipient.thread();
+    let unread = receipts.lock().await.unread(&thread, &messages);
+    let Some(newest) = unread.values().flatten().max().copied() else {
+        return;
+    };
+    // Messages read while receipts are turned off don't get one when they are turned on
+    if !send_receipts {
+        receipts.lock().await.mark_read(&thread, newest);
+        return;
+    }
+
+    let mut manager_clone = manager.clone();
+    let receipts = receipts.clone();
+    local_pool.spawn_pinned(move || async move {
+        let mut read_until = newest;
+        for (author, timestamps) in unread {
+            let oldest = timestamps.iter().min().copied().unwrap_or(newest);
+            if let Err(error) = send_read_receipt(&mut manager_clone, author, timestamps).await {
+                warn!(%error, "Failed to send read receipt");
+                // Receipts are sent again for messages from the oldest one that failed
+                read_until = read_until.min(oldest.saturating_sub(1));
+            }
+        }
+        receipts.lock().await.mark_read(&thread, read_until);
+    });
+}
+
+/// Fetches the profile of the contact again in the background when the cached one is older
+/// than the TTL, it is shown the next time
+fn refresh_stale_profile(
+    manager: &Manager<SqliteStore, Registered>,
+    profiles: &Arc<Mutex<ProfileCache>>,
+    local_pool: &LocalPoolHandle,
+    uuid: Uuid,
+    profile_key: ProfileKey,
+) {
+    let manager = manager.clone();
+    let profiles = profiles.clone();
+    local_pool.spawn_pinned(move || async move {
+        if profiles.lock().await.is_fresh(uuid, profile_key) {
+            return;
+        }
+        if let Err(error) =
+            profile_cache::fetch_profile(&profiles, &manager, uuid, profile_key).await
+        {
+            warn!(%error, "Failed to refresh profile, keeping the cached one");
+        }
+    });
+}
+
 async fn handle_get_contact_info_event(
     manager: &mut Manager<SqliteStore, Registered>,
     uuid_str: String,
     tx_status: &mpsc::Sender<EventApp>,
     local_pool: &LocalPoolHandle,
+    profiles: &Arc<Mutex<ProfileCache>>,
+    identities: &Arc<Mutex<IdentityStore>>,
 ) {
     if let Ok(uuid) = uuid_str.parse() {
         match manager.store().contact_by_id(&uuid).await {
@@ -2850,16 +4825,22 @@ async fn handle_get_contact_info_event(
                     }
                 };
                 let profile = match profile_key {
-                    Some(profile_key) => match manager
-                        .retrieve_profile_by_uuid(contact.uuid, profile_key)
+                    Some(profile_key) => {
+                        match profile_cache::cached_profile(
+                            profiles,
+                            manager,
+                            contact.uuid,
+                            profile_key,
+                        )
                         .await
-                    {
-                        Ok(profile) => Some(profile),
-                        Err(error) => {
-                            error!(%error, "Failed to retrieve profile.");
-                            None
+                        {
+                            Ok(profile) => Some(profile),
+                            Err(error) => {
+                                error!(%error, "Failed to retrieve profile.");
+                                None
+                            }
                         }
-                    },
+                    }
                     None => None,
                 };
 
@@ -2895,7 +4876,12 @@ async fn handle_get_contact_info_event(
                     name: contact_name,
                     description,
                     phone_number: contact.phone_number.as_ref().map(|p| p.to_string()),
-                    verified_state: contact.verified.state,
+                    verification: identity::verification_status(
+                        manager,
+                        &*identities.lock().await,
+                        &contact,
+                    )
+                    .await,
                     expire_timer: contact.expire_timer,
                     has_avatar,
                 };
@@ -2904,12 +4890,17 @@ async fn handle_get_contact_info_event(
                 }
 
                 if has_avatar && let Some(profile_key) = profile_key {
-                    let mut manager_inner = manager.clone();
+                    let manager_inner = manager.clone();
                     let tx_status_inner = tx_status.clone();
+                    let profiles_inner = profiles.clone();
                     local_pool.spawn_pinned(move || async move {
-                        match manager_inner
-                            .retrieve_profile_avatar_by_uuid(contact.uuid, profile_key)
-                            .await
+                        match profile_cache::cached_avatar(
+                            &profiles_inner,
+                            &manager_inner,
+                            contact.uuid,
+                            profile_key,
+                        )
+                        .await
                         {
                             Ok(avatar_option) => {
                                 if let Some(avatar_bytes) = avatar_option
@@ -2925,6 +4916,10 @@ async fn handle_get_contact_info_event(
                         }
                     });
                 }
+
+                if let Some(profile_key) = profile_key {
+                    refresh_stale_profile(manager, profiles, local_pool, contact.uuid, profile_key);
+                }
             }
             Ok(None) => {
                 error!("Contact not found");
@@ -2939,6 +4934,7 @@ async fn handle_get_group_info_event(
     manager: &mut Manager<SqliteStore, Registered>,
     tx_status: &mpsc::Sender<EventApp>,
     local_pool: &LocalPoolHandle,
+    profiles: &Arc<Mutex<ProfileCache>>,
 ) {
     let group_result = manager.store().group(master_key).await;
     let group = match group_result {
@@ -2960,7 +4956,7 @@ async fn handle_get_group_info_event(
         .iter()
         .map(|member: &Member| {
             let member_uuid = member.uuid;
-            let mut inner_manager = manager.clone();
+            let inner_manager = manager.clone();
             async move {
                 let contact_result = inner_manager.store().contact_by_id(&member_uuid).await;
                 let contact_option = match contact_result {
@@ -2970,9 +4966,13 @@ async fn handle_get_group_info_event(
                         return None;
                     }
                 };
-                let profile_result = inner_manager
-                    .retrieve_profile_by_uuid(member_uuid, member.profile_key)
-                    .await;
+                let profile_result = profile_cache::cached_profile(
+                    profiles,
+                    &inner_manager,
+                    member_uuid,
+                    member.profile_key,
+                )
+                .await;
                 let profile_option = match profile_result {
                     Ok(profile) => Some(profile),
                     Err(error) => {
@@ -3022,9 +5022,13 @@ async fn handle_get_group_info_event(
                     }
                     None => {
                         let member_uuid = member.uuid;
-                        let profile_result = inner_manager
-                            .retrieve_profile_by_uuid(member_uuid, member.profile_key)
-                            .await;
+                        let profile_result = profile_cache::cached_profile(
+                            profiles,
+                            &inner_manager,
+                            member_uuid,
+                            member.profile_key,
+                        )
+                        .await;
                         let profile_option = match profile_result {
                             Ok(profile) => Some(profile),
                             Err(error) => {
@@ -3071,6 +5075,16 @@ async fn handle_get_group_info_event(
     let results = join_all(members_futures).await;
     let members = results.into_iter().flatten().collect();
 
+    for member in &group.members {
+        refresh_stale_profile(
+            manager,
+            profiles,
+            local_pool,
+            member.uuid,
+            member.profile_key,
+        );
+    }
+
     let description = group.description.unwrap_or_default();
     let group_info = GroupInfo {
         master_key,
@@ -3078,18 +5092,42 @@ async fn handle_get_group_info_event(
         description,
         has_avatar: !group.avatar.is_empty(),
         members,
+        expire_timer: group
+            .disappearing_messages_timer
+            .as_ref()
+            .map(|timer| timer.duration)
+            .unwrap_or(0),
     };
 
     if !group.avatar.is_empty() {
-        let master_key_inner = master_key.to_vec();
+        let avatar_path = group.avatar.clone();
         let mut manager_inner = manager.clone();
         let tx_status_inner = tx_status.clone();
+        let profiles_inner = profiles.clone();
         local_pool.spawn_pinned(move || async move {
-            let group_ctx = GroupContextV2 {
-                master_key: Some(master_key_inner),
-                ..Default::default()
+            let cached = profiles_inner
+                .lock()
+                .await
+                .group_avatar(master_key, &avatar_path);
+            let avatar_result = match cached {
+                Some(avatar_bytes) => Ok(Some(avatar_bytes)),
+                None => {
+                    let group_ctx = GroupContextV2 {
+                        master_key: Some(master_key.to_vec()),
+                        ..Default::default()
+                    };
+                    let result = manager_inner.retrieve_group_avatar(group_ctx).await;
+                    if let Ok(Some(avatar_bytes)) = &result {
+                        profiles_inner.lock().await.insert_group_avatar(
+                            master_key,
+                            &avatar_path,
+                            avatar_bytes,
+                        );
+                    }
+                    result
+                }
             };
-            match manager_inner.retrieve_group_avatar(group_ctx).await {
+            match avatar_result {
                 Ok(avatar_option) => {
                     if let Some(avatar_bytes) = avatar_option {
                         match tx_status_inner.send(EventApp::GroupAvatarReceived(avatar_bytes)) {
@@ -3115,16 +5153,18 @@ async fn handle_get_member_avatar_event(
     manager: &mut Manager<SqliteStore, Registered>,
     tx_status: &mpsc::Sender<EventApp>,
     local_pool: &LocalPoolHandle,
+    profiles: &Arc<Mutex<ProfileCache>>,
 ) {
     if member_info.has_avatar {
         let uuid = member_info.uuid;
         let profile_key = member_info.profile_key;
-        let mut manager_inner = manager.clone();
+        let manager_inner = manager.clone();
         let tx_status_inner = tx_status.clone();
+        let profiles_inner = profiles.clone();
         local_pool.spawn_pinned(move || async move {
-            let avatar_result = manager_inner
-                .retrieve_profile_avatar_by_uuid(uuid, profile_key)
-                .await;
+            let avatar_result =
+                profile_cache::cached_avatar(&profiles_inner, &manager_inner, uuid, profile_key)
+                    .await;
 
             match avatar_result {
                 Ok(avatar_option) => {
@@ -3156,28 +5196,31 @@ pub fn handle_checking_qr_code(tx: mpsc::Sender<EventApp>) {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn handle_react_to_message(
     recipient: RecipientId,
     message_dto: MessageDto,
+    emoji: String,
     remove: bool,
     manager: &Manager<SqliteStore, Registered>,
     tx_status: &mpsc::Sender<EventApp>,
     local_pool: &LocalPoolHandle,
+    identities: &Arc<Mutex<IdentityStore>>,
 ) {
-    let emoji = "👍".to_string();
-
     let target_send_timestamp = message_dto.timestamp;
     let target_author_aci = message_dto.uuid.to_string();
 
     let recipient_clone = recipient.clone();
     let tx_status_clone = tx_status.clone();
     let manager_clone = manager.clone();
+    let identities = identities.clone();
 
     local_pool.spawn_pinned(move || async move {
         let send_result = match recipient_clone {
             RecipientId::Contact(uuid) => {
                 send::contact::send_reaction_message_tui(
                     manager_clone,
+                    &identities,
                     uuid.to_string(),
                     target_send_timestamp,
                     target_author_aci.clone(),
@@ -3189,6 +5232,7 @@ pub fn handle_react_to_message(
             RecipientId::Group(master_key) => {
                 send::group::send_reaction_message_tui(
                     manager_clone,
+                    &identities,
                     &master_key,
                     target_send_timestamp,
                     target_author_aci.clone(),
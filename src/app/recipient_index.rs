@@ -0,0 +1,407 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use presage::libsignal_service::prelude::{Content, Uuid};
+use presage::model::contacts::Contact;
+use presage::store::{ContentsStore, Thread};
+use presage::{Manager, manager::Registered};
+use presage_store_sqlite::SqliteStore;
+use tokio::sync::Mutex;
+use tracing::error;
+
+use crate::app::utils::{get_messages_backoff, shown_message_timestamp};
+use crate::app::{
+    DisplayContact, DisplayRecipient, DisplayRecipientType, RecipientId, contact_display_name,
+    group_to_display_group,
+};
+use crate::contacts;
+use crate::groups::{self, group_identifier};
+use crate::message_requests::{self, MessageRequests, RequestResponse};
+use crate::messages::deletion::HiddenConversations;
+use crate::profile_cache::{self, ProfileCache};
+use crate::search::SearchIndex;
+
+/// Least time between two recipient lists sent to the UI while a backlog of messages is received
+const FLUSH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Conversations of the account with their latest message, kept up to date thread by thread
+/// as messages arrive instead of listing the whole store again
+pub struct RecipientIndex {
+    recipients: HashMap<RecipientId, DisplayRecipient>,
+    /// Profile names of contacts without a name, `None` for those without a profile name, so
+    /// every profile is fetched at most once
+    profile_names: HashMap<Uuid, Option<String>>,
+    /// Whether the list changed since it was sent to the UI
+    dirty: bool,
+    last_flush: Option<Instant>,
+    profiles: Arc<Mutex<ProfileCache>>,
+    /// Conversations deleted from the list, they are left out until a newer message arrives
+    hidden: HiddenConversations,
+    requests: MessageRequests,
+}
+
+impl RecipientIndex {
+    pub fn new(
+        profiles: Arc<Mutex<ProfileCache>>,
+        hidden: HiddenConversations,
+        requests: MessageRequests,
+    ) -> Self {
+        Self {
+            recipients: HashMap::new(),
+            profile_names: HashMap::new(),
+            dirty: false,
+            last_flush: None,
+            profiles,
+            hidden,
+            requests,
+        }
+    }
+
+    /// Lists contacts and groups from the store, e.g. after contacts were synchronized.
+    ///
+    /// Latest messages are looked up only for conversations which are not in the index yet.
+    pub async fn rebuild(&mut self, manager: &mut Manager<SqliteStore, Registered>) {
+        let mut recipients = HashMap::with_capacity(self.recipients.len());
+
+        match contacts::list_contacts_tui(manager).await {
+            Ok(contacts) => {
+                for contact in contacts.into_iter().flatten() {
+                    if self.requests.is_blocked(contact.uuid) {
+                        continue;
+                    }
+                    let id = RecipientId::Contact(contact.uuid);
+                    let timestamp = self.latest_message_timestamp(manager, &id).await;
+                    let recipient = DisplayRecipient {
+                        recipient_type: self.contact_recipient(manager, &contact).await,
+                        latest_message_timestamp: timestamp,
+                        request: self.requests.is_request(contact.uuid, Some(&contact)),
+                    };
+                    recipients.insert(id, recipient);
+                }
+            }
+            Err(error) => error!(%error, "Failed to retrieve contacts"),
+        }
+
+        match groups::list_groups_tui(manager).await {
+            Ok(groups) => {
+                for (master_key, group) in groups.into_iter().flatten() {
+                    let id = RecipientId::Group(master_key);
+                    let timestamp = self.latest_message_timestamp(manager, &id).await;
+                    if let Some(display_group) = group_to_display_group(group, master_key) {
+                        let recipient = DisplayRecipient {
+                            recipient_type: DisplayRecipientType::Group(display_group),
+                            latest_message_timestamp: timestamp,
+                            request: false,
+                        };
+                        recipients.insert(id, recipient);
+                    }
+                }
+            }
+            Err(error) => error!(%error, "Failed to retrieve groups"),
+        }
+
+        // Senders who aren't contacts are known only from their message requests
+        for uuid in self.requests.senders() {
+            let id = RecipientId::Contact(uuid);
+            if recipients.contains_key(&id) {
+                continue;
+            }
+            let timestamp = self.latest_message_timestamp(manager, &id).await;
+            let recipient = DisplayRecipient {
+                recipient_type: self.sender_recipient(manager, uuid).await,
+                latest_message_timestamp: timestamp,
+                request: self.requests.is_pending(uuid),
+            };
+            recipients.insert(id, recipient);
+        }
+
+        if recipients != self.recipients {
+            self.recipients = recipients;
+            self.dirty = true;
+        }
+    }
+
+    /// Moves the conversation of a received or synchronized message to its timestamp, a
+    /// conversation which is not in the index yet is loaded from the store
+    pub async fn record(&mut self, manager: &Manager<SqliteStore, Registered>, content: &Content) {
+        let Some(timestamp) = shown_message_timestamp(&content.body) else {
+            return;
+        };
+        let id = match Thread::try_from(content) {
+            Ok(Thread::Contact(uuid)) => RecipientId::Contact(uuid),
+            Ok(Thread::Group(master_key)) => RecipientId::Group(master_key),
+            Err(_) => return,
+        };
+        self.set_latest(manager, id, Some(timestamp)).await;
+    }
+
+    /// Looks up the latest message of a single conversation again, e.g. after we sent one or
+    /// deleted some
+    pub async fn refresh(&mut self, manager: &Manager<SqliteStore, Registered>, id: &RecipientId) {
+        let timestamp = get_messages_backoff(manager, &id.thread()).await;
+        match self.recipients.get_mut(id) {
+            Some(recipient) => {
+                if recipient.latest_message_timestamp != timestamp {
+                    recipient.latest_message_timestamp = timestamp;
+                    self.dirty = true;
+                }
+            }
+            None => self.set_latest(manager, id.clone(), timestamp).await,
+        }
+    }
+
+    /// Sorts a received message into message requests, returns `false` for a message of a
+    /// blocked sender, which is deleted and shouldn't be handled any further
+    pub async fn screen(
+        &mut self,
+        manager: &Manager<SqliteStore, Registered>,
+        content: &Content,
+    ) -> bool {
+        let uuid = match Thread::try_from(content) {
+            Ok(Thread::Contact(uuid)) => uuid,
+            _ => return true,
+        };
+        let was_pending = self.requests.is_pending(uuid);
+        let keep =
+            match message_requests::screen_message(manager, &mut self.requests, content).await {
+                Ok(keep) => keep,
+                Err(error) => {
+                    error!(%error, "Failed to sort message into message requests");
+                    true
+                }
+            };
+        if self.requests.is_pending(uuid) != was_pending {
+            self.update_request(manager, uuid).await;
+        }
+        keep
+    }
+
+    /// Answers the message request of the sender, see `message_requests::record_response`. An
+    /// accepted request moves to the chats and a deleted or blocked one leaves the list.
+    pub async fn record_response(
+        &mut self,
+        manager: &Manager<SqliteStore, Registered>,
+        sender: Uuid,
+        response: RequestResponse,
+        search_index: Option<&SearchIndex>,
+    ) -> Result<()> {
+        message_requests::record_response(
+            manager,
+            &mut self.requests,
+            sender,
+            response,
+            search_index,
+        )
+        .await?;
+        match response {
+            RequestResponse::Accept => self.update_request(manager, sender).await,
+            RequestResponse::Delete | RequestResponse::Block => {
+                if self
+                    .recipients
+                    .remove(&RecipientId::Contact(sender))
+                    .is_some()
+                {
+                    self.dirty = true;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Senders who aren't contacts but whose conversations are listed
+    pub fn request_senders(&self) -> Vec<Uuid> {
+        self.requests.senders()
+    }
+
+    /// Leaves the conversation out of the list until a newer message arrives
+    pub fn hide(&mut self, id: &RecipientId) {
+        self.hidden.hide(id.clone());
+        if let Some(recipient) = self.recipients.get_mut(id) {
+            recipient.latest_message_timestamp = None;
+        }
+        self.dirty = true;
+    }
+
+    /// Finds the group conversation whose identifier is `group_id`
+    pub fn find_group(&self, group_id: &[u8]) -> Option<RecipientId> {
+        self.recipients.keys().find_map(|id| match id {
+            RecipientId::Group(master_key) if group_identifier(*master_key) == group_id => {
+                Some(id.clone())
+            }
+            _ => None,
+        })
+    }
+
+    /// Recipients sorted by their latest message if the list changed, unless the last list was
+    /// sent less than `FLUSH_INTERVAL` ago and `force` isn't set
+    pub fn take_update(&mut self, force: bool) -> Option<Vec<DisplayRecipient>> {
+        let due = self
+            .last_flush
+            .is_none_or(|last_flush| last_flush.elapsed() >= FLUSH_INTERVAL);
+        if !self.dirty || !(force || due) {
+            return None;
+        }
+        self.dirty = false;
+        self.last_flush = Some(Instant::now());
+
+        let mut recipients = self
+            .recipients
+            .iter()
+            .filter(|(id, recipient)| {
+                !self
+                    .hidden
+                    .is_hidden(id, recipient.latest_message_timestamp)
+            })
+            .map(|(_, recipient)| recipient.clone())
+            .collect::<Vec<_>>();
+        // Message requests are listed after the chats
+        recipients.sort_by(|a, b| {
+            a.request
+                .cmp(&b.request)
+                .then_with(|| {
+                    b.latest_message_timestamp
+                        .unwrap_or(0)
+                        .cmp(&a.latest_message_timestamp.unwrap_or(0))
+                })
+                .then_with(|| a.display_name().cmp(b.display_name()))
+        });
+        Some(recipients)
+    }
+
+    async fn set_latest(
+        &mut self,
+        manager: &Manager<SqliteStore, Registered>,
+        id: RecipientId,
+        timestamp: Option<u64>,
+    ) {
+        if let Some(recipient) = self.recipients.get_mut(&id) {
+            if recipient.latest_message_timestamp < timestamp {
+                recipient.latest_message_timestamp = timestamp;
+                self.dirty = true;
+            }
+            return;
+        }
+
+        let (recipient_type, request) = match &id {
+            RecipientId::Contact(uuid) if self.requests.is_blocked(*uuid) => return,
+            RecipientId::Contact(uuid) => match manager.store().contact_by_id(uuid).await {
+                Ok(Some(contact)) => (
+                    self.contact_recipient(manager, &contact).await,
+                    self.requests.is_request(*uuid, Some(&contact)),
+                ),
+                Ok(None) if self.requests.is_pending(*uuid) || self.requests.is_accepted(*uuid) => {
+                    (
+                        self.sender_recipient(manager, *uuid).await,
+                        self.requests.is_pending(*uuid),
+                    )
+                }
+                Ok(None) => return,
+                Err(error) => {
+                    error!(%error, "Failed to retrieve contact");
+                    return;
+                }
+            },
+            RecipientId::Group(master_key) => match manager.store().group(*master_key).await {
+                Ok(Some(group)) => match group_to_display_group(group, *master_key) {
+                    Some(display_group) => (DisplayRecipientType::Group(display_group), false),
+                    None => return,
+                },
+                Ok(None) => return,
+                Err(error) => {
+                    error!(%error, "Failed to retrieve group");
+                    return;
+                }
+            },
+        };
+        self.recipients.insert(
+            id,
+            DisplayRecipient {
+                recipient_type,
+                latest_message_timestamp: timestamp,
+                request,
+            },
+        );
+        self.dirty = true;
+    }
+
+    async fn latest_message_timestamp(
+        &self,
+        manager: &Manager<SqliteStore, Registered>,
+        id: &RecipientId,
+    ) -> Option<u64> {
+        match self.recipients.get(id) {
+            Some(recipient) => recipient.latest_message_timestamp,
+            None => get_messages_backoff(manager, &id.thread()).await,
+        }
+    }
+
+    /// Updates whether the conversation with the sender is listed as a message request
+    async fn update_request(&mut self, manager: &Manager<SqliteStore, Registered>, uuid: Uuid) {
+        let contact = match manager.store().contact_by_id(&uuid).await {
+            Ok(contact) => contact,
+            Err(error) => {
+                error!(%error, "Failed to retrieve contact");
+                return;
+            }
+        };
+        let request = self.requests.is_request(uuid, contact.as_ref());
+        if let Some(recipient) = self.recipients.get_mut(&RecipientId::Contact(uuid))
+            && recipient.request != request
+        {
+            recipient.request = request;
+            self.dirty = true;
+        }
+    }
+
+    async fn profile_name(
+        &mut self,
+        manager: &Manager<SqliteStore, Registered>,
+        uuid: Uuid,
+    ) -> Option<String> {
+        match self.profile_names.get(&uuid) {
+            Some(profile_name) => profile_name.clone(),
+            None => {
+                let profile_name = profile_cache::profile_name(&self.profiles, manager, uuid).await;
+                self.profile_names.insert(uuid, profile_name.clone());
+                profile_name
+            }
+        }
+    }
+
+    async fn contact_recipient(
+        &mut self,
+        manager: &Manager<SqliteStore, Registered>,
+        contact: &Contact,
+    ) -> DisplayRecipientType {
+        let profile_name = if contact.name.is_empty() {
+            self.profile_name(manager, contact.uuid).await
+        } else {
+            None
+        };
+        let display_name = contact_display_name(contact, profile_name);
+        let phone_number = contact
+            .phone_number
+            .as_ref()
+            .map(|phone_number| phone_number.to_string());
+        DisplayRecipientType::Contact(DisplayContact::new(
+            display_name,
+            contact.uuid,
+            phone_number,
+        ))
+    }
+
+    /// Sender who isn't a contact, named after their profile
+    async fn sender_recipient(
+        &mut self,
+        manager: &Manager<SqliteStore, Registered>,
+        uuid: Uuid,
+    ) -> DisplayRecipientType {
+        let display_name = self
+            .profile_name(manager, uuid)
+            .await
+            .unwrap_or_else(|| uuid.to_string());
+        DisplayRecipientType::Contact(DisplayContact::new(display_name, uuid, None))
+    }
+}
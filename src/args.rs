@@ -1,9 +1,27 @@
-use clap::{ArgGroup, Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+
+use crate::config::ImageEncoding;
 
 #[derive(Parser)]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Command,
+
+    /// Format in which listed data is printed
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable text
+    Text,
+    /// Single JSON document
+    Json,
+    /// One JSON document per line
+    Ndjson,
 }
 
 #[derive(Subcommand)]
@@ -32,12 +50,45 @@ pub enum Command {
     /// Delete message
     DeleteMessage(DeleteMessageArgs),
 
+    /// Delete a message of any author on this device only
+    DeleteForMe(DeleteMessageArgs),
+
+    /// Delete all messages of a conversation on this device only
+    ClearHistory(LocalConversationArgs),
+
+    /// Delete a conversation with its messages on this device and remove it from the list
+    DeleteConversation(LocalConversationArgs),
+
+    /// Prints message requests of senders who aren't contacts
+    ListRequests,
+
+    /// Accept a message request and share the profile with the sender
+    AcceptRequest(AcceptRequestArgs),
+
+    /// Delete a message request with its messages on this device
+    DeleteRequest(RequestArgs),
+
+    /// Block the sender of a message request and delete their messages on this device
+    BlockRequest(RequestArgs),
+
     // React to message
     ReactToMessage(ReactToMessageArgs),
 
+    /// Edit text of a sent message
+    EditMessage(EditMessageArgs),
+
+    /// Set disappearing messages timer of the conversation with a contact
+    SetExpireTimer(SetExpireTimerArgs),
+
     /// Prints messages from given point in time
     ListMessages(ListMessagesArgs),
 
+    /// Searches the message history
+    Search(SearchArgs),
+
+    /// Exports the whole history of a conversation
+    Export(ExportArgs),
+
     /// Prints the messages received from the last synchronization
     Receive,
 
@@ -58,6 +109,20 @@ pub enum Command {
 
     /// Delete an account
     UnlinkAccount(DeleteAccountArgs),
+
+    /// Runs in the background and serves JSON-RPC requests over a Unix socket
+    Daemon(DaemonArgs),
+}
+
+#[derive(Args)]
+pub struct DaemonArgs {
+    /// Path of the Unix socket to listen on
+    #[arg(short, long)]
+    pub socket: Option<PathBuf>,
+
+    /// Name of the account to serve, can be repeated. All accounts are served by default
+    #[arg(short, long)]
+    pub account: Vec<String>,
 }
 
 #[derive(Args)]
@@ -95,6 +160,10 @@ pub struct SendMessageArgs {
     #[arg(short, long)]
     pub text_message: String,
 
+    /// Turn markdown-like syntax, e.g. `**bold**`, into text styles instead of sending it as it is
+    #[arg(long)]
+    pub markdown: bool,
+
     /// Timestamp of the message that would be replied to
     #[arg(short, long)]
     pub quote: Option<u64>,
@@ -102,13 +171,17 @@ pub struct SendMessageArgs {
 
 #[derive(Args)]
 pub struct SendMessageToGroupArgs {
-    /// Name of the group that the message should be send to
+    /// Name or ID of the group that the message should be send to
     #[arg(short, long)]
     pub recipient: String,
 
-    /// Content of the message
+    /// Content of the message, members are mentioned by `@{uuid}`
     #[arg(short, long)]
     pub text_message: String,
+
+    /// Turn markdown-like syntax, e.g. `**bold**`, into text styles instead of sending it as it is
+    #[arg(long)]
+    pub markdown: bool,
 }
 
 #[derive(Args)]
@@ -121,7 +194,7 @@ pub struct ListMessagesArgs {
     #[arg(short, long, group = "recipient")]
     pub contact: Option<String>,
 
-    /// Name of the group that the message history should be shown
+    /// Name or ID of the group that the message history should be shown
     #[arg(short, long, group = "recipient")]
     pub group: Option<String>,
 
@@ -129,6 +202,71 @@ pub struct ListMessagesArgs {
     pub from: Option<String>,
 }
 
+#[derive(Args)]
+pub struct SearchArgs {
+    /// Words that the messages have to contain, the last letters of a word may be left out
+    pub query: String,
+
+    /// Name or UUID of the contact to search in
+    #[arg(short, long, conflicts_with = "group")]
+    pub contact: Option<String>,
+
+    /// Name or ID of the group to search in
+    #[arg(short, long)]
+    pub group: Option<String>,
+
+    /// Only messages sent on this day or later, as YYYY-MM-DD or a timestamp in milliseconds
+    #[arg(long)]
+    pub from: Option<String>,
+
+    /// Only messages sent on this day or earlier, as YYYY-MM-DD or a timestamp in milliseconds
+    #[arg(long)]
+    pub to: Option<String>,
+
+    /// Only messages with an attachment
+    #[arg(long)]
+    pub has_attachment: bool,
+
+    /// Maximum number of printed messages
+    #[arg(short, long, default_value_t = 50)]
+    pub limit: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum ExportFormat {
+    Json,
+    Markdown,
+    /// Single HTML page with embedded styles
+    Html,
+}
+
+#[derive(Args)]
+#[command(group(
+    ArgGroup::new("recipient")
+        .required(true)
+))]
+pub struct ExportArgs {
+    /// Name, phone number or UUID of the contact whose conversation should be exported
+    #[arg(short, long, group = "recipient")]
+    pub contact: Option<String>,
+
+    /// Name or ID of the group whose conversation should be exported
+    #[arg(short, long, group = "recipient")]
+    pub group: Option<String>,
+
+    /// Format of the export
+    #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+    pub format: ExportFormat,
+
+    /// File to write the export to, it's printed when not given
+    #[arg(short, long)]
+    pub file: Option<PathBuf>,
+
+    /// Download attachments into a folder next to the file and link them from the export
+    #[arg(short, long, requires = "file")]
+    pub attachments: bool,
+}
+
 #[derive(Args)]
 pub struct SendAttachmentArgs {
     /// Uuid of the contact that the message should be send to
@@ -139,9 +277,34 @@ pub struct SendAttachmentArgs {
     #[arg(short, long, default_value_t = String::from(""))]
     pub text_message: String,
 
-    /// Full path to attachment
+    /// Turn markdown-like syntax, e.g. `**bold**`, into text styles instead of sending it as it is
+    #[arg(long)]
+    pub markdown: bool,
+
+    /// Path to an attachment or a glob pattern, e.g. "photos/*.jpg"; repeat it or give several
+    /// paths to send them in one message
+    #[arg(short, long, required = true, num_args = 1..)]
+    pub attachment_path: Vec<String>,
+
+    /// Caption of an attachment, captions are given to the attachments in their order
     #[arg(short, long)]
-    pub attachment_path: String,
+    pub caption: Vec<String>,
+
+    /// Send images as they are, without scaling them down or removing their metadata
+    #[arg(long)]
+    pub keep_original: bool,
+
+    /// Longest side of sent images in pixels, overrides the config
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_image_dimension: Option<u32>,
+
+    /// Format sent images are re-encoded to, overrides the config
+    #[arg(long, value_enum)]
+    pub image_encoding: Option<ImageEncoding>,
+
+    /// Quality of re-encoded JPEG images from 1 to 100, overrides the config
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub image_quality: Option<u8>,
 
     /// Timestamp of the message that would be replied to
     #[arg(short, long)]
@@ -158,7 +321,7 @@ pub struct DeleteMessageArgs {
     #[arg(short, long, group = "recipient")]
     pub contact: Option<String>,
 
-    /// Name of the group from which the message would be deleted
+    /// Name or ID of the group from which the message would be deleted
     #[arg(short, long, group = "recipient")]
     pub group: Option<String>,
 
@@ -167,6 +330,80 @@ pub struct DeleteMessageArgs {
     pub timestamp: u64,
 }
 
+#[derive(Args)]
+#[command(group(
+    ArgGroup::new("recipient")
+        .required(true)
+))]
+pub struct LocalConversationArgs {
+    /// Name, phone number or UUID of the contact whose conversation should be deleted
+    #[arg(short, long, group = "recipient")]
+    pub contact: Option<String>,
+
+    /// Name or ID of the group whose conversation should be deleted
+    #[arg(short, long, group = "recipient")]
+    pub group: Option<String>,
+
+    /// Delete without asking for confirmation
+    #[arg(short, long)]
+    pub yes: bool,
+}
+
+#[derive(Args)]
+pub struct AcceptRequestArgs {
+    /// UUID or profile name of the sender, as printed by `list-requests`
+    #[arg(short, long)]
+    pub sender: String,
+}
+
+#[derive(Args)]
+pub struct RequestArgs {
+    /// UUID or profile name of the sender, as printed by `list-requests`
+    #[arg(short, long)]
+    pub sender: String,
+
+    /// Answer without asking for confirmation
+    #[arg(short, long)]
+    pub yes: bool,
+}
+
+#[derive(Args)]
+pub struct SetExpireTimerArgs {
+    /// Uuid, name or phone number of the contact
+    #[arg(short, long)]
+    pub contact: String,
+
+    /// Time after which messages disappear, e.g. 30s, 5m, 1h, 1d, 1w, or off
+    pub timer: String,
+}
+
+#[derive(Args)]
+#[command(group(
+    ArgGroup::new("recipient")
+        .required(true)
+))]
+pub struct EditMessageArgs {
+    /// Uuid of the contact conversation in which the message would be edited
+    #[arg(short, long, group = "recipient")]
+    pub contact: Option<String>,
+
+    /// Name or ID of the group in which the message would be edited
+    #[arg(short, long, group = "recipient")]
+    pub group: Option<String>,
+
+    /// timestamp of the message that would be edited
+    #[arg(short, long)]
+    pub timestamp: u64,
+
+    /// New text of the message
+    #[arg(long)]
+    pub text_message: String,
+
+    /// Turn markdown-like syntax, e.g. `**bold**`, into text styles instead of sending it as it is
+    #[arg(long)]
+    pub markdown: bool,
+}
+
 #[derive(Args)]
 #[command(group(
     ArgGroup::new("recipient")
@@ -177,11 +414,19 @@ pub struct ReactToMessageArgs {
     #[arg(short, long, group = "recipient")]
     pub contact: Option<String>,
 
-    /// Name of the group from which the message would be reacted to
+    /// Name or ID of the group from which the message would be reacted to
     #[arg(short, long, group = "recipient")]
     pub group: Option<String>,
 
     /// timestamp of the message that would be reacted to
     #[arg(short, long)]
     pub timestamp: u64,
+
+    /// Emoji of the reaction, either the emoji itself or its shortcode, e.g. heart
+    #[arg(short, long, default_value = "👍")]
+    pub emoji: String,
+
+    /// Removes our reaction with the emoji instead of adding it
+    #[arg(short, long)]
+    pub remove: bool,
 }
@@ -1,15 +1,24 @@
 use crate::{
+    account_management::create_registered_manager,
+    app::RecipientId,
+    args::{OutputFormat, SearchArgs},
     contacts::list_contacts_cli,
-    groups::list_groups_cli,
+    groups::{find_master_key, list_groups_cli},
+    message_requests::{MessageRequests, pending_requests_cli},
     messages::receive::{MessageDto, contact, group, receive_messages_cli},
+    messages::send::contact::find_uuid,
+    output::{ContactOutput, GroupOutput, ProfileOutput, RequestOutput, print_item, print_items},
     profile::{get_my_profile_avatar_cli, get_profile_cli},
+    search::{SearchFilter, SearchHit, SearchIndex},
+    sidecar::AccountFile,
     ui::utils::get_local_timestamp,
 };
-use anyhow::Result;
-use presage::model::{contacts::Contact, groups::Group};
+use anyhow::{Result, anyhow, bail};
+use chrono::{Local, NaiveDate, NaiveTime};
+use std::io::Write;
 use viuer::{Config, print_from_file};
 
-fn print_contact(contact: &Contact) {
+fn print_contact(contact: &ContactOutput) {
     println!("Name: {}", contact.name);
     println!("UUID: {}", contact.uuid);
     if let Some(phone_number) = &contact.phone_number {
@@ -17,38 +26,71 @@ fn print_contact(contact: &Contact) {
     }
 }
 
-pub async fn print_contacts() -> Result<()> {
-    let contacts = list_contacts_cli().await?;
+pub async fn print_contacts(output: OutputFormat) -> Result<()> {
+    let contacts = list_contacts_cli()
+        .await?
+        .into_iter()
+        .flatten()
+        .map(|contact| ContactOutput::from(&contact))
+        .collect::<Vec<_>>();
 
-    for contact in contacts.into_iter().flatten() {
-        print_contact(&contact);
+    print_items(output, &contacts, |contact| {
+        print_contact(contact);
         println!("================");
-    }
-    Ok(())
+    })
 }
 
-fn print_group(group: &Group) {
+fn print_group(group: &GroupOutput) {
     println!("Name: {}", group.title);
+    println!("ID: {}", group.master_key);
     if let Some(desc) = &group.description {
         println!("Description: {desc}");
     }
 }
 
-pub async fn print_groups() -> Result<()> {
-    let groups = list_groups_cli().await?;
-
-    let groups = groups
+pub async fn print_groups(output: OutputFormat) -> Result<()> {
+    let groups = list_groups_cli()
+        .await?
         .into_iter()
         .flatten()
-        .map(|(_, group)| group)
+        .map(|(master_key, group)| GroupOutput::from((&master_key, &group)))
         .collect::<Vec<_>>();
 
-    for group in groups {
-        print_group(&group);
+    print_items(output, &groups, |group| {
+        print_group(group);
         println!("================");
+    })
+}
+
+fn print_request(request: &RequestOutput) {
+    println!(
+        "Name: {}",
+        request
+            .profile_name
+            .as_deref()
+            .unwrap_or("<no profile name>")
+    );
+    println!("UUID: {}", request.uuid);
+    println!(
+        "Phone number: {}",
+        request.phone_number.as_deref().unwrap_or("hidden")
+    );
+    if let Some(message) = &request.latest_message {
+        print_body(message);
     }
+}
 
-    Ok(())
+pub async fn print_requests(output: OutputFormat) -> Result<()> {
+    let requests = pending_requests_cli()
+        .await?
+        .iter()
+        .map(RequestOutput::from)
+        .collect::<Vec<_>>();
+
+    print_items(output, &requests, |request| {
+        print_request(request);
+        println!("================");
+    })
 }
 
 fn print_message(message: &MessageDto) {
@@ -74,8 +116,12 @@ fn print_body(message: &MessageDto) {
     let datetime_local = get_local_timestamp(message.timestamp);
 
     if message.sender {
+        let status = message
+            .receipt_status()
+            .map(|status| format!(" ({})", status.label()))
+            .unwrap_or_default();
         println!(
-            "[{}] Me -> {}",
+            "[{}] Me -> {}{status}",
             datetime_local.format("%Y-%m-%d %H:%M:%S"),
             message.text
         );
@@ -103,44 +149,118 @@ fn print_reactions(message: &MessageDto) {
         println!("┆ Reaction: {emoji}");
     }
 }
-pub async fn print_messages_from_contact(recipient: String, from: Option<String>) -> Result<()> {
+pub async fn print_messages_from_contact(
+    recipient: String,
+    from: Option<String>,
+    output: OutputFormat,
+) -> Result<()> {
     let mut messages = contact::list_messages_cli(recipient, from).await?;
 
     // reversing the order of messages to print them out from the oldest to the latest
     messages.reverse();
 
-    for message in messages {
-        print_message(&message);
-    }
-    Ok(())
+    print_items(output, &messages, print_message)
 }
 
-pub async fn print_messages_from_group(group: String, from: Option<String>) -> Result<()> {
+pub async fn print_messages_from_group(
+    group: String,
+    from: Option<String>,
+    output: OutputFormat,
+) -> Result<()> {
     let mut messages = group::list_messages_cli(group, from).await?;
 
     messages.reverse();
 
-    for message in messages {
-        print_message(&message);
-    }
-    Ok(())
+    print_items(output, &messages, print_message)
 }
 
-pub async fn print_received_message() -> Result<()> {
+pub async fn print_received_message(output: OutputFormat) -> Result<()> {
     let mut messages = receive_messages_cli().await?;
 
     // reversing the order of messages to print them out from the oldest to the latest
     messages.reverse();
 
-    for message in messages {
-        print_message(&message);
+    print_items(output, &messages, print_message)
+}
+
+/// Parses day as YYYY-MM-DD in local time or a timestamp in milliseconds, `end_of_day` picks
+/// the last millisecond of the day instead of the first one
+fn parse_date_bound(value: &str, end_of_day: bool) -> Result<u64> {
+    if let Ok(timestamp) = value.parse::<u64>() {
+        return Ok(timestamp);
     }
-    Ok(())
+
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| anyhow!("Invalid date '{value}', expected YYYY-MM-DD or a timestamp"))?;
+    let time = if end_of_day {
+        NaiveTime::from_hms_milli_opt(23, 59, 59, 999)
+    } else {
+        NaiveTime::from_hms_opt(0, 0, 0)
+    }
+    .expect("Time of day is valid");
+
+    match date.and_time(time).and_local_timezone(Local).earliest() {
+        Some(datetime) => Ok(datetime.timestamp_millis() as u64),
+        None => bail!("Date '{value}' does not exist in the local time zone"),
+    }
+}
+
+fn print_search_hit(hit: &SearchHit) {
+    let datetime_local = get_local_timestamp(hit.timestamp);
+    let direction = if hit.outgoing { "Me ->" } else { "Them <-" };
+
+    println!(
+        "[{}] ({}) {direction} {}",
+        datetime_local.format("%Y-%m-%d %H:%M:%S"),
+        hit.timestamp,
+        hit.snippet
+    );
 }
 
-pub async fn print_profile() -> Result<()> {
+pub async fn print_search_results(args: SearchArgs, output: OutputFormat) -> Result<()> {
+    let mut manager = create_registered_manager().await?;
+
+    let recipient = match (args.contact, args.group) {
+        (Some(contact), _) => Some(RecipientId::Contact(
+            find_uuid(contact, &mut manager).await?,
+        )),
+        (None, Some(group)) => match find_master_key(group, &mut manager).await? {
+            Some(master_key) => Some(RecipientId::Group(master_key)),
+            None => bail!("Group with given name does not exist."),
+        },
+        (None, None) => None,
+    };
+
+    let filter = SearchFilter {
+        recipient,
+        from: args
+            .from
+            .map(|from| parse_date_bound(&from, false))
+            .transpose()?,
+        to: args.to.map(|to| parse_date_bound(&to, true)).transpose()?,
+        has_attachment: args.has_attachment,
+        limit: args.limit,
+    };
+
+    let index = SearchIndex::open_current().await?;
+    index
+        .catch_up(&manager, &MessageRequests::load_current()?.senders())
+        .await?;
+    let mut hits = index.search(&args.query, &filter).await?;
+
+    // printing from the oldest to the latest like the other message listings
+    hits.reverse();
+
+    print_items(output, &hits, print_search_hit)
+}
+
+pub async fn print_profile(output: OutputFormat) -> Result<()> {
     let profile = get_profile_cli().await?;
 
+    if output != OutputFormat::Text {
+        return print_item(output, &ProfileOutput::from(&profile), |_| {});
+    }
+
     println!("Profile:");
     if let Some(name) = &profile.name {
         println!("Name: {name}");
@@ -183,8 +303,13 @@ pub async fn print_profile() -> Result<()> {
 }
 
 fn display_avatar_color(image_data: &[u8]) -> Result<()> {
-    let temp_path = "/tmp/avatar_temp.jpg";
-    std::fs::write(temp_path, image_data)?;
+    // A file of our own, which is removed when it is dropped, so parallel runs don't clash
+    let mut temp_file = tempfile::Builder::new()
+        .prefix("avatar")
+        .suffix(".jpg")
+        .tempfile()?;
+    temp_file.write_all(image_data)?;
+    temp_file.flush()?;
 
     let config = Config {
         width: Some(30),
@@ -193,9 +318,7 @@ fn display_avatar_color(image_data: &[u8]) -> Result<()> {
         ..Default::default()
     };
 
-    print_from_file(temp_path, &config)?;
-
-    std::fs::remove_file(temp_path).ok();
+    print_from_file(temp_file.path(), &config)?;
 
     Ok(())
 }
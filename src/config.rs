@@ -3,6 +3,7 @@ use ratatui::style::Color;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use crate::env::SIGNAL_CONFIG_DIR;
 
@@ -13,6 +14,154 @@ pub struct Config {
     pub attachment_save_dir: PathBuf,
     pub current_account: Option<String>,
     pub notifications_enabled: bool,
+    #[serde(default)]
+    pub retry_policy: RetryPolicy,
+    /// Tell contacts when their messages were read in an opened chat
+    #[serde(default = "default_enabled")]
+    pub send_read_receipts: bool,
+    /// Tell contacts when we are typing a message to them
+    #[serde(default = "default_enabled")]
+    pub send_typing_indicators: bool,
+    #[serde(default)]
+    pub image_processing: ImageProcessing,
+    /// Region of phone numbers given without the country code, e.g. `DE`
+    #[serde(default)]
+    pub default_region: Option<String>,
+    #[serde(default)]
+    pub identity_policy: IdentityPolicy,
+    /// How long cached profiles of contacts are shown before they are fetched again
+    #[serde(default = "default_profile_cache_ttl")]
+    pub profile_cache_ttl_seconds: u64,
+    /// Remove messages their author deleted for everyone from the store, not only hide them
+    #[serde(default)]
+    pub purge_deleted_messages: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_profile_cache_ttl() -> u64 {
+    24 * 60 * 60
+}
+
+/// Controls how messages that failed to send are retried
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Maximum number of send attempts, the first one included
+    pub max_attempts: u32,
+    /// Delay before the first retry
+    pub base_delay_seconds: u64,
+    /// Factor by which the delay grows after every attempt
+    pub backoff_multiplier: f64,
+    /// Upper bound of the delay between two attempts
+    pub max_delay_seconds: u64,
+    /// Fraction of the delay that is randomized, from 0.0 (none) to 1.0
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            base_delay_seconds: 5,
+            backoff_multiplier: 2.0,
+            max_delay_seconds: 300,
+            jitter: 0.2,
+        }
+    }
+}
+
+/// How a changed identity key of a contact, shown as a new safety number, is treated
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IdentityPolicy {
+    /// The new key is used without telling the user
+    Trust,
+    /// The new key is used and the change is shown in the chat
+    #[default]
+    Warn,
+    /// Like `Warn`, but nothing is sent to the contact until the user verifies the new key
+    Block,
+}
+
+/// Format images are re-encoded to before they are sent
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageEncoding {
+    Jpeg,
+    /// Lossless, the quality is not used
+    Webp,
+}
+
+/// Controls how images are prepared before they are uploaded as attachments
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ImageProcessing {
+    /// Images are sent as they are, metadata included, when turned off
+    pub enabled: bool,
+    /// Longest side of a sent image in pixels, larger images are scaled down
+    pub max_dimension: u32,
+    pub encoding: ImageEncoding,
+    /// Quality of JPEG images, from 1 to 100
+    pub quality: u8,
+}
+
+impl Default for ImageProcessing {
+    fn default() -> Self {
+        ImageProcessing {
+            enabled: true,
+            max_dimension: 2048,
+            encoding: ImageEncoding::Jpeg,
+            quality: 85,
+        }
+    }
+}
+
+impl ImageProcessing {
+    /// Settings of a single send, the options that were given replace the configured ones
+    pub fn overridden(
+        mut self,
+        keep_original: bool,
+        max_dimension: Option<u32>,
+        encoding: Option<ImageEncoding>,
+        quality: Option<u8>,
+    ) -> Self {
+        if keep_original {
+            self.enabled = false;
+        }
+        if let Some(max_dimension) = max_dimension {
+            self.max_dimension = max_dimension;
+        }
+        if let Some(encoding) = encoding {
+            self.encoding = encoding;
+        }
+        if let Some(quality) = quality {
+            self.quality = quality;
+        }
+        self
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before the next attempt of a message that was already tried `attempts` times
+    pub fn delay_after(&self, attempts: u32) -> Duration {
+        let exponent = attempts.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let max_delay = self.max_delay_seconds as f64;
+        let delay = (self.base_delay_seconds as f64
+            * self.backoff_multiplier.max(1.0).powi(exponent))
+        .min(max_delay);
+
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let factor = if jitter > 0.0 {
+            rand::random_range(1.0 - jitter..=1.0 + jitter)
+        } else {
+            1.0
+        };
+
+        Duration::from_secs_f64((delay * factor).min(max_delay))
+    }
 }
 
 impl Default for Config {
@@ -30,6 +179,14 @@ impl Default for Config {
             current_account: None,
             notifications_enabled: true,
             attachment_save_dir: download_dir,
+            retry_policy: RetryPolicy::default(),
+            send_read_receipts: true,
+            send_typing_indicators: true,
+            image_processing: ImageProcessing::default(),
+            default_region: None,
+            identity_policy: IdentityPolicy::default(),
+            profile_cache_ttl_seconds: default_profile_cache_ttl(),
+            purge_deleted_messages: false,
         }
     }
 }
@@ -141,4 +298,14 @@ impl Config {
     pub fn toggle_notifications(&mut self) {
         self.notifications_enabled = !self.notifications_enabled;
     }
+
+    /// Toggle sending read receipts
+    pub fn toggle_send_read_receipts(&mut self) {
+        self.send_read_receipts = !self.send_read_receipts;
+    }
+
+    /// Toggle sending typing indicators
+    pub fn toggle_send_typing_indicators(&mut self) {
+        self.send_typing_indicators = !self.send_typing_indicators;
+    }
 }
@@ -0,0 +1,457 @@
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
+
+use anyhow::{Result, anyhow, bail};
+use futures::{StreamExt, pin_mut};
+use presage::Manager;
+use presage::manager::Registered;
+use presage::model::messages::Received;
+use presage_store_sqlite::SqliteStore;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{Mutex, broadcast, mpsc};
+use tokio::task::{self, JoinHandle, LocalSet};
+use tracing::{Level, debug, error, info, span, trace, warn};
+
+use crate::account_management::{create_registered_manager_for_account, list_accounts};
+use crate::config::Config;
+use crate::identity::IdentityStore;
+use crate::message_requests::{MessageRequests, screen_message};
+use crate::messages::deletion;
+use crate::messages::receipts::{ReceiptStore, extract_receipt, receipt_thread};
+use crate::messages::receive::{MessageDto, format_attachments, format_message};
+use crate::paths;
+use crate::search::SearchIndex;
+use crate::sidecar::AccountFile;
+
+mod rpc;
+
+/// Number of received messages a slow subscriber may fall behind before it starts losing them
+const SUBSCRIPTION_CAPACITY: usize = 256;
+
+/// Maximum number of messages kept for the `receive` method of every account
+const RECEIVED_BUFFER_SIZE: usize = 1000;
+
+/// Message received by one of the accounts served by the daemon
+#[derive(Clone)]
+struct ReceivedMessage {
+    account: String,
+    message: MessageDto,
+}
+
+struct Account {
+    name: String,
+    manager: Manager<SqliteStore, Registered>,
+    /// Messages received since the last call of the `receive` method
+    received: Mutex<Vec<MessageDto>>,
+    receipts: Mutex<ReceiptStore>,
+    requests: Mutex<MessageRequests>,
+    identities: Mutex<IdentityStore>,
+    search_index: Option<SearchIndex>,
+}
+
+struct Daemon {
+    accounts: HashMap<String, Rc<Account>>,
+    default_account: Option<String>,
+    messages: broadcast::Sender<ReceivedMessage>,
+}
+
+impl Daemon {
+    /// Returns account with given name or the default one when no name is given
+    fn account(&self, name: Option<&str>) -> Result<Rc<Account>> {
+        let name = match name.or(self.default_account.as_deref()) {
+            Some(name) => name,
+            None => bail!("Daemon serves multiple accounts, `account` has to be specified"),
+        };
+
+        self.accounts
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow!("Account '{name}' is not served by this daemon"))
+    }
+}
+
+/// State of a single client connected to the socket
+struct Connection {
+    out: mpsc::UnboundedSender<String>,
+    subscriptions: HashMap<u64, JoinHandle<()>>,
+    next_subscription: u64,
+}
+
+impl Connection {
+    /// Starts forwarding received messages of `account` (or all accounts) to the client
+    fn subscribe(&mut self, daemon: &Daemon, account: Option<String>) -> u64 {
+        let id = self.next_subscription;
+        self.next_subscription += 1;
+
+        let mut messages = daemon.messages.subscribe();
+        let out = self.out.clone();
+        let handle = task::spawn_local(async move {
+            loop {
+                match messages.recv().await {
+                    Ok(received) => {
+                        if account
+                            .as_ref()
+                            .is_some_and(|name| *name != received.account)
+                        {
+                            continue;
+                        }
+                        let notification = rpc::message_notification(id, &received);
+                        if out.send(notification).is_err() {
+                            break;
+                        }
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!(
+                            subscription = id,
+                            skipped, "Subscriber is too slow, skipped messages"
+                        );
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+
+        self.subscriptions.insert(id, handle);
+        id
+    }
+
+    fn unsubscribe(&mut self, id: u64) -> bool {
+        match self.subscriptions.remove(&id) {
+            Some(handle) => {
+                handle.abort();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        for handle in self.subscriptions.values() {
+            handle.abort();
+        }
+    }
+}
+
+/// Runs the daemon until it is interrupted, all accounts are served when `account_names` is empty
+pub async fn run_daemon(socket: Option<PathBuf>, account_names: Vec<String>) -> Result<()> {
+    // Manager futures are not `Send`, so everything runs on the current thread
+    LocalSet::new()
+        .run_until(serve(
+            socket.unwrap_or_else(paths::daemon_socket),
+            account_names,
+        ))
+        .await
+}
+
+async fn serve(socket: PathBuf, account_names: Vec<String>) -> Result<()> {
+    let account_names = if account_names.is_empty() {
+        list_accounts()?
+    } else {
+        account_names
+    };
+    if account_names.is_empty() {
+        bail!("There are no accounts to serve, link an account first");
+    }
+
+    let mut accounts = HashMap::new();
+    for name in account_names {
+        let manager = create_registered_manager_for_account(&name)
+            .await
+            .map_err(|e| anyhow!("Failed to load account '{name}': {e}"))?;
+        let receipts = ReceiptStore::load_for_account(&name)
+            .map_err(|e| anyhow!("Failed to load receipts of account '{name}': {e}"))?;
+        let requests = MessageRequests::load_for_account(&name)
+            .map_err(|e| anyhow!("Failed to load message requests of account '{name}': {e}"))?;
+        let identities = IdentityStore::load_for_account(&name)
+            .map_err(|e| anyhow!("Failed to load identities of account '{name}': {e}"))?;
+        let search_index = match SearchIndex::open_for_account(&name).await {
+            Ok(search_index) => Some(search_index),
+            Err(error) => {
+                error!(%error, account = name, "Failed to open search index");
+                None
+            }
+        };
+        let account = Account {
+            name: name.clone(),
+            manager,
+            received: Mutex::new(Vec::new()),
+            receipts: Mutex::new(receipts),
+            requests: Mutex::new(requests),
+            identities: Mutex::new(identities),
+            search_index,
+        };
+        accounts.insert(name, Rc::new(account));
+    }
+
+    let default_account = match Config::load().get_current_account() {
+        Some(current) if accounts.contains_key(current) => Some(current.clone()),
+        _ if accounts.len() == 1 => accounts.keys().next().cloned(),
+        _ => None,
+    };
+
+    let (tx_messages, _) = broadcast::channel(SUBSCRIPTION_CAPACITY);
+    for account in accounts.values() {
+        task::spawn_local(receive_loop(account.clone(), tx_messages.clone()));
+    }
+
+    let daemon = Rc::new(Daemon {
+        accounts,
+        default_account,
+        messages: tx_messages,
+    });
+
+    // Service managers stop the daemon with SIGTERM rather than SIGINT
+    let mut terminate = signal(SignalKind::terminate())?;
+    let listener = bind_socket(&socket).await?;
+    println!("Listening on {}", socket.display());
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    task::spawn_local(handle_connection(stream, daemon.clone()));
+                }
+                Err(error) => error!(%error, "Failed to accept connection"),
+            },
+            _ = tokio::signal::ctrl_c() => {
+                info!("Shutting down daemon");
+                break;
+            }
+            _ = terminate.recv() => {
+                info!("Shutting down daemon on SIGTERM");
+                break;
+            }
+        }
+    }
+
+    fs::remove_file(&socket)?;
+    Ok(())
+}
+
+async fn bind_socket(socket: &PathBuf) -> Result<UnixListener> {
+    let parent = match socket.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(parent)?;
+    // Other users could replace the socket with their own in such a directory
+    if fs::metadata(parent)?.permissions().mode() & 0o022 != 0 {
+        bail!(
+            "Directory {} of the socket is writable by other users, choose another one",
+            parent.display()
+        );
+    }
+
+    if socket.exists() {
+        // A socket left behind by a daemon that crashed can't be connected to
+        if UnixStream::connect(socket).await.is_ok() {
+            bail!("Daemon is already running on {}", socket.display());
+        }
+        fs::remove_file(socket)?;
+    }
+
+    let listener = UnixListener::bind(socket)?;
+    // Clients can send messages as any served account, so only the user may connect
+    fs::set_permissions(socket, fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Keeps receiving messages for the account and passes them to subscribers
+async fn receive_loop(account: Rc<Account>, tx: broadcast::Sender<ReceivedMessage>) {
+    let _receiving_span = span!(Level::TRACE, "Daemon receiving loop").entered();
+    let mut manager = account.manager.clone();
+    let mut initialized = false;
+    let purge_deleted = Config::load().purge_deleted_messages;
+
+    // Messages stored while the daemon wasn't running are indexed first
+    if let Some(search_index) = &account.search_index {
+        let senders = account.requests.lock().await.senders();
+        if let Err(error) = search_index.catch_up(&manager, &senders).await {
+            error!(%error, "Failed to index stored messages");
+        }
+    }
+
+    info!(account = account.name, "Start receiving messages");
+    loop {
+        let messages_stream = match manager.receive_messages().await {
+            Ok(messages_stream) => messages_stream,
+            Err(e) => {
+                error!(error = %e, "Stream failed, retry in 3 seconds");
+                tokio::time::sleep(Duration::from_secs(3)).await;
+                continue;
+            }
+        };
+
+        pin_mut!(messages_stream);
+        while let Some(received) = messages_stream.next().await {
+            match received {
+                Received::QueueEmpty => {
+                    debug!("Received queue empty");
+                    if !initialized {
+                        match manager.request_contacts().await {
+                            Ok(_) => info!("Synchronized contacts."),
+                            Err(e) => error!(error = %e, "Failed to synchronize contacts"),
+                        }
+                        initialized = true;
+                    }
+                }
+                Received::Contacts => {
+                    debug!("Received contact");
+                }
+                Received::Content(content) => {
+                    trace!("Received message: {content:#?}");
+
+                    if let Some(receipt) = extract_receipt(&content)
+                        && let Some(thread) = receipt_thread(&manager, &receipt).await
+                    {
+                        account.receipts.lock().await.record(&thread, &receipt);
+                    }
+
+                    // Messages of blocked senders are dropped
+                    let screened =
+                        screen_message(&manager, &mut *account.requests.lock().await, &content)
+                            .await;
+                    match screened {
+                        Ok(true) => {}
+                        Ok(false) => continue,
+                        Err(error) => error!(%error, "Failed to screen message request"),
+                    }
+
+                    if purge_deleted
+                        && let Err(error) =
+                            deletion::purge_deleted_message(&manager, &content).await
+                    {
+                        error!(%error, "Failed to purge message deleted by its author");
+                    }
+
+                    if let Some(search_index) = &account.search_index
+                        && let Err(error) = search_index.index_content(&content).await
+                    {
+                        error!(%error, "Failed to index received message");
+                    }
+
+                    let messages = format_message(&content)
+                        .into_iter()
+                        .chain(format_attachments(&content));
+
+                    let mut received = account.received.lock().await;
+                    for message in messages {
+                        received.push(message.clone());
+                        // There are no subscribers if sending fails, which is fine
+                        let _ = tx.send(ReceivedMessage {
+                            account: account.name.clone(),
+                            message,
+                        });
+                    }
+                    if received.len() > RECEIVED_BUFFER_SIZE {
+                        let overflow = received.len() - RECEIVED_BUFFER_SIZE;
+                        received.drain(..overflow);
+                    }
+                }
+            }
+        }
+
+        error!("Lost connection to stream, reconnecting in 3 seconds");
+        tokio::time::sleep(Duration::from_secs(3)).await;
+    }
+}
+
+/// Reads requests from the client line by line and writes back responses and notifications
+async fn handle_connection(stream: UnixStream, daemon: Rc<Daemon>) {
+    let (reader, mut writer) = stream.into_split();
+    let (tx_out, mut rx_out) = mpsc::unbounded_channel::<String>();
+
+    let writer_task = task::spawn_local(async move {
+        while let Some(line) = rx_out.recv().await {
+            if let Err(error) = writer.write_all(format!("{line}\n").as_bytes()).await {
+                debug!(%error, "Client disconnected");
+                break;
+            }
+        }
+    });
+
+    let mut connection = Connection {
+        out: tx_out,
+        subscriptions: HashMap::new(),
+        next_subscription: 1,
+    };
+
+    let mut lines = BufReader::new(reader).lines();
+    loop {
+        match lines.next_line().await {
+            Ok(Some(line)) if line.trim().is_empty() => {}
+            Ok(Some(line)) => {
+                if let Some(response) = rpc::handle_request(&line, &daemon, &mut connection).await
+                    && connection.out.send(response).is_err()
+                {
+                    break;
+                }
+            }
+            Ok(None) => break,
+            Err(error) => {
+                warn!(%error, "Failed to read from client");
+                break;
+            }
+        }
+    }
+
+    // Dropping the connection stops its subscriptions and closes the writer channel
+    drop(connection);
+    let _ = writer_task.await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn stale_socket_is_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("daemon.sock");
+        // Dropping the listener leaves the socket file behind, as a crash would
+        drop(UnixListener::bind(&socket).unwrap());
+        assert!(socket.exists());
+
+        let listener = bind_socket(&socket).await.unwrap();
+        assert!(UnixStream::connect(&socket).await.is_ok());
+        drop(listener);
+    }
+
+    #[tokio::test]
+    async fn running_daemon_is_not_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("daemon.sock");
+        let _listener = UnixListener::bind(&socket).unwrap();
+
+        assert!(bind_socket(&socket).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn socket_is_only_accessible_by_the_user() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("daemon.sock");
+
+        let _listener = bind_socket(&socket).await.unwrap();
+        let mode = fs::metadata(&socket).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[tokio::test]
+    async fn shared_directory_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o775)).unwrap();
+        let socket = dir.path().join("daemon.sock");
+
+        assert!(bind_socket(&socket).await.is_err());
+        assert!(!socket.exists());
+    }
+}
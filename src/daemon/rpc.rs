@@ -0,0 +1,622 @@
+use std::mem;
+
+use anyhow::{anyhow, bail};
+use presage::Manager;
+use presage::manager::Registered;
+use presage::store::{ContentsStore, Thread};
+use presage_store_sqlite::SqliteStore;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use super::{Account, Connection, Daemon, ReceivedMessage};
+use crate::app::RecipientId;
+use crate::groups::find_master_key_exact;
+use crate::messages::attachments::AttachmentInput;
+use crate::messages::expiry;
+use crate::messages::formatting::escape_markdown;
+use crate::messages::mentions::resolve_mentions;
+use crate::messages::receive::{self, MessageDto, format_message, get_messages_as_message_dto};
+use crate::messages::send;
+use crate::output::{ContactOutput, GroupOutput};
+use crate::recipients::resolve_recipient;
+use crate::search::index_sent;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Used for all errors reported while executing a method
+const SERVER_ERROR: i64 = -32000;
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Serialize)]
+struct Response {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+}
+
+#[derive(Serialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(error: anyhow::Error) -> Self {
+        Self::new(SERVER_ERROR, error.to_string())
+    }
+}
+
+type RpcResult = Result<Value, RpcError>;
+
+/// Handles a single line sent by the client, returns the serialized response if one is expected
+pub(super) async fn handle_request(
+    line: &str,
+    daemon: &Daemon,
+    connection: &mut Connection,
+) -> Option<String> {
+    let request: Request = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => {
+            let error = RpcError::new(PARSE_ERROR, format!("Invalid request: {e}"));
+            return Some(serialize_response(Value::Null, Err(error)));
+        }
+    };
+
+    let result = if request.jsonrpc != "2.0" {
+        Err(RpcError::new(
+            INVALID_REQUEST,
+            "Only JSON-RPC 2.0 is supported",
+        ))
+    } else {
+        call_method(&request.method, request.params, daemon, connection).await
+    };
+
+    // Requests without id are notifications and don't get any response
+    request.id.map(|id| serialize_response(id, result))
+}
+
+/// Notification sent to subscribed clients for every received message
+pub(super) fn message_notification(subscription: u64, received: &ReceivedMessage) -> String {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "receive",
+        "params": {
+            "subscription": subscription,
+            "account": received.account,
+            "message": message_to_json(&received.message),
+        },
+    })
+    .to_string()
+}
+
+fn serialize_response(id: Value, result: RpcResult) -> String {
+    let (result, error) = match result {
+        Ok(value) => (Some(value), None),
+        Err(error) => (None, Some(error)),
+    };
+    let response = Response {
+        jsonrpc: "2.0",
+        id,
+        result,
+        error,
+    };
+    serde_json::to_string(&response).expect("Response is always serializable")
+}
+
+async fn call_method(
+    method: &str,
+    params: Value,
+    daemon: &Daemon,
+    connection: &mut Connection,
+) -> RpcResult {
+    match method {
+        "send" => send_message(parse_params(params)?, daemon).await,
+        "receive" => receive_messages(parse_params(params)?, daemon).await,
+        "list-messages" => list_messages(parse_params(params)?, daemon).await,
+        "list-contacts" => list_contacts(parse_params(params)?, daemon).await,
+        "list-groups" => list_groups(parse_params(params)?, daemon).await,
+        "react" => react_to_message(parse_params(params)?, daemon).await,
+        "delete" => delete_message(parse_params(params)?, daemon).await,
+        "subscribe" => {
+            let params: AccountParams = parse_params(params)?;
+            if let Some(account) = &params.account {
+                daemon.account(Some(account))?;
+            }
+            Ok(json!(connection.subscribe(daemon, params.account)))
+        }
+        "unsubscribe" => {
+            let params: UnsubscribeParams = parse_params(params)?;
+            Ok(json!(connection.unsubscribe(params.subscription)))
+        }
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("Unknown method '{method}'"),
+        )),
+    }
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    let params = if params.is_null() { json!({}) } else { params };
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+}
+
+#[derive(Deserialize)]
+struct AccountParams {
+    account: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct UnsubscribeParams {
+    subscription: u64,
+}
+
+/// Conversation addressed either by contact (name, phone number or UUID) or by group name
+#[derive(Deserialize)]
+struct ConversationParams {
+    contact: Option<String>,
+    group: Option<String>,
+}
+
+impl ConversationParams {
+    async fn resolve(
+        &self,
+        account: &Account,
+        manager: &mut Manager<SqliteStore, Registered>,
+    ) -> Result<RecipientId, RpcError> {
+        match (&self.contact, &self.group) {
+            (Some(contact), None) => {
+                let accepted = account.requests.lock().await.accepted().collect::<Vec<_>>();
+                Ok(RecipientId::Contact(
+                    resolve_recipient(contact, manager, &accepted, false).await?,
+                ))
+            }
+            (None, Some(group)) => match find_master_key_exact(group.clone(), manager).await? {
+                Some(master_key) => Ok(RecipientId::Group(master_key)),
+                None => Err(anyhow!("Group '{group}' not found").into()),
+            },
+            _ => Err(RpcError::new(
+                INVALID_PARAMS,
+                "Exactly one of `contact` and `group` has to be given",
+            )),
+        }
+    }
+}
+
+fn thread(recipient: &RecipientId) -> Thread {
+    match recipient {
+        RecipientId::Contact(uuid) => Thread::Contact(*uuid),
+        RecipientId::Group(master_key) => Thread::Group(*master_key),
+    }
+}
+
+async fn stored_message(
+    manager: &Manager<SqliteStore, Registered>,
+    recipient: &RecipientId,
+    timestamp: u64,
+) -> anyhow::Result<MessageDto> {
+    let content = match manager
+        .store()
+        .message(&thread(recipient), timestamp)
+        .await?
+    {
+        Some(content) => content,
+        None => bail!("Message with given timestamp not found."),
+    };
+    format_message(&content).ok_or_else(|| anyhow!("Could not format message."))
+}
+
+#[derive(Deserialize)]
+struct SendParams {
+    account: Option<String>,
+    #[serde(flatten)]
+    conversation: ConversationParams,
+    #[serde(default)]
+    message: String,
+    attachment: Option<String>,
+    /// Several attachments sent in one message, each with an optional caption
+    #[serde(default)]
+    attachments: Vec<AttachmentInput>,
+    quote: Option<u64>,
+    /// Whether markdown-like syntax in `message` is turned into text styles
+    #[serde(default)]
+    markdown: bool,
+}
+
+async fn send_message(params: SendParams, daemon: &Daemon) -> RpcResult {
+    let mut attachments = params.attachments;
+    if let Some(path) = params.attachment {
+        attachments.insert(0, AttachmentInput::new(path));
+    }
+    if params.message.is_empty() && attachments.is_empty() {
+        return Err(RpcError::new(
+            INVALID_PARAMS,
+            "Either `message` or `attachment` has to be given",
+        ));
+    }
+
+    let message = match params.markdown {
+        true => params.message,
+        false => escape_markdown(&params.message),
+    };
+
+    let account = daemon.account(params.account.as_deref())?;
+    let mut manager = account.manager.clone();
+    let recipient = params.conversation.resolve(&account, &mut manager).await?;
+    let quoted_message = match params.quote {
+        Some(timestamp) => Some(stored_message(&manager, &recipient, timestamp).await?),
+        None => None,
+    };
+    let sent_to = thread(&recipient);
+
+    match (recipient, !attachments.is_empty()) {
+        (RecipientId::Contact(uuid), false) => {
+            send::contact::send_message_tui(
+                uuid.to_string(),
+                message,
+                quoted_message,
+                manager.clone(),
+                &account.identities,
+            )
+            .await?
+        }
+        (RecipientId::Contact(uuid), true) => {
+            send::contact::send_attachment_tui(
+                uuid.to_string(),
+                message,
+                attachments,
+                quoted_message,
+                manager.clone(),
+                &account.identities,
+            )
+            .await?
+        }
+        (RecipientId::Group(master_key), false) => {
+            send::group::send_message_tui(
+                master_key,
+                message,
+                manager.clone(),
+                &account.identities,
+                quoted_message,
+            )
+            .await?
+        }
+        (RecipientId::Group(master_key), true) => {
+            send::group::send_attachment_tui(
+                &master_key,
+                message,
+                attachments,
+                quoted_message,
+                manager.clone(),
+                &account.identities,
+            )
+            .await?
+        }
+    }
+    index_sent(account.search_index.as_ref(), &manager, &sent_to).await;
+
+    Ok(Value::Null)
+}
+
+async fn receive_messages(params: AccountParams, daemon: &Daemon) -> RpcResult {
+    let account = daemon.account(params.account.as_deref())?;
+    let messages = mem::take(&mut *account.received.lock().await);
+
+    Ok(Value::Array(messages.iter().map(message_to_json).collect()))
+}
+
+#[derive(Deserialize)]
+struct ListMessagesParams {
+    account: Option<String>,
+    #[serde(flatten)]
+    conversation: ConversationParams,
+    from: Option<u64>,
+}
+
+async fn list_messages(params: ListMessagesParams, daemon: &Daemon) -> RpcResult {
+    let account = daemon.account(params.account.as_deref())?;
+    let mut manager = account.manager.clone();
+    let from = params.from.map(|from| from.to_string());
+
+    let recipient = params.conversation.resolve(&account, &mut manager).await?;
+    let contents = match &recipient {
+        RecipientId::Contact(uuid) => {
+            receive::contact::list_messages(&manager, uuid.to_string(), from).await?
+        }
+        RecipientId::Group(master_key) => {
+            receive::group::list_messages(&manager, *master_key, from).await?
+        }
+    };
+
+    let mut messages = get_messages_as_message_dto(contents)?;
+    expiry::retain_live(&mut messages);
+    resolve_mentions(&manager, &mut messages).await;
+    account
+        .receipts
+        .lock()
+        .await
+        .apply(&thread(&recipient), &mut messages);
+    // Messages are returned from the oldest to the latest
+    messages.reverse();
+
+    Ok(Value::Array(messages.iter().map(message_to_json).collect()))
+}
+
+async fn list_contacts(params: AccountParams, daemon: &Daemon) -> RpcResult {
+    let account = daemon.account(params.account.as_deref())?;
+    let contacts = account
+        .manager
+        .store()
+        .contacts()
+        .await
+        .map_err(anyhow::Error::new)?
+        .flatten()
+        .map(|contact| json!(ContactOutput::from(&contact)))
+        .collect();
+
+    Ok(Value::Array(contacts))
+}
+
+async fn list_groups(params: AccountParams, daemon: &Daemon) -> RpcResult {
+    let account = daemon.account(params.account.as_deref())?;
+    let groups = account
+        .manager
+        .store()
+        .groups()
+        .await
+        .map_err(anyhow::Error::new)?
+        .flatten()
+        .map(|(master_key, group)| json!(GroupOutput::from((&master_key, &group))))
+        .collect();
+
+    Ok(Value::Array(groups))
+}
+
+#[derive(Deserialize)]
+struct ReactParams {
+    account: Option<String>,
+    #[serde(flatten)]
+    conversation: ConversationParams,
+    timestamp: u64,
+    #[serde(default = "default_reaction")]
+    emoji: String,
+    #[serde(default)]
+    remove: bool,
+}
+
+fn default_reaction() -> String {
+    "👍".to_string()
+}
+
+async fn react_to_message(params: ReactParams, daemon: &Daemon) -> RpcResult {
+    let account = daemon.account(params.account.as_deref())?;
+    let mut manager = account.manager.clone();
+    let recipient = params.conversation.resolve(&account, &mut manager).await?;
+    let target = stored_message(&manager, &recipient, params.timestamp).await?;
+    let target_author_aci = target.uuid.to_string();
+
+    match recipient {
+        RecipientId::Contact(uuid) => {
+            send::contact::send_reaction_message_tui(
+                manager,
+                &account.identities,
+                uuid.to_string(),
+                params.timestamp,
+                target_author_aci,
+                params.remove,
+                params.emoji,
+            )
+            .await?
+        }
+        RecipientId::Group(master_key) => {
+            send::group::send_reaction_message_tui(
+                manager,
+                &account.identities,
+                &master_key,
+                params.timestamp,
+                target_author_aci,
+                params.remove,
+                params.emoji,
+            )
+            .await?
+        }
+    }
+
+    Ok(Value::Null)
+}
+
+#[derive(Deserialize)]
+struct DeleteParams {
+    account: Option<String>,
+    #[serde(flatten)]
+    conversation: ConversationParams,
+    timestamp: u64,
+}
+
+async fn delete_message(params: DeleteParams, daemon: &Daemon) -> RpcResult {
+    let account = daemon.account(params.account.as_deref())?;
+    let mut manager = account.manager.clone();
+    let recipient = params.conversation.resolve(&account, &mut manager).await?;
+    let target = stored_message(&manager, &recipient, params.timestamp).await?;
+
+    if target.uuid != manager.registration_data().service_ids.aci {
+        return Err(anyhow!("Cannot delete message not sent by this account").into());
+    }
+
+    let sent_to = thread(&recipient);
+    match recipient {
+        RecipientId::Contact(uuid) => {
+            send::contact::send_delete_message_tui(
+                manager.clone(),
+                &account.identities,
+                uuid.to_string(),
+                params.timestamp,
+            )
+            .await?
+        }
+        RecipientId::Group(master_key) => {
+            send::group::send_delete_message_tui(
+                master_key,
+                manager.clone(),
+                &account.identities,
+                params.timestamp,
+            )
+            .await?
+        }
+    }
+    index_sent(account.search_index.as_ref(), &manager, &sent_to).await;
+
+    Ok(Value::Null)
+}
+
+fn message_to_json(message: &MessageDto) -> Value {
+    serde_json::to_value(message).expect("Message is always serializable")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use tokio::sync::{broadcast, mpsc};
+    use tokio::task::LocalSet;
+
+    use super::*;
+
+    fn daemon() -> Daemon {
+        Daemon {
+            accounts: HashMap::new(),
+            default_account: None,
+            messages: broadcast::channel(1).0,
+        }
+    }
+
+    fn connection() -> Connection {
+        Connection {
+            out: mpsc::unbounded_channel().0,
+            subscriptions: HashMap::new(),
+            next_subscription: 0,
+        }
+    }
+
+    async fn call(line: &str, daemon: &Daemon, connection: &mut Connection) -> Value {
+        let response = handle_request(line, daemon, connection).await.unwrap();
+        serde_json::from_str(&response).unwrap()
+    }
+
+    fn request(method: &str, params: Value) -> String {
+        json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params}).to_string()
+    }
+
+    #[tokio::test]
+    async fn malformed_requests_are_rejected() {
+        let (daemon, mut connection) = (daemon(), connection());
+
+        let response = call("{", &daemon, &mut connection).await;
+        assert_eq!(response["error"]["code"], PARSE_ERROR);
+        assert_eq!(response["id"], Value::Null);
+
+        let line = r#"{"jsonrpc":"1.0","id":7,"method":"receive"}"#;
+        let response = call(line, &daemon, &mut connection).await;
+        assert_eq!(response["error"]["code"], INVALID_REQUEST);
+        assert_eq!(response["id"], 7);
+    }
+
+    #[tokio::test]
+    async fn unknown_method_is_reported() {
+        let (daemon, mut connection) = (daemon(), connection());
+        let response = call(&request("edit", json!({})), &daemon, &mut connection).await;
+        assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn notifications_get_no_response() {
+        let (daemon, mut connection) = (daemon(), connection());
+        let line = r#"{"jsonrpc":"2.0","method":"edit"}"#;
+        assert!(
+            handle_request(line, &daemon, &mut connection)
+                .await
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn params_are_checked_before_the_account() {
+        let (daemon, mut connection) = (daemon(), connection());
+        let params = json!({"contact": "Alice", "timestamp": "yesterday"});
+        let response = call(&request("delete", params), &daemon, &mut connection).await;
+        assert_eq!(response["error"]["code"], INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn every_method_needs_an_account() {
+        let (daemon, mut connection) = (daemon(), connection());
+        let methods = [
+            ("send", json!({"contact": "Alice", "message": "Hi"})),
+            ("receive", Value::Null),
+            ("list-messages", json!({"contact": "Alice"})),
+            ("list-contacts", Value::Null),
+            ("list-groups", json!({})),
+            ("react", json!({"contact": "Alice", "timestamp": 1})),
+            ("delete", json!({"group": "Friends", "timestamp": 1})),
+        ];
+        for (method, params) in methods {
+            let response = call(&request(method, params), &daemon, &mut connection).await;
+            assert_eq!(response["error"]["code"], SERVER_ERROR, "{method}");
+        }
+
+        let params = json!({"account": "work"});
+        let response = call(&request("receive", params), &daemon, &mut connection).await;
+        assert_eq!(
+            response["error"]["message"],
+            "Account 'work' is not served by this daemon"
+        );
+    }
+
+    #[tokio::test]
+    async fn subscriptions_can_be_cancelled_once() {
+        LocalSet::new()
+            .run_until(async {
+                let (daemon, mut connection) = (daemon(), connection());
+
+                let response = call(&request("subscribe", Value::Null), &daemon, &mut connection);
+                assert_eq!(response.await["result"], 0);
+
+                let params = json!({"subscription": 0});
+                let response = call(
+                    &request("unsubscribe", params.clone()),
+                    &daemon,
+                    &mut connection,
+                );
+                assert_eq!(response.await["result"], true);
+                let response = call(&request("unsubscribe", params), &daemon, &mut connection);
+                assert_eq!(response.await["result"], false);
+
+                let params = json!({"account": "work"});
+                let response = call(&request("subscribe", params), &daemon, &mut connection);
+                assert_eq!(response.await["error"]["code"], SERVER_ERROR);
+            })
+            .await;
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::paths;
+use crate::sidecar::{AccountFile, JsonFile};
+
+/// Emojis the picker knows by shortcode, in the order they are offered
+pub const SHORTCODES: &[(&str, &str)] = &[
+    ("thumbsup", "👍"),
+    ("thumbsdown", "👎"),
+    ("heart", "❤️"),
+    ("joy", "😂"),
+    ("open_mouth", "😮"),
+    ("cry", "😢"),
+    ("rage", "😡"),
+    ("smile", "😄"),
+    ("grin", "😁"),
+    ("wink", "😉"),
+    ("blush", "😊"),
+    ("heart_eyes", "😍"),
+    ("kissing_heart", "😘"),
+    ("thinking", "🤔"),
+    ("rofl", "🤣"),
+    ("sweat_smile", "😅"),
+    ("slightly_smiling_face", "🙂"),
+    ("upside_down_face", "🙃"),
+    ("neutral_face", "😐"),
+    ("unamused", "😒"),
+    ("roll_eyes", "🙄"),
+    ("grimacing", "😬"),
+    ("relieved", "😌"),
+    ("pensive", "😔"),
+    ("sleepy", "😪"),
+    ("sleeping", "😴"),
+    ("sunglasses", "😎"),
+    ("nerd", "🤓"),
+    ("confused", "😕"),
+    ("worried", "😟"),
+    ("scream", "😱"),
+    ("sob", "😭"),
+    ("angry", "😠"),
+    ("skull", "💀"),
+    ("poop", "💩"),
+    ("clown", "🤡"),
+    ("ghost", "👻"),
+    ("robot", "🤖"),
+    ("see_no_evil", "🙈"),
+    ("wave", "👋"),
+    ("ok_hand", "👌"),
+    ("v", "✌️"),
+    ("crossed_fingers", "🤞"),
+    ("muscle", "💪"),
+    ("pray", "🙏"),
+    ("clap", "👏"),
+    ("raised_hands", "🙌"),
+    ("point_up", "☝️"),
+    ("facepalm", "🤦"),
+    ("shrug", "🤷"),
+    ("eyes", "👀"),
+    ("fire", "🔥"),
+    ("sparkles", "✨"),
+    ("star", "⭐"),
+    ("tada", "🎉"),
+    ("100", "💯"),
+    ("check", "✅"),
+    ("x", "❌"),
+    ("question", "❓"),
+    ("exclamation", "❗"),
+    ("warning", "⚠️"),
+    ("broken_heart", "💔"),
+    ("orange_heart", "🧡"),
+    ("yellow_heart", "💛"),
+    ("green_heart", "💚"),
+    ("blue_heart", "💙"),
+    ("purple_heart", "💜"),
+    ("rocket", "🚀"),
+    ("beer", "🍺"),
+    ("coffee", "☕"),
+    ("pizza", "🍕"),
+    ("cake", "🎂"),
+    ("gift", "🎁"),
+    ("sun", "☀️"),
+    ("rainbow", "🌈"),
+    ("cat", "🐱"),
+    ("dog", "🐶"),
+];
+
+/// Emojis shown in the picker before the user reacted with anything
+const DEFAULT_EMOJIS: &[&str] = &["👍", "❤️", "😂", "😮", "😢", "😡"];
+
+/// How many recently used emojis are remembered
+const RECENT_LIMIT: usize = 8;
+
+/// Turns the value given by the user into an emoji, either an emoji itself or a shortcode
+/// with optional colons, e.g. `:heart:`
+pub fn resolve(value: &str) -> Result<String> {
+    let value = value.trim();
+    if value.is_empty() {
+        bail!("Emoji can't be empty");
+    }
+
+    let shortcode = value.trim_matches(':');
+    if let Some((_, emoji)) = SHORTCODES.iter().find(|(code, _)| *code == shortcode) {
+        return Ok(emoji.to_string());
+    }
+    if value.chars().any(|c| c.is_ascii_alphanumeric()) {
+        bail!("Unknown emoji shortcode '{value}'");
+    }
+    Ok(value.to_string())
+}
+
+/// Emojis whose shortcode contains the query, those starting with it come first
+pub fn search(query: &str) -> Vec<&'static str> {
+    let query = query.trim().trim_matches(':').to_lowercase();
+    let (mut prefixed, contained): (Vec<_>, Vec<_>) = SHORTCODES
+        .iter()
+        .copied()
+        .filter(|(code, _)| code.contains(&query))
+        .partition(|(code, _)| code.starts_with(&query));
+    prefixed.extend(contained);
+    prefixed.into_iter().map(|(_, emoji)| emoji).collect()
+}
+
+/// Shortcode of the emoji, if the picker knows it
+pub fn shortcode(emoji: &str) -> Option<&'static str> {
+    SHORTCODES
+        .iter()
+        .find(|(_, known)| *known == emoji)
+        .map(|(code, _)| *code)
+}
+
+/// Emojis used for reactions, persisted per account to offer them first in the picker
+#[derive(Default, Serialize, Deserialize)]
+pub struct EmojiUsage {
+    /// Most recently used first
+    recent: Vec<String>,
+    counts: HashMap<String, u32>,
+    #[serde(skip)]
+    file: JsonFile,
+}
+
+impl AccountFile for EmojiUsage {
+    const NAME: &'static str = "emoji usage";
+
+    fn path(account_name: &str) -> PathBuf {
+        paths::account_emoji_usage_path(account_name)
+    }
+
+    fn file_mut(&mut self) -> &mut JsonFile {
+        &mut self.file
+    }
+}
+
+impl EmojiUsage {
+    pub fn record(&mut self, emoji: &str) {
+        self.recent.retain(|recent| recent != emoji);
+        self.recent.insert(0, emoji.to_string());
+        self.recent.truncate(RECENT_LIMIT);
+        *self.counts.entry(emoji.to_string()).or_default() += 1;
+        self.persist();
+    }
+
+    /// Recent emojis followed by the most frequent ones, without duplicates
+    pub fn suggestions(&self) -> Vec<String> {
+        let mut frequent = self.counts.iter().collect::<Vec<_>>();
+        frequent.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+
+        let mut suggestions: Vec<String> = Vec::new();
+        let candidates = self
+            .recent
+            .iter()
+            .map(String::as_str)
+            .chain(frequent.into_iter().map(|(emoji, _)| emoji.as_str()))
+            .chain(DEFAULT_EMOJIS.iter().copied());
+        for emoji in candidates {
+            if !suggestions.iter().any(|known| known == emoji) {
+                suggestions.push(emoji.to_string());
+            }
+        }
+        suggestions
+    }
+
+    fn persist(&self) {
+        self.file.save(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shortcodes_are_resolved_with_or_without_colons() {
+        assert_eq!(resolve("heart").unwrap(), "❤️");
+        assert_eq!(resolve(" :tada: ").unwrap(), "🎉");
+        assert_eq!(resolve(":100:").unwrap(), "💯");
+    }
+
+    #[test]
+    fn emojis_are_taken_as_they_are() {
+        assert_eq!(resolve("👍").unwrap(), "👍");
+        assert_eq!(resolve("🦀").unwrap(), "🦀");
+    }
+
+    #[test]
+    fn unknown_shortcodes_and_empty_values_fail() {
+        assert!(resolve("crab").is_err());
+        assert!(resolve(":no_such_emoji:").is_err());
+        assert!(resolve("  ").is_err());
+    }
+
+    #[test]
+    fn search_prefers_shortcodes_starting_with_the_query() {
+        let found = search(":heart");
+        assert_eq!(found[0], "❤️");
+        assert!(found.contains(&"💔"));
+        assert_eq!(shortcode("💔"), Some("broken_heart"));
+        assert_eq!(shortcode("🦀"), None);
+    }
+
+    #[test]
+    fn recent_emojis_come_before_frequent_ones() {
+        let mut usage = EmojiUsage::default();
+        usage.record("🎉");
+        usage.record("🎉");
+        usage.record("🔥");
+
+        let suggestions = usage.suggestions();
+        assert_eq!(suggestions[..3], ["🔥", "🎉", "👍"]);
+        assert_eq!(suggestions.len(), 2 + DEFAULT_EMOJIS.len());
+    }
+}
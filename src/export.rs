@@ -0,0 +1,658 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow, bail};
+use presage::Manager;
+use presage::libsignal_service::prelude::Uuid;
+use presage::manager::Registered;
+use presage::store::{ContentsStore, Thread};
+use presage_store_sqlite::SqliteStore;
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tracing::warn;
+
+use crate::account_management::create_registered_manager;
+use crate::app::contact_to_display_contact;
+use crate::args::ExportFormat;
+use crate::groups::find_master_key;
+use crate::messages::attachments::save_attachment;
+use crate::messages::expiry::{self, ExpiryIndex};
+use crate::messages::mentions::resolve_mentions;
+use crate::messages::receive::{MessageDto, get_messages_as_message_dto};
+use crate::messages::send::contact::find_uuid;
+use crate::output::datetime;
+use crate::profile_cache::{self, ProfileCache};
+use crate::sidecar::AccountFile;
+use crate::ui::utils::get_local_timestamp;
+
+#[derive(Serialize)]
+struct ExportedConversation {
+    title: String,
+    contact: Option<Uuid>,
+    group_master_key: Option<String>,
+    exported_at: Option<String>,
+    messages: Vec<ExportedMessage>,
+}
+
+#[derive(Serialize)]
+struct ExportedMessage {
+    timestamp: u64,
+    datetime: Option<String>,
+    sender: Uuid,
+    sender_name: String,
+    outgoing: bool,
+    text: String,
+    /// The author deleted the message for everyone, `text` is only a tombstone
+    deleted: bool,
+    edited_at: Option<u64>,
+    edit_history: Vec<ExportedEdit>,
+    quote: Option<ExportedQuote>,
+    reactions: Vec<ExportedReaction>,
+    attachment: Option<ExportedAttachment>,
+}
+
+/// Earlier version of the text of an edited message
+#[derive(Serialize)]
+struct ExportedEdit {
+    timestamp: u64,
+    datetime: Option<String>,
+    text: String,
+}
+
+#[derive(Serialize)]
+struct ExportedQuote {
+    timestamp: u64,
+    author: Option<String>,
+    author_name: Option<String>,
+    text: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ExportedReaction {
+    author: Uuid,
+    author_name: String,
+    emoji: String,
+}
+
+#[derive(Serialize)]
+struct ExportedAttachment {
+    file_name: Option<String>,
+    content_type: Option<String>,
+    size: Option<u32>,
+    /// Path of the downloaded file relative to the export, `None` if it wasn't downloaded
+    path: Option<String>,
+}
+
+/// Names of the people taking part in the conversation as they are shown in the app
+struct SenderNames {
+    names: HashMap<Uuid, String>,
+}
+
+impl SenderNames {
+    /// Names of the contact of the conversation and of everyone taking part in the messages,
+    /// people who aren't contacts are named after their profiles like in the app
+    async fn load(
+        manager: &Manager<SqliteStore, Registered>,
+        thread: &Thread,
+        messages: &[MessageDto],
+    ) -> Result<Self> {
+        let profiles = Mutex::new(ProfileCache::load_current().unwrap_or_else(|error| {
+            warn!(%error, "Failed to load cached profiles");
+            ProfileCache::default()
+        }));
+
+        let mut participants = HashSet::new();
+        if let Thread::Contact(uuid) = thread {
+            participants.insert(*uuid);
+        }
+        for message in messages {
+            if !message.sender {
+                participants.insert(message.uuid);
+            }
+            participants.extend(message.reactions.keys());
+            participants.extend(
+                message
+                    .quote
+                    .as_ref()
+                    .and_then(|quote| quote.author_aci.as_deref())
+                    .and_then(|author| Uuid::parse_str(author).ok()),
+            );
+        }
+
+        let mut names = HashMap::new();
+        for uuid in participants {
+            let name = match manager.store().contact_by_id(&uuid).await? {
+                Some(contact) => contact_to_display_contact(contact, manager.clone(), &profiles)
+                    .await
+                    .map(|display_contact| display_contact.display_name().to_string()),
+                None => profile_cache::profile_name(&profiles, manager, uuid).await,
+            };
+            if let Some(name) = name {
+                names.insert(uuid, name);
+            }
+        }
+        Ok(Self { names })
+    }
+
+    fn get(&self, uuid: &Uuid, outgoing: bool) -> String {
+        if outgoing {
+            return "Me".to_string();
+        }
+        self.names
+            .get(uuid)
+            .cloned()
+            .unwrap_or_else(|| uuid.to_string())
+    }
+}
+
+/// Writes a contact or group conversation to `file` or to the standard output when it's `None`.
+///
+/// Attachments are downloaded into a folder next to `file` named after it, so the export can be
+/// moved around together with the folder.
+pub async fn export_conversation_cli(
+    contact: Option<String>,
+    group: Option<String>,
+    format: ExportFormat,
+    file: Option<PathBuf>,
+    attachments: bool,
+) -> Result<()> {
+    let mut manager = create_registered_manager().await?;
+
+    let (thread, contact_uuid, group_master_key) = match (contact, group) {
+        (Some(contact), None) => {
+            let uuid = find_uuid(contact, &mut manager).await?;
+            (Thread::Contact(uuid), Some(uuid), None)
+        }
+        (None, Some(group)) => match find_master_key(group, &mut manager).await? {
+            Some(master_key) => (
+                Thread::Group(master_key),
+                None,
+                Some(hex::encode(master_key)),
+            ),
+            None => bail!("Group with given name does not exist."),
+        },
+        _ => bail!("Either a contact or a group has to be given"),
+    };
+
+    let contents = manager.store().messages(&thread, 0..).await?.collect();
+    let mut messages = get_messages_as_message_dto(contents)?;
+    // Disappeared messages are left out like everywhere else, even before they are purged
+    ExpiryIndex::load_current()?.apply(&thread, &mut messages);
+    expiry::retain_live(&mut messages);
+    resolve_mentions(&manager, &mut messages).await;
+    // exports read from the oldest to the latest message
+    messages.reverse();
+
+    let attachments_dir = match (&file, attachments) {
+        (Some(file), true) => Some(attachments_dir(file)?),
+        _ => None,
+    };
+
+    let names = SenderNames::load(&manager, &thread, &messages).await?;
+    // The group may have been given by a part of its name or identifier
+    let title = match &thread {
+        Thread::Contact(uuid) => names.get(uuid, false),
+        Thread::Group(master_key) => match manager.store().group(*master_key).await? {
+            Some(group) => group.title,
+            None => bail!("Group with given name does not exist."),
+        },
+    };
+
+    let mut exported_messages = Vec::with_capacity(messages.len());
+    for message in messages {
+        let attachment_path = match (&attachments_dir, &message.attachment) {
+            (Some(dir), Some(attachment)) => {
+                download_attachment(attachment.clone(), &manager, dir).await
+            }
+            _ => None,
+        };
+        exported_messages.push(export_message(message, &names, attachment_path));
+    }
+
+    let conversation = ExportedConversation {
+        title,
+        contact: contact_uuid,
+        group_master_key,
+        exported_at: datetime(chrono::Utc::now().timestamp_millis() as u64),
+        messages: exported_messages,
+    };
+
+    let contents = match format {
+        ExportFormat::Json => serde_json::to_string_pretty(&conversation)?,
+        ExportFormat::Markdown => render_markdown(&conversation),
+        ExportFormat::Html => render_html(&conversation),
+    };
+
+    match file {
+        Some(file) => {
+            fs::write(&file, contents)
+                .map_err(|e| anyhow!("Failed to write export to {}: {e}", file.display()))?;
+            println!("Exported conversation to {}", file.display());
+        }
+        None => println!("{contents}"),
+    }
+    Ok(())
+}
+
+/// Folder for attachments next to the export, e.g. `chat_attachments` for `chat.html`
+fn attachments_dir(file: &Path) -> Result<PathBuf> {
+    let stem = file
+        .file_stem()
+        .ok_or_else(|| anyhow!("Invalid export file name: {}", file.display()))?
+        .to_string_lossy();
+    let dir = file.with_file_name(format!("{stem}_attachments"));
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Saves the attachment and returns its path relative to the export file
+async fn download_attachment(
+    attachment: presage::proto::AttachmentPointer,
+    manager: &Manager<SqliteStore, Registered>,
+    dir: &Path,
+) -> Option<String> {
+    match save_attachment(attachment, manager.clone(), dir.to_path_buf()).await {
+        Ok(path) => {
+            let dir_name = dir.file_name()?.to_string_lossy();
+            let file_name = path.file_name()?.to_string_lossy();
+            Some(format!("{dir_name}/{file_name}"))
+        }
+        Err(error) => {
+            warn!(%error, "Failed to download attachment for export");
+            None
+        }
+    }
+}
+
+fn export_message(
+    message: MessageDto,
+    names: &SenderNames,
+    attachment_path: Option<String>,
+) -> ExportedMessage {
+    let quote = message.quote.as_ref().map(|quote| ExportedQuote {
+        timestamp: quote.id(),
+        author: quote.author_aci.clone(),
+        author_name: quote
+            .author_aci
+            .as_deref()
+            .and_then(|author| Uuid::parse_str(author).ok())
+            .map(|author| names.get(&author, false)),
+        text: quote.text.clone(),
+    });
+
+    let mut reactions = message
+        .reactions
+        .iter()
+        .filter(|(_, reaction)| !reaction.remove())
+        .map(|(author, reaction)| ExportedReaction {
+            author: *author,
+            author_name: names.get(author, false),
+            emoji: reaction.emoji().to_string(),
+        })
+        .collect::<Vec<_>>();
+    reactions.sort_by_key(|reaction| reaction.author);
+
+    let attachment = message
+        .attachment
+        .as_ref()
+        .map(|attachment| ExportedAttachment {
+            file_name: attachment.file_name.clone(),
+            content_type: attachment.content_type.clone(),
+            size: attachment.size,
+            path: attachment_path,
+        });
+
+    ExportedMessage {
+        timestamp: message.timestamp,
+        datetime: datetime(message.timestamp),
+        sender: message.uuid,
+        sender_name: names.get(&message.uuid, message.sender),
+        outgoing: message.sender,
+        text: message.text,
+        deleted: message.deleted,
+        edited_at: message.edited_at,
+        edit_history: message
+            .edit_history
+            .into_iter()
+            .map(|edit| ExportedEdit {
+                timestamp: edit.timestamp,
+                datetime: datetime(edit.timestamp),
+                text: edit.text,
+            })
+            .collect(),
+        quote,
+        reactions,
+        attachment,
+    }
+}
+
+fn local_time(timestamp: u64) -> String {
+    get_local_timestamp(timestamp)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+/// Marker appended to the time of a message which was edited
+fn edited_marker(message: &ExportedMessage) -> &'static str {
+    match message.edited_at {
+        Some(_) => " (edited)",
+        None => "",
+    }
+}
+
+/// Escapes characters that Markdown would take for formatting, so the text is shown as sent
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        // Headings and list items only start at the beginning of a line
+        if line.starts_with(['#', '-', '+']) {
+            escaped.push('\\');
+        }
+        for c in line.chars() {
+            if matches!(
+                c,
+                '\\' | '`' | '*' | '_' | '~' | '[' | ']' | '<' | '>' | '|'
+            ) {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+fn render_markdown(conversation: &ExportedConversation) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# {}\n", escape_markdown(&conversation.title));
+
+    for message in &conversation.messages {
+        let _ = writeln!(
+            out,
+            "**{}** · {}{}\n",
+            escape_markdown(&message.sender_name),
+            local_time(message.timestamp),
+            edited_marker(message)
+        );
+
+        if let Some(quote) = &message.quote {
+            let author = escape_markdown(quote.author_name.as_deref().unwrap_or("Unknown"));
+            let _ = writeln!(out, "> **{author}** · {}", local_time(quote.timestamp));
+            for line in quote.text.as_deref().unwrap_or("...").lines() {
+                let _ = writeln!(out, "> {}", escape_markdown(line));
+            }
+            out.push('\n');
+        }
+
+        match &message.attachment {
+            Some(ExportedAttachment {
+                file_name,
+                path: Some(path),
+                ..
+            }) => {
+                let name = escape_markdown(file_name.as_deref().unwrap_or(path));
+                let _ = writeln!(out, "[{name}](<{path}>)");
+            }
+            _ => {
+                for line in message.text.lines() {
+                    let _ = writeln!(out, "{}  ", escape_markdown(line));
+                }
+            }
+        }
+
+        if !message.reactions.is_empty() {
+            let reactions = message
+                .reactions
+                .iter()
+                .map(|reaction| {
+                    format!(
+                        "{} {}",
+                        reaction.emoji,
+                        escape_markdown(&reaction.author_name)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            let _ = writeln!(out, "\n_Reactions: {reactions}_");
+        }
+
+        out.push_str("\n---\n\n");
+    }
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+const HTML_STYLE: &str = "
+body { font-family: sans-serif; max-width: 48em; margin: 2em auto; background: #f4f4f4; }
+.message { background: #fff; border-radius: 8px; padding: 0.6em 1em; margin: 0.8em 0; width: 75%; }
+.message.outgoing { margin-left: auto; background: #dcebff; }
+.meta { color: #666; font-size: 0.85em; margin-bottom: 0.3em; }
+.quote { border-left: 3px solid #999; padding-left: 0.6em; color: #555; margin-bottom: 0.4em; }
+.text { white-space: pre-wrap; }
+.reactions { font-size: 0.85em; color: #666; margin-top: 0.3em; }
+img { max-width: 100%; }
+";
+
+fn render_html(conversation: &ExportedConversation) -> String {
+    let title = escape_html(&conversation.title);
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>{HTML_STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>"
+    );
+
+    for message in &conversation.messages {
+        let class = if message.outgoing {
+            "message outgoing"
+        } else {
+            "message"
+        };
+        let _ = writeln!(out, "<div class=\"{class}\">");
+        let _ = writeln!(
+            out,
+            "<div class=\"meta\"><b>{}</b> · {}{}</div>",
+            escape_html(&message.sender_name),
+            local_time(message.timestamp),
+            edited_marker(message)
+        );
+
+        if let Some(quote) = &message.quote {
+            let _ = writeln!(
+                out,
+                "<div class=\"quote\"><b>{}</b> · {}<div class=\"text\">{}</div></div>",
+                escape_html(quote.author_name.as_deref().unwrap_or("Unknown")),
+                local_time(quote.timestamp),
+                escape_html(quote.text.as_deref().unwrap_or("..."))
+            );
+        }
+
+        match &message.attachment {
+            Some(ExportedAttachment {
+                file_name,
+                content_type,
+                path: Some(path),
+                ..
+            }) => {
+                let path = escape_html(path);
+                let name = escape_html(file_name.as_deref().unwrap_or(&path));
+                if content_type
+                    .as_deref()
+                    .is_some_and(|content_type| content_type.starts_with("image/"))
+                {
+                    let _ = writeln!(
+                        out,
+                        "<a href=\"{path}\"><img src=\"{path}\" alt=\"{name}\"></a>"
+                    );
+                } else {
+                    let _ = writeln!(out, "<a href=\"{path}\">{name}</a>");
+                }
+            }
+            _ => {
+                let _ = writeln!(
+                    out,
+                    "<div class=\"text\">{}</div>",
+                    escape_html(&message.text)
+                );
+            }
+        }
+
+        if !message.reactions.is_empty() {
+            let reactions = message
+                .reactions
+                .iter()
+                .map(|reaction| {
+                    format!(
+                        "{} {}",
+                        escape_html(&reaction.emoji),
+                        escape_html(&reaction.author_name)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            let _ = writeln!(out, "<div class=\"reactions\">{reactions}</div>");
+        }
+
+        out.push_str("</div>\n");
+    }
+
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: Uuid = Uuid::from_u128(1);
+    const BOB: Uuid = Uuid::from_u128(2);
+
+    fn message(sender: Uuid, sender_name: &str, text: &str) -> ExportedMessage {
+        ExportedMessage {
+            timestamp: 1_700_000_000_000,
+            datetime: datetime(1_700_000_000_000),
+            sender,
+            sender_name: sender_name.to_string(),
+            outgoing: sender == ALICE,
+            text: text.to_string(),
+            deleted: false,
+            edited_at: None,
+            edit_history: Vec::new(),
+            quote: None,
+            reactions: Vec::new(),
+            attachment: None,
+        }
+    }
+
+    fn conversation(messages: Vec<ExportedMessage>) -> ExportedConversation {
+        ExportedConversation {
+            title: "Tom & <Jerry>".to_string(),
+            contact: Some(BOB),
+            group_master_key: None,
+            exported_at: None,
+            messages,
+        }
+    }
+
+    #[test]
+    fn html_special_characters_are_escaped() {
+        assert_eq!(
+            escape_html(r#"<a href="x">Tom & 'Jerry'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; &#39;Jerry&#39;&lt;/a&gt;"
+        );
+        assert_eq!(escape_html("plain text"), "plain text");
+    }
+
+    #[test]
+    fn markdown_formatting_is_escaped() {
+        assert_eq!(escape_markdown("2*3*4"), r"2\*3\*4");
+        assert_eq!(escape_markdown("see [docs](<x>)"), r"see \[docs\](\<x\>)");
+        assert_eq!(escape_markdown("# not a heading"), r"\# not a heading");
+        assert_eq!(escape_markdown("a\n- b"), "a\n\\- b");
+        assert_eq!(escape_markdown("C:\\tmp"), r"C:\\tmp");
+        assert_eq!(escape_markdown("just text."), "just text.");
+    }
+
+    #[test]
+    fn html_shows_messages_quotes_and_reactions() {
+        let mut reply = message(ALICE, "Me", "<b>not bold</b>");
+        reply.edited_at = Some(1_700_000_001_000);
+        reply.quote = Some(ExportedQuote {
+            timestamp: 1_700_000_000_000,
+            author: Some(BOB.to_string()),
+            author_name: Some("Bob".to_string()),
+            text: Some("Hi & bye".to_string()),
+        });
+        reply.reactions.push(ExportedReaction {
+            author: BOB,
+            author_name: "Bob".to_string(),
+            emoji: "👍".to_string(),
+        });
+        let html = render_html(&conversation(vec![message(BOB, "Bob", "Hi"), reply]));
+
+        assert!(html.contains("<title>Tom &amp; &lt;Jerry&gt;</title>"));
+        assert!(html.contains("<div class=\"message\">"));
+        assert!(html.contains("<div class=\"message outgoing\">"));
+        assert!(html.contains("&lt;b&gt;not bold&lt;/b&gt;"));
+        assert!(html.contains("Hi &amp; bye"));
+        assert!(html.contains(" (edited)"));
+        assert!(html.contains("<div class=\"reactions\">👍 Bob</div>"));
+        assert!(html.ends_with("</html>\n"));
+    }
+
+    #[test]
+    fn html_links_downloaded_attachments() {
+        let mut photo = message(BOB, "Bob", "");
+        photo.attachment = Some(ExportedAttachment {
+            file_name: Some("cat.jpg".to_string()),
+            content_type: Some("image/jpeg".to_string()),
+            size: Some(1),
+            path: Some("chat_attachments/cat.jpg".to_string()),
+        });
+        let html = render_html(&conversation(vec![photo]));
+
+        assert!(html.contains(
+            "<a href=\"chat_attachments/cat.jpg\"><img src=\"chat_attachments/cat.jpg\" alt=\"cat.jpg\"></a>"
+        ));
+    }
+
+    #[test]
+    fn markdown_shows_text_as_sent() {
+        let mut reply = message(ALICE, "Me", "2*3*4\n# 5");
+        reply.quote = Some(ExportedQuote {
+            timestamp: 1_700_000_000_000,
+            author: None,
+            author_name: None,
+            text: None,
+        });
+        reply.reactions.push(ExportedReaction {
+            author: BOB,
+            author_name: "bob_1".to_string(),
+            emoji: "👍".to_string(),
+        });
+        let markdown = render_markdown(&conversation(vec![message(BOB, "Bob", "Hi"), reply]));
+
+        assert!(markdown.starts_with("# Tom & \\<Jerry\\>\n"));
+        assert!(markdown.contains("**Bob** · "));
+        assert!(markdown.contains("Hi  \n"));
+        assert!(markdown.contains("> **Unknown** · "));
+        assert!(markdown.contains("> ...\n"));
+        assert!(markdown.contains("2\\*3\\*4  \n\\# 5  \n"));
+        assert!(markdown.contains("_Reactions: 👍 bob\\_1_"));
+        assert_eq!(markdown.matches("\n---\n").count(), 2);
+    }
+}
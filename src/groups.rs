@@ -1,6 +1,8 @@
 use crate::account_management::create_registered_manager;
-use anyhow::Result;
-use presage::libsignal_service::zkgroup::GroupMasterKeyBytes;
+use crate::matching::{closest, confirm, similarity};
+use anyhow::{Result, bail};
+use presage::libsignal_service::zkgroup::groups::{GroupMasterKey, GroupSecretParams};
+use presage::libsignal_service::zkgroup::{GroupIdentifierBytes, GroupMasterKeyBytes};
 use presage::model::groups::Group;
 use presage::store::ContentsStore;
 use presage::{Manager, manager::Registered};
@@ -24,19 +26,135 @@ pub async fn list_groups_tui(
     list_groups(manager).await
 }
 
-pub async fn find_master_key(
-    group_name: String,
+/// Identifier of the group used by messages which don't carry its master key, e.g. typing
+pub fn group_identifier(master_key: GroupMasterKeyBytes) -> GroupIdentifierBytes {
+    GroupSecretParams::derive_from_master_key(GroupMasterKey::new(master_key))
+        .get_group_identifier()
+}
+
+/// Shortest prefix of the hex encoded master key accepted as the identifier of a group
+const MIN_KEY_PREFIX_LEN: usize = 6;
+
+/// Identifier of the group accepted wherever a group is given by the user
+pub fn group_id(master_key: &GroupMasterKeyBytes) -> String {
+    hex::encode(master_key)
+}
+
+/// Outcome of looking up a group by an identifier or a title given by the user
+enum GroupMatch {
+    Found(GroupMasterKeyBytes),
+    Ambiguous(Vec<(GroupMasterKeyBytes, String)>),
+    /// Groups with a title similar to the query, several if they are equally close
+    Similar(Vec<(GroupMasterKeyBytes, String)>),
+    NotFound,
+}
+
+impl GroupMatch {
+    fn from_candidates(candidates: Vec<(GroupMasterKeyBytes, String)>) -> Option<Self> {
+        match candidates.len() {
+            0 => None,
+            1 => Some(GroupMatch::Found(candidates[0].0)),
+            _ => Some(GroupMatch::Ambiguous(candidates)),
+        }
+    }
+}
+
+/// Looks the group up by its master key or a prefix of it, then by exact title, then by title
+/// ignoring case and finally by a similar title
+async fn match_group(
+    query: &str,
     manager: &mut Manager<SqliteStore, Registered>,
-) -> Result<Option<GroupMasterKeyBytes>> {
-    // TODO: Right now it assumes that all groups have unique names this is. This has to be handled
-    // correctly in future.
-    let group = manager
+) -> Result<GroupMatch> {
+    let groups = manager
         .store()
         .groups()
         .await?
         .filter_map(|g| g.ok())
-        .find(|(_, group)| group.title == group_name);
+        .map(|(master_key, group)| (master_key, group.title))
+        .collect::<Vec<_>>();
+
+    let query = query.trim();
+    let lowercase = query.to_lowercase();
+    let is_key_prefix = query.len() >= MIN_KEY_PREFIX_LEN
+        && query.len() <= 2 * size_of::<GroupMasterKeyBytes>()
+        && query.chars().all(|c| c.is_ascii_hexdigit());
+
+    let filters: [&dyn Fn(&GroupMasterKeyBytes, &str) -> bool; 3] = [
+        &|master_key, _| is_key_prefix && group_id(master_key).starts_with(&lowercase),
+        &|_, title| title == query,
+        &|_, title| title.to_lowercase() == lowercase,
+    ];
+    for filter in filters {
+        let candidates = groups
+            .iter()
+            .filter(|(master_key, title)| filter(master_key, title))
+            .cloned()
+            .collect();
+        if let Some(found) = GroupMatch::from_candidates(candidates) {
+            return Ok(found);
+        }
+    }
+
+    let similar = closest(groups.into_iter().filter_map(|(master_key, title)| {
+        similarity(query, &title).map(|distance| (distance, (master_key, title)))
+    }));
+    if similar.is_empty() {
+        return Ok(GroupMatch::NotFound);
+    }
+    Ok(GroupMatch::Similar(similar))
+}
+
+fn describe_candidates(candidates: &[(GroupMasterKeyBytes, String)]) -> String {
+    candidates
+        .iter()
+        .map(|(master_key, title)| format!("\n  {title} (ID: {})", group_id(master_key)))
+        .collect()
+}
+
+/// Finds the group given by the user as its ID, a unique prefix of it or its title.
+///
+/// When no group has the title, the user is asked to confirm the group with the most similar
+/// title. Fails listing the candidates if the title or the prefix fits several groups, or if
+/// several titles are equally similar.
+pub async fn find_master_key(
+    group_name: String,
+    manager: &mut Manager<SqliteStore, Registered>,
+) -> Result<Option<GroupMasterKeyBytes>> {
+    match match_group(&group_name, manager).await? {
+        GroupMatch::Similar(candidates) if candidates.len() > 1 => bail!(
+            "Group \"{group_name}\" not found and several groups have a similar title, use the ID of one of these groups:{}",
+            describe_candidates(&candidates)
+        ),
+        GroupMatch::Similar(candidates) => {
+            let (master_key, title) = &candidates[0];
+            let question = format!(
+                "Group \"{group_name}\" not found, did you mean \"{title}\" (ID: {})?",
+                group_id(master_key)
+            );
+            Ok(confirm(&question)?.then_some(*master_key))
+        }
+        other => resolve_match(&group_name, other),
+    }
+}
+
+/// Finds the group like `find_master_key` but never asks the user, similar titles don't match
+pub async fn find_master_key_exact(
+    group_name: String,
+    manager: &mut Manager<SqliteStore, Registered>,
+) -> Result<Option<GroupMasterKeyBytes>> {
+    match match_group(&group_name, manager).await? {
+        GroupMatch::Similar(_) => Ok(None),
+        other => resolve_match(&group_name, other),
+    }
+}
 
-    let key = group.map(|g| g.0);
-    Ok(key)
+fn resolve_match(group_name: &str, found: GroupMatch) -> Result<Option<GroupMasterKeyBytes>> {
+    match found {
+        GroupMatch::Found(master_key) => Ok(Some(master_key)),
+        GroupMatch::Ambiguous(candidates) => bail!(
+            "Group \"{group_name}\" is ambiguous, use the ID of one of these groups:{}",
+            describe_candidates(&candidates)
+        ),
+        GroupMatch::Similar(_) | GroupMatch::NotFound => Ok(None),
+    }
 }
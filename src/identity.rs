@@ -0,0 +1,383 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Result, bail};
+use presage::Manager;
+use presage::libsignal_service::prelude::Uuid;
+use presage::libsignal_service::protocol::{
+    DeviceId, Fingerprint, IdentityKey, IdentityKeyStore, ProtocolAddress,
+};
+use presage::manager::Registered;
+use presage::model::contacts::Contact;
+use presage::proto::verified;
+use presage::store::Store;
+use presage_store_sqlite::SqliteStore;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::error;
+
+use crate::config::{Config, IdentityPolicy};
+use crate::messages::send::SendError;
+use crate::paths;
+use crate::sidecar::{AccountFile, JsonFile};
+
+/// Version of safety numbers computed from ACIs, as shown by the official clients
+const FINGERPRINT_VERSION: u32 = 2;
+const FINGERPRINT_ITERATIONS: u32 = 5200;
+
+/// Whether the user compared the safety number with a contact
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VerificationStatus {
+    Unverified,
+    Verified,
+    /// The identity key changed after we first saw it and the new one wasn't verified yet
+    Changed,
+}
+
+impl VerificationStatus {
+    pub fn label(self) -> &'static str {
+        match self {
+            VerificationStatus::Unverified => "No",
+            VerificationStatus::Verified => "Yes",
+            VerificationStatus::Changed => "No, safety number changed",
+        }
+    }
+}
+
+/// Safety number of the conversation with a contact
+#[derive(Clone)]
+pub struct SafetyNumber {
+    pub uuid: Uuid,
+    /// Twelve groups of five digits
+    pub digits: String,
+    /// Content of the QR code the other side can scan
+    pub scannable: Vec<u8>,
+    pub status: VerificationStatus,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct KnownIdentity {
+    /// Hex encoded identity key
+    key: String,
+    verified: bool,
+    /// Whether the key replaced an earlier one
+    changed: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+struct IdentityChange {
+    uuid: Uuid,
+    /// When we noticed the change, in milliseconds since the epoch
+    timestamp: u64,
+}
+
+/// Identity keys of contacts we have seen and whether the user verified them, persisted per
+/// account.
+///
+/// The presage store only keeps the latest key, so changes and verifications are saved next
+/// to it.
+#[derive(Default, Serialize, Deserialize)]
+pub struct IdentityStore {
+    identities: HashMap<Uuid, KnownIdentity>,
+    changes: Vec<IdentityChange>,
+    #[serde(skip)]
+    file: JsonFile,
+}
+
+impl AccountFile for IdentityStore {
+    const NAME: &'static str = "identities";
+
+    fn path(account_name: &str) -> PathBuf {
+        paths::account_identities_path(account_name)
+    }
+
+    fn file_mut(&mut self) -> &mut JsonFile {
+        &mut self.file
+    }
+}
+
+impl IdentityStore {
+    /// Remembers the current key of the contact, returns whether it replaced a different one
+    pub fn observe(&mut self, uuid: Uuid, key: &IdentityKey) -> bool {
+        let key = hex::encode(key.serialize());
+        match self.identities.get_mut(&uuid) {
+            Some(known) if known.key == key => false,
+            Some(known) => {
+                *known = KnownIdentity {
+                    key,
+                    verified: false,
+                    changed: true,
+                };
+                let timestamp = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|duration| duration.as_millis() as u64)
+                    .unwrap_or_default();
+                self.changes.push(IdentityChange { uuid, timestamp });
+                self.persist();
+                true
+            }
+            None => {
+                self.identities.insert(
+                    uuid,
+                    KnownIdentity {
+                        key,
+                        verified: false,
+                        changed: false,
+                    },
+                );
+                self.persist();
+                false
+            }
+        }
+    }
+
+    pub fn status(&self, uuid: Uuid, key: &IdentityKey) -> VerificationStatus {
+        match self.identities.get(&uuid) {
+            Some(known) if known.key != hex::encode(key.serialize()) => VerificationStatus::Changed,
+            Some(known) if known.verified => VerificationStatus::Verified,
+            Some(known) if known.changed => VerificationStatus::Changed,
+            _ => VerificationStatus::Unverified,
+        }
+    }
+
+    pub fn set_verified(&mut self, uuid: Uuid, key: &IdentityKey, verified: bool) {
+        self.identities.insert(
+            uuid,
+            KnownIdentity {
+                key: hex::encode(key.serialize()),
+                verified,
+                changed: false,
+            },
+        );
+        self.persist();
+    }
+
+    /// When the identity key of the contact last changed
+    pub fn last_change(&self, uuid: Uuid) -> Option<u64> {
+        self.changes
+            .iter()
+            .filter(|change| change.uuid == uuid)
+            .map(|change| change.timestamp)
+            .max()
+    }
+
+    /// When the identity keys changed last, for contacts whose new key wasn't verified or
+    /// unverified by the user since
+    pub fn unverified_changes(&self) -> HashMap<Uuid, u64> {
+        self.identities
+            .iter()
+            .filter(|(_, known)| known.changed && !known.verified)
+            .filter_map(|(uuid, _)| Some((*uuid, self.last_change(*uuid)?)))
+            .collect()
+    }
+
+    fn persist(&self) {
+        self.file.save(self);
+    }
+}
+
+/// Identity key of the contact kept by the presage store, `None` before any message was
+/// exchanged
+async fn identity_key(
+    manager: &Manager<SqliteStore, Registered>,
+    uuid: Uuid,
+) -> Result<Option<IdentityKey>> {
+    let device_id = DeviceId::try_from(1u32).expect("1 is a valid device id");
+    let address = ProtocolAddress::new(uuid.to_string(), device_id);
+    Ok(manager
+        .store()
+        .aci_protocol_store()
+        .get_identity(&address)
+        .await?)
+}
+
+/// Compares the identity key of the contact with the one seen before, returns whether it
+/// changed
+pub async fn check_identity(
+    manager: &Manager<SqliteStore, Registered>,
+    identities: &mut IdentityStore,
+    uuid: Uuid,
+) -> Result<bool> {
+    match identity_key(manager, uuid).await? {
+        Some(key) => Ok(identities.observe(uuid, &key)),
+        None => Ok(false),
+    }
+}
+
+/// Refuses sending to contacts whose safety number changed until the user verifies it, if
+/// the identity policy in the config says so
+pub async fn ensure_trusted(
+    manager: &Manager<SqliteStore, Registered>,
+    identities: &Mutex<IdentityStore>,
+    uuids: impl IntoIterator<Item = Uuid>,
+) -> Result<()> {
+    if Config::load().identity_policy != IdentityPolicy::Block {
+        return Ok(());
+    }
+
+    let mut identities = identities.lock().await;
+    for uuid in uuids {
+        let Some(key) = identity_key(manager, uuid).await? else {
+            continue;
+        };
+        identities.observe(uuid, &key);
+        if identities.status(uuid, &key) == VerificationStatus::Changed {
+            return Err(SendError::UntrustedIdentity(uuid).into());
+        }
+    }
+    Ok(())
+}
+
+/// Whether the user verified the contact here or on the primary device, which syncs the
+/// verified state with the contact
+pub async fn verification_status(
+    manager: &Manager<SqliteStore, Registered>,
+    identities: &IdentityStore,
+    contact: &Contact,
+) -> VerificationStatus {
+    let key = match identity_key(manager, contact.uuid).await {
+        Ok(Some(key)) => key,
+        Ok(None) => return VerificationStatus::Unverified,
+        Err(error) => {
+            error!(%error, "Failed to load identity key of the contact");
+            return VerificationStatus::Unverified;
+        }
+    };
+    let status = identities.status(contact.uuid, &key);
+
+    let verified_on_primary = contact.verified.state == Some(verified::State::Verified as i32)
+        && contact.verified.identity_key.as_deref() == Some(&*key.serialize());
+    if status == VerificationStatus::Unverified && verified_on_primary {
+        VerificationStatus::Verified
+    } else {
+        status
+    }
+}
+
+pub async fn safety_number(
+    manager: &Manager<SqliteStore, Registered>,
+    identities: &IdentityStore,
+    uuid: Uuid,
+) -> Result<SafetyNumber> {
+    let Some(remote_key) = identity_key(manager, uuid).await? else {
+        bail!("Identity key of the contact is not known yet, exchange a message first");
+    };
+    let local_key = *manager
+        .store()
+        .aci_protocol_store()
+        .get_identity_key_pair()
+        .await?
+        .identity_key();
+    let local_uuid = manager.registration_data().service_ids.aci;
+
+    let fingerprint = Fingerprint::new(
+        FINGERPRINT_VERSION,
+        FINGERPRINT_ITERATIONS,
+        local_uuid.as_bytes(),
+        &local_key,
+        uuid.as_bytes(),
+        &remote_key,
+    )?;
+    let status = identities.status(uuid, &remote_key);
+
+    Ok(SafetyNumber {
+        uuid,
+        digits: fingerprint.display_string()?,
+        scannable: fingerprint.scannable.serialize()?,
+        status,
+    })
+}
+
+/// Marks the current safety number with the contact as verified or unverified
+pub async fn set_verified(
+    manager: &Manager<SqliteStore, Registered>,
+    identities: &mut IdentityStore,
+    uuid: Uuid,
+    verified: bool,
+) -> Result<SafetyNumber> {
+    let Some(key) = identity_key(manager, uuid).await? else {
+        bail!("Identity key of the contact is not known yet, exchange a message first");
+    };
+    identities.set_verified(uuid, &key, verified);
+    safety_number(manager, identities, uuid).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(byte: u8) -> IdentityKey {
+        let mut bytes = vec![0x05];
+        bytes.extend([byte; 32]);
+        IdentityKey::decode(&bytes).unwrap()
+    }
+
+    #[test]
+    fn first_key_is_unverified() {
+        let mut identities = IdentityStore::default();
+        let uuid = Uuid::nil();
+
+        assert!(!identities.observe(uuid, &key(1)));
+        assert!(!identities.observe(uuid, &key(1)));
+        assert_eq!(
+            identities.status(uuid, &key(1)),
+            VerificationStatus::Unverified
+        );
+        assert!(identities.unverified_changes().is_empty());
+    }
+
+    #[test]
+    fn new_key_is_a_change_until_verified() {
+        let mut identities = IdentityStore::default();
+        let uuid = Uuid::nil();
+        identities.observe(uuid, &key(1));
+        identities.set_verified(uuid, &key(1), true);
+
+        assert!(identities.observe(uuid, &key(2)));
+        assert_eq!(
+            identities.status(uuid, &key(2)),
+            VerificationStatus::Changed
+        );
+        let changed_at = identities.last_change(uuid).unwrap();
+        assert_eq!(
+            identities.unverified_changes(),
+            HashMap::from([(uuid, changed_at)])
+        );
+
+        identities.set_verified(uuid, &key(2), true);
+        assert_eq!(
+            identities.status(uuid, &key(2)),
+            VerificationStatus::Verified
+        );
+        assert!(identities.unverified_changes().is_empty());
+    }
+
+    #[test]
+    fn unverifying_accepts_the_change() {
+        let mut identities = IdentityStore::default();
+        let uuid = Uuid::nil();
+        identities.observe(uuid, &key(1));
+        identities.observe(uuid, &key(2));
+
+        identities.set_verified(uuid, &key(2), false);
+        assert_eq!(
+            identities.status(uuid, &key(2)),
+            VerificationStatus::Unverified
+        );
+        assert!(identities.unverified_changes().is_empty());
+    }
+
+    #[test]
+    fn key_not_seen_yet_counts_as_changed() {
+        let mut identities = IdentityStore::default();
+        let uuid = Uuid::nil();
+        identities.observe(uuid, &key(1));
+        identities.set_verified(uuid, &key(1), true);
+
+        assert_eq!(
+            identities.status(uuid, &key(2)),
+            VerificationStatus::Changed
+        );
+    }
+}
@@ -10,15 +10,26 @@ pub mod args;
 pub mod cli;
 pub mod config;
 pub mod contacts;
+pub mod daemon;
 pub mod devices;
+pub mod emoji;
 pub mod env;
+pub mod export;
 pub mod groups;
+pub mod identity;
 pub mod logger;
+pub mod matching;
+pub mod message_requests;
 pub mod messages;
 mod notifications;
+pub mod output;
 pub mod paths;
 pub mod profile;
+pub mod profile_cache;
+pub mod recipients;
 mod retry_manager;
+pub mod search;
+pub mod sidecar;
 pub mod tui;
 pub mod ui;
 
@@ -30,5 +41,7 @@ pub async fn open_store(path: &Path) -> Result<SqliteStore, SqliteStoreError> {
             .expect("Failed to resolve database path from `PathBuf`"),
     )?
     .create_if_missing(true);
+    // New identity keys are always saved, so the changed safety number can be shown and
+    // verified, `identity_policy` in the config decides what happens until then
     SqliteStore::open_with_options(options, OnNewIdentity::Trust).await
 }
@@ -6,9 +6,14 @@ use signal_client::account_management::{
     unlink_account_cli,
 };
 use signal_client::args::{Cli, Command};
+use signal_client::config::Config;
 use signal_client::logger::init_logger;
+use signal_client::message_requests::{self, RequestResponse};
 use signal_client::messages;
-use signal_client::{cli, contacts, tui};
+use signal_client::messages::attachments::expand_attachment_paths;
+use signal_client::messages::deletion::{self, LocalDeletion};
+use signal_client::messages::formatting::escape_markdown;
+use signal_client::{cli, contacts, daemon, export, tui};
 
 #[tokio::main(flavor = "multi_thread")]
 async fn main() -> Result<()> {
@@ -17,29 +22,49 @@ async fn main() -> Result<()> {
 
     match cli.command {
         Command::SyncContacts => contacts::sync_contacts_cli().await?,
-        Command::ListContacts => cli::print_contacts().await?,
-        Command::ListGroups => cli::print_groups().await?,
+        Command::ListContacts => cli::print_contacts(cli.output).await?,
+        Command::ListGroups => cli::print_groups(cli.output).await?,
         Command::RunApp => tui::run_tui().await?,
         Command::SendMessage(args) => {
-            messages::send::contact::send_message_cli(args.recipient, args.text_message, args.quote)
-                .await?
+            let text = message_text(args.text_message, args.markdown);
+            messages::send::contact::send_message_cli(args.recipient, text, args.quote).await?
         }
         Command::SendMessageToGroup(args) => {
-            messages::send::group::send_message_cli(args.recipient, args.text_message).await?
+            let text = message_text(args.text_message, args.markdown);
+            messages::send::group::send_message_cli(args.recipient, text).await?
         }
         Command::ListMessages(args) => match (args.contact, args.group) {
-            (Some(c), None) => cli::print_messages_from_contact(c, args.from).await?,
-            (None, Some(g)) => cli::print_messages_from_group(g, args.from).await?,
+            (Some(c), None) => cli::print_messages_from_contact(c, args.from, cli.output).await?,
+            (None, Some(g)) => cli::print_messages_from_group(g, args.from, cli.output).await?,
             _ => unreachable!(),
         },
-        Command::Receive => cli::print_received_message().await?,
-        Command::GetProfile => cli::print_profile().await?,
+        Command::Export(args) => {
+            export::export_conversation_cli(
+                args.contact,
+                args.group,
+                args.format,
+                args.file,
+                args.attachments,
+            )
+            .await?
+        }
+        Command::Search(args) => cli::print_search_results(args, cli.output).await?,
+        Command::Receive => cli::print_received_message(cli.output).await?,
+        Command::GetProfile => cli::print_profile(cli.output).await?,
         Command::SendAttachment(args) => {
+            let attachments = expand_attachment_paths(&args.attachment_path, &args.caption)?;
+            let processing = Config::load().image_processing.overridden(
+                args.keep_original,
+                args.max_image_dimension,
+                args.image_encoding,
+                args.image_quality,
+            );
             messages::send::contact::send_attachment_cli(
                 args.recipient,
-                args.text_message,
-                args.attachment_path,
+                message_text(args.text_message, args.markdown),
+                attachments,
                 args.quote,
+                processing,
             )
             .await?
         }
@@ -60,16 +85,84 @@ async fn main() -> Result<()> {
             }
             _ => unreachable!(),
         },
+        Command::DeleteForMe(args) => {
+            deletion::delete_locally_cli(
+                args.contact,
+                args.group,
+                LocalDeletion::Message(args.timestamp),
+                false,
+            )
+            .await?
+        }
+        Command::ClearHistory(args) => {
+            deletion::delete_locally_cli(args.contact, args.group, LocalDeletion::History, args.yes)
+                .await?
+        }
+        Command::DeleteConversation(args) => {
+            deletion::delete_locally_cli(
+                args.contact,
+                args.group,
+                LocalDeletion::Conversation,
+                args.yes,
+            )
+            .await?
+        }
+        Command::ListRequests => cli::print_requests(cli.output).await?,
+        Command::AcceptRequest(args) => {
+            message_requests::respond_cli(args.sender, RequestResponse::Accept, false).await?
+        }
+        Command::DeleteRequest(args) => {
+            message_requests::respond_cli(args.sender, RequestResponse::Delete, args.yes).await?
+        }
+        Command::BlockRequest(args) => {
+            message_requests::respond_cli(args.sender, RequestResponse::Block, args.yes).await?
+        }
         Command::ReactToMessage(args) => match (args.contact, args.group) {
             (Some(c), None) => {
-                messages::send::contact::send_reaction_message_cli(c, args.timestamp).await?
+                messages::send::contact::send_reaction_message_cli(
+                    c,
+                    args.timestamp,
+                    args.emoji,
+                    args.remove,
+                )
+                .await?
             }
             (None, Some(g)) => {
-                messages::send::group::send_delete_message_cli(g, args.timestamp).await?
+                messages::send::group::send_reaction_message_cli(
+                    g,
+                    args.timestamp,
+                    args.emoji,
+                    args.remove,
+                )
+                .await?
             }
             _ => unreachable!(),
         },
+        Command::EditMessage(args) => {
+            let text = message_text(args.text_message, args.markdown);
+            match (args.contact, args.group) {
+                (Some(c), None) => {
+                    messages::send::contact::send_edit_message_cli(c, args.timestamp, text).await?
+                }
+                (None, Some(g)) => {
+                    messages::send::group::send_edit_message_cli(g, args.timestamp, text).await?
+                }
+                _ => unreachable!(),
+            }
+        }
+        Command::SetExpireTimer(args) => {
+            messages::expiry::set_contact_expire_timer_cli(args.contact, args.timer).await?
+        }
+        Command::Daemon(args) => daemon::run_daemon(args.socket, args.account).await?,
     }
 
     Ok(())
 }
+
+/// Text of a message given on the command line, markers are only styles with `--markdown`
+fn message_text(text: String, markdown: bool) -> String {
+    match markdown {
+        true => text,
+        false => escape_markdown(&text),
+    }
+}
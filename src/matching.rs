@@ -0,0 +1,100 @@
+use std::io::{self, IsTerminal, Write};
+
+use anyhow::Result;
+
+/// Number of single character insertions, deletions or substitutions turning one text into
+/// the other
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, a_char) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// How far a name is from the query, ignoring case, or `None` if they are not similar at all.
+///
+/// Names containing the query are similar however long they are, others may differ by a typo
+/// every four characters. The distance is the edit distance in both cases, so names with fewer
+/// characters around the query are closer.
+pub fn similarity(query: &str, name: &str) -> Option<usize> {
+    let query = query.trim().to_lowercase();
+    let name = name.trim().to_lowercase();
+    if query.is_empty() || name.is_empty() {
+        return None;
+    }
+
+    let distance = edit_distance(&query, &name);
+    let allowed = (query.chars().count() / 4).max(1);
+    (distance <= allowed || name.contains(&query)).then_some(distance)
+}
+
+/// Candidates with the smallest distance, several when they are equally close
+pub fn closest<T>(scored: impl IntoIterator<Item = (usize, T)>) -> Vec<T> {
+    let mut closest = Vec::new();
+    let mut best = usize::MAX;
+    for (distance, candidate) in scored {
+        if distance < best {
+            best = distance;
+            closest.clear();
+        }
+        if distance == best {
+            closest.push(candidate);
+        }
+    }
+    closest
+}
+
+/// Asks the user a yes or no question on the terminal, anything else than `y` is a no. The
+/// question goes to stderr, so it doesn't end up in piped output.
+///
+/// Without a terminal, e.g. when the input is piped, nobody can answer and it is a no.
+pub fn confirm(question: &str) -> Result<bool> {
+    if !io::stdin().is_terminal() {
+        return Ok(false);
+    }
+
+    eprint!("{question} [y/N]: ");
+    io::stderr().flush()?;
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    Ok(input.trim().eq_ignore_ascii_case("y"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_containing_the_query_are_closer_with_less_around_it() {
+        assert_eq!(similarity("bob", "Bob"), Some(0));
+        assert_eq!(similarity("bob", "Bobby"), Some(2));
+        assert_eq!(similarity("bob", "Bob from the hiking club"), Some(21));
+        assert!(similarity("bob", "Bobby") < similarity("bob", "Bob Smith"));
+    }
+
+    #[test]
+    fn typos_are_allowed_every_four_characters() {
+        assert_eq!(similarity("alixe", "Alice"), Some(1));
+        assert_eq!(similarity("hiking club", "hikng clb"), Some(2));
+        assert_eq!(similarity("bob", "Rob"), Some(1));
+        assert_eq!(similarity("bob", "Alice"), None);
+        assert_eq!(similarity("", "Alice"), None);
+    }
+
+    #[test]
+    fn equally_close_candidates_are_all_returned() {
+        assert_eq!(closest([(2, "a"), (1, "b"), (3, "c")]), vec!["b"]);
+        assert_eq!(closest([(1, "a"), (2, "b"), (1, "c")]), vec!["a", "c"]);
+        assert!(closest(Vec::<(usize, &str)>::new()).is_empty());
+    }
+}
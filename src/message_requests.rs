@@ -0,0 +1,412 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use anyhow::{Result, bail};
+use presage::Manager;
+use presage::libsignal_service::content::ContentBody;
+use presage::libsignal_service::prelude::{Content, Uuid};
+use presage::manager::Registered;
+use presage::model::contacts::Contact;
+use presage::store::{ContentsStore, Thread};
+use presage_store_sqlite::SqliteStore;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::{debug, error};
+
+use crate::account_management::create_registered_manager;
+use crate::matching::confirm;
+use crate::messages::deletion::clear_history;
+use crate::messages::receive::{MessageDto, get_messages_as_message_dto};
+use crate::messages::send::contact::send_profile_key;
+use crate::paths;
+use crate::profile::load_profiles;
+use crate::profile_cache::{self, ProfileCache};
+use crate::search::SearchIndex;
+use crate::sidecar::{AccountFile, JsonFile};
+
+/// Senders who aren't contacts and how the user answered their messages, persisted per account.
+///
+/// Conversations of pending senders are message requests, we don't reply or send receipts
+/// until the user accepts them. Messages of blocked senders are deleted as they arrive.
+#[derive(Default, Serialize, Deserialize)]
+pub struct MessageRequests {
+    pending: HashSet<Uuid>,
+    accepted: HashSet<Uuid>,
+    blocked: HashSet<Uuid>,
+    #[serde(skip)]
+    file: JsonFile,
+}
+
+impl AccountFile for MessageRequests {
+    const NAME: &'static str = "message requests";
+
+    fn path(account_name: &str) -> PathBuf {
+        paths::account_message_requests_path(account_name)
+    }
+
+    fn file_mut(&mut self) -> &mut JsonFile {
+        &mut self.file
+    }
+}
+
+impl MessageRequests {
+    pub fn is_pending(&self, uuid: Uuid) -> bool {
+        self.pending.contains(&uuid)
+    }
+
+    /// Whether the conversation with the sender is a request, i.e. it wasn't answered and the
+    /// sender didn't become a named contact in the meantime
+    pub fn is_request(&self, uuid: Uuid, contact: Option<&Contact>) -> bool {
+        self.is_pending(uuid) && contact.is_none_or(|contact| contact.name.is_empty())
+    }
+
+    pub fn is_accepted(&self, uuid: Uuid) -> bool {
+        self.accepted.contains(&uuid)
+    }
+
+    pub fn is_blocked(&self, uuid: Uuid) -> bool {
+        self.blocked.contains(&uuid)
+    }
+
+    /// Senders waiting for an answer
+    pub fn pending(&self) -> impl Iterator<Item = Uuid> + '_ {
+        self.pending.iter().copied()
+    }
+
+    /// Senders whose requests were accepted although they aren't contacts
+    pub fn accepted(&self) -> impl Iterator<Item = Uuid> + '_ {
+        self.accepted.iter().copied()
+    }
+
+    /// Senders who aren't contacts but whose conversations are kept, the store can't list them
+    pub fn senders(&self) -> Vec<Uuid> {
+        self.pending().chain(self.accepted()).collect()
+    }
+
+    /// Turns the conversation into a request unless the sender was answered already, returns
+    /// whether it is a new request
+    fn observe(&mut self, uuid: Uuid) -> bool {
+        if self.is_accepted(uuid) || self.is_blocked(uuid) || !self.pending.insert(uuid) {
+            return false;
+        }
+        self.persist();
+        true
+    }
+
+    pub fn accept(&mut self, uuid: Uuid) {
+        self.pending.remove(&uuid);
+        self.accepted.insert(uuid);
+        self.persist();
+    }
+
+    /// Forgets the request, a new message of the sender makes it a request again
+    pub fn decline(&mut self, uuid: Uuid) {
+        self.pending.remove(&uuid);
+        self.accepted.remove(&uuid);
+        self.persist();
+    }
+
+    pub fn block(&mut self, uuid: Uuid) {
+        self.pending.remove(&uuid);
+        self.accepted.remove(&uuid);
+        self.blocked.insert(uuid);
+        self.persist();
+    }
+
+    fn persist(&self) {
+        self.file.save(self);
+    }
+}
+
+/// What the user does with a message request
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RequestResponse {
+    /// Share our profile key and move the conversation to the chats
+    Accept,
+    /// Delete the conversation, a new message of the sender makes it a request again
+    Delete,
+    /// Delete the conversation and every later message of the sender
+    Block,
+}
+
+/// Sorts a received message into message requests. Messages of blocked senders are deleted
+/// from the store and `false` is returned, they shouldn't be handled any further.
+pub async fn screen_message(
+    manager: &Manager<SqliteStore, Registered>,
+    requests: &mut MessageRequests,
+    content: &Content,
+) -> Result<bool> {
+    let Ok(Thread::Contact(uuid)) = Thread::try_from(content) else {
+        return Ok(true);
+    };
+    let sender = content.metadata.sender.raw_uuid();
+
+    if sender != uuid {
+        // We wrote to the sender from another device, which answers the request there
+        if requests.is_pending(uuid) {
+            requests.accept(uuid);
+        }
+        return Ok(true);
+    }
+    if requests.is_blocked(sender) {
+        manager
+            .store()
+            .clone()
+            .delete_message(&Thread::Contact(sender), content.timestamp())
+            .await?;
+        debug!(%sender, "Dropped message of a blocked sender");
+        return Ok(false);
+    }
+
+    if matches!(content.body, ContentBody::DataMessage(_))
+        && manager.store().contact_by_id(&sender).await?.is_none()
+        && requests.observe(sender)
+    {
+        debug!(%sender, "New message request");
+    }
+    Ok(true)
+}
+
+/// Answers the request of the sender. Accepting shares our profile key, so the sender sees our
+/// name and avatar, deleting and blocking delete the conversation on this device.
+pub async fn respond(
+    manager: &mut Manager<SqliteStore, Registered>,
+    requests: &mut MessageRequests,
+    sender: Uuid,
+    response: RequestResponse,
+    search_index: Option<&SearchIndex>,
+) -> Result<()> {
+    if response == RequestResponse::Accept {
+        send_profile_key(manager, sender).await?;
+    }
+    record_response(manager, requests, sender, response, search_index).await
+}
+
+/// Answers the request of the sender on this device only, the profile key of an accepted
+/// request has to be shared before
+pub async fn record_response(
+    manager: &Manager<SqliteStore, Registered>,
+    requests: &mut MessageRequests,
+    sender: Uuid,
+    response: RequestResponse,
+    search_index: Option<&SearchIndex>,
+) -> Result<()> {
+    let thread = Thread::Contact(sender);
+    match response {
+        RequestResponse::Accept => requests.accept(sender),
+        RequestResponse::Delete | RequestResponse::Block => {
+            clear_history(manager, &thread).await?;
+            if let Some(search_index) = search_index {
+                search_index.remove_thread(&thread).await?;
+            }
+            match response {
+                RequestResponse::Block => requests.block(sender),
+                _ => requests.decline(sender),
+            }
+        }
+    }
+    debug!(%sender, ?response, "Answered message request");
+    Ok(())
+}
+
+/// Message request with what is known about the sender
+pub struct PendingRequest {
+    pub uuid: Uuid,
+    pub profile_name: Option<String>,
+    /// Signal doesn't tell the number of senders who aren't contacts, it is only known if they
+    /// are stored without a name
+    pub phone_number: Option<String>,
+    pub latest_message: Option<MessageDto>,
+}
+
+/// Requests waiting for an answer, the newest first
+pub async fn pending_requests(
+    manager: &Manager<SqliteStore, Registered>,
+    requests: &MessageRequests,
+    profiles: &Mutex<ProfileCache>,
+) -> Result<Vec<PendingRequest>> {
+    let mut pending = Vec::new();
+    for uuid in requests.pending() {
+        let contact = manager.store().contact_by_id(&uuid).await?;
+        if !requests.is_request(uuid, contact.as_ref()) {
+            continue;
+        }
+        let messages = manager
+            .store()
+            .messages(&Thread::Contact(uuid), ..)
+            .await?
+            .collect();
+        pending.push(PendingRequest {
+            uuid,
+            profile_name: profile_cache::profile_name(profiles, manager, uuid).await,
+            phone_number: contact
+                .and_then(|contact| contact.phone_number)
+                .map(|phone_number| phone_number.to_string()),
+            latest_message: get_messages_as_message_dto(messages)?.into_iter().next(),
+        });
+    }
+    pending
+        .sort_by_key(|request| Reverse(request.latest_message.as_ref().map(|msg| msg.timestamp)));
+    Ok(pending)
+}
+
+/// Requests waiting for an answer, for use in CLI
+pub async fn pending_requests_cli() -> Result<Vec<PendingRequest>> {
+    let manager = create_registered_manager().await?;
+    let requests = MessageRequests::load_current()?;
+    pending_requests(&manager, &requests, &load_profiles()).await
+}
+
+/// Finds the pending request of the sender given as a UUID or a profile name, names are
+/// compared ignoring case
+fn find_request(query: &str, pending: &[PendingRequest]) -> Result<Uuid> {
+    let query = query.trim();
+    if let Ok(uuid) = Uuid::parse_str(query) {
+        return match pending.iter().any(|request| request.uuid == uuid) {
+            true => Ok(uuid),
+            false => bail!("There is no message request from {uuid}"),
+        };
+    }
+
+    let lowercase = query.to_lowercase();
+    let matches = pending
+        .iter()
+        .filter(|request| {
+            request
+                .profile_name
+                .as_ref()
+                .is_some_and(|name| name.to_lowercase() == lowercase)
+        })
+        .collect::<Vec<_>>();
+    match matches.as_slice() {
+        [request] => Ok(request.uuid),
+        [] => bail!("There is no message request from '{query}', `list-requests` shows them"),
+        _ => bail!(
+            "Sender '{query}' is ambiguous, use the UUID of one of these requests:{}",
+            matches
+                .iter()
+                .map(|request| format!("\n  {}", request.uuid))
+                .collect::<String>()
+        ),
+    }
+}
+
+/// Answers a message request from the command line, deleting and blocking ask for
+/// confirmation unless `yes` is set
+pub async fn respond_cli(sender: String, response: RequestResponse, yes: bool) -> Result<()> {
+    let mut manager = create_registered_manager().await?;
+    let mut requests = MessageRequests::load_current()?;
+    let pending = pending_requests(&manager, &requests, &load_profiles()).await?;
+    let uuid = find_request(&sender, &pending)?;
+
+    let question = match response {
+        RequestResponse::Accept => None,
+        RequestResponse::Delete => Some("Delete the request and its messages on this device?"),
+        RequestResponse::Block => {
+            Some("Block the sender and delete their messages on this device?")
+        }
+    };
+    if let Some(question) = question
+        && !yes
+        && !confirm(question)?
+    {
+        bail!("Cancelled, pass --yes to answer without asking.");
+    }
+
+    let search_index = match SearchIndex::open_current().await {
+        Ok(search_index) => Some(search_index),
+        Err(error) => {
+            error!(%error, "Failed to open the search index, it may still list deleted messages");
+            None
+        }
+    };
+    respond(
+        &mut manager,
+        &mut requests,
+        uuid,
+        response,
+        search_index.as_ref(),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: Uuid = Uuid::from_u128(1);
+    const BOB: Uuid = Uuid::from_u128(2);
+
+    fn request(uuid: Uuid, profile_name: Option<&str>) -> PendingRequest {
+        PendingRequest {
+            uuid,
+            profile_name: profile_name.map(str::to_string),
+            phone_number: None,
+            latest_message: None,
+        }
+    }
+
+    #[test]
+    fn first_message_of_a_sender_is_a_request() {
+        let mut requests = MessageRequests::default();
+        assert!(requests.observe(ALICE));
+        assert!(!requests.observe(ALICE));
+        assert!(requests.is_pending(ALICE));
+        assert!(requests.is_request(ALICE, None));
+        assert_eq!(requests.senders(), vec![ALICE]);
+    }
+
+    #[test]
+    fn accepted_sender_is_no_request_anymore() {
+        let mut requests = MessageRequests::default();
+        requests.observe(ALICE);
+        requests.accept(ALICE);
+
+        assert!(!requests.observe(ALICE));
+        assert!(!requests.is_request(ALICE, None));
+        assert_eq!(requests.accepted().collect::<Vec<_>>(), vec![ALICE]);
+        assert_eq!(requests.senders(), vec![ALICE]);
+    }
+
+    #[test]
+    fn declined_sender_can_request_again() {
+        let mut requests = MessageRequests::default();
+        requests.observe(ALICE);
+        requests.decline(ALICE);
+
+        assert!(requests.senders().is_empty());
+        assert!(requests.observe(ALICE));
+    }
+
+    #[test]
+    fn blocked_sender_stays_blocked() {
+        let mut requests = MessageRequests::default();
+        requests.observe(ALICE);
+        requests.accept(ALICE);
+        requests.block(ALICE);
+
+        assert!(requests.is_blocked(ALICE));
+        assert!(!requests.observe(ALICE));
+        assert!(requests.senders().is_empty());
+    }
+
+    #[test]
+    fn requests_are_found_by_uuid_or_profile_name() {
+        let pending = vec![request(ALICE, Some("Alice")), request(BOB, None)];
+
+        assert_eq!(find_request(&BOB.to_string(), &pending).unwrap(), BOB);
+        assert_eq!(find_request(" alice ", &pending).unwrap(), ALICE);
+        assert!(find_request(&Uuid::from_u128(3).to_string(), &pending).is_err());
+        assert!(find_request("Bob", &pending).is_err());
+    }
+
+    #[test]
+    fn equal_profile_names_are_ambiguous() {
+        let pending = vec![request(ALICE, Some("Alex")), request(BOB, Some("alex"))];
+
+        let error = find_request("Alex", &pending).unwrap_err().to_string();
+        assert!(error.contains(&ALICE.to_string()));
+        assert!(error.contains(&BOB.to_string()));
+    }
+}
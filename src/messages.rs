@@ -1,4 +1,9 @@
 pub mod attachments;
+pub mod deletion;
+pub mod expiry;
+pub mod formatting;
+pub mod mentions;
+pub mod receipts;
 pub mod receive;
 pub mod send;
 pub use receive::format_message;
@@ -1,14 +1,136 @@
 use mime_guess::mime::APPLICATION_OCTET_STREAM;
 use std::fs;
+use std::io::Cursor;
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use chrono::Local;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::{DynamicImage, ImageDecoder, ImageReader};
 use presage::libsignal_service::sender::AttachmentSpec;
 use presage::manager::{Manager, Registered};
 use presage::proto::AttachmentPointer;
+use presage::proto::attachment_pointer::AttachmentIdentifier;
 use presage_store_sqlite::SqliteStore;
-use tracing::info;
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+
+use crate::config::{ImageEncoding, ImageProcessing};
+use crate::messages::send::SendError;
+
+/// Longest side of the image kept for a thumbnail, in pixels
+const THUMBNAIL_MAX_SIDE: u32 = 512;
+
+/// Size the attachment cache of an account may take, least recently used attachments are
+/// deleted above it
+const CACHE_MAX_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Whether the attachment can be shown as an image
+pub fn is_image(attachment_pointer: &AttachmentPointer) -> bool {
+    attachment_pointer.content_type().starts_with("image/")
+}
+
+/// Name of the attachment in the cache, derived from its digest or its key on the CDN
+pub fn cache_key(attachment_pointer: &AttachmentPointer) -> Option<String> {
+    if let Some(digest) = &attachment_pointer.digest {
+        return Some(hex::encode(digest));
+    }
+    match &attachment_pointer.attachment_identifier {
+        Some(AttachmentIdentifier::CdnKey(key)) => Some(hex::encode(key)),
+        Some(AttachmentIdentifier::CdnId(id)) => Some(format!("cdn-{id}")),
+        None => None,
+    }
+}
+
+/// Contents of the attachment, downloaded only when they are not in `cache_dir` yet
+pub async fn get_cached_attachment(
+    attachment_pointer: &AttachmentPointer,
+    manager: &Manager<SqliteStore, Registered>,
+    cache_dir: &Path,
+) -> Result<Vec<u8>> {
+    let cache_path = cache_key(attachment_pointer).map(|key| cache_dir.join(key));
+    if let Some(path) = &cache_path
+        && let Ok(data) = fs::read(path)
+    {
+        // The modification time orders attachments by their last use for eviction
+        if let Err(error) = fs::File::options()
+            .write(true)
+            .open(path)
+            .and_then(|file| file.set_modified(SystemTime::now()))
+        {
+            warn!(%error, path = %path.display(), "Failed to mark cached attachment as used");
+        }
+        return Ok(data);
+    }
+
+    let data = manager
+        .get_attachment(attachment_pointer)
+        .await
+        .map_err(|e| anyhow!("Failed to get attachment data from database: {e}"))?;
+    if data.is_empty() {
+        return Err(anyhow!("Attachment data is empty"));
+    }
+
+    if let Some(path) = &cache_path {
+        let result = fs::create_dir_all(cache_dir).and_then(|_| {
+            let tmp_path = path.with_extension("tmp");
+            fs::write(&tmp_path, &data)?;
+            fs::rename(&tmp_path, path)
+        });
+        if let Err(error) = result {
+            warn!(%error, path = %path.display(), "Failed to cache attachment");
+        }
+        if let Err(error) = evict_cached_attachments(cache_dir, CACHE_MAX_BYTES) {
+            warn!(%error, "Failed to evict cached attachments");
+        }
+    }
+    Ok(data)
+}
+
+/// Deletes the least recently used attachments until the cache fits into `max_bytes`
+fn evict_cached_attachments(cache_dir: &Path, max_bytes: u64) -> Result<()> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(cache_dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_file() {
+            entries.push((metadata.modified()?, metadata.len(), entry.path()));
+        }
+    }
+
+    let mut total: u64 = entries.iter().map(|(_, len, _)| len).sum();
+    if total <= max_bytes {
+        return Ok(());
+    }
+    entries.sort_unstable_by_key(|(modified, _, _)| *modified);
+    for (_, len, path) in entries {
+        if total <= max_bytes {
+            break;
+        }
+        fs::remove_file(&path)?;
+        total -= len;
+        debug!(path = %path.display(), "Evicted cached attachment");
+    }
+    Ok(())
+}
+
+/// Decodes the image attachment, `thumbnail` scales it down for showing it in the chat
+pub async fn load_attachment_image(
+    attachment_pointer: &AttachmentPointer,
+    manager: &Manager<SqliteStore, Registered>,
+    cache_dir: &Path,
+    thumbnail: bool,
+) -> Result<DynamicImage> {
+    let data = get_cached_attachment(attachment_pointer, manager, cache_dir).await?;
+    let image =
+        image::load_from_memory(&data).map_err(|e| anyhow!("Failed to decode image: {e}"))?;
+    Ok(match thumbnail {
+        true => image.thumbnail(THUMBNAIL_MAX_SIDE, THUMBNAIL_MAX_SIDE),
+        false => image,
+    })
+}
 
 // save attachment to given directory
 pub async fn save_attachment(
@@ -78,48 +200,374 @@ fn get_unique_file_path(dir: &Path, file_name: &str) -> PathBuf {
     }
 }
 
-/// Create attachment spec from file path
-pub async fn create_attachment(attachment_path: String) -> Result<(AttachmentSpec, Vec<u8>)> {
+/// Separates attachments in the attachment field of the app
+pub const ATTACHMENT_SEPARATOR: char = ';';
+/// Separates the path of an attachment in the attachment field from its caption
+pub const CAPTION_SEPARATOR: char = '|';
+
+/// Components of the blurhash along both axes, as used by the official clients
+const BLURHASH_COMPONENTS: (u32, u32) = (4, 3);
+/// Longest side of the image the blurhash is computed from, in pixels
+const BLURHASH_MAX_SIDE: u32 = 64;
+
+/// File to attach to a message, with a caption shown under it
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct AttachmentInput {
+    pub path: String,
+    #[serde(default)]
+    pub caption: Option<String>,
+}
+
+impl AttachmentInput {
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            caption: None,
+        }
+    }
+}
+
+/// Parses the attachment field of the app, e.g. `photo.jpg|Sunset; notes.pdf`
+pub fn parse_attachment_field(value: &str) -> Vec<AttachmentInput> {
+    value
+        .split(ATTACHMENT_SEPARATOR)
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.split_once(CAPTION_SEPARATOR) {
+            Some((path, caption)) => AttachmentInput {
+                path: path.trim().to_string(),
+                caption: Some(caption.trim().to_string()).filter(|caption| !caption.is_empty()),
+            },
+            None => AttachmentInput::new(entry),
+        })
+        .collect()
+}
+
+/// Expands glob patterns given on the command line into attachments, captions belong to the
+/// files in the order they were expanded
+pub fn expand_attachment_paths(
+    patterns: &[String],
+    captions: &[String],
+) -> Result<Vec<AttachmentInput>> {
+    let mut paths = Vec::new();
+    for pattern in patterns {
+        if !pattern.contains(['*', '?', '[']) {
+            paths.push(pattern.clone());
+            continue;
+        }
+
+        let mut matched = glob::glob(pattern)
+            .map_err(|e| anyhow!("Invalid attachment pattern '{pattern}': {e}"))?
+            .filter_map(Result::ok)
+            .filter(|path| path.is_file())
+            .map(|path| path.to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        if matched.is_empty() {
+            return Err(anyhow!("No files match the attachment pattern '{pattern}'"));
+        }
+        matched.sort();
+        paths.extend(matched);
+    }
+
+    if captions.len() > paths.len() {
+        return Err(anyhow!(
+            "{} captions were given for {} attachments",
+            captions.len(),
+            paths.len()
+        ));
+    }
+
+    let mut captions = captions.iter();
+    Ok(paths
+        .into_iter()
+        .map(|path| AttachmentInput {
+            path,
+            caption: captions
+                .next()
+                .cloned()
+                .filter(|caption| !caption.is_empty()),
+        })
+        .collect())
+}
+
+/// Dimensions and blurhash of the image, `None` when the file can't be decoded as an image
+fn image_metadata(data: &[u8]) -> Option<(u32, u32, Option<String>)> {
+    let image = image::load_from_memory(data).ok()?;
+    let (width, height) = (image.width(), image.height());
+
+    let small = image
+        .thumbnail(BLURHASH_MAX_SIDE, BLURHASH_MAX_SIDE)
+        .to_rgba8();
+    let (components_x, components_y) = BLURHASH_COMPONENTS;
+    let blur_hash = blurhash::encode(
+        components_x,
+        components_y,
+        small.width(),
+        small.height(),
+        small.as_raw(),
+    )
+    .map_err(|error| warn!(%error, "Failed to compute blurhash"))
+    .ok();
+
+    Some((width, height, blur_hash))
+}
+
+/// File of an attachment, with the content type and file name matching its format
+struct AttachmentFile {
+    data: Vec<u8>,
+    content_type: String,
+    file_name: String,
+}
+
+/// Scales the image down and re-encodes it, which also drops EXIF and XMP metadata.
+///
+/// The orientation from EXIF is applied first, so the image doesn't turn once it is gone.
+/// Animated GIFs are sent as they are. Images that can't be decoded fail, as their metadata,
+/// e.g. the location, can't be dropped.
+fn prepare_image(image: AttachmentFile, processing: &ImageProcessing) -> Result<AttachmentFile> {
+    if !processing.enabled || image.content_type == "image/gif" {
+        return Ok(image);
+    }
+
+    let mut decoder = ImageReader::new(Cursor::new(&image.data))
+        .with_guessed_format()?
+        .into_decoder()?;
+    let file_name = image.file_name;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+
+    let max_dimension = processing.max_dimension.max(1);
+    if image.width() > max_dimension || image.height() > max_dimension {
+        image = image.resize(
+            max_dimension,
+            max_dimension,
+            image::imageops::FilterType::Lanczos3,
+        );
+    }
+
+    // JPEG has no transparency, such images are kept lossless as WebP instead
+    let encoding = match processing.encoding {
+        ImageEncoding::Jpeg if image.color().has_alpha() => ImageEncoding::Webp,
+        encoding => encoding,
+    };
+    let mut encoded = Vec::new();
+    let extension = match encoding {
+        ImageEncoding::Jpeg => {
+            let encoder =
+                JpegEncoder::new_with_quality(&mut encoded, processing.quality.clamp(1, 100));
+            image.to_rgb8().write_with_encoder(encoder)?;
+            "jpg"
+        }
+        ImageEncoding::Webp => {
+            let encoder = WebPEncoder::new_lossless(&mut encoded);
+            image.to_rgba8().write_with_encoder(encoder)?;
+            "webp"
+        }
+    };
+
+    Ok(AttachmentFile {
+        data: encoded,
+        content_type: mime_guess::from_ext(extension)
+            .first_or_octet_stream()
+            .to_string(),
+        file_name: Path::new(&file_name)
+            .with_extension(extension)
+            .to_string_lossy()
+            .to_string(),
+    })
+}
+
+/// Prepares the image and reads its dimensions and blurhash on a blocking thread, decoding
+/// and encoding large images takes a while
+async fn prepare_image_blocking(
+    image: AttachmentFile,
+    processing: &ImageProcessing,
+) -> Result<(AttachmentFile, Option<(u32, u32, Option<String>)>)> {
+    let processing = processing.clone();
+    tokio::task::spawn_blocking(move || -> Result<_> {
+        let file_name = image.file_name.clone();
+        let image = prepare_image(image, &processing).map_err(|error| {
+            SendError::InvalidAttachment(format!(
+                "Failed to remove metadata from image {file_name}: {error}. Send it with \
+                 --keep-original, or turn off image_processing in the config, to send the file \
+                 as it is"
+            ))
+        })?;
+        let metadata = image_metadata(&image.data);
+        Ok((image, metadata))
+    })
+    .await?
+}
+
+/// Create attachment spec from file path, images get their dimensions and blurhash and are
+/// prepared according to `processing`
+pub async fn create_attachment(
+    attachment: &AttachmentInput,
+    processing: &ImageProcessing,
+) -> Result<(AttachmentSpec, Vec<u8>)> {
+    let attachment_path = &attachment.path;
     // Resolve absolute path
-    let path: PathBuf = fs::canonicalize(&attachment_path)
-        .map_err(|_| anyhow::anyhow!("Failed to resolve path: {attachment_path}"))?;
+    let path: PathBuf = fs::canonicalize(attachment_path).map_err(|_| {
+        SendError::InvalidAttachment(format!("Failed to resolve path: {attachment_path}"))
+    })?;
 
     if !path.exists() {
-        return Err(anyhow::anyhow!(
+        return Err(SendError::InvalidAttachment(format!(
             "Attachment file not found: {}",
             path.display()
-        ));
+        ))
+        .into());
     }
 
     if !path.is_file() {
-        return Err(anyhow::anyhow!(
+        return Err(SendError::InvalidAttachment(format!(
             "Attachment path is not a file: {}",
             path.display()
-        ));
+        ))
+        .into());
     }
 
     let file_data = fs::read(&path)?;
     let file_name = path
         .file_name()
-        .ok_or_else(|| anyhow::anyhow!("Invalid file name for path: {}", path.display()))?
+        .ok_or_else(|| {
+            SendError::InvalidAttachment(format!("Invalid file name for path: {}", path.display()))
+        })?
         .to_string_lossy()
         .to_string();
 
+    let content_type = mime_guess::from_path(&path)
+        .first()
+        .unwrap_or(APPLICATION_OCTET_STREAM)
+        .to_string();
+
+    let mut file = AttachmentFile {
+        data: file_data,
+        content_type,
+        file_name,
+    };
+    let (width, height, blur_hash) = if file.content_type.starts_with("image/") {
+        let (prepared, metadata) = prepare_image_blocking(file, processing).await?;
+        file = prepared;
+        match metadata {
+            Some((width, height, blur_hash)) => (Some(width), Some(height), blur_hash),
+            None => (None, None, None),
+        }
+    } else {
+        (None, None, None)
+    };
+    let AttachmentFile {
+        data: file_data,
+        content_type,
+        file_name,
+    } = file;
+
     let attachment_spec = AttachmentSpec {
-        content_type: mime_guess::from_path(&path)
-            .first()
-            .unwrap_or(APPLICATION_OCTET_STREAM)
-            .to_string(),
+        content_type,
         length: file_data.len(),
         file_name: Some(file_name),
         preview: None,
         voice_note: None,
         borderless: None,
-        width: None,
-        height: None,
-        caption: None,
-        blur_hash: None,
+        width,
+        height,
+        caption: attachment.caption.clone(),
+        blur_hash,
     };
 
     Ok((attachment_spec, file_data))
 }
+
+/// Create attachment specs of all files, fails when any of them can't be read
+pub async fn create_attachments(
+    attachments: &[AttachmentInput],
+    processing: &ImageProcessing,
+) -> Result<Vec<(AttachmentSpec, Vec<u8>)>> {
+    if attachments.is_empty() {
+        return Err(anyhow!("No attachment was given"));
+    }
+    let mut specs = Vec::with_capacity(attachments.len());
+    for attachment in attachments {
+        specs.push(create_attachment(attachment, processing).await?);
+    }
+    Ok(specs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn cached(dir: &Path, name: &str, len: usize, age_secs: u64) {
+        let path = dir.join(name);
+        fs::write(&path, vec![0; len]).unwrap();
+        let file = fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(age_secs))
+            .unwrap();
+    }
+
+    #[test]
+    fn least_recently_used_attachments_are_evicted() {
+        let dir = tempfile::tempdir().unwrap();
+        cached(dir.path(), "old", 40, 300);
+        cached(dir.path(), "used", 40, 100);
+        cached(dir.path(), "new", 40, 0);
+
+        evict_cached_attachments(dir.path(), 100).unwrap();
+        assert!(!dir.path().join("old").exists());
+        assert!(dir.path().join("used").exists());
+        assert!(dir.path().join("new").exists());
+    }
+
+    fn file(data: Vec<u8>, content_type: &str, file_name: &str) -> AttachmentFile {
+        AttachmentFile {
+            data,
+            content_type: content_type.to_string(),
+            file_name: file_name.to_string(),
+        }
+    }
+
+    #[test]
+    fn images_are_scaled_down_and_reencoded() {
+        let mut png = Vec::new();
+        DynamicImage::new_rgb8(100, 50)
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let processing = ImageProcessing {
+            max_dimension: 20,
+            ..ImageProcessing::default()
+        };
+
+        let prepared = prepare_image(file(png, "image/png", "photo.png"), &processing).unwrap();
+        assert_eq!(prepared.content_type, "image/jpeg");
+        assert_eq!(prepared.file_name, "photo.jpg");
+        let image = image::load_from_memory(&prepared.data).unwrap();
+        assert_eq!((image.width(), image.height()), (20, 10));
+    }
+
+    #[test]
+    fn undecodable_images_fail_unless_sent_as_they_are() {
+        let data = b"not an image".to_vec();
+        let processing = ImageProcessing::default();
+        assert!(prepare_image(file(data.clone(), "image/jpeg", "a.jpg"), &processing).is_err());
+
+        let original = ImageProcessing {
+            enabled: false,
+            ..ImageProcessing::default()
+        };
+        let kept = prepare_image(file(data.clone(), "image/jpeg", "a.jpg"), &original).unwrap();
+        assert_eq!(kept.data, data);
+    }
+
+    #[test]
+    fn cache_within_the_limit_is_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        cached(dir.path(), "a", 50, 10);
+        cached(dir.path(), "b", 50, 0);
+
+        evict_cached_attachments(dir.path(), 100).unwrap();
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+}
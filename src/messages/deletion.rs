@@ -0,0 +1,303 @@
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Result, bail};
+use presage::Manager;
+use presage::libsignal_service::prelude::Content;
+use presage::manager::Registered;
+use presage::store::{ContentsStore, Thread};
+use presage_store_sqlite::SqliteStore;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error};
+
+use crate::account_management::create_registered_manager;
+use crate::app::RecipientId;
+use crate::groups::find_master_key;
+use crate::matching::confirm;
+use crate::messages::receive::{extract_delete, extract_edit, extract_reaction};
+use crate::messages::send::contact::find_uuid;
+use crate::paths;
+use crate::search::SearchIndex;
+use crate::sidecar::{AccountFile, JsonFile};
+
+/// What is deleted from this device only, nothing is sent to the other side
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LocalDeletion {
+    /// A single message of any author, identified by its timestamp
+    Message(u64),
+    /// All messages of the conversation
+    History,
+    /// All messages, and the conversation is hidden from the list until a new message arrives
+    Conversation,
+}
+
+/// Deletes the message a received deletion for everyone targets from the store, called for
+/// received messages when `purge_deleted_messages` is set in the config. Returns the
+/// conversation and timestamp of the purged message.
+///
+/// Nothing is shown in place of a purged message, as its author can't be checked anymore.
+pub async fn purge_deleted_message(
+    manager: &Manager<SqliteStore, Registered>,
+    content: &Content,
+) -> Result<Option<(Thread, u64)>> {
+    let Some(delete) = extract_delete(content) else {
+        return Ok(None);
+    };
+    let Ok(thread) = Thread::try_from(content) else {
+        return Ok(None);
+    };
+
+    let timestamp = delete.target_sent_timestamp;
+    let Some(target) = manager.store().message(&thread, timestamp).await? else {
+        return Ok(None);
+    };
+    // Only the author of a message may delete it for everyone
+    if target.metadata.sender.raw_uuid() != delete.author {
+        return Ok(None);
+    }
+
+    manager
+        .store()
+        .clone()
+        .delete_message(&thread, timestamp)
+        .await?;
+    debug!(timestamp, "Purged message deleted by its author");
+    Ok(Some((thread, timestamp)))
+}
+
+/// Timestamp of the message a reaction, edit or deletion for everyone refers to
+fn target_timestamp(content: &Content) -> Option<u64> {
+    if let Some((_, reaction)) = extract_reaction(content) {
+        return Some(reaction.target_sent_timestamp());
+    }
+    if let Some(edit) = extract_edit(content) {
+        return Some(edit.target_sent_timestamp);
+    }
+    extract_delete(content).map(|delete| delete.target_sent_timestamp)
+}
+
+/// Deletes a message of any author from this device only, together with the reactions, edits
+/// and deletions for everyone referring to it, so not even its tombstone is left. Returns the
+/// timestamps of all deleted contents.
+pub async fn delete_for_me(
+    manager: &Manager<SqliteStore, Registered>,
+    thread: &Thread,
+    timestamp: u64,
+) -> Result<Vec<u64>> {
+    let mut deleted = manager
+        .store()
+        .messages(thread, ..)
+        .await?
+        .flatten()
+        .filter(|content| target_timestamp(content) == Some(timestamp))
+        .map(|content| content.timestamp())
+        .collect::<Vec<_>>();
+    if manager.store().message(thread, timestamp).await?.is_some() {
+        deleted.push(timestamp);
+    }
+    if deleted.is_empty() {
+        bail!("Message with given timestamp not found.");
+    }
+
+    let mut store = manager.store().clone();
+    for timestamp in &deleted {
+        store.delete_message(thread, *timestamp).await?;
+    }
+    debug!(timestamp, count = deleted.len(), "Deleted message for me");
+    Ok(deleted)
+}
+
+/// Deletes all messages of the conversation from this device only
+pub async fn clear_history(
+    manager: &Manager<SqliteStore, Registered>,
+    thread: &Thread,
+) -> Result<()> {
+    manager.store().clone().clear_thread(thread).await?;
+    debug!("Cleared conversation history");
+    Ok(())
+}
+
+/// Deletes messages from the store and the search index. Hiding a deleted conversation is left
+/// to the caller, which may keep the hidden conversations loaded.
+pub async fn delete_locally(
+    manager: &Manager<SqliteStore, Registered>,
+    thread: &Thread,
+    deletion: LocalDeletion,
+    search_index: Option<&SearchIndex>,
+) -> Result<()> {
+    match deletion {
+        LocalDeletion::Message(timestamp) => {
+            let deleted = delete_for_me(manager, thread, timestamp).await?;
+            if let Some(search_index) = search_index {
+                for timestamp in deleted {
+                    search_index.remove_message(thread, timestamp).await?;
+                }
+            }
+        }
+        LocalDeletion::History | LocalDeletion::Conversation => {
+            clear_history(manager, thread).await?;
+            if let Some(search_index) = search_index {
+                search_index.remove_thread(thread).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct HiddenConversation {
+    recipient: RecipientId,
+    /// When the conversation was deleted, in milliseconds since the epoch
+    hidden_at: u64,
+}
+
+/// Conversations deleted from the list, persisted per account.
+///
+/// The store keeps contacts and groups, so they are only hidden until a newer message arrives.
+#[derive(Default, Serialize, Deserialize)]
+pub struct HiddenConversations {
+    conversations: Vec<HiddenConversation>,
+    #[serde(skip)]
+    file: JsonFile,
+}
+
+impl AccountFile for HiddenConversations {
+    const NAME: &'static str = "hidden conversations";
+
+    fn path(account_name: &str) -> PathBuf {
+        paths::account_hidden_conversations_path(account_name)
+    }
+
+    fn file_mut(&mut self) -> &mut JsonFile {
+        &mut self.file
+    }
+}
+
+impl HiddenConversations {
+    pub fn hide(&mut self, recipient: RecipientId) {
+        let hidden_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis() as u64)
+            .unwrap_or_default();
+        self.conversations
+            .retain(|conversation| conversation.recipient != recipient);
+        self.conversations.push(HiddenConversation {
+            recipient,
+            hidden_at,
+        });
+        self.persist();
+    }
+
+    /// Whether the conversation was deleted and has no message newer than that
+    pub fn is_hidden(
+        &self,
+        recipient: &RecipientId,
+        latest_message_timestamp: Option<u64>,
+    ) -> bool {
+        self.conversations.iter().any(|conversation| {
+            &conversation.recipient == recipient
+                && latest_message_timestamp
+                    .is_none_or(|timestamp| timestamp <= conversation.hidden_at)
+        })
+    }
+
+    fn persist(&self) {
+        self.file.save(self);
+    }
+}
+
+/// Resolves the conversation of the contact or the group given on the command line
+async fn find_recipient(
+    manager: &mut Manager<SqliteStore, Registered>,
+    contact: Option<String>,
+    group: Option<String>,
+) -> Result<RecipientId> {
+    match (contact, group) {
+        (Some(contact), None) => Ok(RecipientId::Contact(find_uuid(contact, manager).await?)),
+        (None, Some(group)) => match find_master_key(group, manager).await? {
+            Some(master_key) => Ok(RecipientId::Group(master_key)),
+            None => bail!("Group with given name does not exist."),
+        },
+        _ => bail!("Either a contact or a group has to be given."),
+    }
+}
+
+/// Deletes messages of a conversation locally from the command line, clearing history asks for
+/// confirmation unless `yes` is set
+pub async fn delete_locally_cli(
+    contact: Option<String>,
+    group: Option<String>,
+    deletion: LocalDeletion,
+    yes: bool,
+) -> Result<()> {
+    let mut manager = create_registered_manager().await?;
+    let recipient = find_recipient(&mut manager, contact, group).await?;
+
+    let question = match deletion {
+        LocalDeletion::Message(_) => None,
+        LocalDeletion::History => Some("Delete all messages of the conversation on this device?"),
+        LocalDeletion::Conversation => {
+            Some("Delete the conversation and all its messages on this device?")
+        }
+    };
+    if let Some(question) = question
+        && !yes
+        && !confirm(question)?
+    {
+        bail!("Deletion cancelled, pass --yes to delete without asking.");
+    }
+
+    let search_index = match SearchIndex::open_current().await {
+        Ok(search_index) => Some(search_index),
+        Err(error) => {
+            error!(%error, "Failed to open the search index, it may still list deleted messages");
+            None
+        }
+    };
+    delete_locally(
+        &manager,
+        &recipient.thread(),
+        deletion,
+        search_index.as_ref(),
+    )
+    .await?;
+    if deletion == LocalDeletion::Conversation {
+        HiddenConversations::load_current()?.hide(recipient);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use presage::libsignal_service::prelude::Uuid;
+
+    use super::*;
+
+    #[test]
+    fn conversation_is_hidden_until_a_newer_message() {
+        let mut hidden = HiddenConversations::default();
+        let contact = RecipientId::Contact(Uuid::nil());
+        hidden.hide(contact.clone());
+        let hidden_at = hidden.conversations[0].hidden_at;
+
+        assert!(hidden.is_hidden(&contact, None));
+        assert!(hidden.is_hidden(&contact, Some(hidden_at)));
+        assert!(!hidden.is_hidden(&contact, Some(hidden_at + 1)));
+        assert!(!hidden.is_hidden(&RecipientId::Group([1; 32]), None));
+    }
+
+    #[test]
+    fn hiding_again_replaces_the_earlier_deletion() {
+        let mut hidden = HiddenConversations::default();
+        let group = RecipientId::Group([1; 32]);
+        hidden.conversations.push(HiddenConversation {
+            recipient: group.clone(),
+            hidden_at: 100,
+        });
+        assert!(!hidden.is_hidden(&group, Some(200)));
+
+        hidden.hide(group.clone());
+        assert_eq!(hidden.conversations.len(), 1);
+        assert!(hidden.is_hidden(&group, Some(200)));
+    }
+}
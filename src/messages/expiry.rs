@@ -0,0 +1,479 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Result, anyhow, bail};
+use presage::Manager;
+use presage::libsignal_service::content::ContentBody;
+use presage::libsignal_service::prelude::{Content, Uuid};
+use presage::libsignal_service::protocol::ServiceId;
+use presage::manager::Registered;
+use presage::proto::data_message::Flags;
+use presage::proto::{DataMessage, SyncMessage, sync_message::Sent};
+use presage::store::{ContentsStore, Thread};
+use presage_store_sqlite::SqliteStore;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::account_management::create_registered_manager;
+use crate::messages::receive::MessageDto;
+use crate::messages::send::contact::find_uuid;
+use crate::messages::send::{SendError, send_failed};
+use crate::paths;
+use crate::search::{parse_recipient_key, thread_key};
+use crate::sidecar::{AccountFile, JsonFile};
+
+/// Timers offered by the info screens, in seconds, `0` turns disappearing messages off
+pub const TIMER_PRESETS: [u32; 8] = [0, 30, 5 * MINUTE, HOUR, 8 * HOUR, DAY, WEEK, 4 * WEEK];
+
+const MINUTE: u32 = 60;
+const HOUR: u32 = 60 * MINUTE;
+const DAY: u32 = 24 * HOUR;
+const WEEK: u32 = 7 * DAY;
+
+fn data_message(content: &Content) -> Option<&DataMessage> {
+    match &content.body {
+        ContentBody::DataMessage(data_message) => Some(data_message),
+        ContentBody::SynchronizeMessage(SyncMessage {
+            sent:
+                Some(Sent {
+                    message: Some(data_message),
+                    ..
+                }),
+            ..
+        }) => Some(data_message),
+        _ => None,
+    }
+}
+
+fn is_timer_update(data_message: &DataMessage) -> bool {
+    data_message.flags() & Flags::ExpirationTimerUpdate as u32 != 0
+}
+
+/// Timer of the message in seconds, `None` if it doesn't disappear
+pub fn message_timer(content: &Content) -> Option<u32> {
+    let data_message = data_message(content)?;
+    match data_message.expire_timer {
+        Some(seconds) if seconds > 0 && !is_timer_update(data_message) => Some(seconds),
+        _ => None,
+    }
+}
+
+/// Time in milliseconds when a message with the timer disappears, if the timer started then
+pub fn timer_end(started_at: u64, seconds: u32) -> u64 {
+    started_at + seconds as u64 * 1000
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// New timer of the conversation if the content changes it
+pub fn timer_update(content: &Content) -> Option<u32> {
+    let data_message = data_message(content)?;
+    is_timer_update(data_message).then(|| data_message.expire_timer.unwrap_or(0))
+}
+
+/// Preset following `seconds`, used to cycle through the presets
+pub fn next_preset(seconds: u32) -> u32 {
+    TIMER_PRESETS
+        .into_iter()
+        .find(|preset| *preset > seconds)
+        .unwrap_or(TIMER_PRESETS[0])
+}
+
+/// Timer formatted for humans, e.g. `5m` or `off`
+pub fn format_timer(seconds: u32) -> String {
+    match seconds {
+        0 => "off".to_string(),
+        s if s % WEEK == 0 => format!("{}w", s / WEEK),
+        s if s % DAY == 0 => format!("{}d", s / DAY),
+        s if s % HOUR == 0 => format!("{}h", s / HOUR),
+        s if s % MINUTE == 0 => format!("{}m", s / MINUTE),
+        s => format!("{s}s"),
+    }
+}
+
+/// Parses timer given as `off`, seconds, or a number with one of `s`, `m`, `h`, `d`, `w`
+pub fn parse_timer(value: &str) -> Result<u32> {
+    let value = value.trim().to_lowercase();
+    if value == "off" {
+        return Ok(0);
+    }
+
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => value.split_at(index),
+        None => (value.as_str(), "s"),
+    };
+    let unit = match unit {
+        "s" => 1,
+        "m" => MINUTE,
+        "h" => HOUR,
+        "d" => DAY,
+        "w" => WEEK,
+        _ => bail!("Invalid timer '{value}', expected e.g. 'off', '30s', '5m', '1h', '1d' or '1w'"),
+    };
+    let number: u32 = number
+        .parse()
+        .map_err(|_| anyhow!("Invalid timer '{value}', expected a number with a unit"))?;
+
+    number
+        .checked_mul(unit)
+        .ok_or_else(|| anyhow!("Timer '{value}' is too long"))
+}
+
+/// Current timer of the conversation in seconds, `0` when messages don't disappear
+pub async fn expire_timer(
+    manager: &Manager<SqliteStore, Registered>,
+    thread: &Thread,
+) -> Result<u32> {
+    let timer = match thread {
+        Thread::Contact(uuid) => manager
+            .store()
+            .contact_by_id(uuid)
+            .await?
+            .map(|contact| contact.expire_timer),
+        Thread::Group(master_key) => manager
+            .store()
+            .group(*master_key)
+            .await?
+            .and_then(|group| group.disappearing_messages_timer)
+            .map(|timer| timer.duration),
+    };
+    Ok(timer.unwrap_or(0))
+}
+
+/// Sets the timer of the conversation on the message unless it already has one.
+///
+/// Other clients take the timer of every incoming message as the timer of the conversation, so
+/// it has to be present on everything we send.
+pub async fn apply_expire_timer(
+    manager: &Manager<SqliteStore, Registered>,
+    thread: &Thread,
+    data_message: &mut DataMessage,
+) -> Result<()> {
+    if data_message.expire_timer.is_none() {
+        let timer = expire_timer(manager, thread).await?;
+        data_message.expire_timer = (timer > 0).then_some(timer);
+    }
+    Ok(())
+}
+
+/// Changes the timer of the conversation with a contact and tells the contact about it
+pub async fn set_contact_expire_timer(
+    manager: &mut Manager<SqliteStore, Registered>,
+    uuid: Uuid,
+    seconds: u32,
+) -> Result<()> {
+    let Some(mut contact) = manager.store().contact_by_id(&uuid).await? else {
+        return Err(SendError::UnknownRecipient(uuid.to_string()).into());
+    };
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+    let data_message = DataMessage {
+        flags: Some(Flags::ExpirationTimerUpdate as u32),
+        expire_timer: Some(seconds),
+        timestamp: Some(timestamp),
+        ..Default::default()
+    };
+    manager
+        .send_message(ServiceId::Aci(uuid.into()), data_message, timestamp)
+        .await
+        .map_err(send_failed)?;
+
+    contact.expire_timer = seconds;
+    manager.store().clone().save_contact(&contact).await?;
+    Ok(())
+}
+
+pub async fn set_contact_expire_timer_cli(recipient: String, timer: String) -> Result<()> {
+    let seconds = parse_timer(&timer)?;
+    let mut manager = create_registered_manager().await?;
+    let uuid = find_uuid(recipient, &mut manager).await?;
+    set_contact_expire_timer(&mut manager, uuid, seconds).await
+}
+
+/// Saves timer changes made by a contact, or by us on another device, to the contact
+pub async fn save_timer_update(
+    manager: &Manager<SqliteStore, Registered>,
+    content: &Content,
+) -> Result<()> {
+    let Some(seconds) = timer_update(content) else {
+        return Ok(());
+    };
+    // Timers of groups are part of the group state, which is kept by presage
+    let Ok(Thread::Contact(uuid)) = Thread::try_from(content) else {
+        return Ok(());
+    };
+
+    if let Some(mut contact) = manager.store().contact_by_id(&uuid).await?
+        && contact.expire_timer != seconds
+    {
+        contact.expire_timer = seconds;
+        manager.store().clone().save_contact(&contact).await?;
+        debug!(%uuid, seconds, "Updated disappearing messages timer");
+    }
+    Ok(())
+}
+
+/// Message whose disappearing timer is running
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+struct Due {
+    /// When the message disappears, first so the set is ordered by it
+    expires_at: u64,
+    thread: String,
+    timestamp: u64,
+}
+
+/// Messages whose disappearing timer is running, ordered by when they disappear, persisted per
+/// account.
+///
+/// Timers of our messages run from the moment they were sent, timers of received messages
+/// from the moment they were read. Messages are tracked once they are listed, so purging only
+/// touches the ones that are due instead of scanning every conversation.
+#[derive(Default, Serialize, Deserialize)]
+pub struct ExpiryIndex {
+    #[serde(default)]
+    due: BTreeSet<Due>,
+    #[serde(skip)]
+    file: JsonFile,
+}
+
+impl AccountFile for ExpiryIndex {
+    const NAME: &'static str = "disappearing messages";
+
+    fn path(account_name: &str) -> PathBuf {
+        paths::account_expiry_path(account_name)
+    }
+
+    fn file_mut(&mut self) -> &mut JsonFile {
+        &mut self.file
+    }
+}
+
+impl ExpiryIndex {
+    /// Starts timers of the listed messages of the conversation which don't run yet, and fills
+    /// in when every tracked message disappears.
+    ///
+    /// Received messages count as read when `read` is set, their timers start at `now`. Timers of
+    /// our messages started when they were sent.
+    pub fn track(&mut self, thread: &Thread, messages: &mut [MessageDto], read: bool, now: u64) {
+        self.apply(thread, messages);
+
+        let thread = thread_key(thread);
+        let mut changed = false;
+        for message in messages {
+            let Some(seconds) = message.expire_timer else {
+                continue;
+            };
+            let expires_at = match message.expires_at {
+                Some(expires_at) => expires_at,
+                None if read => timer_end(now, seconds),
+                None => continue,
+            };
+            message.expires_at = Some(expires_at);
+            changed |= self.due.insert(Due {
+                expires_at,
+                thread: thread.clone(),
+                timestamp: message.timestamp,
+            });
+        }
+
+        if changed {
+            self.persist();
+        }
+    }
+
+    /// Starts timers of received messages of the conversation that were just read, by their
+    /// timestamps and when they disappear
+    pub fn start(&mut self, thread: &Thread, timers: &[(u64, u64)]) {
+        let thread = thread_key(thread);
+        let mut changed = false;
+        for (timestamp, expires_at) in timers {
+            let running = self
+                .due
+                .iter()
+                .any(|due| due.thread == thread && due.timestamp == *timestamp);
+            if !running {
+                changed |= self.due.insert(Due {
+                    expires_at: *expires_at,
+                    thread: thread.clone(),
+                    timestamp: *timestamp,
+                });
+            }
+        }
+
+        if changed {
+            self.persist();
+        }
+    }
+
+    /// Fills in when messages of the conversation disappear whose timers already run
+    pub fn apply(&self, thread: &Thread, messages: &mut [MessageDto]) {
+        let thread = thread_key(thread);
+        let running = self
+            .due
+            .iter()
+            .filter(|due| due.thread == thread)
+            .map(|due| (due.timestamp, due.expires_at))
+            .collect::<HashMap<_, _>>();
+        if running.is_empty() {
+            return;
+        }
+
+        for message in messages {
+            if let Some(expires_at) = running.get(&message.timestamp) {
+                message.expires_at = Some(*expires_at);
+            }
+        }
+    }
+
+    /// Removes messages whose timer ran out by `now` from the index and returns them
+    fn take_due(&mut self, now: u64) -> Vec<(Thread, u64)> {
+        let mut expired = Vec::new();
+        while let Some(due) = self.due.first()
+            && due.expires_at <= now
+        {
+            let due = self.due.pop_first().expect("first entry exists");
+            if let Some(recipient) = parse_recipient_key(&due.thread) {
+                expired.push((recipient.thread(), due.timestamp));
+            }
+        }
+
+        if !expired.is_empty() {
+            self.persist();
+        }
+        expired
+    }
+
+    /// Deletes messages whose timer ran out from the store, returns them
+    pub async fn purge_due(
+        &mut self,
+        manager: &Manager<SqliteStore, Registered>,
+    ) -> Result<Vec<(Thread, u64)>> {
+        let expired = self.take_due(now_millis());
+        let mut store = manager.store().clone();
+        for (thread, timestamp) in &expired {
+            store.delete_message(thread, *timestamp).await?;
+        }
+        if !expired.is_empty() {
+            debug!(count = expired.len(), "Purged expired messages");
+        }
+        Ok(expired)
+    }
+
+    fn persist(&self) {
+        self.file.save(self);
+    }
+}
+
+/// Deletes expired messages of the account and tracks the listed messages of the conversation,
+/// they are read by the user of the CLI
+pub async fn track_listed_cli(
+    manager: &Manager<SqliteStore, Registered>,
+    thread: &Thread,
+    messages: &mut Vec<MessageDto>,
+) -> Result<()> {
+    let mut index = ExpiryIndex::load_current()?;
+    index.track(thread, messages, true, now_millis());
+    index.purge_due(manager).await?;
+    retain_live(messages);
+    Ok(())
+}
+
+/// Drops messages whose timer ran out, they are hidden even before they are purged
+pub fn retain_live(messages: &mut Vec<MessageDto>) {
+    let now = now_millis();
+    messages.retain(|message| message.expires_at.is_none_or(|expires_at| expires_at > now));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(timestamp: u64, expire_timer: Option<u32>, sent: bool) -> MessageDto {
+        let mut message = MessageDto::plain(Uuid::nil(), timestamp, sent, "");
+        message.expire_timer = expire_timer;
+        message.expires_at = expire_timer
+            .filter(|_| sent)
+            .map(|seconds| timer_end(timestamp, seconds));
+        message
+    }
+
+    #[test]
+    fn timers_are_parsed_and_formatted() {
+        assert_eq!(parse_timer("off").unwrap(), 0);
+        assert_eq!(parse_timer("45").unwrap(), 45);
+        assert_eq!(parse_timer(" 5M ").unwrap(), 5 * MINUTE);
+        assert_eq!(parse_timer("2w").unwrap(), 2 * WEEK);
+        assert!(parse_timer("5y").is_err());
+        assert!(parse_timer("99999999w").is_err());
+
+        assert_eq!(format_timer(0), "off");
+        assert_eq!(format_timer(DAY), "1d");
+        assert_eq!(format_timer(90), "90s");
+        assert_eq!(next_preset(4 * WEEK), 0);
+    }
+
+    #[test]
+    fn received_timer_starts_when_read() {
+        let thread = Thread::Contact(Uuid::nil());
+        let mut index = ExpiryIndex::default();
+        let mut messages = vec![message(100, Some(30), false)];
+
+        index.track(&thread, &mut messages, false, 5_000);
+        assert_eq!(messages[0].expires_at, None);
+        assert!(index.take_due(u64::MAX).is_empty());
+
+        index.track(&thread, &mut messages, true, 5_000);
+        assert_eq!(messages[0].expires_at, Some(35_000));
+        assert!(index.take_due(34_999).is_empty());
+        let expired = index.take_due(35_000);
+        assert_eq!(expired.len(), 1);
+        assert!(expired[0] == (thread, 100));
+    }
+
+    #[test]
+    fn sent_timer_runs_from_sending() {
+        let thread = Thread::Group([1; 32]);
+        let mut index = ExpiryIndex::default();
+        let mut messages = vec![message(100, Some(30), true), message(200, None, true)];
+
+        index.track(&thread, &mut messages, false, 90_000);
+        assert_eq!(messages[0].expires_at, Some(30_100));
+        assert_eq!(messages[1].expires_at, None);
+        assert_eq!(index.take_due(90_000).len(), 1);
+    }
+
+    #[test]
+    fn started_timers_are_not_restarted() {
+        let thread = Thread::Contact(Uuid::nil());
+        let mut index = ExpiryIndex::default();
+        index.start(&thread, &[(100, 10_000)]);
+        index.start(&thread, &[(100, 20_000)]);
+
+        // Listing the message again shows the running timer
+        let mut messages = vec![message(100, Some(30), false)];
+        index.track(&thread, &mut messages, true, 15_000);
+        assert_eq!(messages[0].expires_at, Some(10_000));
+        assert_eq!(index.take_due(10_000).len(), 1);
+        assert!(index.take_due(u64::MAX).is_empty());
+    }
+
+    #[test]
+    fn due_messages_are_taken_in_order_of_expiry() {
+        let first = Thread::Contact(Uuid::nil());
+        let second = Thread::Group([2; 32]);
+        let mut index = ExpiryIndex::default();
+        index.start(&first, &[(1, 3_000), (2, 1_000)]);
+        index.start(&second, &[(3, 2_000)]);
+
+        let expired = index.take_due(2_500);
+        assert!(expired == vec![(first.clone(), 2), (second, 3)]);
+        assert!(index.take_due(2_500).is_empty());
+        assert!(index.take_due(3_000) == vec![(first, 1)]);
+    }
+}
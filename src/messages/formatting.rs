@@ -0,0 +1,457 @@
+use std::ops::Range;
+
+use presage::libsignal_service::prelude::Uuid;
+use presage::proto::BodyRange;
+use presage::proto::body_range::{AssociatedValue, Style};
+use serde::Serialize;
+
+use crate::messages::mentions::{
+    MENTION_PLACEHOLDER, Mention, fallback_label, mention_token_regex,
+};
+use crate::messages::receive::MessageDto;
+
+/// Style applied to a part of the message text
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum TextStyle {
+    Bold,
+    Italic,
+    Strikethrough,
+    Monospace,
+    Spoiler,
+}
+
+impl TextStyle {
+    fn from_proto(style: Style) -> Option<Self> {
+        match style {
+            Style::None => None,
+            Style::Bold => Some(TextStyle::Bold),
+            Style::Italic => Some(TextStyle::Italic),
+            Style::Spoiler => Some(TextStyle::Spoiler),
+            Style::Strikethrough => Some(TextStyle::Strikethrough),
+            Style::Monospace => Some(TextStyle::Monospace),
+        }
+    }
+
+    fn to_proto(self) -> Style {
+        match self {
+            TextStyle::Bold => Style::Bold,
+            TextStyle::Italic => Style::Italic,
+            TextStyle::Spoiler => Style::Spoiler,
+            TextStyle::Strikethrough => Style::Strikethrough,
+            TextStyle::Monospace => Style::Monospace,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StyledRange {
+    pub style: TextStyle,
+    /// Bytes of `MessageDto::text` with the style
+    pub range: Range<usize>,
+}
+
+/// Markers of the composer syntax, longer markers have to come before their prefixes
+const MARKERS: [(&str, TextStyle); 6] = [
+    ("**", TextStyle::Bold),
+    ("~~", TextStyle::Strikethrough),
+    ("||", TextStyle::Spoiler),
+    ("`", TextStyle::Monospace),
+    ("*", TextStyle::Italic),
+    ("_", TextStyle::Italic),
+];
+
+/// Characters which keep being text after a backslash instead of starting or ending a style
+const ESCAPABLE: [char; 6] = ['\\', '*', '_', '~', '|', '`'];
+
+/// Message text with mentions and styles taken from the body ranges
+pub struct FormattedBody {
+    pub text: String,
+    pub mentions: Vec<Mention>,
+    pub styles: Vec<StyledRange>,
+}
+
+/// Turns the body and its ranges into text, mention placeholders are replaced by `@` and the
+/// start of the UUID until the names are resolved.
+///
+/// Body ranges count UTF-16 code units, they are converted to bytes of the resulting text.
+pub fn parse_body(body: &str, body_ranges: &[BodyRange]) -> FormattedBody {
+    let mut mention_starts = body_ranges
+        .iter()
+        .filter_map(|range| match &range.associated_value {
+            Some(AssociatedValue::MentionAci(aci)) => Some((
+                range.start() as usize,
+                range.length() as usize,
+                aci.parse::<Uuid>().ok()?,
+            )),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    mention_starts.sort_by_key(|(start, _, _)| *start);
+
+    let mut text = String::with_capacity(body.len());
+    let mut mentions = Vec::new();
+    // Byte offset in `text` for every UTF-16 offset in the body, including the end
+    let mut offsets = Vec::with_capacity(body.len() + 1);
+    let mut pending = mention_starts.into_iter().peekable();
+    let mut skip_until = 0;
+
+    for c in body.chars() {
+        let offset = offsets.len();
+        offsets.extend(std::iter::repeat_n(text.len(), c.len_utf16()));
+        if offset < skip_until {
+            continue;
+        }
+
+        match pending.next_if(|(start, _, _)| *start <= offset) {
+            Some((_, length, uuid)) => {
+                let start = text.len();
+                text.push('@');
+                text.push_str(&fallback_label(uuid));
+                mentions.push(Mention {
+                    uuid,
+                    range: start..text.len(),
+                });
+                skip_until = offset + length.max(1);
+            }
+            None => text.push(c),
+        }
+    }
+    offsets.push(text.len());
+
+    let styles = body_ranges
+        .iter()
+        .filter_map(|range| {
+            let Some(AssociatedValue::Style(style)) = range.associated_value else {
+                return None;
+            };
+            let style = TextStyle::from_proto(Style::try_from(style).ok()?)?;
+            let start = range.start() as usize;
+            let end = start.checked_add(range.length() as usize)?;
+            let start = *offsets.get(start)?;
+            let end = *offsets.get(end).unwrap_or(&text.len());
+            (start < end).then_some(StyledRange {
+                style,
+                range: start..end,
+            })
+        })
+        .collect();
+
+    FormattedBody {
+        text,
+        mentions,
+        styles,
+    }
+}
+
+/// Removes markers of the composer syntax, e.g. `**bold**` or `||spoiler||`, from the text and
+/// returns the styled parts as byte ranges of the plain text. A backslash keeps the following
+/// marker as text, e.g. `\*not italic\*`.
+pub fn parse_markdown(text: &str) -> (String, Vec<StyledRange>) {
+    let mut plain = String::with_capacity(text.len());
+    let mut styles = Vec::new();
+    let mut rest = text;
+
+    'outer: while let Some(c) = rest.chars().next() {
+        if c == '\\'
+            && let Some(escaped) = rest[1..].chars().next()
+            && ESCAPABLE.contains(&escaped)
+        {
+            plain.push(escaped);
+            rest = &rest[1 + escaped.len_utf8()..];
+            continue;
+        }
+
+        for (marker, style) in MARKERS {
+            let Some(after_open) = rest.strip_prefix(marker) else {
+                continue;
+            };
+            // Single markers inside words, e.g. in snake_case or 2*3*4, are not markers
+            if is_in_word(marker) && plain.chars().last().is_some_and(char::is_alphanumeric) {
+                continue;
+            }
+            let Some(close) = find_closing(after_open, marker, style) else {
+                continue;
+            };
+            let inner = &after_open[..close];
+            if inner.is_empty()
+                || inner.starts_with(char::is_whitespace)
+                || inner.ends_with(char::is_whitespace)
+            {
+                continue;
+            }
+
+            let start = plain.len();
+            if style == TextStyle::Monospace {
+                plain.push_str(inner);
+            } else {
+                let (inner_plain, inner_styles) = parse_markdown(inner);
+                plain.push_str(&inner_plain);
+                styles.extend(inner_styles.into_iter().map(|styled| StyledRange {
+                    style: styled.style,
+                    range: styled.range.start + start..styled.range.end + start,
+                }));
+            }
+            styles.push(StyledRange {
+                style,
+                range: start..plain.len(),
+            });
+            rest = &after_open[close + marker.len()..];
+            continue 'outer;
+        }
+
+        plain.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    (plain, styles)
+}
+
+/// Whether the marker only counts at the start or end of a word
+fn is_in_word(marker: &str) -> bool {
+    matches!(marker, "*" | "_")
+}
+
+/// Byte offset of the marker closing a style in the text after the opening one. Escaped markers
+/// don't close it, except in monospace where backslashes are text, and markers which only count
+/// at the end of a word don't close it in the middle of one.
+fn find_closing(text: &str, marker: &str, style: TextStyle) -> Option<usize> {
+    let mut offset = 0;
+    while let Some(c) = text[offset..].chars().next() {
+        let rest = &text[offset..];
+        if c == '\\' && style != TextStyle::Monospace {
+            offset += 1 + rest[1..].chars().next().map_or(0, char::len_utf8);
+            continue;
+        }
+        if let Some(after) = rest.strip_prefix(marker)
+            && !(is_in_word(marker) && after.starts_with(char::is_alphanumeric))
+        {
+            return Some(offset);
+        }
+        offset += c.len_utf8();
+    }
+    None
+}
+
+/// Escapes every marker of the composer syntax, so `create_body` sends the text as it is,
+/// e.g. for the CLI without `--markdown`
+pub fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if ESCAPABLE.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Creates the body of an outgoing message from text in the composer syntax, with mention
+/// tokens replaced by placeholders and both styles and mentions described by body ranges.
+/// Tokens in monospace are code and stay text.
+pub fn create_body(text: &str) -> (String, Vec<BodyRange>) {
+    let (plain, styles) = parse_markdown(text);
+    let is_code = |offset: usize| {
+        styles
+            .iter()
+            .any(|styled| styled.style == TextStyle::Monospace && styled.range.contains(&offset))
+    };
+
+    let mut body = String::with_capacity(plain.len());
+    let mut body_ranges = Vec::new();
+    // Start of every mention token in `plain`, with its length there and in `body`
+    let mut replaced = Vec::new();
+    let mut last_end = 0;
+    for captures in mention_token_regex().captures_iter(&plain) {
+        let token = captures.get(0).expect("Capture 0 is the whole match");
+        if is_code(token.start()) {
+            continue;
+        }
+        body.push_str(&plain[last_end..token.start()]);
+        body_ranges.push(BodyRange {
+            start: Some(utf16_len(&body)),
+            length: Some(MENTION_PLACEHOLDER.len_utf16() as u32),
+            associated_value: Some(AssociatedValue::MentionAci(captures[1].to_lowercase())),
+        });
+        replaced.push((token.start(), token.len(), MENTION_PLACEHOLDER.len_utf8()));
+        body.push(MENTION_PLACEHOLDER);
+        last_end = token.end();
+    }
+    body.push_str(&plain[last_end..]);
+
+    // Offsets after a token move by the difference of the token and placeholder lengths
+    let to_body = |offset: usize| {
+        let mut shifted = offset;
+        for (start, token_len, placeholder_len) in &replaced {
+            if offset >= start + token_len {
+                shifted = shifted - token_len + placeholder_len;
+            } else if offset > *start {
+                shifted = shifted - (offset - start) + placeholder_len;
+            }
+        }
+        shifted
+    };
+    for styled in styles {
+        let start = to_body(styled.range.start);
+        let end = to_body(styled.range.end);
+        body_ranges.push(BodyRange {
+            start: Some(utf16_len(&body[..start])),
+            length: Some(utf16_len(&body[start..end])),
+            associated_value: Some(AssociatedValue::Style(styled.style.to_proto().into())),
+        });
+    }
+
+    (body, body_ranges)
+}
+
+fn utf16_len(text: &str) -> u32 {
+    text.encode_utf16().count() as u32
+}
+
+/// Shown instead of every character of a spoiler
+pub const SPOILER_MASK: char = '░';
+
+/// Text with every character of spoilers masked, for places where they can't be revealed, e.g.
+/// notifications. The masked text has as many characters as the original one.
+pub fn mask_spoilers(text: &str, styles: &[StyledRange]) -> String {
+    let hidden = |offset: usize| {
+        styles
+            .iter()
+            .any(|styled| styled.style == TextStyle::Spoiler && styled.range.contains(&offset))
+    };
+    text.char_indices()
+        .map(|(offset, c)| match hidden(offset) && c != '\n' {
+            true => SPOILER_MASK,
+            false => c,
+        })
+        .collect()
+}
+
+impl MessageDto {
+    /// Whether a part of the text is hidden until the user reveals it
+    pub fn has_spoilers(&self) -> bool {
+        self.styles
+            .iter()
+            .any(|styled| styled.style == TextStyle::Spoiler)
+    }
+
+    /// Text of the message with its spoilers masked
+    pub fn masked_text(&self) -> String {
+        mask_spoilers(&self.text, &self.styles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::mentions::mention_token;
+
+    #[test]
+    fn markers_become_styles_of_the_plain_text() {
+        let (plain, styles) = parse_markdown("**bold _it_** and ||secret|| snake_case `a*b*`");
+        assert_eq!(plain, "bold it and secret snake_case a*b*");
+
+        let styled = |style| {
+            styles
+                .iter()
+                .filter(|styled| styled.style == style)
+                .map(|styled| &plain[styled.range.clone()])
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(styled(TextStyle::Bold), ["bold it"]);
+        assert_eq!(styled(TextStyle::Italic), ["it"]);
+        assert_eq!(styled(TextStyle::Spoiler), ["secret"]);
+        assert_eq!(styled(TextStyle::Monospace), ["a*b*"]);
+    }
+
+    #[test]
+    fn unclosed_or_padded_markers_stay_text() {
+        let (plain, styles) = parse_markdown("2 * 3 * 4 and **open");
+        assert_eq!(plain, "2 * 3 * 4 and **open");
+        assert!(styles.is_empty());
+    }
+
+    #[test]
+    fn single_markers_inside_words_stay_text() {
+        let (plain, styles) = parse_markdown("2*3*4, snake_case_name and *a*b");
+        assert_eq!(plain, "2*3*4, snake_case_name and *a*b");
+        assert!(styles.is_empty());
+
+        let (plain, styles) = parse_markdown("*a*, _b_");
+        assert_eq!(plain, "a, b");
+        assert_eq!(styles.len(), 2);
+    }
+
+    #[test]
+    fn escaped_markers_stay_text() {
+        let (plain, styles) = parse_markdown(r"\*not italic\* **a\*\*b** C:\path `x\`");
+        assert_eq!(plain, r"*not italic* a**b C:\path x\");
+
+        let styled = styles
+            .iter()
+            .map(|styled| (styled.style, &plain[styled.range.clone()]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            styled,
+            [(TextStyle::Bold, "a**b"), (TextStyle::Monospace, r"x\")]
+        );
+    }
+
+    #[test]
+    fn escaped_text_is_sent_as_it_is() {
+        let text = r"**no** _styles_ in \ ||this|| `text`";
+        let (plain, styles) = parse_markdown(&escape_markdown(text));
+        assert_eq!(plain, text);
+        assert!(styles.is_empty());
+    }
+
+    #[test]
+    fn mention_tokens_in_monospace_stay_text() {
+        let token = mention_token(Uuid::new_v4());
+        let (body, body_ranges) = create_body(&format!("`{token}` {token}"));
+        assert_eq!(body, format!("{token} {MENTION_PLACEHOLDER}"));
+
+        let mentions = body_ranges
+            .iter()
+            .filter(|range| matches!(range.associated_value, Some(AssociatedValue::MentionAci(_))))
+            .collect::<Vec<_>>();
+        assert_eq!(mentions.len(), 1);
+        assert_eq!(mentions[0].start(), utf16_len(&token) + 1);
+    }
+
+    #[test]
+    fn created_body_is_parsed_back() {
+        let uuid = Uuid::new_v4();
+        let text = format!("hi {} **ünï** ||x||", mention_token(uuid));
+        let (body, body_ranges) = create_body(&text);
+        assert_eq!(body, format!("hi {MENTION_PLACEHOLDER} ünï x"));
+
+        let parsed = parse_body(&body, &body_ranges);
+        let label = format!("@{}", fallback_label(uuid));
+        assert_eq!(parsed.text, format!("hi {label} ünï x"));
+        assert_eq!(parsed.mentions.len(), 1);
+        assert_eq!(parsed.mentions[0].uuid, uuid);
+        assert_eq!(&parsed.text[parsed.mentions[0].range.clone()], label);
+
+        let styled = parsed
+            .styles
+            .iter()
+            .map(|styled| (styled.style, &parsed.text[styled.range.clone()]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            styled,
+            [(TextStyle::Bold, "ünï"), (TextStyle::Spoiler, "x")]
+        );
+    }
+
+    #[test]
+    fn spoilers_are_masked_character_by_character() {
+        let text = "see ünï\nend";
+        let styles = [StyledRange {
+            style: TextStyle::Spoiler,
+            range: 4..text.len(),
+        }];
+        assert_eq!(mask_spoilers(text, &styles), "see ░░░\n░░░");
+        assert_eq!(mask_spoilers(text, &[]), text);
+    }
+}
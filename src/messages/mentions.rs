@@ -0,0 +1,210 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use presage::Manager;
+use presage::libsignal_service::prelude::Uuid;
+use presage::manager::Registered;
+use presage::store::ContentsStore;
+use presage_store_sqlite::SqliteStore;
+use regex::Regex;
+use tracing::error;
+
+use crate::messages::receive::MessageDto;
+
+/// Character standing in the body for every mention, the name is rendered by the client
+pub(crate) const MENTION_PLACEHOLDER: char = '\u{FFFC}';
+
+/// Member of a conversation mentioned in a message
+#[derive(Clone, Debug)]
+pub struct Mention {
+    pub uuid: Uuid,
+    /// Bytes of `MessageDto::text` showing the mention, including the `@`
+    pub range: Range<usize>,
+}
+
+/// How a mention is written in text given to the send functions, e.g. by the CLI
+pub fn mention_token(uuid: Uuid) -> String {
+    format!("@{{{uuid}}}")
+}
+
+pub(crate) fn mention_token_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(
+            r"@\{([0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12})\}",
+        )
+        .expect("Mention token regex is valid")
+    })
+}
+
+/// Name shown for a mention until the name of the member is known
+pub(crate) fn fallback_label(uuid: Uuid) -> String {
+    uuid.to_string().chars().take(8).collect()
+}
+
+/// Turns the mentions the user picked into mention tokens, other text that looks like a name
+/// after `@` is sent as it is. Mentions which don't fit the text anymore are skipped.
+pub fn encode_mentions(text: &str, mentions: &[Mention]) -> String {
+    let mut mentions = mentions.iter().collect::<Vec<_>>();
+    mentions.sort_by_key(|mention| mention.range.start);
+
+    let mut encoded = String::with_capacity(text.len());
+    let mut last_end = 0;
+    for mention in mentions {
+        if mention.range.start < last_end || text.get(mention.range.clone()).is_none() {
+            continue;
+        }
+        encoded.push_str(&text[last_end..mention.range.start]);
+        encoded.push_str(&mention_token(mention.uuid));
+        last_end = mention.range.end;
+    }
+    encoded.push_str(&text[last_end..]);
+    encoded
+}
+
+impl MessageDto {
+    /// Whether the message mentions the member
+    pub fn mentions_member(&self, uuid: Uuid) -> bool {
+        self.mentions.iter().any(|mention| mention.uuid == uuid)
+    }
+
+    /// Shows the names of mentioned members, members without a name keep the current text
+    pub fn rename_mentions(&mut self, names: &HashMap<Uuid, String>) {
+        if self.mentions.is_empty() {
+            return;
+        }
+
+        let mut text = String::with_capacity(self.text.len());
+        let mut last_end = 0;
+        // Old end of every mention with the change of its length
+        let mut shifts: Vec<(usize, isize)> = Vec::with_capacity(self.mentions.len());
+        for mention in &mut self.mentions {
+            text.push_str(&self.text[last_end..mention.range.start]);
+            let start = text.len();
+            match names.get(&mention.uuid) {
+                Some(name) => {
+                    text.push('@');
+                    text.push_str(name);
+                }
+                None => text.push_str(&self.text[mention.range.clone()]),
+            }
+            last_end = mention.range.end;
+            shifts.push((
+                mention.range.end,
+                (text.len() - start) as isize - mention.range.len() as isize,
+            ));
+            mention.range = start..text.len();
+        }
+        text.push_str(&self.text[last_end..]);
+        self.text = text;
+
+        let shift = |offset: usize| {
+            let delta: isize = shifts
+                .iter()
+                .filter(|(end, _)| *end <= offset)
+                .map(|(_, delta)| delta)
+                .sum();
+            offset.saturating_add_signed(delta)
+        };
+        for styled in &mut self.styles {
+            styled.range = shift(styled.range.start)..shift(styled.range.end);
+        }
+    }
+}
+
+/// Shows names of mentioned members from the store instead of their UUIDs
+pub async fn resolve_mentions(
+    manager: &Manager<SqliteStore, Registered>,
+    messages: &mut [MessageDto],
+) {
+    let mentioned = messages
+        .iter()
+        .flat_map(|message| &message.mentions)
+        .map(|mention| mention.uuid)
+        .collect::<HashSet<_>>();
+
+    let mut names: HashMap<Uuid, String> = HashMap::new();
+    for uuid in mentioned {
+        match manager.store().contact_by_id(&uuid).await {
+            Ok(Some(contact)) if !contact.name.is_empty() => {
+                names.insert(uuid, contact.name);
+            }
+            Ok(Some(contact)) => {
+                if let Some(phone_number) = contact.phone_number {
+                    names.insert(uuid, phone_number.to_string());
+                }
+            }
+            Ok(None) => {}
+            Err(error) => error!(%error, "Failed to look up mentioned contact"),
+        }
+    }
+
+    for message in messages {
+        message.rename_mentions(&names);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::formatting::{StyledRange, TextStyle};
+
+    #[test]
+    fn only_picked_mentions_are_encoded() {
+        let ann = Uuid::new_v4();
+        let text = "@Ann meet @Ann Lee and @Ann";
+        // The member was picked only once, the other names are typed text
+        let picked = [Mention {
+            uuid: ann,
+            range: 0..4,
+        }];
+        assert_eq!(
+            encode_mentions(text, &picked),
+            format!("{} meet @Ann Lee and @Ann", mention_token(ann))
+        );
+    }
+
+    #[test]
+    fn mentions_of_members_sharing_a_name_keep_their_member() {
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let text = "@Ann and @Ann";
+        let picked = [
+            Mention {
+                uuid: second,
+                range: 9..13,
+            },
+            Mention {
+                uuid: first,
+                range: 0..4,
+            },
+        ];
+        assert_eq!(
+            encode_mentions(text, &picked),
+            format!("{} and {}", mention_token(first), mention_token(second))
+        );
+    }
+
+    #[test]
+    fn mentions_outside_the_text_are_skipped() {
+        let uuid = Uuid::new_v4();
+        let picked = [Mention { uuid, range: 3..10 }];
+        assert_eq!(encode_mentions("hi @", &picked), "hi @");
+    }
+
+    #[test]
+    fn renamed_mentions_shift_styles() {
+        let uuid = Uuid::new_v4();
+        let mut message = MessageDto::plain(uuid, 1, false, "@1234abcd look");
+        message.mentions = vec![Mention { uuid, range: 0..9 }];
+        message.styles = vec![StyledRange {
+            style: TextStyle::Bold,
+            range: 10..14,
+        }];
+
+        message.rename_mentions(&HashMap::from([(uuid, "Ann".to_string())]));
+        assert_eq!(message.text, "@Ann look");
+        assert_eq!(message.mentions[0].range, 0..4);
+        assert_eq!(message.styles[0].range, 5..9);
+    }
+}
@@ -0,0 +1,401 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Result, anyhow};
+use presage::Manager;
+use presage::libsignal_service::content::ContentBody;
+use presage::libsignal_service::prelude::{Content, Uuid};
+use presage::libsignal_service::protocol::ServiceId;
+use presage::manager::Registered;
+use presage::proto::{ReceiptMessage, receipt_message};
+use presage::store::{ContentsStore, Thread};
+use presage_store_sqlite::SqliteStore;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::messages::receive::MessageDto;
+use crate::paths;
+use crate::search::thread_key;
+use crate::sidecar::{AccountFile, JsonFile};
+
+/// How far a message got on the side of a single recipient, later states include earlier ones
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ReceiptStatus {
+    Delivered,
+    Read,
+    Viewed,
+}
+
+impl ReceiptStatus {
+    /// Ticks shown next to our messages
+    pub fn ticks(self) -> &'static str {
+        match self {
+            ReceiptStatus::Delivered => "✓✓",
+            ReceiptStatus::Read | ReceiptStatus::Viewed => "✓✓ read",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ReceiptStatus::Delivered => "delivered",
+            ReceiptStatus::Read => "read",
+            ReceiptStatus::Viewed => "viewed",
+        }
+    }
+}
+
+/// Receipt sent by a recipient of our messages
+pub struct IncomingReceipt {
+    pub sender: Uuid,
+    pub status: ReceiptStatus,
+    /// Timestamps of the messages the receipt is for
+    pub timestamps: Vec<u64>,
+}
+
+pub fn extract_receipt(content: &Content) -> Option<IncomingReceipt> {
+    let ContentBody::ReceiptMessage(receipt) = &content.body else {
+        return None;
+    };
+
+    let status = match receipt.r#type() {
+        receipt_message::Type::Delivery => ReceiptStatus::Delivered,
+        receipt_message::Type::Read => ReceiptStatus::Read,
+        receipt_message::Type::Viewed => ReceiptStatus::Viewed,
+    };
+
+    Some(IncomingReceipt {
+        sender: content.metadata.sender.raw_uuid(),
+        status,
+        timestamps: receipt.timestamp.clone(),
+    })
+}
+
+/// Receipts of our messages kept for every conversation
+const RECEIPTS_PER_THREAD: usize = 500;
+
+/// Receipts of our messages and read receipts we sent, persisted per account.
+///
+/// Receipts are not kept in the presage store, so they are saved next to it. Only receipts of
+/// the latest messages of every conversation are kept, older messages are shown without them.
+#[derive(Default, Serialize, Deserialize)]
+pub struct ReceiptStore {
+    /// Status reported by every recipient, by conversation and timestamp of our message
+    #[serde(default)]
+    by_thread: HashMap<String, BTreeMap<u64, HashMap<Uuid, ReceiptStatus>>>,
+    /// Timestamp of the latest received message of every conversation which was read
+    #[serde(default)]
+    read_until: HashMap<String, u64>,
+    /// When reads were tracked for the first time, older messages of conversations without any
+    /// read message count as read, they were most likely read in another client
+    #[serde(default)]
+    tracked_since: Option<u64>,
+    #[serde(skip)]
+    file: JsonFile,
+}
+
+impl AccountFile for ReceiptStore {
+    const NAME: &'static str = "receipts";
+
+    fn path(account_name: &str) -> PathBuf {
+        paths::account_receipts_path(account_name)
+    }
+
+    fn file_mut(&mut self) -> &mut JsonFile {
+        &mut self.file
+    }
+}
+
+impl ReceiptStore {
+    /// Saves the receipt for our messages in the conversation, returns whether the status of
+    /// any message changed
+    pub fn record(&mut self, thread: &Thread, receipt: &IncomingReceipt) -> bool {
+        let receipts = self.by_thread.entry(thread_key(thread)).or_default();
+        let mut changed = false;
+        for timestamp in &receipt.timestamps {
+            let statuses = receipts.entry(*timestamp).or_default();
+            match statuses.get(&receipt.sender) {
+                // Receipts may arrive out of order, a read message can't become only delivered
+                Some(status) if *status >= receipt.status => {}
+                _ => {
+                    statuses.insert(receipt.sender, receipt.status);
+                    changed = true;
+                }
+            }
+        }
+
+        while receipts.len() > RECEIPTS_PER_THREAD {
+            receipts.pop_first();
+        }
+
+        if changed {
+            self.persist();
+        }
+        changed
+    }
+
+    /// Fills in receipts of our messages in the conversation
+    pub fn apply(&self, thread: &Thread, messages: &mut [MessageDto]) {
+        let Some(receipts) = self.by_thread.get(&thread_key(thread)) else {
+            return;
+        };
+        for message in messages {
+            if let Some(receipts) = receipts.get(&message.timestamp) {
+                message.receipts = receipts.clone();
+            }
+        }
+    }
+
+    /// Received messages of the conversation newer than the ones read before, grouped by their
+    /// authors
+    pub fn unread(&mut self, thread: &Thread, messages: &[(Uuid, u64)]) -> HashMap<Uuid, Vec<u64>> {
+        let read_until = match self.read_until.get(&thread_key(thread)) {
+            Some(read_until) => *read_until,
+            None => self.tracked_since(),
+        };
+
+        let mut unread: HashMap<Uuid, Vec<u64>> = HashMap::new();
+        for (author, timestamp) in messages {
+            let timestamps = unread.entry(*author).or_default();
+            // Attachments of a message are listed separately under the same timestamp
+            if *timestamp > read_until && !timestamps.contains(timestamp) {
+                timestamps.push(*timestamp);
+            }
+        }
+        unread.retain(|_, timestamps| !timestamps.is_empty());
+        unread
+    }
+
+    /// Records that received messages of the conversation up to the timestamp were read
+    pub fn mark_read(&mut self, thread: &Thread, until: u64) {
+        let tracked_since = self.tracked_since();
+        let read_until = self
+            .read_until
+            .entry(thread_key(thread))
+            .or_insert(tracked_since);
+        if until > *read_until {
+            *read_until = until;
+            self.persist();
+        }
+    }
+
+    fn tracked_since(&mut self) -> u64 {
+        if let Some(tracked_since) = self.tracked_since {
+            return tracked_since;
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_millis() as u64)
+            .unwrap_or_default();
+        self.tracked_since = Some(now);
+        self.persist();
+        now
+    }
+
+    fn persist(&self) {
+        self.file.save(self);
+    }
+}
+
+/// Conversation of our message the receipt is for, `None` if the message isn't stored or it
+/// can't be looked up
+pub async fn receipt_thread(
+    manager: &Manager<SqliteStore, Registered>,
+    receipt: &IncomingReceipt,
+) -> Option<Thread> {
+    match find_receipt_thread(manager, receipt).await {
+        Ok(thread) => thread,
+        Err(error) => {
+            error!(%error, "Failed to find the conversation of a receipt");
+            None
+        }
+    }
+}
+
+async fn find_receipt_thread(
+    manager: &Manager<SqliteStore, Registered>,
+    receipt: &IncomingReceipt,
+) -> Result<Option<Thread>> {
+    let Some(timestamp) = receipt.timestamps.first() else {
+        return Ok(None);
+    };
+
+    let contact = Thread::Contact(receipt.sender);
+    if manager
+        .store()
+        .message(&contact, *timestamp)
+        .await?
+        .is_some()
+    {
+        return Ok(Some(contact));
+    }
+    for (master_key, group) in manager.store().groups().await?.flatten() {
+        if !group
+            .members
+            .iter()
+            .any(|member| member.uuid == receipt.sender)
+        {
+            continue;
+        }
+        let thread = Thread::Group(master_key);
+        if manager
+            .store()
+            .message(&thread, *timestamp)
+            .await?
+            .is_some()
+        {
+            return Ok(Some(thread));
+        }
+    }
+    Ok(None)
+}
+
+/// Tells `author` that we have read their messages sent at `timestamps`
+pub async fn send_read_receipt(
+    manager: &mut Manager<SqliteStore, Registered>,
+    author: Uuid,
+    timestamps: Vec<u64>,
+) -> Result<()> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+    let receipt = ReceiptMessage {
+        r#type: Some(receipt_message::Type::Read.into()),
+        timestamp: timestamps,
+    };
+
+    manager
+        .send_message(
+            ServiceId::Aci(author.into()),
+            ContentBody::ReceiptMessage(receipt),
+            timestamp,
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to send read receipt: {e}"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receipt(sender: Uuid, status: ReceiptStatus, timestamps: Vec<u64>) -> IncomingReceipt {
+        IncomingReceipt {
+            sender,
+            status,
+            timestamps,
+        }
+    }
+
+    fn status(
+        store: &ReceiptStore,
+        thread: &Thread,
+        timestamp: u64,
+        sender: Uuid,
+    ) -> Option<ReceiptStatus> {
+        store
+            .by_thread
+            .get(&thread_key(thread))?
+            .get(&timestamp)?
+            .get(&sender)
+            .copied()
+    }
+
+    #[test]
+    fn later_status_is_not_downgraded() {
+        let mut store = ReceiptStore::default();
+        let sender = Uuid::new_v4();
+        let thread = Thread::Contact(sender);
+
+        assert!(store.record(&thread, &receipt(sender, ReceiptStatus::Read, vec![10])));
+        assert!(!store.record(
+            &thread,
+            &receipt(sender, ReceiptStatus::Delivered, vec![10])
+        ));
+        assert_eq!(
+            status(&store, &thread, 10, sender),
+            Some(ReceiptStatus::Read)
+        );
+    }
+
+    #[test]
+    fn receipts_are_kept_apart_by_conversation() {
+        let mut store = ReceiptStore::default();
+        let sender = Uuid::new_v4();
+        let contact = Thread::Contact(sender);
+        let group = Thread::Group([1; 32]);
+
+        store.record(&contact, &receipt(sender, ReceiptStatus::Read, vec![10]));
+        store.record(&group, &receipt(sender, ReceiptStatus::Delivered, vec![10]));
+        assert_eq!(
+            status(&store, &contact, 10, sender),
+            Some(ReceiptStatus::Read)
+        );
+        assert_eq!(
+            status(&store, &group, 10, sender),
+            Some(ReceiptStatus::Delivered)
+        );
+    }
+
+    #[test]
+    fn only_latest_receipts_are_kept() {
+        let mut store = ReceiptStore::default();
+        let sender = Uuid::new_v4();
+        let thread = Thread::Contact(sender);
+        let count = RECEIPTS_PER_THREAD as u64 + 10;
+
+        for timestamp in 0..count {
+            store.record(
+                &thread,
+                &receipt(sender, ReceiptStatus::Delivered, vec![timestamp]),
+            );
+        }
+        let receipts = &store.by_thread[&thread_key(&thread)];
+        assert_eq!(receipts.len(), RECEIPTS_PER_THREAD);
+        assert_eq!(receipts.keys().next(), Some(&10));
+    }
+
+    #[test]
+    fn history_before_tracking_is_not_unread() {
+        let mut store = ReceiptStore {
+            tracked_since: Some(100),
+            ..Default::default()
+        };
+        let author = Uuid::new_v4();
+        let thread = Thread::Contact(author);
+
+        let unread = store.unread(&thread, &[(author, 50), (author, 150), (author, 150)]);
+        assert_eq!(unread, HashMap::from([(author, vec![150])]));
+    }
+
+    #[test]
+    fn read_messages_are_not_unread_again() {
+        let mut store = ReceiptStore {
+            tracked_since: Some(0),
+            ..Default::default()
+        };
+        let author = Uuid::new_v4();
+        let thread = Thread::Group([2; 32]);
+
+        store.mark_read(&thread, 20);
+        // A lower timestamp doesn't move the watermark back
+        store.mark_read(&thread, 10);
+        let unread = store.unread(&thread, &[(author, 15), (author, 30)]);
+        assert_eq!(unread, HashMap::from([(author, vec![30])]));
+
+        let other = Thread::Contact(author);
+        assert_eq!(store.unread(&other, &[(author, 15)]).len(), 1);
+    }
+
+    #[test]
+    fn tracking_starts_on_first_use() {
+        let mut store = ReceiptStore::default();
+        let author = Uuid::new_v4();
+
+        assert!(
+            store
+                .unread(&Thread::Contact(author), &[(author, 1)])
+                .is_empty()
+        );
+        assert!(store.tracked_since.is_some());
+    }
+}
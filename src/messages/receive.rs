@@ -1,9 +1,18 @@
 use std::cmp::Reverse;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
+use std::mem;
 
 use crate::account_management::create_registered_manager;
+use crate::config::Config;
 use crate::env::SIGNAL_DISPLAY_FLAGS;
+use crate::message_requests::{MessageRequests, screen_message};
+use crate::messages::deletion::purge_deleted_message;
+use crate::messages::expiry::{format_timer, message_timer, timer_end};
+use crate::messages::formatting::{FormattedBody, StyledRange, parse_body};
+use crate::messages::mentions::Mention;
+use crate::messages::receipts::{ReceiptStatus, ReceiptStore, extract_receipt, receipt_thread};
+use crate::sidecar::AccountFile;
 use anyhow::Result;
 use futures::Stream;
 use futures::{StreamExt, pin_mut};
@@ -11,17 +20,21 @@ use presage::libsignal_service::content::ContentBody;
 use presage::libsignal_service::prelude::Content;
 use presage::libsignal_service::prelude::Uuid;
 use presage::model::messages::Received;
-use presage::proto::data_message::{Quote, Reaction};
+use presage::proto::data_message::{Flags, Quote, Reaction};
 use presage::proto::{
-    AttachmentPointer, DataMessage, GroupContextV2, SyncMessage, sync_message::Sent,
+    AttachmentPointer, DataMessage, EditMessage, GroupContextV2, SyncMessage, sync_message::Sent,
+    typing_message,
 };
 use presage::store::ContentExt;
 use presage_store_sqlite::SqliteStoreError;
-use tracing::trace;
+use tracing::{error, trace};
 
 pub mod contact;
 pub mod group;
 
+/// Shown instead of a message its author deleted for everyone
+const DELETED_TEXT: &str = "This message was deleted";
+
 #[derive(Clone)]
 pub struct MessageDto {
     pub uuid: Uuid,
@@ -32,6 +45,125 @@ pub struct MessageDto {
     pub attachment: Option<AttachmentPointer>,
     pub quote: Option<Quote>,
     pub reactions: HashMap<Uuid, Reaction>,
+    /// Receipts of our message by recipient, empty for received messages
+    pub receipts: HashMap<Uuid, ReceiptStatus>,
+    /// Disappearing timer of the message in seconds, it runs from sending for our messages
+    /// and from reading for received ones
+    pub expire_timer: Option<u32>,
+    /// When the message disappears, in milliseconds since the epoch, `None` until its timer
+    /// started
+    pub expires_at: Option<u64>,
+    /// Timestamp of the latest edit, `None` if the message was never edited
+    pub edited_at: Option<u64>,
+    /// Earlier versions of the text, from the oldest one
+    pub edit_history: Vec<MessageEdit>,
+    /// Members mentioned in the text, ordered by their position
+    pub mentions: Vec<Mention>,
+    /// Styled parts of the text, e.g. bold or spoilers
+    pub styles: Vec<StyledRange>,
+    /// Whether the author deleted the message for everyone, only a tombstone is left then
+    pub deleted: bool,
+}
+
+/// Version of a message text that was replaced by an edit
+#[derive(Clone)]
+pub struct MessageEdit {
+    /// When this version of the text was sent
+    pub timestamp: u64,
+    pub text: String,
+}
+
+/// New text for an earlier message, sent by a contact or by us from another device
+pub struct IncomingEdit {
+    pub author: Uuid,
+    pub target_sent_timestamp: u64,
+    pub timestamp: u64,
+    pub text: String,
+    pub mentions: Vec<Mention>,
+    pub styles: Vec<StyledRange>,
+}
+
+impl MessageDto {
+    /// Least advanced status among the recipients who sent a receipt
+    pub fn receipt_status(&self) -> Option<ReceiptStatus> {
+        self.receipts.values().min().copied()
+    }
+
+    /// Replaces the text with the edited one and keeps the previous version in the history
+    fn apply_edit(&mut self, edit: IncomingEdit) {
+        let previous = MessageEdit {
+            timestamp: self.edited_at.unwrap_or(self.timestamp),
+            text: mem::replace(&mut self.text, edit.text),
+        };
+        self.mentions = edit.mentions;
+        self.styles = edit.styles;
+        self.edit_history.push(previous);
+        self.edited_at = Some(edit.timestamp);
+    }
+
+    /// Message with nothing but a text
+    #[cfg(test)]
+    pub fn plain(uuid: Uuid, timestamp: u64, sender: bool, text: &str) -> Self {
+        MessageDto {
+            uuid,
+            timestamp,
+            text: text.to_string(),
+            sender,
+            group_context: None,
+            attachment: None,
+            quote: None,
+            reactions: HashMap::new(),
+            receipts: HashMap::new(),
+            expire_timer: None,
+            expires_at: None,
+            edited_at: None,
+            edit_history: Vec::new(),
+            mentions: Vec::new(),
+            styles: Vec::new(),
+            deleted: false,
+        }
+    }
+
+    /// What is left of a message deleted for everyone, only its author and time are kept
+    pub fn tombstone(
+        uuid: Uuid,
+        timestamp: u64,
+        sender: bool,
+        group_context: Option<GroupContextV2>,
+        expires_at: Option<u64>,
+    ) -> Self {
+        MessageDto {
+            uuid,
+            timestamp,
+            text: DELETED_TEXT.to_string(),
+            sender,
+            group_context,
+            attachment: None,
+            quote: None,
+            reactions: HashMap::new(),
+            receipts: HashMap::new(),
+            expire_timer: None,
+            expires_at,
+            edited_at: None,
+            edit_history: Vec::new(),
+            mentions: Vec::new(),
+            styles: Vec::new(),
+            deleted: true,
+        }
+    }
+
+    /// Drops the content of the message, edits and reactions included
+    fn apply_delete(&mut self) {
+        let expire_timer = self.expire_timer;
+        *self = MessageDto::tombstone(
+            self.uuid,
+            self.timestamp,
+            self.sender,
+            self.group_context.take(),
+            self.expires_at,
+        );
+        self.expire_timer = expire_timer;
+    }
 }
 
 async fn loop_no_contents(messages: impl Stream<Item = Received>) {
@@ -72,15 +204,46 @@ pub async fn receiving_loop(
     }
 }
 
-fn format_data_message(data_message: &DataMessage) -> (Option<String>, Option<Quote>) {
+/// Text of the message, the quoted message and the mentions and styles in the text
+type FormattedData = (
+    Option<String>,
+    Option<Quote>,
+    Vec<Mention>,
+    Vec<StyledRange>,
+);
+
+fn format_data_message(data_message: &DataMessage) -> FormattedData {
     match data_message {
+        DataMessage {
+            flags: Some(flags),
+            expire_timer,
+            ..
+        } if flags & Flags::ExpirationTimerUpdate as u32 != 0 => (
+            Some(format!(
+                "[TIMER] Disappearing messages: {}",
+                format_timer(expire_timer.unwrap_or(0))
+            )),
+            None,
+            Vec::new(),
+            Vec::new(),
+        ),
         DataMessage {
             body: Some(body),
             quote,
+            body_ranges,
             ..
         } => {
-            let text = body.to_string();
-            ((!text.is_empty()).then_some(text), quote.clone())
+            let FormattedBody {
+                text,
+                mentions,
+                styles,
+            } = parse_body(body, body_ranges);
+            (
+                (!text.is_empty()).then_some(text),
+                quote.clone(),
+                mentions,
+                styles,
+            )
         }
         DataMessage {
             flags: Some(flag),
@@ -89,8 +252,10 @@ fn format_data_message(data_message: &DataMessage) -> (Option<String>, Option<Qu
         } if env::var(SIGNAL_DISPLAY_FLAGS).is_ok() => (
             Some(format!("[FLAG] Data message (flag: {flag})")),
             quote.clone(),
+            Vec::new(),
+            Vec::new(),
         ),
-        _ => (None, None),
+        _ => (None, None, Vec::new(), Vec::new()),
     }
 }
 
@@ -98,8 +263,13 @@ fn format_data_message(data_message: &DataMessage) -> (Option<String>, Option<Qu
 pub fn format_message(content: &Content) -> Option<MessageDto> {
     let timestamp: u64 = content.timestamp();
     let uuid = content.metadata.sender.raw_uuid();
-    let (text, sender, quote) = get_message_text(content);
+    let (text, sender, quote, mentions, styles) = get_message_text(content);
     let group_context = get_message_group_context(content);
+    let expire_timer = message_timer(content);
+    // Timers of received messages start once they are read, see `ExpiryIndex`
+    let expires_at = expire_timer
+        .filter(|_| sender)
+        .map(|seconds| timer_end(timestamp, seconds));
     text.map(|text| MessageDto {
         uuid,
         timestamp,
@@ -109,13 +279,34 @@ pub fn format_message(content: &Content) -> Option<MessageDto> {
         attachment: None,
         quote,
         reactions: HashMap::new(),
+        receipts: HashMap::new(),
+        expire_timer,
+        expires_at,
+        edited_at: None,
+        edit_history: Vec::new(),
+        mentions,
+        styles,
+        deleted: false,
     })
 }
 
-fn get_message_text(content: &Content) -> (Option<String>, bool, Option<Quote>) {
+fn get_message_text(
+    content: &Content,
+) -> (
+    Option<String>,
+    bool,
+    Option<Quote>,
+    Vec<Mention>,
+    Vec<StyledRange>,
+) {
     let mut sender = false;
-    let (text, quote): (Option<String>, Option<Quote>) = match &content.body {
-        ContentBody::NullMessage(_) => (Some("[NULL] <null message>".to_string()), None),
+    let (text, quote, mentions, styles): FormattedData = match &content.body {
+        ContentBody::NullMessage(_) => (
+            Some("[NULL] <null message>".to_string()),
+            None,
+            Vec::new(),
+            Vec::new(),
+        ),
         ContentBody::DataMessage(data_message) => format_data_message(data_message),
         ContentBody::SynchronizeMessage(sync_message) => match sync_message {
             SyncMessage {
@@ -129,17 +320,23 @@ fn get_message_text(content: &Content) -> (Option<String>, bool, Option<Quote>)
                 sender = true;
                 format_data_message(data_message)
             }
-            _ => (None, None),
+            _ => (None, None, Vec::new(), Vec::new()),
         },
-        ContentBody::CallMessage(_) => (Some("[CALL]".to_string()), None),
-        ContentBody::ReceiptMessage(_) => (None, None),
-        // ContentBody::TypingMessage(_) => Some("Typing...".to_string()),
-        ContentBody::TypingMessage(_) => (None, None),
-        ContentBody::StoryMessage(_) => (Some("[STORY] <story message>".to_string()), None),
-        ContentBody::PniSignatureMessage(_) => (None, None),
-        ContentBody::EditMessage(_) => (Some("[EDIT] <edit message>".to_string()), None),
+        ContentBody::CallMessage(_) => (Some("[CALL]".to_string()), None, Vec::new(), Vec::new()),
+        ContentBody::ReceiptMessage(_) => (None, None, Vec::new(), Vec::new()),
+        // Typing is shown under the chat, see `extract_typing`
+        ContentBody::TypingMessage(_) => (None, None, Vec::new(), Vec::new()),
+        ContentBody::StoryMessage(_) => (
+            Some("[STORY] <story message>".to_string()),
+            None,
+            Vec::new(),
+            Vec::new(),
+        ),
+        ContentBody::PniSignatureMessage(_) => (None, None, Vec::new(), Vec::new()),
+        // Edits are applied to the message they target, see `extract_edit`
+        ContentBody::EditMessage(_) => (None, None, Vec::new(), Vec::new()),
     };
-    (text, sender, quote)
+    (text, sender, quote, mentions, styles)
 }
 
 fn get_message_group_context(content: &Content) -> Option<GroupContextV2> {
@@ -162,6 +359,8 @@ fn map_attachment_to_message(
     uuid: Uuid,
     timestamp: u64,
     group_context: Option<GroupContextV2>,
+    expire_timer: Option<u32>,
+    expires_at: Option<u64>,
 ) -> MessageDto {
     let file_name = att.file_name.clone().unwrap_or_else(|| {
         let extension = mime_guess::get_mime_extensions_str(att.content_type())
@@ -179,6 +378,14 @@ fn map_attachment_to_message(
         attachment: Some(att.clone()),
         quote: None,
         reactions: HashMap::new(),
+        receipts: HashMap::new(),
+        expire_timer,
+        expires_at,
+        edited_at: None,
+        edit_history: Vec::new(),
+        mentions: Vec::new(),
+        styles: Vec::new(),
+        deleted: false,
     }
 }
 
@@ -187,11 +394,23 @@ pub fn format_attachments(content: &Content) -> Vec<MessageDto> {
     let timestamp = content.timestamp();
     let uuid = content.metadata.sender.raw_uuid();
     let group_context = get_message_group_context(content);
+    let expire_timer = message_timer(content);
+    let sent_expires_at = expire_timer.map(|seconds| timer_end(timestamp, seconds));
 
     match &content.body {
+        // Timers of received attachments start once they are read, see `ExpiryIndex`
         ContentBody::DataMessage(DataMessage { attachments, .. }) => attachments
             .iter()
-            .map(|att| map_attachment_to_message(att, uuid, timestamp, group_context.clone()))
+            .map(|att| {
+                map_attachment_to_message(
+                    att,
+                    uuid,
+                    timestamp,
+                    group_context.clone(),
+                    expire_timer,
+                    None,
+                )
+            })
             .collect(),
         ContentBody::SynchronizeMessage(SyncMessage {
             sent:
@@ -202,7 +421,16 @@ pub fn format_attachments(content: &Content) -> Vec<MessageDto> {
             ..
         }) => attachments
             .iter()
-            .map(|att| map_attachment_to_message(att, uuid, timestamp, group_context.clone()))
+            .map(|att| {
+                map_attachment_to_message(
+                    att,
+                    uuid,
+                    timestamp,
+                    group_context.clone(),
+                    expire_timer,
+                    sent_expires_at,
+                )
+            })
             .collect(),
         _ => vec![],
     }
@@ -228,6 +456,92 @@ pub fn extract_reaction(content: &Content) -> Option<(Uuid, Reaction)> {
     }
 }
 
+pub fn extract_edit(content: &Content) -> Option<IncomingEdit> {
+    let edit_message = match &content.body {
+        ContentBody::EditMessage(edit_message) => edit_message,
+        ContentBody::SynchronizeMessage(SyncMessage {
+            sent:
+                Some(Sent {
+                    edit_message: Some(edit_message),
+                    ..
+                }),
+            ..
+        }) => edit_message,
+        _ => return None,
+    };
+
+    match edit_message {
+        EditMessage {
+            target_sent_timestamp: Some(target_sent_timestamp),
+            data_message: Some(data_message),
+        } => {
+            let FormattedBody {
+                text,
+                mentions,
+                styles,
+            } = parse_body(
+                data_message.body.as_deref().unwrap_or_default(),
+                &data_message.body_ranges,
+            );
+            Some(IncomingEdit {
+                author: content.metadata.sender.raw_uuid(),
+                target_sent_timestamp: *target_sent_timestamp,
+                timestamp: data_message.timestamp.unwrap_or(content.timestamp()),
+                text,
+                mentions,
+                styles,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Deletion of an earlier message for everyone, sent by its author or by us from another device
+pub struct IncomingDelete {
+    pub author: Uuid,
+    pub target_sent_timestamp: u64,
+}
+
+pub fn extract_delete(content: &Content) -> Option<IncomingDelete> {
+    let data_message = match &content.body {
+        ContentBody::DataMessage(data_message) => data_message,
+        ContentBody::SynchronizeMessage(SyncMessage {
+            sent:
+                Some(Sent {
+                    message: Some(data_message),
+                    ..
+                }),
+            ..
+        }) => data_message,
+        _ => return None,
+    };
+
+    Some(IncomingDelete {
+        author: content.metadata.sender.raw_uuid(),
+        target_sent_timestamp: data_message.delete.as_ref()?.target_sent_timestamp?,
+    })
+}
+
+/// Contact started or stopped typing, in a group when `group_id` is set
+pub struct IncomingTyping {
+    pub sender: Uuid,
+    pub started: bool,
+    /// Group identifier, not the master key
+    pub group_id: Option<Vec<u8>>,
+}
+
+pub fn extract_typing(content: &Content) -> Option<IncomingTyping> {
+    let ContentBody::TypingMessage(typing_message) = &content.body else {
+        return None;
+    };
+
+    Some(IncomingTyping {
+        sender: content.metadata.sender.raw_uuid(),
+        started: typing_message.action() == typing_message::Action::Started,
+        group_id: typing_message.group_id.clone(),
+    })
+}
+
 /// Function to receive messages for CLI interface
 pub async fn receive_messages_cli() -> Result<Vec<MessageDto>> {
     let mut manager = create_registered_manager().await?;
@@ -236,9 +550,30 @@ pub async fn receive_messages_cli() -> Result<Vec<MessageDto>> {
 
     receiving_loop(messages, Some(&mut contents)).await;
 
+    // Receipts aren't shown as messages, they are kept for listing our messages later
+    let mut receipts = ReceiptStore::load_current().ok();
+    let mut requests = MessageRequests::load_current().ok();
+    let purge_deleted = Config::load().purge_deleted_messages;
     let mut result = Vec::new();
 
     for content in contents {
+        if let Some(receipt) = extract_receipt(&content)
+            && let Some(receipts) = receipts.as_mut()
+            && let Some(thread) = receipt_thread(&manager, &receipt).await
+        {
+            receipts.record(&thread, &receipt);
+        }
+        // Messages of blocked senders are dropped
+        if let Some(requests) = requests.as_mut() {
+            match screen_message(&manager, requests, &content).await {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(error) => error!(%error, "Failed to screen message request"),
+            }
+        }
+        if purge_deleted && let Err(error) = purge_deleted_message(&manager, &content).await {
+            error!(%error, "Failed to purge message deleted by its author");
+        }
         if let Some(formatted_message) = format_message(&content) {
             result.push(formatted_message);
         }
@@ -252,27 +587,40 @@ pub async fn receive_messages_cli() -> Result<Vec<MessageDto>> {
 pub fn get_messages_as_message_dto(
     messages: Vec<std::result::Result<Content, SqliteStoreError>>,
 ) -> Result<Vec<MessageDto>> {
-    let mut message_map: HashMap<u64, MessageDto> = HashMap::new();
+    // Attachments of a message share its timestamp, every entry is keyed by the timestamp and its
+    // position in the message, the text comes first
+    let mut message_map: BTreeMap<(u64, usize), MessageDto> = BTreeMap::new();
     let mut reactions: Vec<(Uuid, Reaction)> = Vec::new();
+    let mut edits: Vec<IncomingEdit> = Vec::new();
+    let mut deletes: Vec<IncomingDelete> = Vec::new();
 
     for message in messages.into_iter().flatten() {
         if let Some(reaction) = extract_reaction(&message) {
             reactions.push(reaction);
         }
 
+        if let Some(edit) = extract_edit(&message) {
+            edits.push(edit);
+        }
+
+        if let Some(delete) = extract_delete(&message) {
+            deletes.push(delete);
+        }
+
         if let Some(formatted_message) = format_message(&message) {
-            message_map.insert(formatted_message.timestamp, formatted_message);
+            message_map.insert((formatted_message.timestamp, 0), formatted_message);
         }
 
-        for attachment_msg in format_attachments(&message) {
-            message_map.insert(attachment_msg.timestamp, attachment_msg);
+        for (index, attachment_msg) in format_attachments(&message).into_iter().enumerate() {
+            message_map.insert((attachment_msg.timestamp, index + 1), attachment_msg);
         }
     }
 
     reactions.reverse();
 
     for (uuid, r) in reactions {
-        if let Some(msg) = message_map.get_mut(&r.target_sent_timestamp()) {
+        // Reactions are shown on the first entry of the message
+        if let Some(msg) = entries_of(&mut message_map, r.target_sent_timestamp()).next() {
             if r.remove() {
                 msg.reactions.remove(&uuid);
             } else {
@@ -280,7 +628,184 @@ pub fn get_messages_as_message_dto(
             }
         }
     }
+    apply_edits(&mut message_map, edits);
+    // Deletions are applied last, nothing of the message is shown afterwards
+    apply_deletes(&mut message_map, deletes);
+
+    // Expired messages are left in, so callers can track their timers before hiding them.
+    // Sorting is stable, so the text of a message stays in front of its attachments.
     let mut result: Vec<MessageDto> = message_map.into_values().collect();
     result.sort_by_key(|m| Reverse(m.timestamp));
     Ok(result)
 }
+
+/// Replaces the texts of the messages by their latest edits, in whatever order the edits were
+/// received
+fn apply_edits(message_map: &mut BTreeMap<(u64, usize), MessageDto>, mut edits: Vec<IncomingEdit>) {
+    // Every edit targets the original message, so applying them in order yields the latest text
+    edits.sort_by_key(|edit| edit.timestamp);
+    for edit in edits {
+        if let Some(msg) = message_map.get_mut(&(edit.target_sent_timestamp, 0))
+            // Only the author of a message may edit it
+            && msg.uuid == edit.author
+        {
+            msg.apply_edit(edit);
+        }
+    }
+}
+
+/// Replaces the messages deleted for everyone by tombstones. Deletions of messages which aren't
+/// among the entries, e.g. older ones or purged ones, can't be checked and are left out.
+fn apply_deletes(
+    message_map: &mut BTreeMap<(u64, usize), MessageDto>,
+    deletes: Vec<IncomingDelete>,
+) {
+    for delete in deletes {
+        let timestamp = delete.target_sent_timestamp;
+        let tombstone = match entries_of(message_map, timestamp).next() {
+            // Only the author of a message may delete it for everyone
+            Some(msg) if msg.uuid == delete.author => {
+                let mut tombstone = msg.clone();
+                tombstone.apply_delete();
+                tombstone
+            }
+            _ => continue,
+        };
+        // A single tombstone stands for the text and all attachments of the message
+        message_map.retain(|(entry_timestamp, _), _| *entry_timestamp != timestamp);
+        message_map.insert((timestamp, 0), tombstone);
+    }
+}
+
+/// Entries of the message sent at the timestamp, its text first and then its attachments
+fn entries_of(
+    message_map: &mut BTreeMap<(u64, usize), MessageDto>,
+    timestamp: u64,
+) -> impl Iterator<Item = &mut MessageDto> {
+    message_map
+        .range_mut((timestamp, 0)..=(timestamp, usize::MAX))
+        .map(|(_, msg)| msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: Uuid = Uuid::from_u128(1);
+    const BOB: Uuid = Uuid::from_u128(2);
+
+    fn messages(entries: Vec<MessageDto>) -> BTreeMap<(u64, usize), MessageDto> {
+        entries
+            .into_iter()
+            .map(|msg| ((msg.timestamp, 0), msg))
+            .collect()
+    }
+
+    fn edit(author: Uuid, target_sent_timestamp: u64, timestamp: u64, text: &str) -> IncomingEdit {
+        IncomingEdit {
+            author,
+            target_sent_timestamp,
+            timestamp,
+            text: text.to_string(),
+            mentions: Vec::new(),
+            styles: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn edits_keep_earlier_versions() {
+        let mut map = messages(vec![MessageDto::plain(ALICE, 100, false, "Helo")]);
+        apply_edits(&mut map, vec![edit(ALICE, 100, 200, "Hello")]);
+
+        let msg = &map[&(100, 0)];
+        assert_eq!(msg.text, "Hello");
+        assert_eq!(msg.edited_at, Some(200));
+        assert_eq!(msg.edit_history.len(), 1);
+        assert_eq!(msg.edit_history[0].timestamp, 100);
+        assert_eq!(msg.edit_history[0].text, "Helo");
+    }
+
+    #[test]
+    fn edits_received_out_of_order_end_with_the_latest() {
+        let mut map = messages(vec![MessageDto::plain(ALICE, 100, false, "one")]);
+        apply_edits(
+            &mut map,
+            vec![edit(ALICE, 100, 300, "three"), edit(ALICE, 100, 200, "two")],
+        );
+
+        let msg = &map[&(100, 0)];
+        assert_eq!(msg.text, "three");
+        assert_eq!(msg.edited_at, Some(300));
+        let history = msg
+            .edit_history
+            .iter()
+            .map(|edit| (edit.timestamp, edit.text.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(history, vec![(100, "one"), (200, "two")]);
+    }
+
+    #[test]
+    fn edits_of_other_authors_are_ignored() {
+        let mut map = messages(vec![MessageDto::plain(ALICE, 100, false, "Hi")]);
+        apply_edits(
+            &mut map,
+            vec![edit(BOB, 100, 200, "Bye"), edit(ALICE, 150, 200, "Hey")],
+        );
+
+        let msg = &map[&(100, 0)];
+        assert_eq!(msg.text, "Hi");
+        assert_eq!(msg.edited_at, None);
+        assert!(msg.edit_history.is_empty());
+    }
+
+    fn delete(author: Uuid, target_sent_timestamp: u64) -> IncomingDelete {
+        IncomingDelete {
+            author,
+            target_sent_timestamp,
+        }
+    }
+
+    #[test]
+    fn deleted_messages_become_one_tombstone() {
+        let mut map = messages(vec![MessageDto::plain(ALICE, 100, false, "Hi")]);
+        map.insert((100, 1), MessageDto::plain(ALICE, 100, false, "photo.jpg"));
+        apply_deletes(&mut map, vec![delete(ALICE, 100)]);
+
+        assert_eq!(map.len(), 1);
+        let msg = &map[&(100, 0)];
+        assert!(msg.deleted);
+        assert_eq!(msg.uuid, ALICE);
+        assert_eq!(msg.text, DELETED_TEXT);
+    }
+
+    #[test]
+    fn deletes_of_other_authors_are_ignored() {
+        let mut map = messages(vec![MessageDto::plain(ALICE, 100, false, "Hi")]);
+        apply_deletes(&mut map, vec![delete(BOB, 100)]);
+
+        let msg = &map[&(100, 0)];
+        assert!(!msg.deleted);
+        assert_eq!(msg.text, "Hi");
+    }
+
+    #[test]
+    fn deletes_of_messages_not_listed_add_nothing() {
+        // The target is older than the listed messages or not in the store at all
+        let mut map = messages(vec![MessageDto::plain(ALICE, 100, false, "Hi")]);
+        apply_deletes(&mut map, vec![delete(ALICE, 50), delete(BOB, 150)]);
+
+        assert_eq!(map.len(), 1);
+        assert!(!map[&(100, 0)].deleted);
+    }
+
+    #[test]
+    fn deletes_received_before_their_message_apply_once_it_arrives() {
+        let mut map = messages(Vec::new());
+        apply_deletes(&mut map, vec![delete(ALICE, 100)]);
+        assert!(map.is_empty());
+
+        let mut map = messages(vec![MessageDto::plain(ALICE, 100, false, "Hi")]);
+        apply_deletes(&mut map, vec![delete(ALICE, 100)]);
+        assert!(map[&(100, 0)].deleted);
+    }
+}
@@ -10,7 +10,12 @@ use presage::{
 use presage_store_sqlite::{SqliteStore, SqliteStoreError};
 
 use crate::account_management::create_registered_manager;
+use crate::messages::expiry::track_listed_cli;
+use crate::messages::mentions::resolve_mentions;
+use crate::messages::receipts::ReceiptStore;
 use crate::messages::receive::{MessageDto, get_messages_as_message_dto};
+use crate::recipients::resolve_recipient_cli;
+use crate::sidecar::AccountFile;
 
 pub async fn list_messages(
     manager: &Manager<SqliteStore, Registered>,
@@ -37,12 +42,24 @@ pub async fn list_messages_tui(
     manager: Manager<SqliteStore, Registered>,
 ) -> Result<Vec<MessageDto>> {
     let messages = list_messages(&manager, recipient, Some(from)).await?;
-    get_messages_as_message_dto(messages)
+    let mut messages = get_messages_as_message_dto(messages)?;
+    resolve_mentions(&manager, &mut messages).await;
+    Ok(messages)
 }
 
 /// Returns iterator over stored messeges from certain time for given contact uuid, for use in CLI
 pub async fn list_messages_cli(recipient: String, from: Option<String>) -> Result<Vec<MessageDto>> {
     let manager = create_registered_manager().await?;
-    let messages = list_messages(&manager, recipient, from).await?;
-    get_messages_as_message_dto(messages)
+    let recipient_uuid = resolve_recipient_cli(&recipient, &manager).await?;
+    let thread = Thread::Contact(recipient_uuid);
+
+    let messages = list_messages(&manager, recipient_uuid.to_string(), from).await?;
+    let mut messages = get_messages_as_message_dto(messages)?;
+    // Listed messages are read, expired ones are deleted and hidden
+    track_listed_cli(&manager, &thread, &mut messages).await?;
+    resolve_mentions(&manager, &mut messages).await;
+    if let Ok(receipts) = ReceiptStore::load_current() {
+        receipts.apply(&thread, &mut messages);
+    }
+    Ok(messages)
 }
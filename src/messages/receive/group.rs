@@ -1,6 +1,6 @@
 use std::str::FromStr;
 
-use anyhow::{Ok, Result, bail};
+use anyhow::{Result, bail};
 use presage::{
     Manager,
     libsignal_service::{prelude::Content, zkgroup::GroupMasterKeyBytes},
@@ -9,6 +9,10 @@ use presage::{
 };
 use presage_store_sqlite::{SqliteStore, SqliteStoreError};
 
+use crate::messages::expiry::track_listed_cli;
+use crate::messages::mentions::resolve_mentions;
+use crate::messages::receipts::ReceiptStore;
+use crate::sidecar::AccountFile;
 use crate::{
     account_management::create_registered_manager, messages::receive::get_messages_as_message_dto,
 };
@@ -42,9 +46,17 @@ pub async fn list_messages_cli(recipient: String, from: Option<String>) -> Resul
         Some(mk) => mk,
         None => bail!("Group with given name does not exist."),
     };
+    let thread = Thread::Group(master_key);
 
     let messages = list_messages(&manager, master_key, from).await?;
-    get_messages_as_message_dto(messages)
+    let mut messages = get_messages_as_message_dto(messages)?;
+    // Listed messages are read, expired ones are deleted and hidden
+    track_listed_cli(&manager, &thread, &mut messages).await?;
+    resolve_mentions(&manager, &mut messages).await;
+    if let Ok(receipts) = ReceiptStore::load_current() {
+        receipts.apply(&thread, &mut messages);
+    }
+    Ok(messages)
 }
 
 pub async fn list_messages_tui(
@@ -53,5 +65,7 @@ pub async fn list_messages_tui(
     from: Option<String>,
 ) -> Result<Vec<MessageDto>> {
     let messages = list_messages(&manager, master_key, from).await?;
-    get_messages_as_message_dto(messages)
+    let mut messages = get_messages_as_message_dto(messages)?;
+    resolve_mentions(&manager, &mut messages).await;
+    Ok(messages)
 }
@@ -1,9 +1,71 @@
+use std::fmt;
+
 use anyhow::Result;
-use presage::proto::{DataMessage, data_message::Reaction};
+use presage::Manager;
+use presage::libsignal_service::prelude::Uuid;
+use presage::manager::Registered;
+use presage::proto::typing_message::Action;
+use presage::proto::{DataMessage, EditMessage, TypingMessage, data_message::Reaction};
+use presage::store::{ContentsStore, Thread};
+use presage_store_sqlite::{SqliteStore, SqliteStoreError};
+
+use crate::messages::receive::{MessageDto, format_message};
 
 pub mod contact;
 pub mod group;
 
+/// Reasons a message can't be sent that don't go away by sending it again
+#[derive(Debug)]
+pub enum SendError {
+    /// No contact is known by the name, number or UUID given by the user
+    UnknownRecipient(String),
+    /// No contact has the phone number, in E.164 format
+    UnknownPhoneNumber(String),
+    UnknownGroup,
+    /// The thread has no message with the timestamp
+    UnknownMessage,
+    /// Only messages sent from this account can be edited
+    NotOwnMessage,
+    /// The message to edit has no text
+    NoText,
+    /// The attachment file can't be read, with the reason
+    InvalidAttachment(String),
+    /// Safety number with the contact changed and the identity policy blocks sending until the
+    /// user verifies it
+    UntrustedIdentity(Uuid),
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::UnknownRecipient(query) => write!(f, "Recipient '{query}' not found"),
+            SendError::UnknownPhoneNumber(phone_number) => {
+                write!(f, "No contact has the phone number {phone_number}")
+            }
+            SendError::UnknownGroup => write!(f, "Group with given name does not exist."),
+            SendError::UnknownMessage => write!(f, "Message with given timestamp not found."),
+            SendError::NotOwnMessage => {
+                write!(f, "Only messages sent from this account can be edited.")
+            }
+            SendError::NoText => write!(f, "Message with given timestamp has no text."),
+            SendError::InvalidAttachment(reason) => f.write_str(reason),
+            SendError::UntrustedIdentity(uuid) => write!(
+                f,
+                "Safety number with {uuid} changed, verify it in the contact info before sending"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SendError {}
+
+/// Error of presage sending a message, kept as the source so it can be told apart later, see
+/// `FailureKind::classify`
+pub(crate) fn send_failed(error: presage::Error<SqliteStoreError>) -> anyhow::Error {
+    let message = format!("Failed to send message: {error}");
+    anyhow::Error::new(error).context(message)
+}
+
 pub fn create_reaction_data_message(
     timestamp: u64,
     target_send_timestamp: u64,
@@ -25,3 +87,50 @@ pub fn create_reaction_data_message(
     };
     Ok(data_msg)
 }
+
+pub fn create_edit_message(target_send_timestamp: u64, data_message: DataMessage) -> EditMessage {
+    EditMessage {
+        target_sent_timestamp: Some(target_send_timestamp),
+        data_message: Some(data_message),
+    }
+}
+
+/// Typing indicator, `group_id` is the group identifier when typing in a group
+pub fn create_typing_message(
+    started: bool,
+    timestamp: u64,
+    group_id: Option<Vec<u8>>,
+) -> TypingMessage {
+    let action = if started {
+        Action::Started
+    } else {
+        Action::Stopped
+    };
+    TypingMessage {
+        timestamp: Some(timestamp),
+        action: Some(action.into()),
+        group_id,
+    }
+}
+
+/// Returns stored message with given timestamp, fails if it wasn't sent from this account
+pub(crate) async fn find_own_message(
+    manager: &Manager<SqliteStore, Registered>,
+    thread: &Thread,
+    target_send_timestamp: u64,
+) -> Result<MessageDto> {
+    let content = match manager
+        .store()
+        .message(thread, target_send_timestamp)
+        .await?
+    {
+        Some(content) => content,
+        None => return Err(SendError::UnknownMessage.into()),
+    };
+
+    if content.metadata.sender.raw_uuid() != manager.registration_data().service_ids.aci {
+        return Err(SendError::NotOwnMessage.into());
+    }
+
+    format_message(&content).ok_or_else(|| SendError::NoText.into())
+}
@@ -1,44 +1,40 @@
-use std::str::FromStr;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::account_management::create_registered_manager;
-use crate::messages::attachments::create_attachment;
+use crate::config::{Config, ImageProcessing};
+use crate::emoji;
+use crate::identity::{IdentityStore, ensure_trusted};
+use crate::messages::attachments::{AttachmentInput, create_attachments};
+use crate::messages::expiry::apply_expire_timer;
 use crate::messages::format_message;
+use crate::messages::formatting::create_body;
 use crate::messages::receive::MessageDto;
 use crate::messages::receive::receive_messages_cli;
-use crate::messages::send::create_reaction_data_message;
+use crate::messages::send::{
+    SendError, create_edit_message, create_reaction_data_message, create_typing_message,
+    find_own_message, send_failed,
+};
+use crate::recipients::resolve_recipient_cli;
+use crate::search::index_sent_cli;
 use anyhow::{Result, bail};
+use presage::libsignal_service::content::ContentBody;
 use presage::libsignal_service::protocol::ServiceId;
 use presage::proto::DataMessage;
-use presage::proto::data_message::{Delete, Quote};
+use presage::proto::data_message::{Delete, Flags, Quote};
 use presage::store::{ContentsStore, Thread};
 use presage::{
     Manager, libsignal_service::prelude::Uuid, manager::Registered, model::contacts::Contact,
 };
 use presage_store_sqlite::{SqliteStore, SqliteStoreError};
+use tokio::sync::Mutex;
 use tracing::error;
 
-/// finds contact uuid from string that can be contact_name or contact phone_number
+/// Finds the contact given by the user, see `resolve_recipient_cli`
 pub async fn find_uuid(
     recipient_info: String,
     manager: &mut Manager<SqliteStore, Registered>,
 ) -> Result<Uuid> {
-    let contacts: Vec<Result<Contact, SqliteStoreError>> =
-        manager.store().contacts().await?.collect();
-    let uuid = contacts
-        .into_iter()
-        .filter_map(|c| c.ok())
-        .find(|c| {
-            // Compare first by name, then by phone number
-            // and finally by UUID
-            (c.name == recipient_info)
-                || (c.phone_number.is_some()
-                    && c.phone_number.clone().unwrap().to_string() == recipient_info)
-                || (c.uuid.to_string() == recipient_info)
-        })
-        .map(|c| c.uuid);
-
-    uuid.ok_or_else(|| anyhow::anyhow!("Recipient '{recipient_info}' not found"))
+    resolve_recipient_cli(&recipient_info, manager).await
 }
 
 pub async fn get_address(
@@ -64,12 +60,11 @@ pub fn create_data_message(
         }),
         None => None,
     };
+    // Styles use the composer syntax, see `formatting::parse_markdown`
+    let (body, body_ranges) = create_body(&text_message);
     let data_msg = DataMessage {
-        body: Some(
-            text_message
-                .parse()
-                .map_err(|_| anyhow::anyhow!("Failed to parse text message!"))?,
-        ),
+        body: Some(body),
+        body_ranges,
         timestamp: Some(timestamp),
         quote,
         ..Default::default()
@@ -92,21 +87,38 @@ pub fn create_delete_data_message(
     Ok(data_msg)
 }
 
+/// Sends the message unless the identity policy refuses the contact, see `ensure_trusted`
 pub async fn send(
     manager: &mut Manager<SqliteStore, Registered>,
+    identities: &Mutex<IdentityStore>,
     recipient_addr: ServiceId,
     data_message: DataMessage,
     timestamp: u64,
 ) -> Result<()> {
+    ensure_trusted(manager, identities, [recipient_addr.raw_uuid()]).await?;
+    deliver(manager, recipient_addr, data_message, timestamp).await
+}
+
+/// Sends the message without checking the identity of the contact
+async fn deliver(
+    manager: &mut Manager<SqliteStore, Registered>,
+    recipient_addr: ServiceId,
+    mut data_message: DataMessage,
+    timestamp: u64,
+) -> Result<()> {
+    let thread = Thread::Contact(recipient_addr.raw_uuid());
+    apply_expire_timer(manager, &thread, &mut data_message).await?;
+
     manager
         .send_message(recipient_addr, data_message, timestamp)
         .await
-        .map_err(|e| anyhow::anyhow!("Failed to send message: {e}"))?;
+        .map_err(send_failed)?;
     Ok(())
 }
 
 async fn send_message(
     manager: &mut Manager<SqliteStore, Registered>,
+    identities: &Mutex<IdentityStore>,
     recipient: String,
     text_message: String,
     quoted_message: Option<MessageDto>,
@@ -115,13 +127,21 @@ async fn send_message(
     let recipient_address = get_address(recipient, manager).await?;
     let data_message = create_data_message(text_message, timestamp, quoted_message)?;
 
-    send(manager, recipient_address, data_message, timestamp).await?;
+    send(
+        manager,
+        identities,
+        recipient_address,
+        data_message,
+        timestamp,
+    )
+    .await?;
 
     Ok(())
 }
 
 async fn send_delete_message(
     manager: &mut Manager<SqliteStore, Registered>,
+    identities: &Mutex<IdentityStore>,
     recipient: String,
     target_send_timestamp: u64,
 ) -> Result<()> {
@@ -129,13 +149,133 @@ async fn send_delete_message(
     let recipient_address = get_address(recipient, manager).await?;
     let data_message = create_delete_data_message(timestamp, target_send_timestamp)?;
 
-    send(manager, recipient_address, data_message, timestamp).await?;
+    send(
+        manager,
+        identities,
+        recipient_address,
+        data_message,
+        timestamp,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Sends the edit unless the identity policy refuses the contact, see `ensure_trusted`
+async fn send_edit_message(
+    manager: &mut Manager<SqliteStore, Registered>,
+    identities: &Mutex<IdentityStore>,
+    recipient_uuid: Uuid,
+    target_send_timestamp: u64,
+    text_message: String,
+) -> Result<()> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+    let thread = Thread::Contact(recipient_uuid);
+    let original = find_own_message(manager, &thread, target_send_timestamp).await?;
+    ensure_trusted(manager, identities, [recipient_uuid]).await?;
+
+    let mut data_message = create_data_message(text_message, timestamp, None)?;
+    // The edit replaces the whole message, so the quote has to be sent again
+    data_message.quote = original.quote;
+    apply_expire_timer(manager, &thread, &mut data_message).await?;
+    let edit_message = create_edit_message(target_send_timestamp, data_message);
+
+    manager
+        .send_message(
+            ServiceId::Aci(recipient_uuid.into()),
+            ContentBody::EditMessage(edit_message),
+            timestamp,
+        )
+        .await
+        .map_err(send_failed)?;
+    Ok(())
+}
+
+pub async fn send_edit_message_tui(
+    mut manager: Manager<SqliteStore, Registered>,
+    identities: &Mutex<IdentityStore>,
+    recipient: Uuid,
+    target_send_timestamp: u64,
+    text_message: String,
+) -> Result<()> {
+    send_edit_message(
+        &mut manager,
+        identities,
+        recipient,
+        target_send_timestamp,
+        text_message,
+    )
+    .await
+}
 
+/// replaces text of a message sent to the contact, for usage with CLI
+pub async fn send_edit_message_cli(
+    recipient: String,
+    target_send_timestamp: u64,
+    text_message: String,
+) -> Result<()> {
+    let mut manager = create_registered_manager().await?;
+    let recipient_uuid = find_uuid(recipient, &mut manager).await?;
+    let identities = Mutex::new(IdentityStore::load_current()?);
+    send_edit_message(
+        &mut manager,
+        &identities,
+        recipient_uuid,
+        target_send_timestamp,
+        text_message,
+    )
+    .await?;
+    index_sent_cli(&manager, &Thread::Contact(recipient_uuid)).await;
+    Ok(())
+}
+
+/// Shares our profile key with the contact, so they see our name and avatar, e.g. after we
+/// accepted their message request
+pub async fn send_profile_key(
+    manager: &mut Manager<SqliteStore, Registered>,
+    recipient: Uuid,
+) -> Result<()> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+    let profile_key = manager.registration_data().profile_key();
+    let data_message = DataMessage {
+        flags: Some(Flags::ProfileKeyUpdate as u32),
+        profile_key: Some(profile_key.get_bytes().to_vec()),
+        timestamp: Some(timestamp),
+        ..Default::default()
+    };
+    // Sharing the profile key reveals nothing the user wrote, so the identity isn't checked
+    deliver(
+        manager,
+        ServiceId::Aci(recipient.into()),
+        data_message,
+        timestamp,
+    )
+    .await
+}
+
+/// Tells the contact that we started or stopped typing
+pub async fn send_typing_message_tui(
+    mut manager: Manager<SqliteStore, Registered>,
+    recipient: Uuid,
+    started: bool,
+) -> Result<()> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+    let typing_message = create_typing_message(started, timestamp, None);
+
+    manager
+        .send_message(
+            ServiceId::Aci(recipient.into()),
+            ContentBody::TypingMessage(typing_message),
+            timestamp,
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to send typing message: {e}"))?;
     Ok(())
 }
 
 async fn send_reaction_message(
     manager: &mut Manager<SqliteStore, Registered>,
+    identities: &Mutex<IdentityStore>,
     recipient: String,
     target_send_timestamp: u64,
     target_author_aci: String,
@@ -152,13 +292,21 @@ async fn send_reaction_message(
         emoji,
     )?;
 
-    send(manager, recipient_address, data_message, timestamp).await?;
+    send(
+        manager,
+        identities,
+        recipient_address,
+        data_message,
+        timestamp,
+    )
+    .await?;
 
     Ok(())
 }
 
 pub async fn send_reaction_message_tui(
     mut manager: Manager<SqliteStore, Registered>,
+    identities: &Mutex<IdentityStore>,
     recipient: String,
     target_send_timestamp: u64,
     target_author_aci: String,
@@ -167,6 +315,7 @@ pub async fn send_reaction_message_tui(
 ) -> Result<()> {
     send_reaction_message(
         &mut manager,
+        identities,
         recipient,
         target_send_timestamp,
         target_author_aci,
@@ -182,17 +331,25 @@ pub async fn send_message_tui(
     text_message: String,
     quoted_message: Option<MessageDto>,
     mut manager: Manager<SqliteStore, Registered>,
+    identities: &Mutex<IdentityStore>,
 ) -> Result<()> {
-    // let mut manager = create_registered_manager().await?;
-    send_message(&mut manager, recipient, text_message, quoted_message).await
+    send_message(
+        &mut manager,
+        identities,
+        recipient,
+        text_message,
+        quoted_message,
+    )
+    .await
 }
 
 pub async fn send_delete_message_tui(
     mut manager: Manager<SqliteStore, Registered>,
+    identities: &Mutex<IdentityStore>,
     recipient: String,
     target_send_timestamp: u64,
 ) -> Result<()> {
-    send_delete_message(&mut manager, recipient, target_send_timestamp).await
+    send_delete_message(&mut manager, identities, recipient, target_send_timestamp).await
 }
 
 /// sends text message to recipient ( phone number or name ), for usage with CLI
@@ -202,9 +359,10 @@ pub async fn send_message_cli(
     quoted_message: Option<u64>,
 ) -> Result<()> {
     let mut manager = create_registered_manager().await?;
+    let recipient_uuid = find_uuid(recipient, &mut manager).await?;
+    let recipient = recipient_uuid.to_string();
     let quoted_message_dto = match quoted_message {
         Some(quote_ts) => {
-            let recipient_uuid = Uuid::from_str(&recipient)?;
             let thread = Thread::Contact(recipient_uuid);
             let quoted_data_message = manager.store().message(&thread, quote_ts).await?;
             if let Some(content) = quoted_data_message {
@@ -215,73 +373,93 @@ pub async fn send_message_cli(
         }
         None => None,
     };
-    send_message(&mut manager, recipient, text_message, quoted_message_dto).await
+    let identities = Mutex::new(IdentityStore::load_current()?);
+    send_message(
+        &mut manager,
+        &identities,
+        recipient,
+        text_message,
+        quoted_message_dto,
+    )
+    .await?;
+    index_sent_cli(&manager, &Thread::Contact(recipient_uuid)).await;
+    Ok(())
 }
 
 async fn send_attachment(
     manager: &mut Manager<SqliteStore, Registered>,
+    identities: &Mutex<IdentityStore>,
     recipient: String,
     text_message: String,
-    attachment_path: String,
+    attachments: Vec<AttachmentInput>,
     quoted_message: Option<MessageDto>,
+    processing: &ImageProcessing,
 ) -> Result<()> {
     let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
     let recipient_address = get_address(recipient, manager).await?;
 
-    let attachment_spec = create_attachment(attachment_path).await?;
+    let attachment_specs = create_attachments(&attachments, processing).await?;
 
-    let attachment_specs = vec![attachment_spec];
-
-    let attachments: Result<Vec<_>, _> = manager
+    let attachment_pointers: Result<Vec<_>, _> = manager
         .upload_attachments(attachment_specs)
         .await?
         .into_iter()
         .collect();
-    let attachments = attachments?;
-
-    let attachment_pointer = attachments
-        .into_iter()
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("Failed to get attachment pointer"))?;
+    let attachment_pointers = attachment_pointers?;
+    if attachment_pointers.len() != attachments.len() {
+        bail!("Failed to get attachment pointers");
+    }
 
     let mut data_message = create_data_message(text_message, timestamp, quoted_message)?;
-    data_message.attachments = vec![attachment_pointer];
+    data_message.attachments = attachment_pointers;
 
-    send(manager, recipient_address, data_message, timestamp).await?;
+    send(
+        manager,
+        identities,
+        recipient_address,
+        data_message,
+        timestamp,
+    )
+    .await?;
 
     Ok(())
 }
 
-/// sends attachment to recipient ( phone number or name ), for usage with TUI
+/// sends attachments to recipient ( phone number or name ) in one message, for usage with TUI
 pub async fn send_attachment_tui(
     recipient: String,
     text_message: String,
-    attachment_path: String,
+    attachments: Vec<AttachmentInput>,
     quoted_message: Option<MessageDto>,
     mut manager: Manager<SqliteStore, Registered>,
+    identities: &Mutex<IdentityStore>,
 ) -> Result<()> {
     send_attachment(
         &mut manager,
+        identities,
         recipient,
         text_message,
-        attachment_path,
+        attachments,
         quoted_message,
+        &Config::load().image_processing,
     )
     .await
 }
 
-/// sends attachment to recipient ( phone number or name ), for usage with CLI
+/// sends attachments to recipient ( phone number or name ) in one message, for usage with CLI
 pub async fn send_attachment_cli(
     recipient: String,
     text_message: String,
-    attachment_path: String,
+    attachments: Vec<AttachmentInput>,
     quoted_message: Option<u64>,
+    processing: ImageProcessing,
 ) -> Result<()> {
     receive_messages_cli().await?;
     let mut manager = create_registered_manager().await?;
+    let recipient_uuid = find_uuid(recipient, &mut manager).await?;
+    let recipient = recipient_uuid.to_string();
     let quoted_message_dto = match quoted_message {
         Some(quote_ts) => {
-            let recipient_uuid = Uuid::from_str(&recipient)?;
             let thread = Thread::Contact(recipient_uuid);
             let quoted_data_message = manager.store().message(&thread, quote_ts).await?;
             if let Some(content) = quoted_data_message {
@@ -292,18 +470,24 @@ pub async fn send_attachment_cli(
         }
         None => None,
     };
+    let identities = Mutex::new(IdentityStore::load_current()?);
     send_attachment(
         &mut manager,
+        &identities,
         recipient,
         text_message,
-        attachment_path,
+        attachments,
         quoted_message_dto,
+        &processing,
     )
-    .await
+    .await?;
+    index_sent_cli(&manager, &Thread::Contact(recipient_uuid)).await;
+    Ok(())
 }
 pub async fn send_delete_message_cli(recipient: String, target_send_timestamp: u64) -> Result<()> {
     let mut manager: Manager<SqliteStore, Registered> = create_registered_manager().await?;
-    let uuid = Uuid::from_str(&recipient)?;
+    let uuid = find_uuid(recipient, &mut manager).await?;
+    let recipient = uuid.to_string();
     let thread = Thread::Contact(uuid);
 
     let sender = match manager
@@ -312,13 +496,19 @@ pub async fn send_delete_message_cli(recipient: String, target_send_timestamp: u
         .await?
     {
         Some(con) => con.metadata.sender,
-        None => bail!("Message with given timestamp not found."),
+        None => return Err(SendError::UnknownMessage.into()),
     };
 
     let user = manager.whoami().await?;
 
     match sender.raw_uuid() == user.aci {
-        true => send_delete_message(&mut manager, recipient, target_send_timestamp).await,
+        true => {
+            let identities = Mutex::new(IdentityStore::load_current()?);
+            send_delete_message(&mut manager, &identities, recipient, target_send_timestamp)
+                .await?;
+            index_sent_cli(&manager, &thread).await;
+            Ok(())
+        }
         false => {
             error!("Cannot delete message not send by this user");
             Ok(())
@@ -329,9 +519,13 @@ pub async fn send_delete_message_cli(recipient: String, target_send_timestamp: u
 pub async fn send_reaction_message_cli(
     recipient: String,
     target_send_timestamp: u64,
+    emoji: String,
+    remove: bool,
 ) -> Result<()> {
+    let emoji = emoji::resolve(&emoji)?;
     let mut manager: Manager<SqliteStore, Registered> = create_registered_manager().await?;
-    let uuid = Uuid::from_str(&recipient)?;
+    let uuid = find_uuid(recipient, &mut manager).await?;
+    let recipient = uuid.to_string();
     let thread = Thread::Contact(uuid);
 
     let raw_message = match manager
@@ -340,7 +534,7 @@ pub async fn send_reaction_message_cli(
         .await?
     {
         Some(msg) => msg,
-        None => bail!("Message with given timestamp not found."),
+        None => return Err(SendError::UnknownMessage.into()),
     };
 
     let dto = match format_message(&raw_message) {
@@ -350,11 +544,10 @@ pub async fn send_reaction_message_cli(
 
     let target_author_aci = dto.uuid.to_string();
 
-    let emoji = "👍".to_string();
-    let remove = false;
-
+    let identities = Mutex::new(IdentityStore::load_current()?);
     send_reaction_message(
         &mut manager,
+        &identities,
         recipient,
         target_send_timestamp,
         target_author_aci,
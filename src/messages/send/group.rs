@@ -1,27 +1,47 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::account_management::create_registered_manager;
-use crate::groups::find_master_key;
+use crate::emoji;
+use crate::groups::{find_master_key, group_identifier};
+use crate::identity::{IdentityStore, ensure_trusted};
+use crate::messages::expiry::apply_expire_timer;
 use crate::messages::format_message;
+use crate::messages::formatting::create_body;
 use crate::messages::receive::{MessageDto, receive_messages_cli};
-use crate::messages::send::create_reaction_data_message;
+use crate::messages::send::{
+    SendError, create_edit_message, create_reaction_data_message, create_typing_message,
+    find_own_message, send_failed,
+};
+use crate::search::index_sent_cli;
 use anyhow::{Result, bail};
+use presage::libsignal_service::content::ContentBody;
+use presage::libsignal_service::prelude::Uuid;
 use presage::proto::data_message::{Delete, Quote};
 use presage::proto::{DataMessage, GroupContextV2};
 use presage::store::{ContentsStore, Thread};
 use presage::{Manager, libsignal_service::zkgroup::GroupMasterKeyBytes, manager::Registered};
 use presage_store_sqlite::SqliteStore;
+use tokio::sync::Mutex;
 use tracing::error;
 
-use crate::messages::attachments::create_attachment;
+use crate::config::Config;
+use crate::messages::attachments::{AttachmentInput, create_attachments};
 
 pub async fn send_message_tui(
     master_key: GroupMasterKeyBytes,
     text_message: String,
     mut manager: Manager<SqliteStore, Registered>,
+    identities: &Mutex<IdentityStore>,
     quoted_message: Option<MessageDto>,
 ) -> Result<()> {
-    send_message(&mut manager, master_key, text_message, quoted_message).await
+    send_message(
+        &mut manager,
+        identities,
+        master_key,
+        text_message,
+        quoted_message,
+    )
+    .await
 }
 
 pub async fn send_message_cli(group_name: String, text_message: String) -> Result<()> {
@@ -30,18 +50,22 @@ pub async fn send_message_cli(group_name: String, text_message: String) -> Resul
 
     let master_key = match find_master_key(group_name.clone(), &mut manager).await? {
         Some(key) => key,
-        None => bail!("Dind't find key for group \"{group_name}\""),
+        None => return Err(SendError::UnknownGroup.into()),
     };
 
-    send_message(&mut manager, master_key, text_message, None).await
+    let identities = Mutex::new(IdentityStore::load_current()?);
+    send_message(&mut manager, &identities, master_key, text_message, None).await?;
+    index_sent_cli(&manager, &Thread::Group(master_key)).await;
+    Ok(())
 }
 
 pub async fn send_delete_message_tui(
     master_key: GroupMasterKeyBytes,
     mut manager: Manager<SqliteStore, Registered>,
+    identities: &Mutex<IdentityStore>,
     target_send_timestamp: u64,
 ) -> Result<()> {
-    send_delete_message(&mut manager, master_key, target_send_timestamp).await
+    send_delete_message(&mut manager, identities, master_key, target_send_timestamp).await
 }
 
 pub async fn send_delete_message_cli(recipient: String, target_send_timestamp: u64) -> Result<()> {
@@ -50,7 +74,7 @@ pub async fn send_delete_message_cli(recipient: String, target_send_timestamp: u
     let master_key = find_master_key(recipient, &mut manager).await?;
     let master_key = match master_key {
         Some(mk) => mk,
-        None => bail!("Group with given name does not exist."),
+        None => return Err(SendError::UnknownGroup.into()),
     };
 
     let thread = Thread::Group(master_key);
@@ -61,13 +85,19 @@ pub async fn send_delete_message_cli(recipient: String, target_send_timestamp: u
         .await?
     {
         Some(con) => con.metadata.sender,
-        None => bail!("Message with given timestamp not found."),
+        None => return Err(SendError::UnknownMessage.into()),
     };
 
     let user = manager.whoami().await?;
 
     match sender.raw_uuid() == user.aci {
-        true => send_delete_message(&mut manager, master_key, target_send_timestamp).await,
+        true => {
+            let identities = Mutex::new(IdentityStore::load_current()?);
+            send_delete_message(&mut manager, &identities, master_key, target_send_timestamp)
+                .await?;
+            index_sent_cli(&manager, &thread).await;
+            Ok(())
+        }
         false => {
             error!("Cannot delete message not send by this user");
             Ok(())
@@ -77,6 +107,7 @@ pub async fn send_delete_message_cli(recipient: String, target_send_timestamp: u
 
 async fn send_message(
     manager: &mut Manager<SqliteStore, Registered>,
+    identities: &Mutex<IdentityStore>,
     master_key: GroupMasterKeyBytes,
     text_message: String,
     quoted_message: Option<MessageDto>,
@@ -85,34 +116,150 @@ async fn send_message(
 
     let data_message = create_data_message(text_message, &master_key, timestamp, quoted_message);
 
-    send(manager, &master_key, data_message, timestamp).await
+    send(manager, identities, &master_key, data_message, timestamp).await
 }
 
 async fn send_delete_message(
     manager: &mut Manager<SqliteStore, Registered>,
+    identities: &Mutex<IdentityStore>,
     master_key: GroupMasterKeyBytes,
     target_send_timestamp: u64,
 ) -> Result<()> {
     let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
     let data_message = create_delete_data_message(&master_key, timestamp, target_send_timestamp);
 
-    let send_result = send(manager, &master_key, data_message, timestamp).await;
-    if let Err(e) = send_result {
-        error!("{e}");
-    }
+    send(manager, identities, &master_key, data_message, timestamp).await
+}
+
+/// Sends the edit unless the identity policy refuses one of the members, see `ensure_trusted`
+async fn send_edit_message(
+    manager: &mut Manager<SqliteStore, Registered>,
+    identities: &Mutex<IdentityStore>,
+    master_key: GroupMasterKeyBytes,
+    target_send_timestamp: u64,
+    text_message: String,
+) -> Result<()> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+    let thread = Thread::Group(master_key);
+    let original = find_own_message(manager, &thread, target_send_timestamp).await?;
+    let members = other_members(manager, &master_key).await?;
+    ensure_trusted(manager, identities, members).await?;
+
+    // The edit replaces the whole message, so the quote has to be sent again
+    let data_message = create_data_message(text_message, &master_key, timestamp, None);
+    let mut data_message = DataMessage {
+        quote: original.quote,
+        ..data_message
+    };
+    apply_expire_timer(manager, &thread, &mut data_message).await?;
+    let edit_message = create_edit_message(target_send_timestamp, data_message);
+
+    manager
+        .send_message_to_group(
+            &master_key,
+            ContentBody::EditMessage(edit_message),
+            timestamp,
+        )
+        .await
+        .map_err(send_failed)
+}
+
+/// Tells members of the group that we started or stopped typing
+pub async fn send_typing_message_tui(
+    master_key: GroupMasterKeyBytes,
+    mut manager: Manager<SqliteStore, Registered>,
+    started: bool,
+) -> Result<()> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+    let group_id = group_identifier(master_key).to_vec();
+    let typing_message = create_typing_message(started, timestamp, Some(group_id));
+
+    manager
+        .send_message_to_group(
+            &master_key,
+            ContentBody::TypingMessage(typing_message),
+            timestamp,
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to send typing message: {e}"))
+}
+
+pub async fn send_edit_message_tui(
+    master_key: GroupMasterKeyBytes,
+    mut manager: Manager<SqliteStore, Registered>,
+    identities: &Mutex<IdentityStore>,
+    target_send_timestamp: u64,
+    text_message: String,
+) -> Result<()> {
+    send_edit_message(
+        &mut manager,
+        identities,
+        master_key,
+        target_send_timestamp,
+        text_message,
+    )
+    .await
+}
+
+pub async fn send_edit_message_cli(
+    recipient: String,
+    target_send_timestamp: u64,
+    text_message: String,
+) -> Result<()> {
+    let mut manager = create_registered_manager().await?;
+
+    let master_key = match find_master_key(recipient, &mut manager).await? {
+        Some(master_key) => master_key,
+        None => return Err(SendError::UnknownGroup.into()),
+    };
+
+    let identities = Mutex::new(IdentityStore::load_current()?);
+    send_edit_message(
+        &mut manager,
+        &identities,
+        master_key,
+        target_send_timestamp,
+        text_message,
+    )
+    .await?;
+    index_sent_cli(&manager, &Thread::Group(master_key)).await;
     Ok(())
 }
 
+/// Members of the group other than us, whose identities are checked before sending
+async fn other_members(
+    manager: &Manager<SqliteStore, Registered>,
+    master_key: &GroupMasterKeyBytes,
+) -> Result<Vec<Uuid>> {
+    let own_uuid = manager.registration_data().service_ids.aci;
+    Ok(match manager.store().group(*master_key).await? {
+        Some(group) => group
+            .members
+            .iter()
+            .map(|member| member.uuid)
+            .filter(|uuid| *uuid != own_uuid)
+            .collect(),
+        None => Vec::new(),
+    })
+}
+
+/// Sends the message to the group unless the identity policy refuses one of its members, see
+/// `ensure_trusted`
 pub async fn send(
     manager: &mut Manager<SqliteStore, Registered>,
+    identities: &Mutex<IdentityStore>,
     recipient: &GroupMasterKeyBytes,
-    data_message: DataMessage,
+    mut data_message: DataMessage,
     timestamp: u64,
 ) -> Result<()> {
+    let members = other_members(manager, recipient).await?;
+    ensure_trusted(manager, identities, members).await?;
+    apply_expire_timer(manager, &Thread::Group(*recipient), &mut data_message).await?;
+
     manager
         .send_message_to_group(recipient, data_message.clone(), timestamp)
         .await
-        .map_err(|e| anyhow::anyhow!("Failed to send message: {e}"))
+        .map_err(send_failed)
 }
 
 pub fn create_data_message(
@@ -131,8 +278,12 @@ pub fn create_data_message(
         }),
         None => None,
     };
+    // Styles use the composer syntax and mentions are written as tokens in the text, see
+    // `formatting::parse_markdown` and `mentions::mention_token`
+    let (body, body_ranges) = create_body(&text_message);
     DataMessage {
-        body: Some(text_message),
+        body: Some(body),
+        body_ranges,
         group_v2: Some(GroupContextV2 {
             master_key: Some(master_key),
 
@@ -146,54 +297,54 @@ pub fn create_data_message(
     }
 }
 
-/// Send message with attachment
+/// Send message with attachments
 async fn send_attachment(
     manager: &mut Manager<SqliteStore, Registered>,
+    identities: &Mutex<IdentityStore>,
     master_key: &GroupMasterKeyBytes,
     text_message: String,
     quoted_message: Option<MessageDto>,
-    attachment_path: String,
+    attachments: Vec<AttachmentInput>,
 ) -> Result<()> {
     let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
 
-    let attachment_spec = create_attachment(attachment_path).await?;
-
-    let attachment_specs = vec![attachment_spec];
+    let attachment_specs =
+        create_attachments(&attachments, &Config::load().image_processing).await?;
 
-    let attachments: Result<Vec<_>, _> = manager
+    let attachment_pointers: Result<Vec<_>, _> = manager
         .upload_attachments(attachment_specs)
         .await?
         .into_iter()
         .collect();
-    let attachments = attachments?;
-
-    let attachment_pointer = attachments
-        .into_iter()
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("Failed to get attachment pointer"))?;
+    let attachment_pointers = attachment_pointers?;
+    if attachment_pointers.len() != attachments.len() {
+        bail!("Failed to get attachment pointers");
+    }
 
     let mut data_message = create_data_message(text_message, master_key, timestamp, quoted_message);
-    data_message.attachments = vec![attachment_pointer];
+    data_message.attachments = attachment_pointers;
 
-    send(manager, master_key, data_message, timestamp).await?;
+    send(manager, identities, master_key, data_message, timestamp).await?;
 
     Ok(())
 }
 
-/// sends attachment to recipient ( phone number or name ), for usage with TUI
+/// sends attachments to the group in one message, for usage with TUI
 pub async fn send_attachment_tui(
     master_key: &GroupMasterKeyBytes,
     text_message: String,
-    attachment_path: String,
+    attachments: Vec<AttachmentInput>,
     quoted_message: Option<MessageDto>,
     mut manager: Manager<SqliteStore, Registered>,
+    identities: &Mutex<IdentityStore>,
 ) -> Result<()> {
     send_attachment(
         &mut manager,
+        identities,
         master_key,
         text_message,
         quoted_message,
-        attachment_path,
+        attachments,
     )
     .await
 }
@@ -224,6 +375,7 @@ pub fn create_delete_data_message(
 
 async fn send_reaction_message(
     manager: &mut Manager<SqliteStore, Registered>,
+    identities: &Mutex<IdentityStore>,
     master_key: &GroupMasterKeyBytes,
     target_send_timestamp: u64,
     target_author_aci: String,
@@ -239,13 +391,14 @@ async fn send_reaction_message(
         emoji,
     )?;
 
-    send(manager, master_key, data_message, timestamp).await?;
+    send(manager, identities, master_key, data_message, timestamp).await?;
 
     Ok(())
 }
 
 pub async fn send_reaction_message_tui(
     mut manager: Manager<SqliteStore, Registered>,
+    identities: &Mutex<IdentityStore>,
     master_key: &GroupMasterKeyBytes,
     target_send_timestamp: u64,
     target_author_aci: String,
@@ -254,6 +407,7 @@ pub async fn send_reaction_message_tui(
 ) -> Result<()> {
     send_reaction_message(
         &mut manager,
+        identities,
         master_key,
         target_send_timestamp,
         target_author_aci,
@@ -266,13 +420,16 @@ pub async fn send_reaction_message_tui(
 pub async fn send_reaction_message_cli(
     recipient: String,
     target_send_timestamp: u64,
+    emoji: String,
+    remove: bool,
 ) -> Result<()> {
+    let emoji = emoji::resolve(&emoji)?;
     let mut manager = create_registered_manager().await?;
 
     let master_key = find_master_key(recipient, &mut manager).await?;
     let master_key = match master_key {
         Some(mk) => mk,
-        None => bail!("Group with given name does not exist."),
+        None => return Err(SendError::UnknownGroup.into()),
     };
 
     let thread = Thread::Group(master_key);
@@ -283,7 +440,7 @@ pub async fn send_reaction_message_cli(
         .await?
     {
         Some(msg) => msg,
-        None => bail!("Message with given timestamp not found."),
+        None => return Err(SendError::UnknownMessage.into()),
     };
 
     let dto = match format_message(&raw_message) {
@@ -293,11 +450,10 @@ pub async fn send_reaction_message_cli(
 
     let target_author_aci = dto.uuid.to_string();
 
-    let emoji = "👍".to_string();
-    let remove = false;
-
+    let identities = Mutex::new(IdentityStore::load_current()?);
     send_reaction_message(
         &mut manager,
+        &identities,
         &master_key,
         target_send_timestamp,
         target_author_aci,
@@ -0,0 +1,311 @@
+use anyhow::Result;
+use chrono::{DateTime, SecondsFormat};
+use presage::libsignal_service::Profile;
+use presage::libsignal_service::prelude::Uuid;
+use presage::libsignal_service::zkgroup::GroupMasterKeyBytes;
+use presage::model::contacts::Contact;
+use presage::model::groups::Group;
+use serde::{Serialize, Serializer};
+
+use crate::app::RecipientId;
+use crate::args::OutputFormat;
+use crate::message_requests::PendingRequest;
+use crate::messages::formatting::TextStyle;
+use crate::messages::receipts::ReceiptStatus;
+use crate::messages::receive::MessageDto;
+use crate::search::SearchHit;
+
+/// Prints items in the requested format, `print_text` is used for the human readable one
+pub fn print_items<T: Serialize>(
+    format: OutputFormat,
+    items: &[T],
+    print_text: impl Fn(&T),
+) -> Result<()> {
+    match format {
+        OutputFormat::Text => items.iter().for_each(print_text),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(items)?),
+        OutputFormat::Ndjson => {
+            for item in items {
+                println!("{}", serde_json::to_string(item)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prints a single item in the requested format, `print_text` is used for the human readable one
+pub fn print_item<T: Serialize>(
+    format: OutputFormat,
+    item: &T,
+    print_text: impl Fn(&T),
+) -> Result<()> {
+    match format {
+        OutputFormat::Text => print_text(item),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(item)?),
+        OutputFormat::Ndjson => println!("{}", serde_json::to_string(item)?),
+    }
+    Ok(())
+}
+
+/// Milliseconds since the epoch formatted as RFC 3339 date in UTC
+pub(crate) fn datetime(timestamp: u64) -> Option<String> {
+    DateTime::from_timestamp_millis(timestamp as i64)
+        .map(|datetime| datetime.to_rfc3339_opts(SecondsFormat::Millis, true))
+}
+
+#[derive(Serialize)]
+struct MessageOutput<'a> {
+    timestamp: u64,
+    datetime: Option<String>,
+    sender: Uuid,
+    outgoing: bool,
+    text: &'a str,
+    /// The author deleted the message for everyone, `text` is only a tombstone
+    deleted: bool,
+    edited_at: Option<u64>,
+    edit_history: Vec<EditOutput<'a>>,
+    expires_at: Option<u64>,
+    /// Members mentioned in the text
+    mentions: Vec<Uuid>,
+    styles: Vec<StyleOutput>,
+    group_master_key: Option<String>,
+    quote: Option<QuoteOutput<'a>>,
+    reactions: Vec<ReactionOutput<'a>>,
+    receipts: Vec<ReceiptOutput>,
+    attachment: Option<AttachmentOutput<'a>>,
+}
+
+/// Styled part of the text, `start` and `end` are byte offsets
+#[derive(Serialize)]
+struct StyleOutput {
+    style: TextStyle,
+    start: usize,
+    end: usize,
+}
+
+#[derive(Serialize)]
+struct EditOutput<'a> {
+    timestamp: u64,
+    text: &'a str,
+}
+
+#[derive(Serialize)]
+struct QuoteOutput<'a> {
+    timestamp: u64,
+    author: Option<&'a str>,
+    text: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct ReactionOutput<'a> {
+    author: Uuid,
+    emoji: &'a str,
+}
+
+#[derive(Serialize)]
+struct ReceiptOutput {
+    recipient: Uuid,
+    status: ReceiptStatus,
+}
+
+#[derive(Serialize)]
+struct AttachmentOutput<'a> {
+    file_name: Option<&'a str>,
+    content_type: Option<&'a str>,
+    size: Option<u32>,
+    width: Option<u32>,
+    height: Option<u32>,
+    caption: Option<&'a str>,
+}
+
+impl Serialize for MessageDto {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut reactions = self
+            .reactions
+            .iter()
+            .filter(|(_, reaction)| !reaction.remove())
+            .map(|(author, reaction)| ReactionOutput {
+                author: *author,
+                emoji: reaction.emoji(),
+            })
+            .collect::<Vec<_>>();
+        // Reactions are kept in a map, sorting makes the output stable
+        reactions.sort_by_key(|reaction| reaction.author);
+
+        let mut receipts = self
+            .receipts
+            .iter()
+            .map(|(recipient, status)| ReceiptOutput {
+                recipient: *recipient,
+                status: *status,
+            })
+            .collect::<Vec<_>>();
+        receipts.sort_by_key(|receipt| receipt.recipient);
+
+        MessageOutput {
+            timestamp: self.timestamp,
+            datetime: datetime(self.timestamp),
+            sender: self.uuid,
+            outgoing: self.sender,
+            text: &self.text,
+            deleted: self.deleted,
+            edited_at: self.edited_at,
+            edit_history: self
+                .edit_history
+                .iter()
+                .map(|edit| EditOutput {
+                    timestamp: edit.timestamp,
+                    text: &edit.text,
+                })
+                .collect(),
+            expires_at: self.expires_at,
+            mentions: self.mentions.iter().map(|mention| mention.uuid).collect(),
+            styles: self
+                .styles
+                .iter()
+                .map(|styled| StyleOutput {
+                    style: styled.style,
+                    start: styled.range.start,
+                    end: styled.range.end,
+                })
+                .collect(),
+            group_master_key: self
+                .group_context
+                .as_ref()
+                .and_then(|context| context.master_key.as_ref())
+                .map(hex::encode),
+            quote: self.quote.as_ref().map(|quote| QuoteOutput {
+                timestamp: quote.id(),
+                author: quote.author_aci.as_deref(),
+                text: quote.text.as_deref(),
+            }),
+            reactions,
+            receipts,
+            attachment: self.attachment.as_ref().map(|attachment| AttachmentOutput {
+                file_name: attachment.file_name.as_deref(),
+                content_type: attachment.content_type.as_deref(),
+                size: attachment.size,
+                width: attachment.width,
+                height: attachment.height,
+                caption: attachment.caption.as_deref(),
+            }),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[derive(Serialize)]
+struct SearchHitOutput<'a> {
+    contact: Option<Uuid>,
+    group_master_key: Option<String>,
+    timestamp: u64,
+    datetime: Option<String>,
+    sender: Uuid,
+    outgoing: bool,
+    text: &'a str,
+    snippet: &'a str,
+}
+
+impl Serialize for SearchHit {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (contact, group_master_key) = match &self.recipient {
+            RecipientId::Contact(uuid) => (Some(*uuid), None),
+            RecipientId::Group(master_key) => (None, Some(hex::encode(master_key))),
+        };
+
+        SearchHitOutput {
+            contact,
+            group_master_key,
+            timestamp: self.timestamp,
+            datetime: datetime(self.timestamp),
+            sender: self.sender,
+            outgoing: self.outgoing,
+            text: &self.text,
+            snippet: &self.snippet,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[derive(Serialize)]
+pub struct ContactOutput {
+    pub uuid: Uuid,
+    pub name: String,
+    pub phone_number: Option<String>,
+}
+
+impl From<&Contact> for ContactOutput {
+    fn from(contact: &Contact) -> Self {
+        Self {
+            uuid: contact.uuid,
+            name: contact.name.clone(),
+            phone_number: contact.phone_number.as_ref().map(|phone| phone.to_string()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct GroupOutput {
+    pub master_key: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub revision: u32,
+    pub members: Vec<Uuid>,
+}
+
+impl From<(&GroupMasterKeyBytes, &Group)> for GroupOutput {
+    fn from((master_key, group): (&GroupMasterKeyBytes, &Group)) -> Self {
+        Self {
+            master_key: hex::encode(master_key),
+            title: group.title.clone(),
+            description: group.description.clone(),
+            revision: group.revision,
+            members: group.members.iter().map(|member| member.uuid).collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct RequestOutput {
+    pub uuid: Uuid,
+    pub profile_name: Option<String>,
+    pub phone_number: Option<String>,
+    pub latest_message: Option<MessageDto>,
+}
+
+impl From<&PendingRequest> for RequestOutput {
+    fn from(request: &PendingRequest) -> Self {
+        Self {
+            uuid: request.uuid,
+            profile_name: request.profile_name.clone(),
+            phone_number: request.phone_number.clone(),
+            latest_message: request.latest_message.clone(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ProfileOutput {
+    pub name: Option<String>,
+    pub given_name: Option<String>,
+    pub family_name: Option<String>,
+    pub about: Option<String>,
+    pub about_emoji: Option<String>,
+    pub unrestricted_unidentified_access: bool,
+}
+
+impl From<&Profile> for ProfileOutput {
+    fn from(profile: &Profile) -> Self {
+        Self {
+            name: profile.name.as_ref().map(|name| name.to_string()),
+            given_name: profile.name.as_ref().map(|name| name.given_name.clone()),
+            family_name: profile
+                .name
+                .as_ref()
+                .and_then(|name| name.family_name.clone()),
+            about: profile.about.clone(),
+            about_emoji: profile.about_emoji.clone(),
+            unrestricted_unidentified_access: profile.unrestricted_unidentified_access,
+        }
+    }
+}
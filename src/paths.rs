@@ -75,6 +75,25 @@ pub fn assets() -> PathBuf {
     .into()
 }
 
+/// Default location of the Unix socket the daemon listens on
+pub fn daemon_socket() -> PathBuf {
+    static PATH: OnceLock<PathBuf> = OnceLock::new();
+    PATH.get_or_init(|| {
+        if cfg!(debug_assertions) {
+            PathBuf::from("./signal_client/daemon.sock")
+        } else {
+            match ensure_data_dir() {
+                Ok(data_dir) => data_dir.join("signal_client/daemon.sock"),
+                Err(error) => {
+                    error!(?error);
+                    PathBuf::from("./signal_client/daemon.sock")
+                }
+            }
+        }
+    })
+    .into()
+}
+
 pub fn accounts_dir() -> PathBuf {
     if let Ok(accounts_dir) = env::var(ACCOUNTS_DIR) {
         return PathBuf::from(accounts_dir);
@@ -96,6 +115,60 @@ pub fn accounts_dir() -> PathBuf {
     .into()
 }
 
+pub fn account_dir(account_name: &str) -> PathBuf {
+    accounts_dir().join(account_name)
+}
+
 pub fn account_store_path(account_name: &str) -> PathBuf {
-    accounts_dir().join(account_name).join("store.db")
+    account_dir(account_name).join("store.db")
+}
+
+/// Location of the persisted outbox with messages that were not delivered yet
+pub fn account_outbox_path(account_name: &str) -> PathBuf {
+    account_dir(account_name).join("outbox.json")
+}
+
+/// Location of receipts of our messages and of read receipts we sent
+pub fn account_receipts_path(account_name: &str) -> PathBuf {
+    account_dir(account_name).join("receipts.json")
+}
+
+/// Location of identity keys of contacts seen before and of their verification
+pub fn account_identities_path(account_name: &str) -> PathBuf {
+    account_dir(account_name).join("identities.json")
+}
+
+/// Location of senders who aren't contacts and of the answers to their message requests
+pub fn account_message_requests_path(account_name: &str) -> PathBuf {
+    account_dir(account_name).join("message_requests.json")
+}
+
+/// Location of messages whose disappearing timer is running, ordered by when they disappear
+pub fn account_expiry_path(account_name: &str) -> PathBuf {
+    account_dir(account_name).join("expiring_messages.json")
+}
+
+/// Location of emojis recently and frequently used for reactions
+pub fn account_emoji_usage_path(account_name: &str) -> PathBuf {
+    account_dir(account_name).join("emoji.json")
+}
+
+/// Directory with downloaded attachments, so every attachment is fetched only once
+pub fn account_attachment_cache_dir(account_name: &str) -> PathBuf {
+    account_dir(account_name).join("attachments")
+}
+
+/// Directory with profiles and avatars of contacts and avatars of groups
+pub fn account_profile_cache_dir(account_name: &str) -> PathBuf {
+    account_dir(account_name).join("profiles")
+}
+
+/// Location of conversations deleted from the list, hidden until a new message arrives
+pub fn account_hidden_conversations_path(account_name: &str) -> PathBuf {
+    account_dir(account_name).join("hidden_conversations.json")
+}
+
+/// Location of the full-text index over messages of the account
+pub fn account_search_index_path(account_name: &str) -> PathBuf {
+    account_dir(account_name).join("search.db")
 }
@@ -1,51 +1,53 @@
 use crate::account_management::create_registered_manager;
+use crate::profile_cache::{self, ProfileCache};
 use anyhow::Result;
+use presage::libsignal_service::prelude::{ProfileKey, Uuid};
 use presage::{Manager, libsignal_service::Profile, manager::Registered};
 use presage_store_sqlite::SqliteStore;
+use tokio::sync::Mutex;
+use tracing::warn;
 
-pub async fn get_profile_tui(manager: &mut Manager<SqliteStore, Registered>) -> Result<Profile> {
-    manager.retrieve_profile().await.map_err(anyhow::Error::new)
+/// Our own ACI and profile key, known without asking the server
+fn own_profile_key(manager: &Manager<SqliteStore, Registered>) -> (Uuid, ProfileKey) {
+    let registration_data = manager.registration_data();
+    (
+        registration_data.service_ids.aci,
+        registration_data.profile_key(),
+    )
 }
 
-pub async fn get_profile_cli() -> Result<Profile> {
-    let mut manager = create_registered_manager().await?;
-    manager.retrieve_profile().await.map_err(anyhow::Error::new)
+/// Cached profiles of the current account, an empty cache which isn't saved if they can't be
+/// loaded
+pub(crate) fn load_profiles() -> Mutex<ProfileCache> {
+    let profiles = ProfileCache::load_current().unwrap_or_else(|error| {
+        warn!(%error, "Failed to load cached profiles");
+        ProfileCache::default()
+    });
+    Mutex::new(profiles)
 }
 
-pub async fn get_my_profile_avatar_cli() -> Result<Option<Vec<u8>>> {
-    let mut manager = create_registered_manager().await?;
-
-    let registration_data = manager.registration_data();
-    let profile_key = registration_data.profile_key();
+pub async fn get_profile_tui(
+    manager: &Manager<SqliteStore, Registered>,
+    profiles: &Mutex<ProfileCache>,
+) -> Result<Profile> {
+    let (uuid, profile_key) = own_profile_key(manager);
+    profile_cache::profile(profiles, manager, uuid, profile_key).await
+}
 
-    let whoami = manager.whoami().await?;
-    let uuid = whoami.aci;
+pub async fn get_profile_cli() -> Result<Profile> {
+    let manager = create_registered_manager().await?;
+    get_profile_tui(&manager, &load_profiles()).await
+}
 
-    match manager
-        .retrieve_profile_avatar_by_uuid(uuid, profile_key)
-        .await
-    {
-        Ok(Some(avatar_bytes)) => Ok(Some(avatar_bytes.to_vec())),
-        Ok(None) => Ok(None),
-        Err(e) => Err(anyhow::Error::new(e)),
-    }
+pub async fn get_my_profile_avatar_cli() -> Result<Option<Vec<u8>>> {
+    let manager = create_registered_manager().await?;
+    get_my_profile_avatar_tui(&manager, &load_profiles()).await
 }
 
 pub async fn get_my_profile_avatar_tui(
-    manager: &mut Manager<SqliteStore, Registered>,
+    manager: &Manager<SqliteStore, Registered>,
+    profiles: &Mutex<ProfileCache>,
 ) -> Result<Option<Vec<u8>>> {
-    let registration_data = manager.registration_data();
-    let profile_key = registration_data.profile_key();
-
-    let whoami = manager.whoami().await?;
-    let uuid = whoami.aci;
-
-    match manager
-        .retrieve_profile_avatar_by_uuid(uuid, profile_key)
-        .await
-    {
-        Ok(Some(avatar_bytes)) => Ok(Some(avatar_bytes.to_vec())),
-        Ok(None) => Ok(None),
-        Err(e) => Err(anyhow::Error::new(e)),
-    }
+    let (uuid, profile_key) = own_profile_key(manager);
+    profile_cache::cached_avatar(profiles, manager, uuid, profile_key).await
 }
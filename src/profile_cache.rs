@@ -0,0 +1,427 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Result, bail};
+use presage::Manager;
+use presage::libsignal_service::Profile;
+use presage::libsignal_service::prelude::{ProfileKey, Uuid};
+use presage::libsignal_service::zkgroup::GroupMasterKeyBytes;
+use presage::manager::Registered;
+use presage::store::ContentsStore;
+use presage_store_sqlite::SqliteStore;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::{error, warn};
+
+use crate::config::Config;
+use crate::groups::group_id;
+use crate::paths;
+use crate::sidecar::JsonFile;
+
+/// File in the cache directory listing the cached profiles, avatars are saved next to it
+const INDEX_FILE: &str = "profiles.json";
+
+#[derive(Serialize, Deserialize, Clone)]
+struct CachedProfile {
+    /// Hex encoded profile key the profile was fetched with, a new key makes the entry outdated
+    profile_key: String,
+    profile: Profile,
+    /// When the profile was fetched, in seconds since the epoch
+    fetched_at: u64,
+    /// Avatar of the profile the saved avatar file belongs to, `None` if none is saved
+    avatar: Option<String>,
+}
+
+/// Profiles and avatars of contacts and avatars of groups, persisted per account so they are
+/// shown right away and without network.
+///
+/// Profiles are fetched again once they are older than the TTL from the config, avatars once
+/// the profile or group points to a different avatar.
+#[derive(Default, Serialize, Deserialize)]
+pub struct ProfileCache {
+    profiles: HashMap<Uuid, CachedProfile>,
+    /// Avatar of each group the saved avatar file belongs to, by group ID
+    group_avatars: HashMap<String, String>,
+    #[serde(skip)]
+    file: JsonFile,
+    #[serde(skip)]
+    dir: Option<PathBuf>,
+    #[serde(skip)]
+    ttl: Duration,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+fn avatar_file(uuid: Uuid) -> String {
+    format!("{uuid}.avatar")
+}
+
+fn group_avatar_file(group_id: &str) -> String {
+    format!("group-{group_id}.avatar")
+}
+
+impl ProfileCache {
+    /// Loads the cache kept in `dir`, all later changes are written back to it
+    pub fn load(dir: PathBuf, ttl: Duration) -> Result<Self> {
+        let (mut cache, file): (ProfileCache, _) =
+            JsonFile::load(dir.join(INDEX_FILE), "cached profiles")?;
+        cache.file = file;
+        cache.dir = Some(dir);
+        cache.ttl = ttl;
+        Ok(cache)
+    }
+
+    pub fn load_for_account(account_name: &str) -> Result<Self> {
+        Self::load(
+            paths::account_profile_cache_dir(account_name),
+            Duration::from_secs(Config::load().profile_cache_ttl_seconds),
+        )
+    }
+
+    /// Loads the cache of the account that is currently selected in the config
+    pub fn load_current() -> Result<Self> {
+        match Config::load().get_current_account() {
+            Some(account_name) => Self::load_for_account(account_name),
+            None => bail!("No account is selected, cached profiles belong to an account"),
+        }
+    }
+
+    fn entry(&self, uuid: Uuid, profile_key: ProfileKey) -> Option<&CachedProfile> {
+        self.profiles
+            .get(&uuid)
+            .filter(|entry| entry.profile_key == hex::encode(profile_key.get_bytes()))
+    }
+
+    /// Cached profile fetched with the profile key, however old it is
+    pub fn profile(&self, uuid: Uuid, profile_key: ProfileKey) -> Option<&Profile> {
+        self.entry(uuid, profile_key).map(|entry| &entry.profile)
+    }
+
+    /// Whether the profile was fetched with the profile key less than the TTL ago
+    pub fn is_fresh(&self, uuid: Uuid, profile_key: ProfileKey) -> bool {
+        self.entry(uuid, profile_key)
+            .is_some_and(|entry| now().saturating_sub(entry.fetched_at) < self.ttl.as_secs())
+    }
+
+    pub fn insert_profile(&mut self, uuid: Uuid, profile_key: ProfileKey, profile: Profile) {
+        // The saved avatar stays valid as long as the profile points to the same one
+        let avatar = self
+            .profiles
+            .get(&uuid)
+            .and_then(|entry| entry.avatar.clone())
+            .filter(|avatar| profile.avatar.as_ref() == Some(avatar));
+        if avatar.is_none() {
+            self.remove_file(&avatar_file(uuid));
+        }
+
+        self.profiles.insert(
+            uuid,
+            CachedProfile {
+                profile_key: hex::encode(profile_key.get_bytes()),
+                profile,
+                fetched_at: now(),
+                avatar,
+            },
+        );
+        self.persist();
+    }
+
+    /// Saved avatar of the contact, if it is the one their cached profile points to
+    pub fn avatar(&self, uuid: Uuid, profile_key: ProfileKey) -> Option<Vec<u8>> {
+        let entry = self.entry(uuid, profile_key)?;
+        if entry.avatar.is_none() || entry.avatar != entry.profile.avatar {
+            return None;
+        }
+        self.read_file(&avatar_file(uuid))
+    }
+
+    /// Saves the avatar of the contact, it belongs to the avatar of their cached profile
+    pub fn insert_avatar(&mut self, uuid: Uuid, profile_key: ProfileKey, avatar: &[u8]) {
+        let Some(avatar_path) = self
+            .entry(uuid, profile_key)
+            .and_then(|entry| entry.profile.avatar.clone())
+        else {
+            return;
+        };
+        if !self.write_file(&avatar_file(uuid), avatar) {
+            return;
+        }
+        if let Some(entry) = self.profiles.get_mut(&uuid) {
+            entry.avatar = Some(avatar_path);
+        }
+        self.persist();
+    }
+
+    /// Saved avatar of the group, if it is the one at `avatar_path`
+    pub fn group_avatar(
+        &self,
+        master_key: GroupMasterKeyBytes,
+        avatar_path: &str,
+    ) -> Option<Vec<u8>> {
+        let group_id = group_id(&master_key);
+        if self.group_avatars.get(&group_id)? != avatar_path {
+            return None;
+        }
+        self.read_file(&group_avatar_file(&group_id))
+    }
+
+    pub fn insert_group_avatar(
+        &mut self,
+        master_key: GroupMasterKeyBytes,
+        avatar_path: &str,
+        avatar: &[u8],
+    ) {
+        let group_id = group_id(&master_key);
+        if !self.write_file(&group_avatar_file(&group_id), avatar) {
+            return;
+        }
+        self.group_avatars.insert(group_id, avatar_path.to_string());
+        self.persist();
+    }
+
+    fn read_file(&self, name: &str) -> Option<Vec<u8>> {
+        let path = self.dir.as_ref()?.join(name);
+        match fs::read(&path) {
+            Ok(contents) => Some(contents),
+            Err(error) => {
+                warn!(%error, path = %path.display(), "Failed to read cached avatar");
+                None
+            }
+        }
+    }
+
+    /// Writes the file into the cache directory, returns whether it was saved
+    fn write_file(&self, name: &str, contents: &[u8]) -> bool {
+        let Some(dir) = &self.dir else {
+            return false;
+        };
+        let path = dir.join(name);
+        let result = fs::create_dir_all(dir).and_then(|_| {
+            let tmp_path = path.with_extension("tmp");
+            fs::write(&tmp_path, contents)?;
+            fs::rename(&tmp_path, &path)
+        });
+        match result {
+            Ok(()) => true,
+            Err(error) => {
+                error!(%error, path = %path.display(), "Failed to cache avatar");
+                false
+            }
+        }
+    }
+
+    fn remove_file(&self, name: &str) {
+        if let Some(dir) = &self.dir {
+            let path = dir.join(name);
+            if path.exists()
+                && let Err(error) = fs::remove_file(&path)
+            {
+                warn!(%error, path = %path.display(), "Failed to remove cached avatar");
+            }
+        }
+    }
+
+    fn persist(&self) {
+        self.file.save(self);
+    }
+}
+
+/// Fetches the profile of the contact and saves it in the cache
+pub async fn fetch_profile(
+    profiles: &Mutex<ProfileCache>,
+    manager: &Manager<SqliteStore, Registered>,
+    uuid: Uuid,
+    profile_key: ProfileKey,
+) -> Result<Profile> {
+    let profile = manager
+        .clone()
+        .retrieve_profile_by_uuid(uuid, profile_key)
+        .await?;
+    profiles
+        .lock()
+        .await
+        .insert_profile(uuid, profile_key, profile.clone());
+    Ok(profile)
+}
+
+/// Cached profile of the contact however old it is, so it is shown right away and offline. It
+/// is only fetched when nothing is cached for the profile key.
+pub async fn cached_profile(
+    profiles: &Mutex<ProfileCache>,
+    manager: &Manager<SqliteStore, Registered>,
+    uuid: Uuid,
+    profile_key: ProfileKey,
+) -> Result<Profile> {
+    let cached = profiles.lock().await.profile(uuid, profile_key).cloned();
+    match cached {
+        Some(profile) => Ok(profile),
+        None => fetch_profile(profiles, manager, uuid, profile_key).await,
+    }
+}
+
+/// Profile of the contact, fetched again when the cached one is older than the TTL. If that
+/// fails, e.g. without network, the cached one is used however old it is.
+pub async fn profile(
+    profiles: &Mutex<ProfileCache>,
+    manager: &Manager<SqliteStore, Registered>,
+    uuid: Uuid,
+    profile_key: ProfileKey,
+) -> Result<Profile> {
+    let (cached, fresh) = {
+        let profiles = profiles.lock().await;
+        (
+            profiles.profile(uuid, profile_key).cloned(),
+            profiles.is_fresh(uuid, profile_key),
+        )
+    };
+    match cached {
+        Some(profile) if fresh => Ok(profile),
+        Some(profile) => match fetch_profile(profiles, manager, uuid, profile_key).await {
+            Ok(profile) => Ok(profile),
+            Err(error) => {
+                warn!(%error, "Failed to refresh profile, using the cached one");
+                Ok(profile)
+            }
+        },
+        None => fetch_profile(profiles, manager, uuid, profile_key).await,
+    }
+}
+
+/// Given and family name from the profile of the contact, the profile is fetched unless it is
+/// cached already
+pub async fn profile_name(
+    profiles: &Mutex<ProfileCache>,
+    manager: &Manager<SqliteStore, Registered>,
+    uuid: Uuid,
+) -> Option<String> {
+    let profile_key = match manager.store().profile_key(&uuid).await {
+        Ok(profile_key_option) => profile_key_option?,
+        Err(error) => {
+            error!(%error, "Failed to retreive profile key from the store.");
+            return None;
+        }
+    };
+
+    let profile = cached_profile(profiles, manager, uuid, profile_key)
+        .await
+        .ok()?;
+    let profile_name = profile.name?;
+    Some(match &profile_name.family_name {
+        Some(family_mame) => {
+            format!("{} {family_mame}", profile_name.given_name)
+        }
+        None => profile_name.given_name.clone(),
+    })
+}
+
+/// Avatar of the contact, fetched only when the saved one isn't the avatar of their cached
+/// profile
+pub async fn cached_avatar(
+    profiles: &Mutex<ProfileCache>,
+    manager: &Manager<SqliteStore, Registered>,
+    uuid: Uuid,
+    profile_key: ProfileKey,
+) -> Result<Option<Vec<u8>>> {
+    if let Some(avatar) = profiles.lock().await.avatar(uuid, profile_key) {
+        return Ok(Some(avatar));
+    }
+
+    let avatar = manager
+        .clone()
+        .retrieve_profile_avatar_by_uuid(uuid, profile_key)
+        .await?
+        .map(|avatar| avatar.to_vec());
+    if let Some(avatar) = &avatar {
+        profiles
+            .lock()
+            .await
+            .insert_avatar(uuid, profile_key, avatar);
+    }
+    Ok(avatar)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: Uuid = Uuid::from_u128(1);
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    fn profile(avatar: Option<&str>) -> Profile {
+        Profile {
+            name: None,
+            about: None,
+            about_emoji: None,
+            avatar: avatar.map(str::to_string),
+            unrestricted_unidentified_access: false,
+        }
+    }
+
+    fn cache(ttl: Duration) -> ProfileCache {
+        ProfileCache {
+            ttl,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn profile_is_fresh_until_the_ttl_passes() {
+        let key = ProfileKey::create([1; 32]);
+        let mut cache = cache(HOUR);
+        assert!(!cache.is_fresh(ALICE, key));
+
+        cache.insert_profile(ALICE, key, profile(None));
+        assert!(cache.is_fresh(ALICE, key));
+
+        cache.profiles.get_mut(&ALICE).unwrap().fetched_at -= HOUR.as_secs();
+        assert!(!cache.is_fresh(ALICE, key));
+        // Outdated profiles are still shown until they are fetched again
+        assert!(cache.profile(ALICE, key).is_some());
+    }
+
+    #[test]
+    fn zero_ttl_fetches_every_time() {
+        let key = ProfileKey::create([1; 32]);
+        let mut cache = cache(Duration::ZERO);
+        cache.insert_profile(ALICE, key, profile(None));
+        assert!(!cache.is_fresh(ALICE, key));
+    }
+
+    #[test]
+    fn new_profile_key_outdates_the_profile() {
+        let old_key = ProfileKey::create([1; 32]);
+        let new_key = ProfileKey::create([2; 32]);
+        let mut cache = cache(HOUR);
+        cache.insert_profile(ALICE, old_key, profile(None));
+
+        assert!(!cache.is_fresh(ALICE, new_key));
+        assert!(cache.profile(ALICE, new_key).is_none());
+    }
+
+    #[test]
+    fn avatar_is_kept_while_the_profile_points_to_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = ProfileKey::create([1; 32]);
+        let mut cache = ProfileCache::load(dir.path().to_path_buf(), HOUR).unwrap();
+        cache.insert_profile(ALICE, key, profile(Some("avatar-1")));
+        cache.insert_avatar(ALICE, key, b"first");
+        assert_eq!(cache.avatar(ALICE, key).as_deref(), Some(&b"first"[..]));
+
+        cache.insert_profile(ALICE, key, profile(Some("avatar-1")));
+        assert_eq!(cache.avatar(ALICE, key).as_deref(), Some(&b"first"[..]));
+
+        cache.insert_profile(ALICE, key, profile(Some("avatar-2")));
+        assert_eq!(cache.avatar(ALICE, key), None);
+        assert!(!dir.path().join(avatar_file(ALICE)).exists());
+
+        let cache = ProfileCache::load(dir.path().to_path_buf(), HOUR).unwrap();
+        assert!(cache.is_fresh(ALICE, key));
+    }
+}
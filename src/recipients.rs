@@ -0,0 +1,386 @@
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow, bail};
+use phonenumber::{Mode, country};
+use presage::Manager;
+use presage::libsignal_service::prelude::Uuid;
+use presage::manager::Registered;
+use presage::store::ContentsStore;
+use presage_store_sqlite::SqliteStore;
+use tracing::error;
+
+use crate::config::Config;
+use crate::matching::{closest, confirm, similarity};
+use crate::message_requests::MessageRequests;
+use crate::messages::send::SendError;
+use crate::sidecar::AccountFile;
+
+/// Contact the user may mean, with every name they can be found by
+struct Candidate {
+    uuid: Uuid,
+    /// Name given to the contact, empty if there is none
+    name: String,
+    profile_name: Option<String>,
+    /// Phone number in E.164 format
+    phone_number: Option<String>,
+}
+
+impl Candidate {
+    fn names(&self) -> impl Iterator<Item = &str> {
+        Some(self.name.as_str())
+            .filter(|name| !name.is_empty())
+            .into_iter()
+            .chain(self.profile_name.as_deref())
+    }
+
+    fn describe(&self) -> String {
+        let name = self.names().next().unwrap_or("<no name>");
+        match &self.phone_number {
+            Some(phone_number) => format!("{name} ({phone_number}, {})", self.uuid),
+            None => format!("{name} ({})", self.uuid),
+        }
+    }
+}
+
+/// Whether the text is meant as a phone number rather than a name
+fn looks_like_phone_number(text: &str) -> bool {
+    text.chars().filter(char::is_ascii_digit).count() >= 3
+        && text
+            .chars()
+            .all(|c| c.is_ascii_digit() || " +-().".contains(c))
+}
+
+/// Phone number in E.164 format, numbers without the country code are taken as numbers of the
+/// `default_region` from the config
+fn normalize_phone_number(text: &str, default_region: Option<&str>) -> Result<String> {
+    let region = match default_region {
+        Some(region) => Some(
+            country::Id::from_str(&region.to_uppercase())
+                .map_err(|_| anyhow!("Unknown default region '{region}' in the config"))?,
+        ),
+        None => None,
+    };
+
+    let number = phonenumber::parse(region, text).map_err(|e| {
+        anyhow!("'{text}' is not a valid phone number ({e}), set `default_region` in the config for numbers without the country code")
+    })?;
+    if !number.is_valid() {
+        bail!("'{text}' is not a valid phone number");
+    }
+    Ok(number.format().mode(Mode::E164).to_string())
+}
+
+/// Name from the profile of the contact, only profiles already in the store are used, nothing
+/// is fetched to resolve a name
+async fn stored_profile_name(
+    manager: &Manager<SqliteStore, Registered>,
+    uuid: Uuid,
+) -> Option<String> {
+    match manager.store().profile_key(&uuid).await {
+        Ok(Some(profile_key)) => match manager.store().profile(uuid, profile_key).await {
+            Ok(profile) => {
+                profile
+                    .and_then(|profile| profile.name)
+                    .map(|name| match &name.family_name {
+                        Some(family_name) => format!("{} {family_name}", name.given_name),
+                        None => name.given_name.clone(),
+                    })
+            }
+            Err(error) => {
+                error!(%error, "Failed to load profile of a contact");
+                None
+            }
+        },
+        Ok(None) => None,
+        Err(error) => {
+            error!(%error, "Failed to load profile key of a contact");
+            None
+        }
+    }
+}
+
+async fn load_candidates(
+    manager: &Manager<SqliteStore, Registered>,
+    accepted: &[Uuid],
+) -> Result<Vec<Candidate>> {
+    let contacts = manager
+        .store()
+        .contacts()
+        .await?
+        .filter_map(|contact| contact.ok())
+        .collect::<Vec<_>>();
+
+    let mut candidates = Vec::with_capacity(contacts.len());
+    for contact in contacts {
+        candidates.push(Candidate {
+            uuid: contact.uuid,
+            name: contact.name,
+            profile_name: stored_profile_name(manager, contact.uuid).await,
+            phone_number: contact
+                .phone_number
+                .map(|phone_number| phone_number.to_string()),
+        });
+    }
+
+    // Senders whose message requests were accepted can be written to as well
+    for &uuid in accepted {
+        if candidates.iter().all(|candidate| candidate.uuid != uuid) {
+            candidates.push(Candidate {
+                uuid,
+                name: String::new(),
+                profile_name: stored_profile_name(manager, uuid).await,
+                phone_number: None,
+            });
+        }
+    }
+    Ok(candidates)
+}
+
+fn describe_candidates(candidates: &[&Candidate]) -> String {
+    candidates
+        .iter()
+        .map(|candidate| format!("\n  {}", candidate.describe()))
+        .collect()
+}
+
+fn single(query: &str, matches: Vec<&Candidate>) -> Result<Option<Uuid>> {
+    match matches.as_slice() {
+        [] => Ok(None),
+        [candidate] => Ok(Some(candidate.uuid)),
+        _ => bail!(
+            "Recipient '{query}' is ambiguous, use the UUID or phone number of one of these contacts:{}",
+            describe_candidates(&matches)
+        ),
+    }
+}
+
+/// Finds the contact given by the user as a UUID, a phone number, a contact name or a profile
+/// name.
+///
+/// A UUID has to be one of a contact. Phone numbers are compared in E.164 format. Names are
+/// compared exactly, then ignoring case and finally by similarity, a similar name is only used
+/// after the user confirms it when `confirm_similar` is set. Names shared by several contacts,
+/// or equally similar to the query, fail listing the candidates. Senders of `accepted` message
+/// requests are found like contacts.
+pub async fn resolve_recipient(
+    query: &str,
+    manager: &Manager<SqliteStore, Registered>,
+    accepted: &[Uuid],
+    confirm_similar: bool,
+) -> Result<Uuid> {
+    let query = query.trim();
+    if let Ok(uuid) = Uuid::parse_str(query) {
+        // Every UUID parses, so it is only taken if we can write to the contact
+        if accepted.contains(&uuid) || manager.store().contact_by_id(&uuid).await?.is_some() {
+            return Ok(uuid);
+        }
+        return Err(SendError::UnknownRecipient(query.to_string()).into());
+    }
+
+    let candidates = load_candidates(manager, accepted).await?;
+    find_candidate(
+        query,
+        &candidates,
+        Config::load().default_region.as_deref(),
+        confirm_similar.then_some(confirm as fn(&str) -> Result<bool>),
+    )
+}
+
+/// Picks the contact meant by the query, see `resolve_recipient`. A similar name is only taken
+/// when `confirm_similar` asks the user and they agree.
+fn find_candidate(
+    query: &str,
+    candidates: &[Candidate],
+    default_region: Option<&str>,
+    confirm_similar: Option<fn(&str) -> Result<bool>>,
+) -> Result<Uuid> {
+    if looks_like_phone_number(query) {
+        let phone_number = normalize_phone_number(query, default_region)?;
+        let matches = candidates
+            .iter()
+            .filter(|candidate| candidate.phone_number.as_deref() == Some(phone_number.as_str()))
+            .collect();
+        return single(query, matches)?
+            .ok_or_else(|| SendError::UnknownPhoneNumber(phone_number).into());
+    }
+
+    let lowercase = query.to_lowercase();
+    let filters: [&dyn Fn(&str) -> bool; 2] = [&|name| name == query, &|name| {
+        name.to_lowercase() == lowercase
+    }];
+    for filter in filters {
+        let matches = candidates
+            .iter()
+            .filter(|candidate| candidate.names().any(filter))
+            .collect();
+        if let Some(uuid) = single(query, matches)? {
+            return Ok(uuid);
+        }
+    }
+
+    if let Some(confirm) = confirm_similar {
+        let similar = closest(candidates.iter().filter_map(|candidate| {
+            candidate
+                .names()
+                .filter_map(|name| similarity(query, name))
+                .min()
+                .map(|distance| (distance, candidate))
+        }));
+        match similar.as_slice() {
+            [] => {}
+            [candidate] => {
+                if confirm(&format!(
+                    "Recipient '{query}' not found, did you mean {}?",
+                    candidate.describe()
+                ))? {
+                    return Ok(candidate.uuid);
+                }
+            }
+            _ => bail!(
+                "Recipient '{query}' not found and several contacts have a similar name, use the UUID or phone number of one of these contacts:{}",
+                describe_candidates(&similar)
+            ),
+        }
+    }
+
+    Err(SendError::UnknownRecipient(query.to_string()).into())
+}
+
+/// Finds the contact given to the CLI like `resolve_recipient`, with the message requests
+/// accepted in the current account. A similar name is used after the user confirms it.
+pub async fn resolve_recipient_cli(
+    query: &str,
+    manager: &Manager<SqliteStore, Registered>,
+) -> Result<Uuid> {
+    let accepted = match MessageRequests::load_current() {
+        Ok(requests) => requests.accepted().collect(),
+        Err(error) => {
+            error!(%error, "Failed to load message requests");
+            Vec::new()
+        }
+    };
+    resolve_recipient(query, manager, &accepted, true).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(name: &str, profile_name: Option<&str>, phone_number: Option<&str>) -> Candidate {
+        Candidate {
+            uuid: Uuid::new_v4(),
+            name: name.to_string(),
+            profile_name: profile_name.map(str::to_string),
+            phone_number: phone_number.map(str::to_string),
+        }
+    }
+
+    fn unknown_recipient(result: Result<Uuid>) -> bool {
+        matches!(
+            result.unwrap_err().downcast_ref(),
+            Some(SendError::UnknownRecipient(_))
+        )
+    }
+
+    #[test]
+    fn phone_numbers_are_told_apart_from_names() {
+        assert!(looks_like_phone_number("+48 601-234-567"));
+        assert!(looks_like_phone_number("(555) 123"));
+        assert!(!looks_like_phone_number("12"));
+        assert!(!looks_like_phone_number("Anna 123"));
+        assert!(!looks_like_phone_number("R2-D2"));
+    }
+
+    #[test]
+    fn phone_numbers_are_normalized_to_e164() {
+        assert_eq!(
+            normalize_phone_number("+48 601-234-567", None).unwrap(),
+            "+48601234567"
+        );
+        assert_eq!(
+            normalize_phone_number("601 234 567", Some("pl")).unwrap(),
+            "+48601234567"
+        );
+        assert!(normalize_phone_number("601 234 567", None).is_err());
+        assert!(normalize_phone_number("601 234 567", Some("nowhere")).is_err());
+        assert!(normalize_phone_number("+48 601", None).is_err());
+    }
+
+    #[test]
+    fn contacts_are_found_by_phone_number_in_any_format() {
+        let candidates = [
+            candidate("Anna", None, Some("+48601234567")),
+            candidate("Bob", None, Some("+48601234568")),
+        ];
+        let uuid = find_candidate("601-234-567", &candidates, Some("PL"), None).unwrap();
+        assert_eq!(uuid, candidates[0].uuid);
+
+        let error = find_candidate("+48 601 234 569", &candidates, None, None).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(SendError::UnknownPhoneNumber(number)) if number == "+48601234569"
+        ));
+    }
+
+    #[test]
+    fn exact_names_come_before_names_in_other_case() {
+        let candidates = [
+            candidate("anna", None, None),
+            candidate("Anna", None, None),
+            candidate("", Some("Bob Smith"), None),
+        ];
+        let find = |query| find_candidate(query, &candidates, None, None);
+        assert_eq!(find("Anna").unwrap(), candidates[1].uuid);
+        assert_eq!(find("bob smith").unwrap(), candidates[2].uuid);
+        assert!(find("ANNA").is_err());
+    }
+
+    #[test]
+    fn shared_names_are_ambiguous() {
+        let candidates = [
+            candidate("Anna", None, Some("+48601234567")),
+            candidate("", Some("Anna"), None),
+        ];
+        let error = find_candidate("Anna", &candidates, None, None).unwrap_err();
+        let message = error.to_string();
+        assert!(message.contains("ambiguous"));
+        assert!(message.contains(&candidates[0].uuid.to_string()));
+        assert!(message.contains(&candidates[1].uuid.to_string()));
+    }
+
+    #[test]
+    fn similar_names_are_only_taken_after_confirmation() {
+        let candidates = [
+            candidate("Katarzyna", None, None),
+            candidate("Bob", None, None),
+        ];
+
+        let uuid = find_candidate("Katarzina", &candidates, None, Some(|_| Ok(true))).unwrap();
+        assert_eq!(uuid, candidates[0].uuid);
+        assert!(unknown_recipient(find_candidate(
+            "Katarzina",
+            &candidates,
+            None,
+            Some(|_| Ok(false))
+        )));
+        assert!(unknown_recipient(find_candidate(
+            "Katarzina",
+            &candidates,
+            None,
+            None
+        )));
+    }
+
+    #[test]
+    fn equally_similar_names_are_listed_instead_of_asking() {
+        let candidates = [candidate("Anna", None, None), candidate("Anne", None, None)];
+        let error = find_candidate(
+            "Anni",
+            &candidates,
+            None,
+            Some(|_| panic!("Nobody should be asked")),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("several contacts"));
+    }
+}
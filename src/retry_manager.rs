@@ -1,8 +1,16 @@
 use crate::app::RecipientId;
+use crate::config::RetryPolicy;
 use crate::messages::receive::MessageDto;
+use crate::messages::send::SendError;
+use crate::sidecar::JsonFile;
+use anyhow::{Error, Result};
+use presage::libsignal_service::sender::MessageSenderError;
+use presage_store_sqlite::SqliteStoreError;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
 use uuid::Uuid;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -11,20 +19,66 @@ pub enum DeliveryStatus {
     Sending,
     Sent,
     Failed(String),
+    /// Failed for a reason that can't go away by itself, the message is never retried
+    Undeliverable(String),
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailureKind {
+    /// Sending again won't help, e.g. the recipient doesn't exist
+    Permanent,
+    /// Sending again may succeed, e.g. the network was down
+    Transient,
+}
+
+impl FailureKind {
+    /// Looks for a known permanent failure among the causes of the error, anything else is
+    /// taken as transient
+    pub fn classify(error: &Error) -> Self {
+        let permanent = error.chain().any(|cause| {
+            cause.is::<SendError>()
+                || matches!(
+                    cause.downcast_ref::<presage::Error<SqliteStoreError>>(),
+                    Some(presage::Error::UnknownGroup)
+                )
+                || matches!(
+                    cause.downcast_ref::<MessageSenderError>(),
+                    Some(
+                        MessageSenderError::NotFound { .. }
+                            | MessageSenderError::UntrustedIdentity { .. }
+                    )
+                )
+        });
+
+        if permanent {
+            FailureKind::Permanent
+        } else {
+            FailureKind::Transient
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct OutgoingMessage {
     pub id: String,
     pub recipient: RecipientId,
     pub text: String,
+    // `MessageDto` holds protobuf types that can't be serialized, only the timestamp of the
+    // quoted message is persisted and the message itself is looked up in the store after loading.
+    #[serde(skip)]
     pub quoted_message: Option<MessageDto>,
+    pub quoted_timestamp: Option<u64>,
     pub delete_target_send_timestamp: Option<u64>,
+    /// Timestamp of our message whose text is replaced by `text`
+    #[serde(default)]
+    pub edit_target_send_timestamp: Option<u64>,
     pub attachment_path: Option<String>,
     pub status: DeliveryStatus,
     pub retry_count: u32,
     pub created_at: u64,
     pub last_attempt_at: Option<u64>,
+    #[serde(default)]
+    pub next_attempt_at: Option<u64>,
 }
 
 impl OutgoingMessage {
@@ -40,39 +94,37 @@ impl OutgoingMessage {
             recipient,
             text,
             attachment_path,
+            quoted_timestamp: quoted_message.as_ref().map(|quote| quote.timestamp),
             quoted_message,
             delete_target_send_timestamp,
+            edit_target_send_timestamp: None,
             status: DeliveryStatus::Pending,
             retry_count: 0,
             created_at: Self::current_timestamp(),
             last_attempt_at: None,
+            next_attempt_at: None,
         }
     }
 
-    pub fn should_retry(&self, max_retries: u32, retry_delay_seconds: u64) -> bool {
-        if !matches!(self.status, DeliveryStatus::Failed(_)) {
-            return false;
+    /// Outgoing edit of an earlier message sent to `recipient`
+    pub fn edit(recipient: RecipientId, text: String, target_send_timestamp: u64) -> Self {
+        Self {
+            edit_target_send_timestamp: Some(target_send_timestamp),
+            ..Self::new(recipient, text, None, None, None)
         }
+    }
 
-        if let DeliveryStatus::Failed(ref reason) = self.status
-            && reason
-                .to_lowercase()
-                .contains("websocket closing while waiting")
-        {
+    pub fn should_retry(&self, policy: &RetryPolicy) -> bool {
+        if !matches!(self.status, DeliveryStatus::Failed(_)) {
             return false;
         }
 
-        if self.retry_count >= max_retries {
+        if self.retry_count >= policy.max_attempts {
             return false;
         }
 
-        if let Some(last_attempt) = self.last_attempt_at {
-            let now = Self::current_timestamp();
-            let delay_ms = retry_delay_seconds * 1000;
-            (now - last_attempt) >= delay_ms
-        } else {
-            true
-        }
+        self.next_attempt_at
+            .is_none_or(|next_attempt| Self::current_timestamp() >= next_attempt)
     }
 
     pub fn mark_sending(&mut self) {
@@ -85,8 +137,18 @@ impl OutgoingMessage {
         self.status = DeliveryStatus::Sent;
     }
 
-    pub fn mark_failed(&mut self, reason: String) {
-        self.status = DeliveryStatus::Failed(reason);
+    pub fn mark_failed(&mut self, reason: String, kind: FailureKind, policy: &RetryPolicy) {
+        match kind {
+            FailureKind::Permanent => {
+                self.status = DeliveryStatus::Undeliverable(reason);
+                self.next_attempt_at = None;
+            }
+            FailureKind::Transient => {
+                self.status = DeliveryStatus::Failed(reason);
+                let delay = policy.delay_after(self.retry_count).as_millis() as u64;
+                self.next_attempt_at = Some(Self::current_timestamp() + delay);
+            }
+        }
     }
 
     fn current_timestamp() -> u64 {
@@ -99,46 +161,113 @@ impl OutgoingMessage {
 
 pub struct RetryManager {
     outgoing_messages: HashMap<String, OutgoingMessage>,
-    max_retries: u32,
-    retry_delay_seconds: u64,
+    outbox: JsonFile,
+    policy: RetryPolicy,
 }
 
 impl Default for RetryManager {
     fn default() -> Self {
-        Self::new()
+        Self::new(RetryPolicy::default())
     }
 }
 
 impl RetryManager {
-    pub fn new() -> Self {
+    pub fn new(policy: RetryPolicy) -> Self {
         Self {
             outgoing_messages: HashMap::new(),
-            max_retries: 3,
-            retry_delay_seconds: 30,
+            outbox: JsonFile::default(),
+            policy,
         }
     }
 
+    /// Replaces the messages kept in memory with the outbox stored under `path`.
+    ///
+    /// All later changes are written back to the same file. Messages that were still pending or
+    /// being sent when the outbox was saved never got a result, so they are marked as failed to
+    /// have them picked up by the next retry.
+    pub fn load_outbox(&mut self, path: PathBuf) -> Result<()> {
+        let (messages, outbox): (Vec<OutgoingMessage>, _) = JsonFile::load(path, "outbox")?;
+
+        self.outgoing_messages = messages
+            .into_iter()
+            .map(|mut msg| {
+                if matches!(
+                    msg.status,
+                    DeliveryStatus::Pending | DeliveryStatus::Sending
+                ) {
+                    msg.status = DeliveryStatus::Failed("Interrupted before delivery".to_string());
+                    msg.next_attempt_at = None;
+                }
+                (msg.id.clone(), msg)
+            })
+            .collect();
+        self.outbox = outbox;
+
+        info!(
+            count = self.outgoing_messages.len(),
+            "Loaded undelivered messages from outbox"
+        );
+        Ok(())
+    }
+
+    /// Writes all messages that were not sent yet to the outbox file
+    fn persist(&self) {
+        let unsent = self
+            .outgoing_messages
+            .values()
+            .filter(|msg| msg.status != DeliveryStatus::Sent)
+            .collect::<Vec<_>>();
+
+        self.outbox.save(&unsent);
+    }
+
     pub fn add_message(&mut self, message: OutgoingMessage) -> String {
         let id = message.id.clone();
         self.outgoing_messages.insert(id.clone(), message);
+        self.persist();
         id
     }
 
     pub fn mark_sent(&mut self, message_id: &str) {
         if let Some(msg) = self.outgoing_messages.get_mut(message_id) {
             msg.mark_sent();
+            self.persist();
         }
     }
 
     pub fn mark_sending(&mut self, message_id: &str) {
         if let Some(msg) = self.outgoing_messages.get_mut(message_id) {
             msg.mark_sending();
+            self.persist();
+        }
+    }
+
+    pub fn mark_failed(&mut self, message_id: &str, error: &Error) {
+        if let Some(msg) = self.outgoing_messages.get_mut(message_id) {
+            let kind = FailureKind::classify(error);
+            if kind == FailureKind::Permanent {
+                warn!(%error, "Message can't be delivered, it won't be retried");
+            }
+            msg.mark_failed(error.to_string(), kind, &self.policy);
+            self.persist();
         }
     }
 
-    pub fn mark_failed(&mut self, message_id: &str, reason: String) {
+    /// Returns ids, recipients and quote timestamps of messages whose quote wasn't restored yet
+    pub fn unresolved_quotes(&self) -> Vec<(String, RecipientId, u64)> {
+        self.outgoing_messages
+            .values()
+            .filter(|msg| msg.quoted_message.is_none())
+            .filter_map(|msg| {
+                msg.quoted_timestamp
+                    .map(|timestamp| (msg.id.clone(), msg.recipient.clone(), timestamp))
+            })
+            .collect()
+    }
+
+    pub fn restore_quote(&mut self, message_id: &str, quoted_message: MessageDto) {
         if let Some(msg) = self.outgoing_messages.get_mut(message_id) {
-            msg.mark_failed(reason);
+            msg.quoted_message = Some(quoted_message);
         }
     }
 
@@ -146,26 +275,49 @@ impl RetryManager {
         let mut retry_messages = Vec::new();
 
         for msg in self.outgoing_messages.values_mut() {
-            if msg.should_retry(self.max_retries, self.retry_delay_seconds) {
+            if msg.should_retry(&self.policy) {
                 retry_messages.push(msg.clone());
             }
         }
         retry_messages
     }
+
+    /// Time left until the earliest scheduled retry, `None` if there is nothing to retry
+    pub fn next_retry_in(&self) -> Option<Duration> {
+        let now = OutgoingMessage::current_timestamp();
+        self.outgoing_messages
+            .values()
+            .filter(|msg| {
+                matches!(msg.status, DeliveryStatus::Failed(_))
+                    && msg.retry_count < self.policy.max_attempts
+            })
+            .map(|msg| {
+                let next_attempt = msg.next_attempt_at.unwrap_or(now);
+                Duration::from_millis(next_attempt.saturating_sub(now))
+            })
+            .min()
+    }
+
     pub fn cleanup_old_messages(&mut self) {
         let cutoff = OutgoingMessage::current_timestamp() - (24 * 60 * 60 * 1000); // 24 hours
 
         self.outgoing_messages.retain(|_, msg| {
             msg.created_at > cutoff
                 || (!matches!(msg.status, DeliveryStatus::Sent)
-                    && msg.retry_count < self.max_retries)
+                    && msg.retry_count < self.policy.max_attempts)
         });
+        self.persist();
     }
 
     pub fn failed_count(&self) -> usize {
         self.outgoing_messages
             .values()
-            .filter(|msg| matches!(msg.status, DeliveryStatus::Failed(_)))
+            .filter(|msg| {
+                matches!(
+                    msg.status,
+                    DeliveryStatus::Failed(_) | DeliveryStatus::Undeliverable(_)
+                )
+            })
             .count()
     }
 
@@ -175,3 +327,159 @@ impl RetryManager {
             .map(|msg| &msg.status)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::*;
+
+    #[test]
+    fn send_errors_are_permanent() {
+        let error = Error::new(SendError::UnknownGroup);
+        assert_eq!(FailureKind::classify(&error), FailureKind::Permanent);
+    }
+
+    #[test]
+    fn context_keeps_the_failure_permanent() {
+        let error = Error::new(SendError::UnknownRecipient("Alice".to_string()))
+            .context("Failed to send message");
+        assert_eq!(FailureKind::classify(&error), FailureKind::Permanent);
+    }
+
+    #[test]
+    fn other_errors_are_transient() {
+        // Only the type tells, not the text
+        let error = anyhow!("Recipient 'Alice' not found");
+        assert_eq!(FailureKind::classify(&error), FailureKind::Transient);
+    }
+
+    #[test]
+    fn untrusted_identity_is_permanent() {
+        let error = Error::new(SendError::UntrustedIdentity(Uuid::nil()));
+        assert_eq!(FailureKind::classify(&error), FailureKind::Permanent);
+    }
+
+    fn policy_without_jitter() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay_seconds: 5,
+            backoff_multiplier: 2.0,
+            max_delay_seconds: 30,
+            jitter: 0.0,
+        }
+    }
+
+    fn message() -> OutgoingMessage {
+        OutgoingMessage::new(
+            RecipientId::Contact(Uuid::nil()),
+            "Hello".to_string(),
+            None,
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn delay_grows_until_the_limit() {
+        let policy = policy_without_jitter();
+        let delays = (1..=5)
+            .map(|attempts| policy.delay_after(attempts).as_secs())
+            .collect::<Vec<_>>();
+        assert_eq!(delays, vec![5, 10, 20, 30, 30]);
+    }
+
+    #[test]
+    fn jitter_stays_within_its_fraction_and_the_limit() {
+        let policy = RetryPolicy {
+            jitter: 0.5,
+            ..policy_without_jitter()
+        };
+        for _ in 0..100 {
+            let delay = policy.delay_after(2).as_secs_f64();
+            assert!((5.0..=15.0).contains(&delay), "{delay}");
+            assert!(policy.delay_after(10).as_secs_f64() <= 30.0);
+        }
+    }
+
+    #[test]
+    fn transient_failure_is_retried_once_due() {
+        let policy = policy_without_jitter();
+        let mut msg = message();
+        msg.mark_sending();
+        msg.mark_failed("offline".to_string(), FailureKind::Transient, &policy);
+
+        assert_eq!(msg.status, DeliveryStatus::Failed("offline".to_string()));
+        let delay = msg.next_attempt_at.unwrap() - msg.last_attempt_at.unwrap();
+        assert!((5000..6000).contains(&delay), "{delay}");
+        assert!(!msg.should_retry(&policy));
+
+        msg.next_attempt_at = Some(0);
+        assert!(msg.should_retry(&policy));
+    }
+
+    #[test]
+    fn retries_stop_after_the_last_attempt() {
+        let policy = policy_without_jitter();
+        let mut msg = message();
+        for _ in 0..policy.max_attempts {
+            msg.mark_sending();
+            msg.mark_failed("offline".to_string(), FailureKind::Transient, &policy);
+            msg.next_attempt_at = Some(0);
+        }
+        assert!(!msg.should_retry(&policy));
+    }
+
+    #[test]
+    fn permanent_failure_is_never_retried() {
+        let policy = policy_without_jitter();
+        let mut msg = message();
+        msg.mark_sending();
+        msg.mark_failed("no such user".to_string(), FailureKind::Permanent, &policy);
+
+        assert_eq!(
+            msg.status,
+            DeliveryStatus::Undeliverable("no such user".to_string())
+        );
+        assert_eq!(msg.next_attempt_at, None);
+        assert!(!msg.should_retry(&policy));
+    }
+
+    #[test]
+    fn cleanup_keeps_old_unsent_messages() {
+        let mut manager = RetryManager::new(policy_without_jitter());
+        let mut pending = message();
+        pending.created_at = 0;
+        let pending = manager.add_message(pending);
+        let mut sent = message();
+        sent.created_at = 0;
+        sent.status = DeliveryStatus::Sent;
+        let sent = manager.add_message(sent);
+
+        manager.cleanup_old_messages();
+        assert_eq!(
+            manager.message_status(&pending),
+            Some(&DeliveryStatus::Pending)
+        );
+        assert_eq!(manager.message_status(&sent), None);
+    }
+
+    #[test]
+    fn interrupted_messages_are_retried_after_loading_the_outbox() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("outbox.json");
+        let mut manager = RetryManager::new(policy_without_jitter());
+        manager.load_outbox(path.clone()).unwrap();
+        let id = manager.add_message(message());
+        manager.mark_sending(&id);
+        drop(manager);
+
+        let mut manager = RetryManager::new(policy_without_jitter());
+        manager.load_outbox(path).unwrap();
+        assert!(matches!(
+            manager.message_status(&id),
+            Some(DeliveryStatus::Failed(_))
+        ));
+        assert_eq!(manager.messages_to_retry().len(), 1);
+    }
+}
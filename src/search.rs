@@ -0,0 +1,470 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow, bail};
+use presage::Manager;
+use presage::libsignal_service::content::ContentBody;
+use presage::libsignal_service::prelude::{Content, Uuid};
+use presage::libsignal_service::zkgroup::GroupMasterKeyBytes;
+use presage::manager::Registered;
+use presage::store::{ContentExt, ContentsStore, Thread};
+use presage_store_sqlite::SqliteStore;
+use sqlx::Row;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use tracing::{debug, error, warn};
+
+use crate::app::RecipientId;
+use crate::config::Config;
+use crate::messages::receive::{extract_delete, extract_edit, format_attachments, format_message};
+use crate::paths;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS messages (
+        id INTEGER PRIMARY KEY,
+        thread TEXT NOT NULL,
+        timestamp INTEGER NOT NULL,
+        sender TEXT NOT NULL,
+        outgoing INTEGER NOT NULL,
+        body TEXT NOT NULL,
+        has_attachment INTEGER NOT NULL,
+        UNIQUE (thread, timestamp)
+    );
+    CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts
+        USING fts5(body, content='messages', content_rowid='id');
+    CREATE TRIGGER IF NOT EXISTS messages_ai AFTER INSERT ON messages BEGIN
+        INSERT INTO messages_fts(rowid, body) VALUES (new.id, new.body);
+    END;
+    CREATE TRIGGER IF NOT EXISTS messages_ad AFTER DELETE ON messages BEGIN
+        INSERT INTO messages_fts(messages_fts, rowid, body) VALUES ('delete', old.id, old.body);
+    END;
+    CREATE TRIGGER IF NOT EXISTS messages_au AFTER UPDATE ON messages BEGIN
+        INSERT INTO messages_fts(messages_fts, rowid, body) VALUES ('delete', old.id, old.body);
+        INSERT INTO messages_fts(rowid, body) VALUES (new.id, new.body);
+    END;
+    CREATE TABLE IF NOT EXISTS coverage (
+        thread TEXT PRIMARY KEY,
+        indexed_until INTEGER NOT NULL
+    );
+";
+
+/// Restrictions applied to results of a search
+#[derive(Clone, Default)]
+pub struct SearchFilter {
+    pub recipient: Option<RecipientId>,
+    /// Lowest timestamp of a message in milliseconds, inclusive
+    pub from: Option<u64>,
+    /// Highest timestamp of a message in milliseconds, inclusive
+    pub to: Option<u64>,
+    pub has_attachment: bool,
+    pub limit: u32,
+}
+
+/// Message matching a search query
+#[derive(Clone)]
+pub struct SearchHit {
+    pub recipient: RecipientId,
+    pub timestamp: u64,
+    pub sender: Uuid,
+    pub outgoing: bool,
+    pub text: String,
+    /// Part of the text around the match, matched terms are wrapped in `[` and `]`
+    pub snippet: String,
+}
+
+/// Full-text index over message bodies of a single account.
+///
+/// The index lives in its own database next to the account store, so it can be thrown away and
+/// rebuilt from the store at any time.
+#[derive(Clone)]
+pub struct SearchIndex {
+    pool: SqlitePool,
+}
+
+impl SearchIndex {
+    pub async fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await
+            .map_err(|e| anyhow!("Failed to open search index: {e}"))?;
+
+        sqlx::raw_sql(SCHEMA).execute(&pool).await?;
+        Ok(Self { pool })
+    }
+
+    pub async fn open_for_account(account_name: &str) -> Result<Self> {
+        Self::open(&paths::account_search_index_path(account_name)).await
+    }
+
+    /// Opens the index of the account that is currently selected in the config
+    pub async fn open_current() -> Result<Self> {
+        match Config::load().get_current_account() {
+            Some(account_name) => Self::open_for_account(account_name).await,
+            None => bail!("No account is selected, the search index belongs to an account"),
+        }
+    }
+
+    /// Adds received message from the content to the index, content without any text is skipped
+    pub async fn index_content(&self, content: &Content) -> Result<()> {
+        let Ok(thread) = Thread::try_from(content) else {
+            return Ok(());
+        };
+        // Messages we sent from another device arrive as sync messages
+        let outgoing = matches!(content.body, ContentBody::SynchronizeMessage(_));
+        self.index_in_thread(&thread, content, outgoing).await
+    }
+
+    /// Adds message from the content to the index of the conversation. Messages sent from this
+    /// device are stored with us as the sender, so the thread can't be told from the content.
+    async fn index_in_thread(
+        &self,
+        thread: &Thread,
+        content: &Content,
+        outgoing: bool,
+    ) -> Result<()> {
+        // Edits replace the text of the original message instead of being indexed on their own
+        if let Some(edit) = extract_edit(content) {
+            sqlx::query(
+                "UPDATE messages SET body = ? WHERE thread = ? AND timestamp = ? AND sender = ?",
+            )
+            .bind(edit.text)
+            .bind(thread_key(thread))
+            .bind(edit.target_sent_timestamp as i64)
+            .bind(edit.author.to_string())
+            .execute(&self.pool)
+            .await?;
+            return Ok(());
+        }
+
+        // Messages deleted for everyone shouldn't be found anymore
+        if let Some(delete) = extract_delete(content) {
+            sqlx::query("DELETE FROM messages WHERE thread = ? AND timestamp = ? AND sender = ?")
+                .bind(thread_key(thread))
+                .bind(delete.target_sent_timestamp as i64)
+                .bind(delete.author.to_string())
+                .execute(&self.pool)
+                .await?;
+            return Ok(());
+        }
+
+        let message = format_message(content);
+        let attachments = format_attachments(content);
+        let body = message
+            .iter()
+            .map(|message| message.text.as_str())
+            .chain(
+                attachments
+                    .iter()
+                    .map(|attachment| attachment.text.as_str()),
+            )
+            .collect::<Vec<_>>()
+            .join("\n");
+        if body.is_empty() {
+            return Ok(());
+        }
+
+        sqlx::query(
+            "INSERT INTO messages (thread, timestamp, sender, outgoing, body, has_attachment)
+             VALUES (?, ?, ?, ?, ?, ?)
+             ON CONFLICT (thread, timestamp) DO UPDATE SET body = excluded.body,
+                has_attachment = excluded.has_attachment",
+        )
+        .bind(thread_key(thread))
+        .bind(content.timestamp() as i64)
+        .bind(content.metadata.sender.raw_uuid().to_string())
+        .bind(outgoing)
+        .bind(body)
+        .bind(!attachments.is_empty())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Removes a message from the index, e.g. after it disappeared
+    pub async fn remove_message(&self, thread: &Thread, timestamp: u64) -> Result<()> {
+        sqlx::query("DELETE FROM messages WHERE thread = ? AND timestamp = ?")
+            .bind(thread_key(thread))
+            .bind(timestamp as i64)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Removes all messages of the conversation from the index, e.g. after its history was cleared
+    pub async fn remove_thread(&self, thread: &Thread) -> Result<()> {
+        sqlx::query("DELETE FROM messages WHERE thread = ?")
+            .bind(thread_key(thread))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Indexes messages of all conversations which the index doesn't cover yet, e.g. saved
+    /// while a client without the index was using the account.
+    ///
+    /// Conversations with `senders` who aren't contacts can't be listed from the store, see
+    /// `MessageRequests::senders`. Conversations indexed before are caught up as well.
+    pub async fn catch_up(
+        &self,
+        manager: &Manager<SqliteStore, Registered>,
+        senders: &[Uuid],
+    ) -> Result<()> {
+        let mut threads = Vec::new();
+        for contact in manager.store().contacts().await?.flatten() {
+            threads.push(Thread::Contact(contact.uuid));
+        }
+        for (master_key, _) in manager.store().groups().await?.flatten() {
+            threads.push(Thread::Group(master_key));
+        }
+        threads.extend(senders.iter().map(|uuid| Thread::Contact(*uuid)));
+        let indexed: Vec<String> =
+            sqlx::query_scalar("SELECT thread FROM coverage UNION SELECT thread FROM messages")
+                .fetch_all(&self.pool)
+                .await?;
+        threads.extend(
+            indexed
+                .iter()
+                .filter_map(|key| parse_recipient_key(key))
+                .map(|recipient| recipient.thread()),
+        );
+
+        let mut seen = HashSet::new();
+        for thread in threads {
+            if seen.insert(thread_key(&thread)) {
+                self.catch_up_thread(manager, &thread).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Indexes messages of the conversation which were stored after the newest message the
+    /// index covers, e.g. messages just sent from this device, which don't come back from the
+    /// server.
+    ///
+    /// Coverage is tracked apart from the indexed messages, a message indexed as it arrived
+    /// doesn't mean that the older ones were indexed too.
+    pub async fn catch_up_thread(
+        &self,
+        manager: &Manager<SqliteStore, Registered>,
+        thread: &Thread,
+    ) -> Result<()> {
+        let from = self.uncovered_from(thread).await?;
+        let own_aci = manager.registration_data().service_ids.aci;
+        let mut indexed_until = None;
+        let mut indexed = 0;
+        for content in manager.store().messages(thread, from..).await?.flatten() {
+            let outgoing = content.metadata.sender.raw_uuid() == own_aci
+                || matches!(content.body, ContentBody::SynchronizeMessage(_));
+            self.index_in_thread(thread, &content, outgoing).await?;
+            indexed_until = indexed_until.max(Some(content.timestamp()));
+            indexed += 1;
+        }
+
+        if let Some(indexed_until) = indexed_until {
+            self.cover(thread, indexed_until).await?;
+            debug!(indexed, "Indexed stored messages");
+        }
+        Ok(())
+    }
+
+    /// Lowest timestamp of stored messages of the conversation the index doesn't cover
+    async fn uncovered_from(&self, thread: &Thread) -> Result<u64> {
+        let covered: Option<i64> =
+            sqlx::query_scalar("SELECT indexed_until FROM coverage WHERE thread = ?")
+                .bind(thread_key(thread))
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(covered.map(|timestamp| timestamp as u64 + 1).unwrap_or(0))
+    }
+
+    /// Records that stored messages of the conversation up to the timestamp are indexed
+    async fn cover(&self, thread: &Thread, indexed_until: u64) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO coverage (thread, indexed_until) VALUES (?, ?)
+             ON CONFLICT (thread) DO UPDATE
+                SET indexed_until = MAX(indexed_until, excluded.indexed_until)",
+        )
+        .bind(thread_key(thread))
+        .bind(indexed_until as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn search(&self, query: &str, filter: &SearchFilter) -> Result<Vec<SearchHit>> {
+        let Some(query) = fts_query(query) else {
+            return Ok(Vec::new());
+        };
+
+        let rows = sqlx::query(
+            "SELECT m.thread, m.timestamp, m.sender, m.outgoing, m.body,
+                snippet(messages_fts, 0, '[', ']', '…', 12) AS snippet
+             FROM messages_fts JOIN messages m ON m.id = messages_fts.rowid
+             WHERE messages_fts MATCH ?1
+                AND (?2 IS NULL OR m.thread = ?2)
+                AND (?3 IS NULL OR m.timestamp >= ?3)
+                AND (?4 IS NULL OR m.timestamp <= ?4)
+                AND (?5 = 0 OR m.has_attachment = 1)
+             ORDER BY m.timestamp DESC
+             LIMIT ?6",
+        )
+        .bind(query)
+        .bind(filter.recipient.as_ref().map(recipient_key))
+        .bind(filter.from.map(|from| from as i64))
+        .bind(filter.to.map(|to| to as i64))
+        .bind(filter.has_attachment)
+        .bind(filter.limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut hits = Vec::with_capacity(rows.len());
+        for row in rows {
+            let thread: String = row.try_get("thread")?;
+            let Some(recipient) = parse_recipient_key(&thread) else {
+                warn!(thread, "Skipping search hit from unknown conversation");
+                continue;
+            };
+            let sender: String = row.try_get("sender")?;
+            hits.push(SearchHit {
+                recipient,
+                timestamp: row.try_get::<i64, _>("timestamp")? as u64,
+                sender: Uuid::from_str(&sender)?,
+                outgoing: row.try_get("outgoing")?,
+                text: row.try_get("body")?,
+                snippet: row.try_get("snippet")?,
+            });
+        }
+        Ok(hits)
+    }
+}
+
+/// Indexes messages just sent to the conversation, they don't come back from the server.
+/// Failures are only logged, the message was sent anyway.
+pub async fn index_sent(
+    search_index: Option<&SearchIndex>,
+    manager: &Manager<SqliteStore, Registered>,
+    thread: &Thread,
+) {
+    if let Some(search_index) = search_index
+        && let Err(error) = search_index.catch_up_thread(manager, thread).await
+    {
+        error!(%error, "Failed to index sent message");
+    }
+}
+
+/// Indexes messages just sent from the command line in the index of the current account
+pub async fn index_sent_cli(manager: &Manager<SqliteStore, Registered>, thread: &Thread) {
+    match SearchIndex::open_current().await {
+        Ok(search_index) => index_sent(Some(&search_index), manager, thread).await,
+        Err(error) => error!(%error, "Failed to open the search index"),
+    }
+}
+
+/// Identifies the conversation in files and tables next to the store
+pub(crate) fn thread_key(thread: &Thread) -> String {
+    match thread {
+        Thread::Contact(uuid) => format!("contact:{uuid}"),
+        Thread::Group(master_key) => format!("group:{}", hex::encode(master_key)),
+    }
+}
+
+fn recipient_key(recipient: &RecipientId) -> String {
+    match recipient {
+        RecipientId::Contact(uuid) => thread_key(&Thread::Contact(*uuid)),
+        RecipientId::Group(master_key) => thread_key(&Thread::Group(*master_key)),
+    }
+}
+
+pub(crate) fn parse_recipient_key(key: &str) -> Option<RecipientId> {
+    match key.split_once(':')? {
+        ("contact", uuid) => Uuid::from_str(uuid).ok().map(RecipientId::Contact),
+        ("group", master_key) => {
+            let master_key: GroupMasterKeyBytes = hex::decode(master_key).ok()?.try_into().ok()?;
+            Some(RecipientId::Group(master_key))
+        }
+        _ => None,
+    }
+}
+
+/// Turns user input into an FTS5 query where every word has to match as a prefix.
+///
+/// Words are quoted, so characters with a special meaning in FTS5 are searched for literally.
+fn fts_query(query: &str) -> Option<String> {
+    let terms = query
+        .split_whitespace()
+        .map(|term| format!("\"{}\"*", term.replace('"', "\"\"")))
+        .collect::<Vec<_>>();
+
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn open_index(dir: &tempfile::TempDir) -> SearchIndex {
+        SearchIndex::open(&dir.path().join("search.sqlite"))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn uncovered_thread_is_indexed_from_the_start() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = open_index(&dir).await;
+        let thread = Thread::Contact(Uuid::new_v4());
+
+        assert_eq!(index.uncovered_from(&thread).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn coverage_only_moves_forward() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = open_index(&dir).await;
+        let thread = Thread::Group([7; 32]);
+
+        index.cover(&thread, 200).await.unwrap();
+        index.cover(&thread, 100).await.unwrap();
+        assert_eq!(index.uncovered_from(&thread).await.unwrap(), 201);
+
+        let other = Thread::Contact(Uuid::new_v4());
+        assert_eq!(index.uncovered_from(&other).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn coverage_is_kept_across_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let thread = Thread::Contact(Uuid::new_v4());
+        open_index(&dir).await.cover(&thread, 42).await.unwrap();
+
+        let index = open_index(&dir).await;
+        assert_eq!(index.uncovered_from(&thread).await.unwrap(), 43);
+    }
+
+    #[test]
+    fn thread_keys_are_parsed_back() {
+        let uuid = Uuid::new_v4();
+        let contact = parse_recipient_key(&thread_key(&Thread::Contact(uuid)));
+        assert!(contact == Some(RecipientId::Contact(uuid)));
+        let group = parse_recipient_key(&thread_key(&Thread::Group([3; 32])));
+        assert!(group == Some(RecipientId::Group([3; 32])));
+        assert!(parse_recipient_key("contact:nonsense").is_none());
+    }
+
+    #[test]
+    fn query_words_match_as_quoted_prefixes() {
+        assert_eq!(fts_query("  "), None);
+        assert_eq!(
+            fts_query("hello \"wor"),
+            Some("\"hello\"* \"\"\"wor\"*".to_string())
+        );
+    }
+}
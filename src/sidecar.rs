@@ -0,0 +1,234 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use anyhow::{Result, anyhow, bail};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use tokio::runtime::Handle;
+use tracing::error;
+
+use crate::config::Config;
+
+/// JSON file next to the presage store holding state presage doesn't keep.
+///
+/// Saving only takes a snapshot of the value, the file is written on a blocking thread. Snapshots
+/// taken while a write is running are written together once it's done, so a burst of changes
+/// costs a write or two. Whatever wasn't written yet is written when the file is flushed or
+/// dropped.
+#[derive(Default)]
+pub struct JsonFile {
+    path: Option<PathBuf>,
+    /// What the file holds, for errors and logs
+    name: &'static str,
+    shared: Arc<Shared>,
+}
+
+#[derive(Default)]
+struct Shared {
+    pending: Mutex<Pending>,
+    /// Held while writing, so snapshots are written in the order they were taken
+    write: Mutex<()>,
+}
+
+#[derive(Default)]
+struct Pending {
+    /// Latest snapshot that wasn't written yet
+    contents: Option<String>,
+    /// Whether a blocking task is writing snapshots
+    writing: bool,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // Nothing is left half updated under these locks, so a panic elsewhere doesn't matter
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl JsonFile {
+    /// Loads the value stored under `path`, or the default if there is no file yet. The value
+    /// is saved back to the same file with the returned `JsonFile`.
+    pub fn load<T: DeserializeOwned + Default>(
+        path: PathBuf,
+        name: &'static str,
+    ) -> Result<(T, Self)> {
+        let value = if path.exists() {
+            let contents = fs::read_to_string(&path)?;
+            serde_json::from_str(&contents).map_err(|e| anyhow!("Failed to parse {name}: {e}"))?
+        } else {
+            T::default()
+        };
+        let file = JsonFile {
+            path: Some(path),
+            name,
+            shared: Arc::default(),
+        };
+        Ok((value, file))
+    }
+
+    /// Writes the value to the file, in the background when called inside the runtime
+    pub fn save(&self, value: &impl Serialize) {
+        if self.path.is_none() {
+            return;
+        }
+        let contents = match serde_json::to_string(value) {
+            Ok(contents) => contents,
+            Err(error) => {
+                error!(%error, name = self.name, "Failed to serialize state for its file");
+                return;
+            }
+        };
+
+        let Ok(runtime) = Handle::try_current() else {
+            lock(&self.shared.pending).contents = Some(contents);
+            self.flush();
+            return;
+        };
+        let mut pending = lock(&self.shared.pending);
+        pending.contents = Some(contents);
+        if pending.writing {
+            return;
+        }
+        pending.writing = true;
+        drop(pending);
+
+        let path = self.path.clone();
+        let name = self.name;
+        let shared = self.shared.clone();
+        runtime.spawn_blocking(move || {
+            loop {
+                let _write = lock(&shared.write);
+                let contents = {
+                    let mut pending = lock(&shared.pending);
+                    match pending.contents.take() {
+                        Some(contents) => contents,
+                        None => {
+                            pending.writing = false;
+                            return;
+                        }
+                    }
+                };
+                write(path.as_ref(), name, contents);
+            }
+        });
+    }
+
+    /// Writes the latest saved value right away, if it wasn't written yet
+    pub fn flush(&self) {
+        let _write = lock(&self.shared.write);
+        let contents = lock(&self.shared.pending).contents.take();
+        if let Some(contents) = contents {
+            write(self.path.as_ref(), self.name, contents);
+        }
+    }
+}
+
+impl Drop for JsonFile {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+fn write(path: Option<&PathBuf>, name: &str, contents: String) {
+    let Some(path) = path else {
+        return;
+    };
+
+    let result = (|| -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Write to a temporary file first, so a crash can't leave a truncated file
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, contents)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    })();
+
+    if let Err(error) = result {
+        error!(%error, path = %path.display(), "Failed to persist {name}");
+    }
+}
+
+/// State of an account kept in a `JsonFile` in the account directory
+pub trait AccountFile: Serialize + DeserializeOwned + Default {
+    /// What the file holds, for errors and logs
+    const NAME: &'static str;
+
+    /// Location of the file of the account
+    fn path(account_name: &str) -> PathBuf;
+
+    fn file_mut(&mut self) -> &mut JsonFile;
+
+    /// Loads the state stored under `path`, all later changes are written back to the same file
+    fn load(path: PathBuf) -> Result<Self> {
+        let (mut value, file): (Self, _) = JsonFile::load(path, Self::NAME)?;
+        *value.file_mut() = file;
+        Ok(value)
+    }
+
+    fn load_for_account(account_name: &str) -> Result<Self> {
+        Self::load(Self::path(account_name))
+    }
+
+    /// Loads the state of the account that is currently selected in the config
+    fn load_current() -> Result<Self> {
+        match Config::load().get_current_account() {
+            Some(account_name) => Self::load_for_account(account_name),
+            None => bail!("No account is selected to load {} for", Self::NAME),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn missing_file_loads_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let (value, _file): (HashMap<String, u32>, _) =
+            JsonFile::load(dir.path().join("state.json"), "state").unwrap();
+        assert!(value.is_empty());
+    }
+
+    #[test]
+    fn saved_value_is_loaded_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("state.json");
+        let (mut value, file): (HashMap<String, u32>, _) =
+            JsonFile::load(path.clone(), "state").unwrap();
+        value.insert("a".to_string(), 1);
+        file.save(&value);
+        drop(file);
+
+        let (loaded, _file): (HashMap<String, u32>, _) = JsonFile::load(path, "state").unwrap();
+        assert_eq!(loaded, value);
+    }
+
+    #[test]
+    fn broken_file_fails_to_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        fs::write(&path, "{").unwrap();
+        let result: Result<(HashMap<String, u32>, _)> = JsonFile::load(path, "state");
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn latest_snapshot_is_written_last() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let (_, file): (Vec<u32>, _) = JsonFile::load(path.clone(), "state").unwrap();
+        for count in 0..100 {
+            file.save(&(0..count).collect::<Vec<u32>>());
+        }
+        // Background writes of older snapshots finish before the flush
+        file.flush();
+        drop(file);
+
+        let (loaded, _file): (Vec<u32>, _) = JsonFile::load(path, "state").unwrap();
+        assert_eq!(loaded, (0..99).collect::<Vec<u32>>());
+    }
+}
@@ -2,11 +2,15 @@ mod account_selector;
 pub mod captcha;
 pub mod chat;
 pub mod contact_list;
+pub mod emoji_picker;
 pub mod frame;
 pub mod group_info;
+pub mod image_viewer;
 pub mod input;
 pub mod linking;
 pub mod options;
+pub mod safety_number;
+pub mod search;
 pub mod utils;
 
 pub use account_selector::*;
@@ -1,21 +1,31 @@
+use chrono::{DateTime, Local};
 use presage::proto::data_message::Quote;
 use ratatui::{
     Frame,
     layout::{Constraint, Direction, Layout, Rect},
     style::{Modifier, Style},
-    text::Line,
-    widgets::{Block, BorderType, Borders, Paragraph, Wrap},
+    text::{Line, Span, Text},
+    widgets::{Block, BorderType, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap},
 };
+use ratatui_image::Image;
 
 use crate::{
     app::{App, CurrentScreen, RecipientId},
-    messages::receive::MessageDto,
+    messages::{
+        attachments,
+        formatting::{FormattedBody, SPOILER_MASK, TextStyle, mask_spoilers, parse_body},
+        receive::MessageDto,
+    },
     ui::{
         input::render_input_and_attachment,
         utils::{get_local_timestamp, render_scrollbar},
     },
 };
 
+/// Size of image thumbnails in the chat, in cells
+pub const THUMBNAIL_WIDTH: u16 = 24;
+pub const THUMBNAIL_HEIGHT: u16 = 8;
+
 #[derive(Debug)]
 enum Visibility {
     Full,         // fully visible
@@ -39,6 +49,37 @@ pub fn render_chat(frame: &mut Frame, app: &App, area: Rect) {
         None => &vec![],
     };
 
+    let mut chat_area = vertical_chunks[0];
+    if let Some(notice) = safety_number_notice(app, &recipient.id()) {
+        let chat_chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(1), Constraint::Min(1)])
+            .split(chat_area);
+        chat_area = chat_chunks[1];
+
+        let notice = Paragraph::new(notice).style(
+            Style::default()
+                .fg(app.config.get_error_color())
+                .add_modifier(Modifier::BOLD),
+        );
+        frame.render_widget(notice, chat_chunks[0]);
+    }
+    if let Some(typing) = typing_text(app, &recipient.id()) {
+        let chat_chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(1)])
+            .split(chat_area);
+        chat_area = chat_chunks[0];
+
+        let typing = Paragraph::new(typing).style(
+            Style::default()
+                .fg(app.config.get_secondary_color())
+                .add_modifier(Modifier::ITALIC),
+        );
+        frame.render_widget(typing, chat_chunks[1]);
+    }
+    let vertical_chunks = [chat_area, vertical_chunks[1]];
+
     if !messages.is_empty() {
         let msg_padding = 2;
         let margin = vertical_chunks[0].width.saturating_div(4);
@@ -81,6 +122,47 @@ pub fn render_chat(frame: &mut Frame, app: &App, area: Rect) {
     }
 
     render_input_and_attachment(frame, app, &vertical_chunks);
+    render_mention_candidates(frame, app, vertical_chunks[0]);
+}
+
+/// Renders members matching the typed mention at the bottom of the chat, above the input
+fn render_mention_candidates(frame: &mut Frame, app: &App, chat_area: Rect) {
+    let candidates = app.mention_candidates();
+    if candidates.is_empty() {
+        return;
+    }
+
+    let height = (candidates.len() as u16).min(5) + 2;
+    let width = candidates
+        .iter()
+        .map(|(_, name)| name.chars().count() as u16 + 4)
+        .max()
+        .unwrap_or_default()
+        .clamp(20, chat_area.width);
+    let area = Rect {
+        x: chat_area.x,
+        y: chat_area.y + chat_area.height.saturating_sub(height),
+        width,
+        height: height.min(chat_area.height),
+    };
+
+    let items = candidates
+        .into_iter()
+        .map(|(_, name)| ListItem::new(format!("@{name}")))
+        .collect::<Vec<_>>();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .title("Mention"),
+        )
+        .style(Style::default().fg(app.config.get_primary_color()))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+    let mut state = ListState::default().with_selected(Some(app.mention_selected));
+    frame.render_widget(Clear, area);
+    frame.render_stateful_widget(list, area, &mut state);
 }
 
 fn calculate_message_dimensions(
@@ -109,6 +191,11 @@ fn calculate_message_dimensions(
             total_lines += (len / max_width + 1) as u16;
         }
 
+        if shows_thumbnail(app, msg) {
+            total_lines += THUMBNAIL_HEIGHT;
+            longest_line_len = longest_line_len.max(THUMBNAIL_WIDTH as usize);
+        }
+
         let message_height = total_lines + 3;
 
         let actual_width = longest_line_len
@@ -129,16 +216,7 @@ fn calculate_quote_block(app: &App, quote: &Quote, max_width: usize) -> (u16, us
         .format("%Y-%m-%d %H:%M:%S")
         .to_string();
 
-    let quoted_preview = quote
-        .text
-        .clone()
-        .unwrap_or_else(|| "...".to_string())
-        .lines()
-        .next()
-        .unwrap_or("")
-        .chars()
-        .take(80)
-        .collect::<String>();
+    let quoted_preview = quoted_preview(quote);
 
     let info_line = format!("┆ {author_name} · {quote_time}");
     let max_line_len = info_line
@@ -215,8 +293,10 @@ fn render_messages(
             .borders(Borders::ALL)
             .border_type(BorderType::Rounded);
 
-        if let CurrentScreen::InspectMesseges = app.current_screen
-            && idx == app.message_selected
+        if matches!(
+            app.current_screen,
+            CurrentScreen::InspectMesseges | CurrentScreen::EmojiPicker
+        ) && idx == app.message_selected
         {
             style = style.add_modifier(Modifier::REVERSED);
         }
@@ -245,13 +325,18 @@ fn render_messages(
             block = block.title_bottom(reaction_text);
         }
 
+        let own_message = app.uuid == Some(msg.uuid);
+        if own_message {
+            block = block.title_top(Line::from(message_ticks(msg)).right_aligned());
+        }
+
         let para: Paragraph = match visibility {
             Visibility::Full => Paragraph::new(text_content)
                 .style(style)
                 .block(
                     block
                         .clone()
-                        .title_top(datetime_local.format("%Y-%m-%d %H:%M:%S").to_string())
+                        .title_top(message_title(msg, &datetime_local))
                         .title_bottom(
                             Line::from(get_display_name(app, msg.uuid.to_string().as_str()))
                                 .right_aligned(),
@@ -260,19 +345,15 @@ fn render_messages(
                 .wrap(Wrap { trim: false }),
 
             Visibility::Partial(remaining_height) => {
-                let max_text_lines = remaining_height.saturating_sub(1);
-                let visible_lines: Vec<String> = text_content
-                    .lines()
-                    .rev()
-                    .take(max_text_lines as usize)
-                    .map(|s| s.to_string())
-                    .collect();
-
-                let visible_text = visible_lines
-                    .into_iter()
-                    .rev()
-                    .collect::<Vec<_>>()
-                    .join("\n");
+                let max_text_lines = remaining_height.saturating_sub(1) as usize;
+                let skipped_lines = text_content.lines.len().saturating_sub(max_text_lines);
+                let visible_text = Text::from(
+                    text_content
+                        .lines
+                        .into_iter()
+                        .skip(skipped_lines)
+                        .collect::<Vec<_>>(),
+                );
                 height = *remaining_height;
 
                 Paragraph::new(visible_text)
@@ -283,9 +364,7 @@ fn render_messages(
         };
 
         let mut x_pos = vertical_chunks[0].x;
-        // let id = app.recipients[app.selected_recipient].0.id();
-        let uuid_string = app.uuid.unwrap_or_default().to_string();
-        if uuid_string == msg.uuid.to_string() {
+        if own_message {
             x_pos = vertical_chunks[0].x + vertical_chunks[0].width - width;
         }
 
@@ -297,20 +376,182 @@ fn render_messages(
         };
         frame.render_widget(para, msg_area);
 
+        if matches!(visibility, Visibility::Full) && shows_thumbnail(app, msg) {
+            render_thumbnail(frame, app, msg, msg_area);
+        }
+
         y_pos += height;
     }
 }
 
-fn build_message_content(app: &App, msg: &MessageDto) -> String {
-    let mut text_content = String::new();
+/// Line shown under the chat while someone in the conversation is typing
+fn typing_text(app: &App, recipient: &RecipientId) -> Option<String> {
+    let typing = app.typing.get(recipient)?;
+    let mut names = typing
+        .keys()
+        .map(|uuid| get_display_name(app, &uuid.to_string()))
+        .collect::<Vec<_>>();
+    names.sort();
+
+    match names.as_slice() {
+        [] => None,
+        [name] => Some(format!("{name} is typing...")),
+        names => Some(format!("{} are typing...", names.join(", "))),
+    }
+}
+
+/// Line shown above the chat with a contact whose safety number changed and wasn't verified yet
+fn safety_number_notice(app: &App, recipient: &RecipientId) -> Option<String> {
+    let RecipientId::Contact(uuid) = recipient else {
+        return None;
+    };
+    let changed_at = get_local_timestamp(*app.safety_number_changes.get(uuid)?);
+    Some(format!(
+        "Safety number changed on {}, verify it in the contact info",
+        changed_at.format("%Y-%m-%d %H:%M")
+    ))
+}
+
+/// Time of the message followed by a marker when it was edited
+fn message_title(msg: &MessageDto, datetime_local: &DateTime<Local>) -> String {
+    let datetime = datetime_local.format("%Y-%m-%d %H:%M:%S");
+    match msg.edited_at {
+        Some(_) => format!("{datetime} (edited)"),
+        None => datetime.to_string(),
+    }
+}
+
+/// Ticks of our message, a single one until any recipient sends a receipt
+fn message_ticks(msg: &MessageDto) -> &'static str {
+    msg.receipt_status()
+        .map(|status| status.ticks())
+        .unwrap_or("✓")
+}
+
+fn build_message_content(app: &App, msg: &MessageDto) -> Text<'static> {
+    let mut lines = Vec::new();
 
     if let Some(quote) = &msg.quote {
-        text_content.push_str(&render_quote_block(app, quote));
-        text_content.push('\n');
+        lines.extend(
+            render_quote_block(app, quote)
+                .lines()
+                .map(|line| Line::from(line.to_string())),
+        );
+        lines.push(Line::default());
+    }
+
+    lines.extend(message_lines(app, msg));
+    if shows_thumbnail(app, msg) {
+        // Space for the thumbnail, which is drawn over the paragraph
+        lines.extend((0..THUMBNAIL_HEIGHT).map(|_| Line::default()));
     }
+    Text::from(lines)
+}
 
-    text_content.push_str(&msg.text);
-    text_content
+/// Whether space for a thumbnail of the attachment is kept in the message
+fn shows_thumbnail(app: &App, msg: &MessageDto) -> bool {
+    app.config.show_images
+        && app.picker.is_some()
+        && msg.attachment.as_ref().is_some_and(attachments::is_image)
+}
+
+/// Draws the thumbnail at the bottom of the message, or a placeholder while it is loading
+fn render_thumbnail(frame: &mut Frame, app: &App, msg: &MessageDto, msg_area: Rect) {
+    let area = Rect {
+        x: msg_area.x + 1,
+        y: (msg_area.y + msg_area.height).saturating_sub(THUMBNAIL_HEIGHT + 2),
+        width: THUMBNAIL_WIDTH.min(msg_area.width.saturating_sub(2)),
+        height: THUMBNAIL_HEIGHT,
+    };
+
+    let thumbnail = msg
+        .attachment
+        .as_ref()
+        .and_then(attachments::cache_key)
+        .and_then(|key| app.attachment_thumbnails.get(&key));
+    match thumbnail {
+        Some(protocol) => frame.render_widget(Image::new(protocol), area),
+        None => frame.render_widget(
+            Paragraph::new("Loading image...")
+                .style(Style::default().fg(app.config.get_secondary_color())),
+            area,
+        ),
+    }
+}
+
+/// Lines of the message text with mentions highlighted, mentions of us stand out more.
+///
+/// Styled parts get their modifiers and spoilers are masked until the user reveals them.
+fn message_lines(app: &App, msg: &MessageDto) -> Vec<Line<'static>> {
+    if msg.deleted {
+        let tombstone_style = Style::default()
+            .fg(app.config.get_secondary_color())
+            .add_modifier(Modifier::ITALIC);
+        return vec![Line::styled(msg.text.clone(), tombstone_style)];
+    }
+
+    let mention_style = Style::default()
+        .fg(app.config.get_accent_color())
+        .add_modifier(Modifier::BOLD);
+    let spoilers_revealed = app.revealed_spoilers.contains(&msg.timestamp);
+
+    let mut boundaries = vec![0, msg.text.len()];
+    for range in msg
+        .mentions
+        .iter()
+        .map(|mention| &mention.range)
+        .chain(msg.styles.iter().map(|styled| &styled.range))
+    {
+        boundaries.extend([range.start, range.end]);
+    }
+    boundaries.retain(|offset| msg.text.is_char_boundary(*offset));
+    boundaries.sort_unstable();
+    boundaries.dedup();
+
+    let mut lines = vec![Line::default()];
+    for window in boundaries.windows(2) {
+        let (start, end) = (window[0], window[1]);
+        let contains = |range: &std::ops::Range<usize>| range.start <= start && end <= range.end;
+
+        let mut style = match msg.mentions.iter().find(|mention| contains(&mention.range)) {
+            Some(mention) if app.uuid == Some(mention.uuid) => {
+                mention_style.add_modifier(Modifier::UNDERLINED)
+            }
+            Some(_) => mention_style,
+            None => Style::default(),
+        };
+        let mut hidden = false;
+        for styled in msg.styles.iter().filter(|styled| contains(&styled.range)) {
+            style = match styled.style {
+                TextStyle::Bold => style.add_modifier(Modifier::BOLD),
+                TextStyle::Italic => style.add_modifier(Modifier::ITALIC),
+                TextStyle::Strikethrough => style.add_modifier(Modifier::CROSSED_OUT),
+                TextStyle::Monospace => style.fg(app.config.get_secondary_color()),
+                TextStyle::Spoiler if spoilers_revealed => style.add_modifier(Modifier::DIM),
+                TextStyle::Spoiler => {
+                    hidden = true;
+                    style
+                }
+            };
+        }
+
+        for (index, part) in msg.text[start..end].split('\n').enumerate() {
+            if index > 0 {
+                lines.push(Line::default());
+            }
+            if !part.is_empty()
+                && let Some(line) = lines.last_mut()
+            {
+                // Masked with the same number of characters so the height of the message stays
+                let part = match hidden {
+                    true => part.chars().map(|_| SPOILER_MASK).collect(),
+                    false => part.to_string(),
+                };
+                line.push_span(Span::styled(part, style));
+            }
+        }
+    }
+    lines
 }
 
 fn render_quote_block(app: &App, quote: &Quote) -> String {
@@ -319,16 +560,7 @@ fn render_quote_block(app: &App, quote: &Quote) -> String {
         .format("%Y-%m-%d %H:%M:%S")
         .to_string();
 
-    let quoted_preview = quote
-        .text
-        .clone()
-        .unwrap_or_else(|| "...".to_string())
-        .lines()
-        .next()
-        .unwrap_or("")
-        .chars()
-        .take(80)
-        .collect::<String>();
+    let quoted_preview = quoted_preview(quote);
 
     format!(
         "┆ {author_name} · {quote_time}\n┆ {}\n",
@@ -336,6 +568,18 @@ fn render_quote_block(app: &App, quote: &Quote) -> String {
     )
 }
 
+/// First line of the quoted text, its spoilers stay masked as the quote can't reveal them
+fn quoted_preview(quote: &Quote) -> String {
+    let text = match &quote.text {
+        Some(body) => {
+            let FormattedBody { text, styles, .. } = parse_body(body, &quote.body_ranges);
+            mask_spoilers(&text, &styles)
+        }
+        None => "...".to_string(),
+    };
+    text.lines().next().unwrap_or("").chars().take(80).collect()
+}
+
 fn get_display_name(app: &App, author_aci: &str) -> String {
     for (recipient, _) in &app.recipients {
         if let RecipientId::Contact(uuid) = recipient.id()
@@ -1,4 +1,7 @@
+use std::ops::Range;
+
 use ratatui::layout::Alignment;
+use ratatui::text::Line;
 use ratatui::{
     Frame,
     layout::{Constraint, Direction, Layout, Rect},
@@ -7,15 +10,48 @@ use ratatui::{
 };
 use ratatui_image::{Resize, StatefulImage};
 
+use crate::messages::expiry::format_timer;
 use crate::{app::App, ui::utils::render_scrollbar};
 
-/// Renders the contact list in the left chunk of the screen
+/// Renders the contact list in the left chunk of the screen, message requests are listed in
+/// their own section under the chats
 pub fn render_contact_list(frame: &mut Frame, app: &App, area: Rect) {
-    let list_items: Vec<ListItem> = app
+    // Requests are sorted after the chats
+    let requests_start = app
         .recipients
         .iter()
-        .enumerate()
-        .map(|(i, (recipient, _))| {
+        .position(|(recipient, _)| recipient.is_request())
+        .unwrap_or(app.recipients.len());
+    if requests_start == app.recipients.len() {
+        render_section(frame, app, area, "Chats", 0..requests_start);
+        return;
+    }
+
+    // Every request takes two lines, and the block four more
+    let requests_height = ((app.recipients.len() - requests_start) * 2 + 4) as u16;
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(4),
+            Constraint::Length(requests_height.min(area.height / 2)),
+        ])
+        .split(area);
+    render_section(frame, app, layout[0], "Chats", 0..requests_start);
+    render_section(
+        frame,
+        app,
+        layout[1],
+        "Requests",
+        requests_start..app.recipients.len(),
+    );
+}
+
+/// Renders the recipients in `range` as a list, requests with the number of the sender
+fn render_section(frame: &mut Frame, app: &App, area: Rect, title: &str, range: Range<usize>) {
+    let list_items: Vec<ListItem> = app.recipients[range.clone()]
+        .iter()
+        .zip(range.clone())
+        .map(|((recipient, _), i)| {
             let mut style = Style::default().fg(app.config.get_primary_color());
             if i == app.selected_recipient {
                 style = style
@@ -24,23 +60,40 @@ pub fn render_contact_list(frame: &mut Frame, app: &App, area: Rect) {
                     .fg(app.config.get_accent_color());
             }
             let name = recipient.display_name().to_string();
-            ListItem::new(name).style(style)
+            if !recipient.is_request() {
+                return ListItem::new(name).style(style);
+            }
+            let number = recipient
+                .phone_number()
+                .unwrap_or("number hidden")
+                .to_string();
+            ListItem::new(vec![
+                Line::from(name),
+                Line::styled(
+                    number,
+                    Style::default().fg(app.config.get_secondary_color()),
+                ),
+            ])
+            .style(style)
         })
         .collect();
 
     let chat_list_widget = List::new(list_items.clone()).block(
         Block::default()
             .padding(Padding::new(1, 1, 1, 1))
-            .title("Chats")
+            .title(title)
             .borders(Borders::ALL)
             .border_style(Style::default().fg(app.config.get_primary_color())),
     );
 
+    let selected = range
+        .contains(&app.selected_recipient)
+        .then(|| app.selected_recipient - range.start);
     let mut list_state = ListState::default();
-    list_state.select(Some(app.selected_recipient));
+    list_state.select(selected);
 
     frame.render_stateful_widget(chat_list_widget, area, &mut list_state);
-    render_scrollbar(frame, app.selected_recipient, list_items.len(), area);
+    render_scrollbar(frame, selected.unwrap_or(0), list_items.len(), area);
 }
 
 /// Renders contact information screen
@@ -113,14 +166,12 @@ pub fn render_contact_info_compact(frame: &mut Frame, app: &mut App, area: Rect)
 
     if let Some(contact) = &app.selected_contact_info {
         info_text.push_str(&format!(
-            "\nNAME:\n{}\n\nPHONE:\n{}\n\nABOUT:\n{}\n\nVERIFIED:\n{}\n\nAVATAR:\n{}\n",
+            "\nNAME:\n{}\n\nPHONE:\n{}\n\nABOUT:\n{}\n\nVERIFIED:\n{}\n\nDISAPPEARING MESSAGES:\n{}\n\nAVATAR:\n{}\n",
             contact.name,
             contact.phone_number.as_deref().unwrap_or("Not set"),
             contact.description.as_deref().unwrap_or(""),
-            match contact.verified_state {
-                Some(state) if state > 0 => "Yes",
-                _ => "No",
-            },
+            contact.verification.label(),
+            format_timer(contact.expire_timer),
             if contact.has_avatar { "Set" } else { "Not set" },
         ));
     } else {
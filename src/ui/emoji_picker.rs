@@ -0,0 +1,69 @@
+use ratatui::{
+    Frame,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
+    widgets::{Block, BorderType, Borders, Clear, List, ListItem, ListState, Paragraph},
+};
+
+use crate::{app::App, emoji, ui::utils::centered_rect_fixed_size};
+
+/// Renders the reaction picker over the chat, with the shortcode search on top
+pub fn render_emoji_picker(frame: &mut Frame, app: &App, area: Rect) {
+    let popup_area = centered_rect_fixed_size(40, 16, area);
+    frame.render_widget(Clear, popup_area);
+
+    let vertical_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(1)])
+        .split(popup_area);
+
+    let input = Paragraph::new(app.emoji_query.as_str())
+        .style(Style::default().fg(app.config.get_accent_color()))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .title("React with"),
+        );
+    frame.render_widget(input, vertical_chunks[0]);
+    frame.set_cursor_position((
+        vertical_chunks[0].x + app.emoji_query.chars().count() as u16 + 1,
+        vertical_chunks[0].y + 1,
+    ));
+
+    let own_reaction = app.own_reaction();
+    let options = app.emoji_options();
+    let items: Vec<ListItem> = options
+        .iter()
+        .map(|emoji| {
+            let mut text = format!("{emoji}  {}", emoji::shortcode(emoji).unwrap_or(""));
+            if own_reaction.as_deref() == Some(emoji.as_str()) {
+                text.push_str("  (remove)");
+            }
+            ListItem::new(text)
+        })
+        .collect();
+
+    let title = if app.emoji_query.is_empty() {
+        "Recent and frequent"
+    } else {
+        "Matching shortcodes"
+    };
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .title(title),
+        )
+        .style(Style::default().fg(app.config.get_primary_color()))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+    let mut state = ListState::default();
+    if !options.is_empty() {
+        state.select(Some(app.emoji_selected));
+    }
+
+    frame.render_stateful_widget(list, vertical_chunks[1], &mut state);
+}
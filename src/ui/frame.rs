@@ -8,6 +8,8 @@ use ratatui::{
 
 use crate::{
     app::UiStatusMessage,
+    message_requests::RequestResponse,
+    messages::{attachments, deletion::LocalDeletion},
     ui::{
         captcha::render_captcha,
         group_info::{render_group_info, render_member_info},
@@ -19,8 +21,12 @@ use crate::{
     ui::{
         chat::render_chat,
         contact_list::{render_contact_info_compact, render_contact_list},
+        emoji_picker::render_emoji_picker,
+        image_viewer::render_image_viewer,
         linking::{render_qrcode, render_textarea},
         options::render_options,
+        safety_number::render_safety_number,
+        search::render_search,
         utils::{render_paragraph, render_popup},
     },
 };
@@ -145,11 +151,80 @@ pub fn render_ui(frame: &mut Frame, app: &mut App) {
                 render_popup(frame, frame.area(), &status_message);
             }
         }
+        CurrentScreen::ConfirmLocalDelete => {
+            if let Some((_, deletion)) = &app.pending_local_deletion {
+                let name = app
+                    .recipients
+                    .get(app.selected_recipient)
+                    .map(|recipient| recipient.0.display_name())
+                    .unwrap_or_default();
+                let question = match deletion {
+                    LocalDeletion::Message(_) => {
+                        "Delete the selected message on this device?".to_string()
+                    }
+                    LocalDeletion::History => {
+                        format!("Delete all messages of '{name}' on this device?")
+                    }
+                    LocalDeletion::Conversation => format!(
+                        "Delete the conversation '{name}' on this device?\n\
+            It is removed from the list until a new message arrives."
+                    ),
+                };
+                let text = format!(
+                    "{question}\n\
+            Other devices and the other side keep their messages.\n\n\
+            Press 'y' to confirm deletion\n\
+            Press 'n' or ESC to cancel",
+                );
+                let status_message = UiStatusMessage::Info(text);
+                render_popup(frame, frame.area(), &status_message);
+            }
+        }
+        CurrentScreen::ConfirmRequest => {
+            if let Some((_, response)) = &app.pending_request_response {
+                let name = app
+                    .recipients
+                    .get(app.selected_recipient)
+                    .map(|recipient| recipient.0.display_name())
+                    .unwrap_or_default();
+                let question = match response {
+                    RequestResponse::Accept => format!("Accept the message request of '{name}'?"),
+                    RequestResponse::Delete => {
+                        format!("Delete the message request of '{name}' on this device?")
+                    }
+                    RequestResponse::Block => format!(
+                        "Block '{name}'?\n\
+            Their messages are deleted and later ones are dropped."
+                    ),
+                };
+                let text = format!(
+                    "{question}\n\n\
+            Press 'y' to confirm\n\
+            Press 'n' or ESC to cancel",
+                );
+                let status_message = UiStatusMessage::Info(text);
+                render_popup(frame, frame.area(), &status_message);
+            }
+        }
         CurrentScreen::InspectMesseges => {
             render_contact_list(frame, app, main_chunks[0]);
             render_chat(frame, app, main_chunks[1]);
             render_footer(frame, app, chunks[1]);
         }
+        CurrentScreen::EmojiPicker => {
+            render_contact_list(frame, app, main_chunks[0]);
+            render_chat(frame, app, main_chunks[1]);
+            render_emoji_picker(frame, app, main_chunks[1]);
+            render_footer(frame, app, chunks[1]);
+        }
+        CurrentScreen::ImageViewer => {
+            render_image_viewer(frame, app, chunks[0]);
+            render_footer(frame, app, chunks[1]);
+        }
+        CurrentScreen::SafetyNumber => {
+            render_safety_number(frame, app, chunks[0]);
+            render_footer(frame, app, chunks[1]);
+        }
         CurrentScreen::Popup => {
             let status_message = match app.ui_status_info.clone() {
                 Some(message) => message.status_message,
@@ -160,16 +235,29 @@ pub fn render_ui(frame: &mut Frame, app: &mut App) {
         CurrentScreen::Recaptcha => {
             render_captcha(frame, frame.area(), app);
         }
+        CurrentScreen::Search => {
+            render_contact_list(frame, app, main_chunks[0]);
+            render_search(frame, app, main_chunks[1]);
+            render_footer(frame, app, chunks[1]);
+        }
     }
 }
 
 /// Renders the footer section at the bottom of the screen.
 fn render_footer(frame: &mut Frame, app: &App, area: Rect) {
     let current_keys_hint = match app.current_screen {
-        CurrentScreen::Main => Span::styled(
-            "(q) to quit | (↑ ↓) to navigate | (→) to select chat | (i) for contact info | (a) for accounts panel | (e) for options",
-            Style::default().fg(app.config.get_primary_color()),
-        ),
+        CurrentScreen::Main => {
+            let request_info = match app.selected_is_request() {
+                true => " | (y) to accept | (x) to delete | (b) to block",
+                false => "",
+            };
+            Span::styled(
+                format!(
+                    "(q) to quit | (↑ ↓) to navigate | (→) to select chat | (i) for contact info | (a) for accounts panel | (e) for options | (/) to search{request_info}"
+                ),
+                Style::default().fg(app.config.get_primary_color()),
+            )
+        }
         CurrentScreen::Writing => {
             let retry_info = if let Ok(manager) = app.retry_manager.try_lock() {
                 let failed_count = manager.failed_count();
@@ -191,7 +279,9 @@ fn render_footer(frame: &mut Frame, app: &App, area: Rect) {
 
             let mut reply_info = "";
 
-            if app.quoted_message.is_some() {
+            if app.editing_message.is_some() {
+                reply_info = " | (CTRL+r) to stop editing"
+            } else if app.quoted_message.is_some() {
                 reply_info = " | (CTRL+r) to stop replying"
             }
 
@@ -204,12 +294,20 @@ fn render_footer(frame: &mut Frame, app: &App, area: Rect) {
                 },
             )
         }
+        CurrentScreen::Search => Span::styled(
+            "(ESC) to go back | (↑ ↓) to navigate | (ENTER) to jump to message",
+            Style::default().fg(app.config.get_primary_color()),
+        ),
+        CurrentScreen::EmojiPicker => Span::styled(
+            "(ESC) to go back | (↑ ↓) to navigate | type to search by shortcode | (ENTER) to react",
+            Style::default().fg(app.config.get_primary_color()),
+        ),
         CurrentScreen::Options => Span::styled(
             "(q) to exit | (↑ ↓) to navigate | (ENTER/SPACE) to toggle option",
             Style::default().fg(app.config.get_primary_color()),
         ),
         CurrentScreen::ContactInfo => Span::styled(
-            "(q) to exit | (← or ESC) to go back",
+            "(q) to exit | (← or ESC) to go back | (t) to change disappearing messages | (v) to view the safety number",
             Style::default().fg(app.config.get_primary_color()),
         ),
         CurrentScreen::GroupInfo => Span::styled(
@@ -220,6 +318,14 @@ fn render_footer(frame: &mut Frame, app: &App, area: Rect) {
             "(q) to exit | (← or ESC) to go back",
             Style::default().fg(app.config.get_primary_color()),
         ),
+        CurrentScreen::ImageViewer => Span::styled(
+            "(q or ESC) to close the image",
+            Style::default().fg(app.config.get_primary_color()),
+        ),
+        CurrentScreen::SafetyNumber => Span::styled(
+            "(q or ESC) to go back | (v) to mark as verified or unverified",
+            Style::default().fg(app.config.get_primary_color()),
+        ),
         CurrentScreen::InspectMesseges => {
             let selected_recipient_id = app.recipients[app.selected_recipient].0.id();
             let optional_messages = match selected_recipient_id {
@@ -227,18 +333,38 @@ fn render_footer(frame: &mut Frame, app: &App, area: Rect) {
                 RecipientId::Group(group_key) => app.group_messages.get(&group_key),
             };
 
+            let selected_message =
+                optional_messages.and_then(|messages| messages.get(app.message_selected));
+
             let mut save_attachment_info = "";
-            if let Some(messages) = optional_messages
-                && let Some(Some(_att)) = messages
-                    .get(app.message_selected)
-                    .map(|msg| msg.attachment.as_ref())
+            if let Some(Some(att)) = selected_message.map(|msg| msg.attachment.as_ref()) {
+                save_attachment_info = match app.config.show_images && attachments::is_image(att) {
+                    true => " | (s) to save attachment | (o) to open image",
+                    false => " | (s) to save attachment",
+                };
+            }
+
+            let mut spoiler_info = "";
+            if let Some(msg) = selected_message
+                && msg.has_spoilers()
+            {
+                spoiler_info = match app.revealed_spoilers.contains(&msg.timestamp) {
+                    true => " | (v) to hide spoilers",
+                    false => " | (v) to reveal spoilers",
+                };
+            }
+
+            let mut edit_info = "";
+            if let Some(msg) = selected_message
+                && Some(msg.uuid) == app.uuid
+                && msg.attachment.is_none()
             {
-                save_attachment_info = " | (s) to save attachment"
+                edit_info = " | (e) to edit"
             }
 
             Span::styled(
                 format!(
-                    "(q) to exit inspection mode | (← or ESC) to go back | (r) to reply | (d) to delete | (c) to like{save_attachment_info}"
+                    "(q) to exit inspection mode | (← or ESC) to go back | (r) to reply | (d) to delete | (x) to delete for me | (h) to clear history | (X) to delete chat | (c) to like | (p) to pick reaction{edit_info}{spoiler_info}{save_attachment_info}"
                 ),
                 Style::default().fg(app.config.get_primary_color()),
             )
@@ -7,6 +7,8 @@ use ratatui::{
 use ratatui_image::{Resize, StatefulImage};
 
 use crate::app::App;
+use crate::messages::expiry::format_timer;
+use crate::messages::receipts::ReceiptStatus;
 
 pub fn render_group_info(frame: &mut Frame, app: &mut App, area: Rect) {
     let layout = Layout::default()
@@ -98,9 +100,11 @@ pub fn render_group_info(frame: &mut Frame, app: &mut App, area: Rect) {
             "\n \
             NAME:\n{}\n\n \
             DESCRIPTION:\n{}\n\n \
+            DISAPPEARING MESSAGES:\n{}\n\n \
             AVATAR:\n{}\n",
             group.name,
             group.description,
+            format_timer(group.expire_timer),
             if group.has_avatar { "Set" } else { "Not set" },
         ));
     } else {
@@ -113,6 +117,15 @@ pub fn render_group_info(frame: &mut Frame, app: &mut App, area: Rect) {
         .alignment(Alignment::Left)
         .style(Style::default().fg(app.config.get_primary_color()));
 
+    // Receipts of our latest message, messages are sorted from the newest one
+    let last_sent_receipts = app.selected_group_info.as_ref().and_then(|group_info| {
+        app.group_messages
+            .get(&group_info.master_key)?
+            .iter()
+            .find(|msg| app.uuid == Some(msg.uuid))
+            .map(|msg| &msg.receipts)
+    });
+
     let members_list: Vec<ListItem> = match &app.selected_group_info {
         Some(group_info) => group_info
             .members
@@ -133,7 +146,13 @@ pub fn render_group_info(frame: &mut Frame, app: &mut App, area: Rect) {
                         None => member.uuid.to_string(),
                     },
                 };
-                ListItem::new(display_name).style(style)
+                let receipt = match last_sent_receipts {
+                    Some(receipts) if app.uuid != Some(member.uuid) => {
+                        receipt_label(receipts.get(&member.uuid).copied())
+                    }
+                    _ => String::new(),
+                };
+                ListItem::new(format!("{display_name}{receipt}")).style(style)
             })
             .collect(),
         None => vec![],
@@ -147,6 +166,14 @@ pub fn render_group_info(frame: &mut Frame, app: &mut App, area: Rect) {
     frame.render_stateful_widget(members_widget, members_rect, &mut members_state);
 }
 
+/// Status of our latest message for a single member
+fn receipt_label(status: Option<ReceiptStatus>) -> String {
+    match status {
+        Some(status) => format!("  ✓✓ {}", status.label()),
+        None => "  ✓ sent".to_string(),
+    }
+}
+
 pub fn render_member_info(frame: &mut Frame, app: &mut App, area: Rect) {
     let layout = Layout::default()
         .direction(Direction::Vertical)
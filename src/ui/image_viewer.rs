@@ -0,0 +1,38 @@
+use ratatui::{
+    Frame,
+    layout::{Alignment, Rect},
+    style::Style,
+    widgets::{Block, BorderType, Borders, Clear, Paragraph},
+};
+use ratatui_image::{Resize, StatefulImage};
+
+use crate::app::App;
+
+/// Renders the image attachment of the selected message over the whole area
+pub fn render_image_viewer(frame: &mut Frame, app: &mut App, area: Rect) {
+    frame.render_widget(Clear, area);
+
+    let title = app
+        .selected_attachment_name()
+        .unwrap_or_else(|| "Image".to_string());
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .title(title);
+    let inner_area = block.inner(area);
+    frame.render_widget(block, area);
+
+    match app.image_viewer.as_mut() {
+        Some(image) => frame.render_stateful_widget(
+            StatefulImage::new().resize(Resize::Fit(None)),
+            inner_area,
+            image,
+        ),
+        None => {
+            let placeholder = Paragraph::new("Loading image...")
+                .alignment(Alignment::Center)
+                .style(Style::default().fg(app.config.get_secondary_color()));
+            frame.render_widget(placeholder, inner_area);
+        }
+    }
+}
@@ -14,9 +14,12 @@ pub fn render_input_and_attachment(frame: &mut Frame, app: &App, vertical_chunks
         .constraints([Constraint::Ratio(3, 5), Constraint::Ratio(2, 5)])
         .split(vertical_chunks[1]);
 
-    let input_title = match app.quoted_message.is_some() {
-        true => "Input - Replying",
-        false => "Input",
+    let input_title = if app.editing_message.is_some() {
+        "Input - Editing"
+    } else if app.quoted_message.is_some() {
+        "Input - Replying"
+    } else {
+        "Input"
     };
 
     let input_text = &app.recipients[app.selected_recipient].1;
@@ -43,7 +46,7 @@ pub fn render_input_and_attachment(frame: &mut Frame, app: &App, vertical_chunks
 
     let attachment_title = match &app.attachment_error {
         Some(error) => format!("Attachment Path - ERROR: {error}"),
-        None => "Attachment Path".to_string(),
+        None => "Attachment Paths (; separated, | caption)".to_string(),
     };
 
     let attachment_style = match &app.attachment_error {
@@ -153,6 +153,22 @@ pub fn render_options(frame: &mut Frame, app: &mut App) {
                 "Disabled"
             }
         ),
+        format!(
+            "Read Receipts: {}",
+            if app.config.send_read_receipts {
+                "Enabled"
+            } else {
+                "Disabled"
+            }
+        ),
+        format!(
+            "Typing Indicators: {}",
+            if app.config.send_typing_indicators {
+                "Enabled"
+            } else {
+                "Disabled"
+            }
+        ),
     ];
 
     let config_items: Vec<ListItem> = config_options
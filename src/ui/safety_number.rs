@@ -0,0 +1,86 @@
+use qrcode::QrCode;
+use ratatui::{
+    Frame,
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::Style,
+    widgets::{Block, BorderType, Borders, Clear, Paragraph},
+};
+use tui_qrcode::{Colors, QrCodeWidget};
+
+use crate::{app::App, ui::utils::centered_rect_fixed_size};
+
+/// Digits of the safety number per group and groups per line, as the official clients show it
+const DIGITS_PER_GROUP: usize = 5;
+const GROUPS_PER_LINE: usize = 4;
+
+/// Renders the safety number with the selected contact as digits and a QR code
+pub fn render_safety_number(frame: &mut Frame, app: &App, area: Rect) {
+    frame.render_widget(Clear, area);
+
+    let name = app
+        .selected_contact_info
+        .as_ref()
+        .map(|contact| contact.name.as_str())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("contact");
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .title(format!("Safety number with {name}"));
+    let inner_area = block.inner(area);
+    frame.render_widget(block, area);
+
+    let Some(safety_number) = &app.safety_number else {
+        let placeholder = Paragraph::new("Loading safety number...")
+            .alignment(Alignment::Center)
+            .style(Style::default().fg(app.config.get_secondary_color()));
+        frame.render_widget(placeholder, inner_area);
+        return;
+    };
+
+    let vertical_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(6), Constraint::Min(1)])
+        .split(inner_area);
+
+    let groups = safety_number
+        .digits
+        .as_bytes()
+        .chunks(DIGITS_PER_GROUP)
+        .map(|group| String::from_utf8_lossy(group).into_owned())
+        .collect::<Vec<_>>();
+    let mut text = groups
+        .chunks(GROUPS_PER_LINE)
+        .map(|line| line.join(" "))
+        .collect::<Vec<_>>()
+        .join("\n");
+    text.push_str(&format!("\n\nVERIFIED: {}", safety_number.status.label()));
+    let digits = Paragraph::new(text)
+        .alignment(Alignment::Center)
+        .style(Style::default().fg(app.config.get_primary_color()));
+    frame.render_widget(digits, vertical_chunks[0]);
+
+    let qr_area = vertical_chunks[1];
+    match QrCode::new(&safety_number.scannable) {
+        Ok(qr_code) if qr_area.width >= 50 && qr_area.height >= 25 => {
+            let widget = QrCodeWidget::new(qr_code).colors(Colors::Inverted);
+            frame.render_widget(widget, centered_rect_fixed_size(50, 25, qr_area));
+        }
+        Ok(_) => {
+            let text = format!(
+                "Terminal too small to show QR code.\nMinimum size 50x25, current size {}x{}",
+                qr_area.width, qr_area.height
+            );
+            let message = Paragraph::new(text)
+                .alignment(Alignment::Center)
+                .style(Style::default().fg(app.config.get_secondary_color()));
+            frame.render_widget(message, qr_area);
+        }
+        Err(error) => {
+            let message = Paragraph::new(format!("Failed to create QR code: {error}"))
+                .alignment(Alignment::Center)
+                .style(Style::default().fg(app.config.get_error_color()));
+            frame.render_widget(message, qr_area);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use ratatui::{
+    Frame,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, BorderType, Borders, List, ListItem, ListState, Paragraph},
+};
+
+use crate::{
+    app::{App, RecipientId},
+    search::SearchHit,
+    ui::utils::get_local_timestamp,
+};
+
+/// Renders the search input and the list of matching messages
+pub fn render_search(frame: &mut Frame, app: &App, area: Rect) {
+    let vertical_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(1)])
+        .split(area);
+
+    let input = Paragraph::new(app.search_query.as_str())
+        .style(Style::default().fg(app.config.get_accent_color()))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .title("Search"),
+        );
+    frame.render_widget(input, vertical_chunks[0]);
+    frame.set_cursor_position((
+        vertical_chunks[0].x + app.search_query.chars().count() as u16 + 1,
+        vertical_chunks[0].y + 1,
+    ));
+
+    let items: Vec<ListItem> = app
+        .search_results
+        .iter()
+        .map(|hit| render_hit(app, hit))
+        .collect();
+
+    let title = if app.search_query.trim().is_empty() {
+        "Type to search messages".to_string()
+    } else {
+        format!("Results ({})", app.search_results.len())
+    };
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .title(title),
+        )
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+    let mut state = ListState::default();
+    if !app.search_results.is_empty() {
+        state.select(Some(app.search_selected));
+    }
+
+    frame.render_stateful_widget(list, vertical_chunks[1], &mut state);
+}
+
+fn render_hit<'a>(app: &App, hit: &'a SearchHit) -> ListItem<'a> {
+    let datetime_local = get_local_timestamp(hit.timestamp);
+    let conversation = app
+        .recipients
+        .iter()
+        .find(|(recipient, _)| recipient.id() == hit.recipient)
+        .map(|(recipient, _)| recipient.display_name().to_string())
+        .unwrap_or_else(|| match hit.recipient {
+            RecipientId::Contact(uuid) => uuid.to_string(),
+            RecipientId::Group(_) => "Unknown group".to_string(),
+        });
+
+    let header = Line::from(vec![
+        Span::styled(
+            datetime_local.format("%Y-%m-%d %H:%M:%S").to_string(),
+            Style::default().fg(app.config.get_secondary_color()),
+        ),
+        Span::raw(" · "),
+        Span::styled(
+            conversation,
+            Style::default()
+                .fg(app.config.get_primary_color())
+                .add_modifier(Modifier::BOLD),
+        ),
+    ]);
+    let snippet = Line::from(hit.snippet.replace('\n', " "));
+
+    ListItem::new(vec![header, snippet, Line::default()])
+}
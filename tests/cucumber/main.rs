@@ -692,6 +692,15 @@ async fn contact_has_uuid(world: &mut TestWorld) {
     );
 }
 
+#[then("the output should be valid JSON")]
+async fn output_is_valid_json(world: &mut TestWorld) {
+    let output = world.get_output_string();
+    assert!(
+        serde_json::from_str::<serde_json::Value>(&output).is_ok(),
+        "Output is not valid JSON: {output}"
+    );
+}
+
 #[then(regex = r#"the current account should be "([^"]*)""#)]
 async fn current_account_should_be(world: &mut TestWorld, account_alias: String) {
     let account_config = world.test_config.get_account(&account_alias).unwrap();
@@ -14,4 +14,9 @@ Feature: CLI Contact Management
 
   Scenario: Sync contacts
     When I run "sync-contacts"
-    Then contacts should be synchronized successfully
\ No newline at end of file
+    Then contacts should be synchronized successfully
+
+  Scenario: List contacts as JSON
+    When I run "list-contacts --output json"
+    Then the output should be valid JSON
+    And I should see contact "bob" in the output
@@ -11,6 +11,7 @@ Feature: CLI Group Management
   Scenario: List groups
     When I run "list-groups"
     Then I should see group "Test" in the output
+    And I should see "ID: " in the output
 
   Scenario: Send message to group
     When I run "send-message-to-group --recipient 'Test' --text-message 'Hello group'"
@@ -20,4 +21,9 @@ Feature: CLI Group Management
   Scenario: List group messages
     Given group "Test" has message "Group chat" from "bob"
     When I run "list-messages --group 'Test'"
-    Then I should see "Group chat" in the output
\ No newline at end of file
+    Then I should see "Group chat" in the output
+
+  Scenario: List group messages by title in other case
+    Given group "Test" has message "Group chat" from "bob"
+    When I run "list-messages --group 'test'"
+    Then I should see "Group chat" in the output
@@ -38,4 +38,10 @@ Feature: CLI Messaging
     Given account "bob" sent "New message" to "alice"
     When I run "receive"
     Then I should see "New message" in the output
-    And the sender should be "bob"
\ No newline at end of file
+    And the sender should be "bob"
+
+  Scenario: Export conversation as Markdown
+    Given account "bob" sent "Hi Alice" to "alice"
+    When I run "receive"
+    And I run "export --contact bob --format markdown"
+    Then I should see "Hi Alice" in the output